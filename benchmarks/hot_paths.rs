@@ -0,0 +1,135 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Benchmarks for the most used code paths of this crate. Run them from the
+//! `benchmarks` directory with
+//!
+//! ```bash
+//! cargo bench
+//! ```
+//!
+//! A name can be given on the command line to only run the matching
+//! benchmarks, e.g. `cargo bench -- selection`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use chemfiles::{Frame, PerfOptions, Selection, Trajectory};
+
+fn read_frame(path: &str, step: usize) -> Frame {
+    let mut trajectory = Trajectory::open(path, 'r').unwrap();
+    let mut frame = Frame::new();
+    trajectory.read_step(step, &mut frame).unwrap();
+    frame
+}
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    group.bench_function("xyz/100 frames", |b| {
+        b.iter(|| {
+            let mut trajectory = Trajectory::open("../data/water.xyz", 'r').unwrap();
+            let mut frame = Frame::new();
+            while trajectory.read(&mut frame).is_ok() {
+                black_box(&frame);
+            }
+        });
+    });
+
+    group.bench_function("xyz/read_step", |b| {
+        b.iter(|| {
+            let mut trajectory = Trajectory::open("../data/water.xyz", 'r').unwrap();
+            let mut frame = Frame::new();
+            trajectory.read_step(50, &mut frame).unwrap();
+            black_box(&frame);
+        });
+    });
+    group.finish();
+}
+
+fn write(c: &mut Criterion) {
+    let frame = read_frame("../data/water.xyz", 0);
+
+    let mut group = c.benchmark_group("write");
+    for format in ["XYZ", "PDB"] {
+        group.bench_function(format!("{}/memory", format.to_lowercase()), |b| {
+            b.iter(|| {
+                let mut trajectory = Trajectory::memory_writer(format).unwrap();
+                trajectory.write(&frame).unwrap();
+                black_box(trajectory.memory_buffer().unwrap().len());
+            });
+        });
+    }
+    group.finish();
+}
+
+fn selection(c: &mut Criterion) {
+    let frame = read_frame("../data/water.xyz", 0);
+
+    let mut group = c.benchmark_group("selection");
+    let mut selection = Selection::new("name O").unwrap();
+    group.bench_function("name", |b| b.iter(|| black_box(selection.list(&frame))));
+
+    let mut selection = Selection::new("pairs: name(#1) O and name(#2) H and distance(#1, #2) < 1.2").unwrap();
+    group.bench_function("pairs distance", |b| b.iter(|| black_box(selection.evaluate(&frame))));
+    group.finish();
+}
+
+fn atoms(c: &mut Criterion) {
+    let frame = read_frame("../data/water.xyz", 0);
+
+    let mut group = c.benchmark_group("atoms");
+    group.bench_function("names", |b| {
+        b.iter(|| {
+            for atom in frame.iter_atoms() {
+                black_box(atom.name());
+            }
+        });
+    });
+
+    group.bench_function("interned names", |b| {
+        b.iter(|| {
+            for atom in frame.iter_atoms() {
+                black_box(atom.name_interned());
+            }
+        });
+    });
+
+    for buffer in [2, 10, 64] {
+        chemfiles::set_perf_options(PerfOptions {
+            atom_string_buffer: buffer,
+            ..PerfOptions::default()
+        });
+        group.bench_function(format!("names with buffer={buffer}"), |b| {
+            b.iter(|| {
+                for atom in frame.iter_atoms() {
+                    black_box(atom.name());
+                }
+            });
+        });
+    }
+    chemfiles::set_perf_options(PerfOptions::default());
+    group.finish();
+}
+
+fn neighbors(c: &mut Criterion) {
+    let frame = read_frame("../data/water.xyz", 0);
+
+    let mut group = c.benchmark_group("neighbors");
+    group.bench_function("guess_bonds", |b| {
+        b.iter(|| {
+            let mut frame = frame.clone();
+            frame.guess_bonds().unwrap();
+            black_box(frame.topology().bonds_count());
+        });
+    });
+
+    group.bench_function("guess_bonds_with_tolerance", |b| {
+        b.iter(|| {
+            let mut frame = frame.clone();
+            frame.guess_bonds_with_tolerance(1.2).unwrap();
+            black_box(frame.topology().bonds_count());
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, read, write, selection, atoms, neighbors);
+criterion_main!(benches);
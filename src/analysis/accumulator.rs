@@ -0,0 +1,39 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::Error;
+
+/// An `Accumulator` collects data from the frames of a trajectory one at a
+/// time, such as `Adf` or `Rmsf`.
+///
+/// Accumulators used on different segments of a trajectory can be merged
+/// together with `Accumulator::merge` to get the results for the whole
+/// trajectory. With the `serde` feature enabled, the accumulators can also be
+/// serialized at any point, and deserialized later to resume the analysis
+/// from this checkpoint. Progress trackers are not part of the serialized
+/// state.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis::{Accumulator, Adf}};
+/// let mut first = Adf::new(2.0, 180);
+/// for frame in Trajectory::open("part-1.xyz", 'r').unwrap().frames() {
+///     first.add_frame(&frame.unwrap());
+/// }
+///
+/// let mut second = Adf::new(2.0, 180);
+/// for frame in Trajectory::open("part-2.xyz", 'r').unwrap().frames() {
+///     second.add_frame(&frame.unwrap());
+/// }
+///
+/// first.merge(&second).unwrap();
+/// ```
+pub trait Accumulator {
+    /// Merge the data accumulated by `other` into this accumulator, as if all
+    /// the frames given to `other` were also given to `self`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the two accumulators were created with
+    /// incompatible settings.
+    fn merge(&mut self, other: &Self) -> Result<(), Error>;
+}
@@ -0,0 +1,357 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::{Error, Status};
+use crate::linalg;
+use crate::Frame;
+
+use super::{Accumulator, NeighborList, ProgressTracker, Results};
+
+/// `Adf` computes the angular distribution function of a system, *i.e.* the
+/// distribution of the angles formed by triplets of atoms `i-j-k` where both
+/// `i` and `k` are closer than a cutoff to the central atom `j`. This is
+/// also known as the bond-angle distribution, and complements the radial
+/// distribution function to characterize the structure of liquids and
+/// glasses.
+///
+/// Neighbors are found with a `NeighborList`, using the minimum image
+/// convention for periodic cells. The distribution is accumulated over all
+/// the frames given to `Adf::add_frame`, and normalized such that its
+/// integral over [0°, 180°] is 1.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis::Adf};
+/// let mut trajectory = Trajectory::open("silica.xyz", 'r').unwrap();
+///
+/// let mut adf = Adf::new(2.0, 180);
+/// for frame in trajectory.frames() {
+///     adf.add_frame(&frame.unwrap());
+/// }
+///
+/// for (angle, value) in adf.angles().iter().zip(adf.values()) {
+///     println!("{} {}", angle, value);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Adf {
+    neighbors: NeighborList,
+    histogram: Vec<f64>,
+    frames: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    progress: Option<ProgressTracker>,
+}
+
+/// Serialized data of an `Adf`, checked before creating the `Adf`
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AdfData {
+    neighbors: NeighborList,
+    histogram: Vec<f64>,
+    frames: usize,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<AdfData> for Adf {
+    type Error = Error;
+
+    fn try_from(data: AdfData) -> Result<Adf, Error> {
+        if data.histogram.is_empty() {
+            return Err(Error::new(
+                Status::FormatError,
+                "the angular distribution function needs at least one bin",
+            ));
+        }
+
+        if data.histogram.iter().any(|&count| !(count >= 0.0 && count.is_finite())) {
+            return Err(Error::new(
+                Status::FormatError,
+                "the angular distribution function histogram must contain positive numbers",
+            ));
+        }
+
+        return Ok(Adf {
+            neighbors: data.neighbors,
+            histogram: data.histogram,
+            frames: data.frames,
+            progress: None,
+        });
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Adf {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Adf, D::Error> {
+        let data = AdfData::deserialize(deserializer)?;
+        Adf::try_from(data).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Adf {
+    /// Create a new `Adf` using the given neighbors `cutoff` (in Angstroms)
+    /// and `nbins` bins to cover angles between 0° and 180°.
+    ///
+    /// # Panics
+    ///
+    /// If `cutoff` is not a positive finite number, or if `nbins` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::Adf;
+    /// let adf = Adf::new(3.0, 90);
+    /// assert_eq!(adf.angles().len(), 90);
+    /// assert_eq!(adf.angles()[0], 1.0);
+    /// ```
+    pub fn new(cutoff: f64, nbins: usize) -> Adf {
+        assert!(nbins > 0, "the angular distribution function needs at least one bin");
+        Adf {
+            neighbors: NeighborList::new(cutoff),
+            histogram: vec![0.0; nbins],
+            frames: 0,
+            progress: None,
+        }
+    }
+
+    /// Add the triplets of atoms in `frame` to this distribution.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::Adf};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+    ///
+    /// let mut adf = Adf::new(1.2, 18);
+    /// adf.add_frame(&frame);
+    /// assert_eq!(adf.frames_count(), 1);
+    /// ```
+    pub fn add_frame(&mut self, frame: &Frame) {
+        self.neighbors.compute(frame);
+
+        // vectors from each atom to all its neighbors
+        let mut vectors = vec![Vec::new(); frame.size()];
+        for pair in self.neighbors.pairs() {
+            vectors[pair.first].push(pair.vector);
+            vectors[pair.second].push(linalg::scale(pair.vector, -1.0));
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let nbins = self.histogram.len() as f64;
+        for vectors in &vectors {
+            for (n, &first) in vectors.iter().enumerate() {
+                for &second in &vectors[(n + 1)..] {
+                    let norms = (linalg::norm2(first) * linalg::norm2(second)).sqrt();
+                    if norms == 0.0 {
+                        // overlapping atoms do not define an angle
+                        continue;
+                    }
+                    let cosine = (linalg::dot(first, second) / norms).clamp(-1.0, 1.0);
+
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    let bin = (cosine.acos() / std::f64::consts::PI * nbins) as usize;
+                    let bin = usize::min(bin, self.histogram.len() - 1);
+                    self.histogram[bin] += 1.0;
+                }
+            }
+        }
+
+        self.frames += 1;
+        if let Some(progress) = &mut self.progress {
+            progress.tick();
+        }
+    }
+
+    /// Report the progress of this analysis to the given `tracker` after
+    /// each call to `Adf::add_frame`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::{Adf, Progress, ProgressTracker};
+    /// let mut adf = Adf::new(3.0, 90);
+    /// adf.set_progress(ProgressTracker::new(
+    ///     |progress: &Progress| eprintln!("ADF: {} frames", progress.frames),
+    ///     None,
+    /// ));
+    /// ```
+    pub fn set_progress(&mut self, tracker: ProgressTracker) {
+        self.progress = Some(tracker);
+    }
+
+    /// Get the number of frames added to this distribution.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::Adf;
+    /// assert_eq!(Adf::new(3.0, 90).frames_count(), 0);
+    /// ```
+    pub fn frames_count(&self) -> usize {
+        self.frames
+    }
+
+    /// Get the angle at the center of each bin, in degrees.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::Adf;
+    /// let adf = Adf::new(3.0, 4);
+    /// assert_eq!(adf.angles(), [22.5, 67.5, 112.5, 157.5]);
+    /// ```
+    pub fn angles(&self) -> Vec<f64> {
+        #[allow(clippy::cast_precision_loss)]
+        let width = 180.0 / self.histogram.len() as f64;
+        #[allow(clippy::cast_precision_loss)]
+        return (0..self.histogram.len()).map(|i| (i as f64 + 0.5) * width).collect();
+    }
+
+    /// Get the value of the distribution in each bin, in degree⁻¹. The
+    /// distribution is normalized such that its integral over all angles is
+    /// 1, or contains only zeros if no triplets were found.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::Adf};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+    ///
+    /// let mut adf = Adf::new(1.2, 18);
+    /// adf.add_frame(&frame);
+    ///
+    /// // the only angle (90°) is in the 10th bin, with a width of 10°
+    /// assert_eq!(adf.values()[9], 0.1);
+    /// ```
+    pub fn values(&self) -> Vec<f64> {
+        let total = self.histogram.iter().sum::<f64>();
+        if total == 0.0 {
+            return vec![0.0; self.histogram.len()];
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let width = 180.0 / self.histogram.len() as f64;
+        return self.histogram.iter().map(|count| count / (total * width)).collect();
+    }
+}
+
+impl Accumulator for Adf {
+    fn merge(&mut self, other: &Adf) -> Result<(), Error> {
+        #[allow(clippy::float_cmp)]
+        if self.histogram.len() != other.histogram.len() || self.neighbors.cutoff() != other.neighbors.cutoff() {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                "can not merge angular distribution functions with different cutoff or number of bins",
+            ));
+        }
+
+        for (count, other) in self.histogram.iter_mut().zip(&other.histogram) {
+            *count += other;
+        }
+        self.frames += other.frames;
+        return Ok(());
+    }
+}
+
+impl Results for Adf {
+    fn columns(&self) -> Vec<String> {
+        vec!["angle".into(), "adf".into()]
+    }
+
+    fn rows(&self) -> Vec<Vec<f64>> {
+        self.angles()
+            .into_iter()
+            .zip(self.values())
+            .map(|(angle, value)| vec![angle, value])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, UnitCell};
+
+    #[test]
+    fn adf() {
+        // simple cubic lattice: first neighbors make 90° and 180° angles
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([8.0, 8.0, 8.0]));
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    let position = [2.0 * f64::from(i), 2.0 * f64::from(j), 2.0 * f64::from(k)];
+                    frame.add_atom(&Atom::new("Po"), position, None);
+                }
+            }
+        }
+
+        let mut adf = Adf::new(2.5, 36);
+        adf.add_frame(&frame);
+        adf.add_frame(&frame);
+        assert_eq!(adf.frames_count(), 2);
+
+        // each atom has 6 neighbors, giving 12 angles at 90° and 3 at 180°
+        let values = adf.values();
+        approx::assert_ulps_eq!(values[18], 12.0 / 15.0 / 5.0);
+        approx::assert_ulps_eq!(values[35], 3.0 / 15.0 / 5.0);
+        approx::assert_ulps_eq!(values.iter().sum::<f64>() * 5.0, 1.0);
+
+        let rows = adf.rows();
+        assert_eq!(rows.len(), 36);
+        assert_eq!(rows[18], [92.5, values[18]]);
+
+        let empty = Adf::new(1.0, 10);
+        assert_eq!(empty.values(), [0.0; 10]);
+    }
+
+    #[test]
+    fn merge() {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+
+        let mut first = Adf::new(1.2, 18);
+        first.add_frame(&frame);
+        let mut second = first.clone();
+        second.add_frame(&frame);
+
+        first.merge(&second).unwrap();
+        assert_eq!(first.frames_count(), 3);
+        assert_eq!(first.values()[9], 0.1);
+
+        assert!(first.merge(&Adf::new(1.2, 36)).is_err());
+        assert!(first.merge(&Adf::new(1.5, 18)).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde() {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+
+        let mut adf = Adf::new(1.2, 18);
+        adf.add_frame(&frame);
+
+        let json = serde_json::to_string(&adf).unwrap();
+        let mut restored: Adf = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.frames_count(), 1);
+        assert_eq!(restored.values(), adf.values());
+
+        // resume the analysis from the checkpoint
+        restored.add_frame(&frame);
+        assert_eq!(restored.frames_count(), 2);
+
+        let invalid = json.replace("1.2", "-1.0");
+        assert!(serde_json::from_str::<Adf>(&invalid).is_err());
+
+        let empty = r#"{"neighbors": {"cutoff": 1.2}, "histogram": [], "frames": 1}"#;
+        assert!(serde_json::from_str::<Adf>(empty).is_err());
+
+        let negative = r#"{"neighbors": {"cutoff": 1.2}, "histogram": [1.0, -2.0], "frames": 1}"#;
+        assert!(serde_json::from_str::<Adf>(negative).is_err());
+    }
+}
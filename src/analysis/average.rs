@@ -0,0 +1,146 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::{Error, Status};
+use crate::linalg::{self, Vector3D};
+use crate::{Frame, Selection, Trajectory};
+
+/// Compute the average structure of the atoms matching `selection` over all
+/// the frames in the `trajectory`.
+///
+/// The selection is evaluated on the first frame, and the same atoms are used
+/// for all the other frames. If `align` is `true`, each frame is superposed
+/// onto the first one before averaging, removing global rotations and
+/// translations. The returned frame contains the selected atoms, with the
+/// topology, unit cell and step of the first frame, and averaged positions
+/// (and velocities if the trajectory contains them).
+///
+/// # Errors
+///
+/// This function fails if the selection is invalid or matches multiple atoms
+/// (for example `"pairs: ..."`), if the trajectory is
+/// empty or can not be read, or if the frames do not all contain the same
+/// number of atoms.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis};
+/// let mut trajectory = Trajectory::open("protein.pdb", 'r').unwrap();
+/// let average = analysis::average_structure(&mut trajectory, "name CA", true).unwrap();
+///
+/// let mut output = Trajectory::open("average.pdb", 'w').unwrap();
+/// output.write(&average).unwrap();
+/// ```
+pub fn average_structure(trajectory: &mut Trajectory, selection: &str, align: bool) -> Result<Frame, Error> {
+    let mut selection = Selection::new(selection)?;
+    if selection.size() != 1 {
+        return Err(Error::new(
+            Status::SelectionError,
+            "average structure requires a selection matching single atoms",
+        ));
+    }
+
+    let mut first: Option<(Frame, Vec<usize>)> = None;
+    let mut reference = Vec::new();
+    let mut positions: Vec<Vector3D> = Vec::new();
+    let mut velocities: Vec<Vector3D> = Vec::new();
+    let mut count = 0_usize;
+
+    for frame in trajectory.frames() {
+        let frame = frame?;
+        let atoms = if let Some((ref first, ref atoms)) = first {
+            if frame.size() != first.size() {
+                return Err(Error::new(
+                    Status::ChemfilesError,
+                    format!(
+                        "frame at step {} contains {} atoms, expected {}",
+                        frame.step(),
+                        frame.size(),
+                        first.size()
+                    ),
+                ));
+            }
+            atoms.clone()
+        } else {
+            let atoms = selection.list(&frame);
+            reference = atoms.iter().map(|&i| frame.positions()[i]).collect::<Vec<_>>();
+            positions = vec![[0.0; 3]; atoms.len()];
+            velocities = vec![[0.0; 3]; atoms.len()];
+            first = Some((frame.clone(), atoms.clone()));
+            atoms
+        };
+
+        let selected = atoms.iter().map(|&i| frame.positions()[i]).collect::<Vec<_>>();
+        let superposition = if align {
+            Some(linalg::superpose(&reference, &selected, None))
+        } else {
+            None
+        };
+
+        for (k, &position) in selected.iter().enumerate() {
+            let position = superposition.as_ref().map_or(position, |s| s.apply(position));
+            positions[k] = linalg::add(positions[k], position);
+        }
+
+        if let Some(frame_velocities) = frame.velocities() {
+            for (k, &i) in atoms.iter().enumerate() {
+                let velocity = frame_velocities[i];
+                let velocity = superposition
+                    .as_ref()
+                    .map_or(velocity, |s| linalg::mat_vec(&s.rotation, velocity));
+                velocities[k] = linalg::add(velocities[k], velocity);
+            }
+        }
+        count += 1;
+    }
+
+    let (first, atoms) = first.ok_or_else(|| {
+        Error::new(
+            Status::ChemfilesError,
+            "can not compute the average structure of an empty trajectory",
+        )
+    })?;
+
+    #[allow(clippy::cast_precision_loss)]
+    let factor = 1.0 / count as f64;
+    let mut average = first.subset(&atoms);
+    for (position, &sum) in average.positions_mut().iter_mut().zip(&positions) {
+        *position = linalg::scale(sum, factor);
+    }
+    if let Some(average_velocities) = average.velocities_mut() {
+        for (velocity, &sum) in average_velocities.iter_mut().zip(&velocities) {
+            *velocity = linalg::scale(sum, factor);
+        }
+    }
+
+    return Ok(average);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average() {
+        let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let average = average_structure(&mut trajectory, "name O", false).unwrap();
+        assert_eq!(average.size(), 99);
+        assert_eq!(average.atom(0).name(), "O");
+
+        let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let mut expected = [0.0; 3];
+        for frame in trajectory.frames() {
+            expected = linalg::add(expected, frame.unwrap().positions()[0]);
+        }
+        let expected = linalg::scale(expected, 1.0 / 100.0);
+        crate::assert_vector3d_eq(&average.positions()[0], &expected, 1e-12);
+
+        let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let aligned = average_structure(&mut trajectory, "all", true).unwrap();
+        assert_eq!(aligned.size(), 297);
+
+        let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+        assert!(average_structure(&mut trajectory, "not a selection", true).is_err());
+        let error = average_structure(&mut trajectory, "pairs: all", true).unwrap_err();
+        assert_eq!(error.status, Status::SelectionError);
+    }
+}
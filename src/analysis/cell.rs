@@ -0,0 +1,113 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::Error;
+use crate::{Frame, Trajectory};
+
+use super::Results;
+
+/// Time series of the unit cell parameters in a trajectory, created by
+/// `analysis::cell_series`. All the vectors contain one entry per frame.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CellSeries {
+    /// Step of each frame
+    pub steps: Vec<usize>,
+    /// Lengths of the unit cell, in Angstroms
+    pub lengths: Vec<[f64; 3]>,
+    /// Angles of the unit cell, in degrees
+    pub angles: Vec<[f64; 3]>,
+    /// Volume of the unit cell, in cubic Angstroms
+    pub volumes: Vec<f64>,
+}
+
+impl CellSeries {
+    /// Add the unit cell parameters of `frame` at the end of this series
+    pub(crate) fn push(&mut self, frame: &Frame) {
+        let cell = frame.cell();
+        self.steps.push(frame.step());
+        self.lengths.push(cell.lengths());
+        self.angles.push(cell.angles());
+        self.volumes.push(cell.volume());
+    }
+}
+
+impl Results for CellSeries {
+    fn columns(&self) -> Vec<String> {
+        ["step", "a", "b", "c", "alpha", "beta", "gamma", "volume"]
+            .iter()
+            .map(|&name| name.into())
+            .collect()
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn rows(&self) -> Vec<Vec<f64>> {
+        let mut rows = Vec::with_capacity(self.steps.len());
+        for (i, &step) in self.steps.iter().enumerate() {
+            let [a, b, c] = self.lengths[i];
+            let [alpha, beta, gamma] = self.angles[i];
+            rows.push(vec![step as f64, a, b, c, alpha, beta, gamma, self.volumes[i]]);
+        }
+        return rows;
+    }
+}
+
+/// Read all the frames in `trajectory` and collect the unit cell lengths,
+/// angles and volume for each one of them. This is useful to check the
+/// equilibration of simulations with a varying cell, such as NPT runs.
+///
+/// A single frame is reused to read the whole trajectory. Frames without
+/// unit cell have zero lengths and volume.
+///
+/// # Errors
+///
+/// This function fails if any of the frames can not be read.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis};
+/// let mut trajectory = Trajectory::open("npt.dcd", 'r').unwrap();
+/// let series = analysis::cell_series(&mut trajectory).unwrap();
+///
+/// let average = series.volumes.iter().sum::<f64>() / series.volumes.len() as f64;
+/// println!("average volume: {} A^3", average);
+/// ```
+pub fn cell_series(trajectory: &mut Trajectory) -> Result<CellSeries, Error> {
+    let nsteps = trajectory.try_nsteps()?;
+    let mut series = CellSeries {
+        steps: Vec::with_capacity(nsteps),
+        lengths: Vec::with_capacity(nsteps),
+        angles: Vec::with_capacity(nsteps),
+        volumes: Vec::with_capacity(nsteps),
+    };
+
+    let mut frame = Frame::new();
+    for step in 0..nsteps {
+        trajectory.read_step(step, &mut frame)?;
+        series.push(&frame);
+    }
+
+    return Ok(series);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series() {
+        let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+        trajectory.set_cell(&crate::UnitCell::new([20.0, 21.0, 22.0]));
+        let series = cell_series(&mut trajectory).unwrap();
+
+        assert_eq!(series.steps.len(), 100);
+        assert_eq!(series.steps[10], 10);
+        assert_eq!(series.lengths.len(), 100);
+        assert_eq!(series.lengths[42], [20.0, 21.0, 22.0]);
+        assert_eq!(series.angles[0], [90.0, 90.0, 90.0]);
+        approx::assert_ulps_eq!(series.volumes[99], 20.0 * 21.0 * 22.0);
+
+        let rows = series.rows();
+        assert_eq!(series.columns().len(), 8);
+        assert_eq!(rows.len(), 100);
+        assert_eq!(rows[3], [3.0, 20.0, 21.0, 22.0, 90.0, 90.0, 90.0, 9240.0]);
+    }
+}
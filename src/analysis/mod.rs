@@ -0,0 +1,53 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Analysis algorithms working on `Frame` and `Trajectory`, implemented on
+//! top of the chemfiles API.
+
+mod accumulator;
+pub use self::accumulator::Accumulator;
+
+mod adf;
+pub use self::adf::Adf;
+
+mod average;
+pub use self::average::average_structure;
+
+mod cell;
+pub use self::cell::{cell_series, CellSeries};
+
+mod pbc;
+
+mod molecules;
+pub use self::molecules::{per_molecule, MoleculeMetric};
+
+mod neighbors;
+pub use self::neighbors::{NeighborList, NeighborPair};
+
+mod parallel;
+pub use self::parallel::{run_parallel, FrameAnalyzer, Reduce};
+
+pub mod polymer;
+
+pub use crate::progress::{Progress, ProgressSink, ProgressTracker};
+
+mod results;
+pub use self::results::{Results, Table};
+
+mod rdf;
+pub use self::rdf::{rdf, rdf_with_progress, Rdf};
+
+mod residues;
+pub use self::residues::{residue_bfactors, residue_occupancies};
+
+mod rmsd;
+pub use self::rmsd::{align, rmsd, rmsd_symmetric};
+
+mod rmsf;
+pub use self::rmsf::{rmsf, Rmsf};
+
+mod unwrap;
+pub use self::unwrap::Unwrapper;
+
+mod voronoi;
+pub use self::voronoi::{radical_voronoi_neighbors, voronoi_neighbors};
@@ -0,0 +1,189 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::{Error, Status};
+use crate::linalg::{self, Vector3D};
+use crate::{Frame, Trajectory};
+
+use super::pbc::{bonded_neighbors, unwrapped_positions};
+
+/// Metric computed for each molecule by `analysis::per_molecule`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MoleculeMetric {
+    /// Mass-weighted radius of gyration, in Angstroms
+    RadiusOfGyration,
+    /// Distance between the first and the last atom of the molecule (using
+    /// atomic indexes order), in Angstroms
+    EndToEndDistance,
+    /// Norm of the dipole moment computed from the atomic charges and the
+    /// center of mass, in e·Å
+    Dipole,
+}
+
+/// Compute the given `metric` for every molecule in every frame of the
+/// `trajectory`, returning a matrix with one row per frame and one column
+/// per molecule.
+///
+/// Molecules are the groups of bonded atoms (see `Topology::molecules`) in
+/// the first frame, and the same molecules are used for all frames. Files
+/// without bonds information need a topology set with
+/// `Trajectory::set_topology`, otherwise each atom is its own molecule.
+/// Molecules split across periodic boundaries are made whole by following
+/// the bonds before computing the metric. Virtual sites (see
+/// `Atom::is_virtual`) are ignored when weighting by mass.
+///
+/// # Errors
+///
+/// This function fails if any frame can not be read, or if the frames do not
+/// all contain the same number of atoms.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis::{self, MoleculeMetric}};
+/// let mut trajectory = Trajectory::open("melt.lammpstrj", 'r').unwrap();
+/// trajectory.set_topology_file("melt.pdb").unwrap();
+///
+/// let radii = analysis::per_molecule(&mut trajectory, MoleculeMetric::RadiusOfGyration).unwrap();
+/// for (step, values) in radii.iter().enumerate() {
+///     println!("step {}: Rg of first chain = {}", step, values[0]);
+/// }
+/// ```
+pub fn per_molecule(trajectory: &mut Trajectory, metric: MoleculeMetric) -> Result<Vec<Vec<f64>>, Error> {
+    let mut molecules = Vec::new();
+    let mut neighbors = Vec::new();
+    let mut natoms = 0;
+
+    let mut results = Vec::new();
+    let mut frame = Frame::new();
+    for step in 0..trajectory.try_nsteps()? {
+        trajectory.read_step(step, &mut frame)?;
+        if step == 0 {
+            let topology = frame.topology();
+            molecules = topology.molecules();
+            neighbors = bonded_neighbors(&topology);
+            natoms = frame.size();
+        } else if frame.size() != natoms {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!(
+                    "frame at step {} contains {} atoms, expected {}",
+                    step,
+                    frame.size(),
+                    natoms
+                ),
+            ));
+        }
+
+        let masses = frame.analysis_masses();
+        let charges = if metric == MoleculeMetric::Dipole {
+            frame.iter_atoms().map(|atom| atom.charge()).collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+
+        let values = molecules
+            .iter()
+            .map(|molecule| {
+                let positions = unwrapped_positions(&frame, molecule, &neighbors);
+                let weights = molecule.iter().map(|&i| masses[i]).collect::<Vec<_>>();
+                match metric {
+                    MoleculeMetric::RadiusOfGyration => radius_of_gyration(&positions, &weights),
+                    MoleculeMetric::EndToEndDistance => {
+                        let first = positions[0];
+                        let last = positions[positions.len() - 1];
+                        linalg::norm2(linalg::sub(last, first)).sqrt()
+                    }
+                    MoleculeMetric::Dipole => {
+                        let center = linalg::weighted_center(&positions, Some(&weights));
+                        let mut dipole = [0.0; 3];
+                        for (position, &i) in positions.iter().zip(molecule) {
+                            let relative = linalg::sub(*position, center);
+                            dipole = linalg::add(dipole, linalg::scale(relative, charges[i]));
+                        }
+                        linalg::norm2(dipole).sqrt()
+                    }
+                }
+            })
+            .collect();
+        results.push(values);
+    }
+
+    return Ok(results);
+}
+
+/// Compute the weighted radius of gyration of the given `positions`
+pub(crate) fn radius_of_gyration(positions: &[Vector3D], weights: &[f64]) -> f64 {
+    let center = linalg::weighted_center(positions, Some(weights));
+    let mut sum = 0.0;
+    let mut total = 0.0;
+    for (&position, &weight) in positions.iter().zip(weights) {
+        sum += weight * linalg::norm2(linalg::sub(position, center));
+        total += weight;
+    }
+    if total == 0.0 {
+        return 0.0;
+    }
+    return (sum / total).sqrt();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, Topology, UnitCell};
+
+    #[test]
+    fn metrics() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        let mut oxygen = Atom::new("O");
+        oxygen.set_charge(-0.8);
+        let mut hydrogen = Atom::new("H");
+        hydrogen.set_charge(0.4);
+
+        // first molecule split across the periodic boundary
+        frame.add_atom(&Atom::new("C"), [9.5, 5.0, 5.0], None);
+        frame.add_atom(&Atom::new("C"), [0.5, 5.0, 5.0], None);
+        frame.add_atom(&Atom::new("C"), [1.5, 5.0, 5.0], None);
+        // second molecule
+        frame.add_atom(&oxygen, [5.0, 5.0, 5.0], None);
+        frame.add_atom(&hydrogen, [6.0, 5.0, 5.0], None);
+        frame.add_atom(&hydrogen, [4.0, 5.0, 5.0], None);
+
+        frame.add_bond(0, 1);
+        frame.add_bond(1, 2);
+        frame.add_bond(3, 4);
+        frame.add_bond(3, 5);
+
+        let mut trajectory = Trajectory::memory_writer("XYZ").unwrap();
+        trajectory.write(&frame).unwrap();
+        trajectory.write(&frame).unwrap();
+        let data = trajectory.into_bytes().unwrap();
+
+        let mut trajectory = Trajectory::memory_reader(&data, "XYZ").unwrap();
+        trajectory.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        let mut topology = Topology::clone(&frame.topology());
+        trajectory.set_topology(&topology);
+
+        let end_to_end = per_molecule(&mut trajectory, MoleculeMetric::EndToEndDistance).unwrap();
+        assert_eq!(end_to_end.len(), 2);
+        approx::assert_ulps_eq!(end_to_end[1][0], 2.0, epsilon = 1e-12);
+        approx::assert_ulps_eq!(end_to_end[1][1], 1.0, epsilon = 1e-12);
+
+        let radius = per_molecule(&mut trajectory, MoleculeMetric::RadiusOfGyration).unwrap();
+        approx::assert_ulps_eq!(radius[0][0], f64::sqrt(2.0 / 3.0), epsilon = 1e-12);
+
+        let dipole = per_molecule(&mut trajectory, MoleculeMetric::Dipole).unwrap();
+        approx::assert_ulps_eq!(dipole[0][1], 0.0, epsilon = 1e-12);
+
+        // virtual sites do not contribute to the mass-weighted metrics
+        topology.atom_mut(2).set_virtual(true);
+        trajectory.set_topology(&topology);
+        let radius = per_molecule(&mut trajectory, MoleculeMetric::RadiusOfGyration).unwrap();
+        approx::assert_ulps_eq!(radius[0][0], 0.5, epsilon = 1e-12);
+        topology.atom_mut(2).set_virtual(false);
+
+        topology.add_atom(&Atom::new("X"));
+        trajectory.set_topology(&topology);
+        assert!(per_molecule(&mut trajectory, MoleculeMetric::Dipole).is_err());
+    }
+}
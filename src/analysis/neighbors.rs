@@ -0,0 +1,855 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use super::Results;
+use crate::linalg::{self, Matrix3, Vector3D};
+use crate::{CellShape, Frame};
+
+/// A pair of atoms closer than the cutoff of a `NeighborList`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NeighborPair {
+    /// Index of the first atom in the pair, always smaller than `second`
+    pub first: usize,
+    /// Index of the second atom in the pair
+    pub second: usize,
+    /// Distance between the two atoms, in Angstroms
+    pub distance: f64,
+    /// Vector from the first atom to the second one, using the minimum image
+    /// convention for periodic cells
+    pub vector: [f64; 3],
+}
+
+/// `NeighborList` finds all the pairs of atoms closer than a given cutoff in
+/// a frame, using a cell list to only compute the distances between nearby
+/// atoms.
+///
+/// Distances use the minimum image convention with the unit cell of the
+/// frame. This is exact for orthorhombic cells, and for triclinic cells the
+/// images in the neighboring cells are checked, which is only exact for
+/// cells which are not too skewed. Infinite cells do not use periodic
+/// boundary conditions.
+///
+/// When analyzing consecutive frames of a trajectory, `NeighborList::update`
+/// can reuse the work done for the previous frames: with a Verlet skin set by
+/// `NeighborList::with_skin`, the list keeps all the pairs closer than
+/// `cutoff + skin` and only rebuilds this list when an atom moved by more
+/// than half the skin.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, Frame, UnitCell, analysis::NeighborList};
+/// let mut frame = Frame::new();
+/// frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+/// frame.add_atom(&Atom::new("Ar"), [0.5, 0.0, 0.0], None);
+/// frame.add_atom(&Atom::new("Ar"), [9.5, 0.0, 0.0], None);
+/// frame.add_atom(&Atom::new("Ar"), [5.0, 0.0, 0.0], None);
+///
+/// let mut neighbors = NeighborList::new(2.0);
+/// neighbors.compute(&frame);
+///
+/// let pairs = neighbors.pairs();
+/// assert_eq!(pairs.len(), 1);
+/// assert_eq!((pairs[0].first, pairs[0].second), (0, 1));
+/// assert!((pairs[0].distance - 1.0).abs() < 1e-12);
+/// assert_eq!(neighbors.neighbors(1), [0]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct NeighborList {
+    cutoff: f64,
+    skin: f64,
+    pairs: Vec<NeighborPair>,
+    neighbors: Vec<Vec<usize>>,
+    /// Pairs closer than `cutoff + skin` at the last rebuild
+    candidates: Vec<(usize, usize)>,
+    /// Positions of the atoms at the last rebuild
+    reference: Vec<Vector3D>,
+    /// Cell matrix at the last rebuild, `None` if the list was never built
+    reference_cell: Option<Matrix3>,
+    rebuilds: usize,
+}
+
+/// Only the cutoff and skin are serialized, the pairs are computed again when
+/// calling `NeighborList::compute` or `NeighborList::update`
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NeighborListData {
+    cutoff: f64,
+    #[serde(default)]
+    skin: f64,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for NeighborList {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = NeighborListData {
+            cutoff: self.cutoff,
+            skin: self.skin,
+        };
+        data.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for NeighborList {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<NeighborList, D::Error> {
+        let data = NeighborListData::deserialize(deserializer)?;
+        if !(data.cutoff > 0.0 && data.cutoff.is_finite()) {
+            return Err(serde::de::Error::custom(format!(
+                "the neighbor list cutoff must be a positive number, got {}",
+                data.cutoff
+            )));
+        }
+        if !(data.skin >= 0.0 && data.skin.is_finite()) {
+            return Err(serde::de::Error::custom(format!(
+                "the neighbor list skin must be a positive number, got {}",
+                data.skin
+            )));
+        }
+        return Ok(NeighborList::new(data.cutoff).with_skin(data.skin));
+    }
+}
+
+impl NeighborList {
+    /// Create a new `NeighborList` finding pairs closer than `cutoff`
+    /// (in Angstroms).
+    ///
+    /// # Panics
+    ///
+    /// If `cutoff` is not a positive finite number.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::NeighborList;
+    /// let neighbors = NeighborList::new(3.5);
+    /// assert_eq!(neighbors.cutoff(), 3.5);
+    /// assert!(neighbors.pairs().is_empty());
+    /// ```
+    pub fn new(cutoff: f64) -> NeighborList {
+        assert!(
+            cutoff > 0.0 && cutoff.is_finite(),
+            "the neighbor list cutoff must be a positive number, got {cutoff}"
+        );
+        NeighborList {
+            cutoff,
+            skin: 0.0,
+            pairs: Vec::new(),
+            neighbors: Vec::new(),
+            candidates: Vec::new(),
+            reference: Vec::new(),
+            reference_cell: None,
+            rebuilds: 0,
+        }
+    }
+
+    /// Use a Verlet `skin` (in Angstroms) with this neighbor list. The list
+    /// of candidate pairs used by `NeighborList::update` then contains all
+    /// pairs closer than `cutoff + skin`, and is only rebuilt when an atom
+    /// moved by more than `skin / 2` since the last rebuild. Larger skins
+    /// give less frequent but more expensive rebuilds.
+    ///
+    /// # Panics
+    ///
+    /// If `skin` is negative or not finite.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::NeighborList;
+    /// let neighbors = NeighborList::new(3.5).with_skin(0.5);
+    /// assert_eq!(neighbors.skin(), 0.5);
+    /// ```
+    #[must_use]
+    pub fn with_skin(mut self, skin: f64) -> NeighborList {
+        assert!(
+            skin >= 0.0 && skin.is_finite(),
+            "the neighbor list skin must be a positive number, got {skin}"
+        );
+        self.skin = skin;
+        self.reference_cell = None;
+        self
+    }
+
+    /// Get the cutoff of this neighbor list, in Angstroms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::NeighborList;
+    /// assert_eq!(NeighborList::new(3.5).cutoff(), 3.5);
+    /// ```
+    pub fn cutoff(&self) -> f64 {
+        self.cutoff
+    }
+
+    /// Get the Verlet skin of this neighbor list, in Angstroms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::NeighborList;
+    /// assert_eq!(NeighborList::new(3.5).skin(), 0.0);
+    /// ```
+    pub fn skin(&self) -> f64 {
+        self.skin
+    }
+
+    /// Get the number of times the list of candidate pairs was rebuilt from
+    /// scratch by `NeighborList::compute` and `NeighborList::update`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::NeighborList};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+    ///
+    /// let mut neighbors = NeighborList::new(2.0).with_skin(0.5);
+    /// neighbors.update(&frame);
+    /// neighbors.update(&frame);
+    /// assert_eq!(neighbors.rebuilds(), 1);
+    /// ```
+    pub fn rebuilds(&self) -> usize {
+        self.rebuilds
+    }
+
+    /// Find all the pairs of atoms closer than the cutoff in `frame`,
+    /// replacing the pairs found previously.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::NeighborList};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("Ar"), [1.0, 0.0, 0.0], None);
+    ///
+    /// let mut neighbors = NeighborList::new(2.0);
+    /// neighbors.compute(&frame);
+    /// assert_eq!(neighbors.pairs().len(), 1);
+    /// ```
+    pub fn compute(&mut self, frame: &Frame) {
+        let (matrix, inverse) = cell_matrices(frame);
+        let positions = frame.positions();
+        self.rebuild(positions, &matrix, inverse.as_ref());
+        self.filter(positions, &matrix, inverse.as_ref());
+    }
+
+    /// Find all the pairs of atoms closer than the cutoff in `frame`,
+    /// replacing the pairs found previously. Contrary to
+    /// `NeighborList::compute`, this only checks the candidate pairs found
+    /// during the last rebuild, unless an atom moved by more than half the
+    /// skin, or the number of atoms or the unit cell changed.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::NeighborList};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("Ar"), [2.2, 0.0, 0.0], None);
+    ///
+    /// let mut neighbors = NeighborList::new(2.0).with_skin(0.5);
+    /// neighbors.update(&frame);
+    /// assert!(neighbors.pairs().is_empty());
+    ///
+    /// // small displacements re-use the candidate pairs
+    /// frame.positions_mut()[1][0] = 1.9;
+    /// neighbors.update(&frame);
+    /// assert_eq!(neighbors.pairs().len(), 1);
+    /// assert_eq!(neighbors.rebuilds(), 1);
+    /// ```
+    pub fn update(&mut self, frame: &Frame) {
+        let (matrix, inverse) = cell_matrices(frame);
+        let positions = frame.positions();
+        if self.needs_rebuild(positions, &matrix, inverse.as_ref()) {
+            self.rebuild(positions, &matrix, inverse.as_ref());
+        }
+        self.filter(positions, &matrix, inverse.as_ref());
+    }
+
+    /// Check if the candidate pairs need to be rebuilt for the given
+    /// `positions` and cell
+    fn needs_rebuild(&self, positions: &[Vector3D], matrix: &Matrix3, inverse: Option<&Matrix3>) -> bool {
+        if self.reference_cell.as_ref() != Some(matrix) || self.reference.len() != positions.len() {
+            return true;
+        }
+
+        let threshold = 0.25 * self.skin * self.skin;
+        return positions.iter().zip(&self.reference).any(|(&position, &reference)| {
+            let mut displacement = linalg::sub(position, reference);
+            if let Some(inverse) = inverse {
+                displacement = linalg::minimum_image(matrix, inverse, displacement);
+            }
+            linalg::norm2(displacement) > threshold
+        });
+    }
+
+    /// Find all the candidate pairs closer than `cutoff + skin` using a cell
+    /// list
+    fn rebuild(&mut self, positions: &[Vector3D], matrix: &Matrix3, inverse: Option<&Matrix3>) {
+        let range = self.cutoff + self.skin;
+        let grid = match inverse {
+            Some(inverse) => CellGrid::periodic(positions, matrix, inverse, range),
+            None => CellGrid::open(positions, range),
+        };
+
+        self.candidates.clear();
+        let range2 = range * range;
+        for bin in 0..grid.bins_count() {
+            let atoms = grid.bin(bin);
+            let (others, count) = grid.neighbor_bins(bin);
+            for &other in &others[..count] {
+                for &i in atoms {
+                    for &j in grid.bin(other) {
+                        if i >= j {
+                            continue;
+                        }
+
+                        let mut vector = linalg::sub(positions[j], positions[i]);
+                        if let Some(inverse) = inverse {
+                            vector = linalg::minimum_image(matrix, inverse, vector);
+                        }
+
+                        if linalg::norm2(vector) < range2 {
+                            self.candidates.push((i, j));
+                        }
+                    }
+                }
+            }
+        }
+        self.candidates.sort_unstable();
+
+        self.reference = positions.to_vec();
+        self.reference_cell = Some(*matrix);
+        self.rebuilds += 1;
+    }
+
+    /// Find the pairs closer than the cutoff among the candidate pairs
+    fn filter(&mut self, positions: &[Vector3D], matrix: &Matrix3, inverse: Option<&Matrix3>) {
+        self.pairs.clear();
+        let cutoff2 = self.cutoff * self.cutoff;
+        for &(i, j) in &self.candidates {
+            let mut vector = linalg::sub(positions[j], positions[i]);
+            if let Some(inverse) = inverse {
+                vector = linalg::minimum_image(matrix, inverse, vector);
+            }
+
+            let distance2 = linalg::norm2(vector);
+            if distance2 < cutoff2 {
+                self.pairs.push(NeighborPair {
+                    first: i,
+                    second: j,
+                    distance: distance2.sqrt(),
+                    vector,
+                });
+            }
+        }
+
+        self.neighbors.clear();
+        self.neighbors.resize(positions.len(), Vec::new());
+        for pair in &self.pairs {
+            self.neighbors[pair.first].push(pair.second);
+            self.neighbors[pair.second].push(pair.first);
+        }
+        for neighbors in &mut self.neighbors {
+            neighbors.sort_unstable();
+        }
+    }
+
+    /// Get all the pairs found by the last call to `NeighborList::compute`
+    /// or `NeighborList::update`, sorted by the index of the first and then the second atom.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::NeighborList};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("Ar"), [0.0, 1.5, 0.0], None);
+    ///
+    /// let mut neighbors = NeighborList::new(2.0);
+    /// neighbors.compute(&frame);
+    /// assert_eq!(neighbors.pairs()[0].vector, [0.0, 1.5, 0.0]);
+    /// ```
+    pub fn pairs(&self) -> &[NeighborPair] {
+        &self.pairs
+    }
+
+    /// Get the sorted indexes of the neighbors of the atom at `index`, as
+    /// found by the last call to `NeighborList::compute` or
+    /// `NeighborList::update`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds for the last frame given to
+    /// `NeighborList::compute` or `NeighborList::update`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::NeighborList};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("Ar"), [1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("Ar"), [2.0, 0.0, 0.0], None);
+    ///
+    /// let mut neighbors = NeighborList::new(1.5);
+    /// neighbors.compute(&frame);
+    /// assert_eq!(neighbors.neighbors(1), [0, 2]);
+    /// assert_eq!(neighbors.neighbors(2), [1]);
+    /// ```
+    pub fn neighbors(&self, index: usize) -> &[usize] {
+        &self.neighbors[index]
+    }
+}
+
+/// Get the cell matrix of `frame`, and its inverse for periodic cells
+fn cell_matrices(frame: &Frame) -> (Matrix3, Option<Matrix3>) {
+    let cell = frame.cell();
+    let matrix = cell.matrix();
+    let inverse = if cell.shape() == CellShape::Infinite {
+        None
+    } else {
+        linalg::inverse(&matrix)
+    };
+    return (matrix, inverse);
+}
+
+impl Results for NeighborList {
+    fn columns(&self) -> Vec<String> {
+        ["first", "second", "distance", "x", "y", "z"]
+            .iter()
+            .map(|&name| name.into())
+            .collect()
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn rows(&self) -> Vec<Vec<f64>> {
+        self.pairs
+            .iter()
+            .map(|pair| {
+                let [x, y, z] = pair.vector;
+                vec![pair.first as f64, pair.second as f64, pair.distance, x, y, z]
+            })
+            .collect()
+    }
+}
+
+/// Largest number of bins per point in a `CellGrid`. Sparse systems use bins
+/// larger than the cutoff, to keep the memory used by the grid proportional
+/// to the number of points.
+const MAX_BINS_PER_POINT: usize = 2;
+
+/// Spatial decomposition of a set of points into bins at least as large as
+/// the cutoff, so neighbors of a point are always in the same or adjacent
+/// bins.
+struct CellGrid {
+    /// Number of bins in each direction
+    shape: [usize; 3],
+    /// Are the bins periodic in each direction
+    periodic: bool,
+    /// Indexes of the points, sorted by bin
+    points: Vec<usize>,
+    /// The points in bin `i` are `points[starts[i]..starts[i + 1]]`
+    starts: Vec<usize>,
+}
+
+impl CellGrid {
+    /// Create a grid for points in a periodic cell. Directions where the
+    /// cell is too small to contain three bins use a single bin, so that
+    /// each pair of bins is only visited once.
+    fn periodic(positions: &[Vector3D], matrix: &Matrix3, inverse: &Matrix3, cutoff: f64) -> CellGrid {
+        let columns = [
+            [matrix[0][0], matrix[1][0], matrix[2][0]],
+            [matrix[0][1], matrix[1][1], matrix[2][1]],
+            [matrix[0][2], matrix[1][2], matrix[2][2]],
+        ];
+        let volume = linalg::dot(columns[0], linalg::cross(columns[1], columns[2])).abs();
+
+        let mut counts = [1.0; 3];
+        for (d, count) in counts.iter_mut().enumerate() {
+            // distance between opposite faces of the cell
+            let face = linalg::cross(columns[(d + 1) % 3], columns[(d + 2) % 3]);
+            let width = volume / linalg::norm2(face).sqrt();
+            *count = (width / cutoff).floor();
+        }
+        let mut shape = grid_shape(counts, positions.len());
+        for size in &mut shape {
+            if *size < 3 {
+                *size = 1;
+            }
+        }
+
+        let bins = positions.iter().map(|&position| {
+            let fractional = linalg::mat_vec(inverse, position);
+            let mut bin = [0; 3];
+            for d in 0..3 {
+                let wrapped = fractional[d] - fractional[d].floor();
+                bin[d] = bin_index(wrapped, shape[d]);
+            }
+            bin
+        });
+        return CellGrid::new(shape, true, bins);
+    }
+
+    /// Create a grid covering the bounding box of points without periodic
+    /// boundary conditions
+    fn open(positions: &[Vector3D], cutoff: f64) -> CellGrid {
+        let mut lower = [f64::INFINITY; 3];
+        let mut upper = [f64::NEG_INFINITY; 3];
+        for position in positions {
+            for d in 0..3 {
+                lower[d] = f64::min(lower[d], position[d]);
+                upper[d] = f64::max(upper[d], position[d]);
+            }
+        }
+
+        let mut counts = [1.0; 3];
+        for d in 0..3 {
+            if upper[d] > lower[d] {
+                counts[d] = ((upper[d] - lower[d]) / cutoff).floor();
+            }
+        }
+        let shape = grid_shape(counts, positions.len());
+
+        let bins = positions.iter().map(|position| {
+            let mut bin = [0; 3];
+            for d in 0..3 {
+                let extent = upper[d] - lower[d];
+                let relative = if extent > 0.0 {
+                    (position[d] - lower[d]) / extent
+                } else {
+                    0.0
+                };
+                bin[d] = bin_index(relative, shape[d]);
+            }
+            bin
+        });
+        return CellGrid::new(shape, false, bins);
+    }
+
+    /// Create a grid with the given `shape`, containing points in the given
+    /// `bins`
+    fn new(shape: [usize; 3], periodic: bool, bins: impl Iterator<Item = [usize; 3]>) -> CellGrid {
+        let mut grid = CellGrid {
+            shape,
+            periodic,
+            points: Vec::new(),
+            starts: vec![0; shape[0] * shape[1] * shape[2] + 1],
+        };
+
+        // counting sort of the points by bin
+        let bins = bins.map(|bin| grid.index(bin)).collect::<Vec<_>>();
+        for &bin in &bins {
+            grid.starts[bin + 1] += 1;
+        }
+        for i in 1..grid.starts.len() {
+            grid.starts[i] += grid.starts[i - 1];
+        }
+
+        let mut next = grid.starts.clone();
+        grid.points = vec![0; bins.len()];
+        for (i, &bin) in bins.iter().enumerate() {
+            grid.points[next[bin]] = i;
+            next[bin] += 1;
+        }
+        return grid;
+    }
+
+    /// Get the total number of bins in this grid
+    fn bins_count(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    /// Get the points in the given `bin`
+    fn bin(&self, bin: usize) -> &[usize] {
+        &self.points[self.starts[bin]..self.starts[bin + 1]]
+    }
+
+    fn index(&self, bin: [usize; 3]) -> usize {
+        (bin[0] * self.shape[1] + bin[1]) * self.shape[2] + bin[2]
+    }
+
+    /// Get the indexes of the bins adjacent to `bin` (including `bin`
+    /// itself), each one only once. The bins are stored in the first values
+    /// of the returned array, and the second returned value is their number.
+    fn neighbor_bins(&self, bin: usize) -> ([usize; 27], usize) {
+        let position = [
+            bin / (self.shape[1] * self.shape[2]),
+            (bin / self.shape[2]) % self.shape[1],
+            bin % self.shape[2],
+        ];
+
+        // adjacent positions in each direction, without duplicates
+        let mut adjacent = [[0; 3]; 3];
+        let mut counts = [0; 3];
+        for d in 0..3 {
+            for delta in -1..=1 {
+                if let Some(value) = self.shift(position[d], delta, self.shape[d]) {
+                    if !adjacent[d][..counts[d]].contains(&value) {
+                        adjacent[d][counts[d]] = value;
+                        counts[d] += 1;
+                    }
+                }
+            }
+        }
+
+        let mut neighbors = [0; 27];
+        let mut count = 0;
+        for &x in &adjacent[0][..counts[0]] {
+            for &y in &adjacent[1][..counts[1]] {
+                for &z in &adjacent[2][..counts[2]] {
+                    neighbors[count] = self.index([x, y, z]);
+                    count += 1;
+                }
+            }
+        }
+        return (neighbors, count);
+    }
+
+    /// Shift the bin `position` by `delta` (-1, 0 or 1) in a direction
+    /// containing `size` bins, wrapping around for periodic grids
+    fn shift(&self, position: usize, delta: i32, size: usize) -> Option<usize> {
+        let shifted = match delta {
+            -1 => position.checked_sub(1),
+            1 => Some(position + 1),
+            _ => Some(position),
+        };
+        if self.periodic {
+            return Some(shifted.map_or(size - 1, |value| value % size));
+        }
+        return shifted.filter(|&value| value < size);
+    }
+}
+
+/// Get the number of bins in each direction of a grid for `npoints` points,
+/// from the number of bins as large as the cutoff fitting in each direction
+/// (`counts`). The bins are made larger if needed to use at most
+/// `MAX_BINS_PER_POINT` bins per point.
+fn grid_shape(mut counts: [f64; 3], npoints: usize) -> [usize; 3] {
+    #[allow(clippy::cast_precision_loss)]
+    let max = (MAX_BINS_PER_POINT * usize::max(npoints, 1)) as f64;
+    for count in &mut counts {
+        *count = if count.is_finite() { count.max(1.0) } else { 1.0 };
+    }
+
+    loop {
+        let total = counts.iter().product::<f64>();
+        if total <= max {
+            break;
+        }
+
+        // shrink all the directions containing more than one bin by the same
+        // factor. Each iteration reduces the number of bins, since the counts
+        // are rounded down.
+        let free = counts.iter().filter(|&&count| count > 1.0).count();
+        #[allow(clippy::cast_precision_loss)]
+        let factor = (total / max).powf(1.0 / free as f64);
+        for count in &mut counts {
+            if *count > 1.0 {
+                *count = (*count / factor).floor().max(1.0);
+            }
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    return [counts[0] as usize, counts[1] as usize, counts[2] as usize];
+}
+
+/// Get the bin containing the relative coordinate `value` (in [0, 1]) in a
+/// direction with `count` bins
+fn bin_index(value: f64, count: usize) -> usize {
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    let index = (value * count as f64) as usize;
+    return usize::min(index, count - 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, UnitCell};
+
+    /// Find all pairs closer than `cutoff` by checking all the distances
+    fn brute_force(frame: &Frame, cutoff: f64) -> Vec<(usize, usize, f64)> {
+        let matrix = frame.cell().matrix();
+        let inverse = linalg::inverse(&matrix);
+        let positions = frame.positions();
+
+        let mut pairs = Vec::new();
+        for i in 0..frame.size() {
+            for j in (i + 1)..frame.size() {
+                let mut vector = linalg::sub(positions[j], positions[i]);
+                if let Some(inverse) = &inverse {
+                    vector = linalg::minimum_image(&matrix, inverse, vector);
+                }
+                let distance = linalg::norm2(vector).sqrt();
+                if distance < cutoff {
+                    pairs.push((i, j, distance));
+                }
+            }
+        }
+        return pairs;
+    }
+
+    fn lattice_frame(cell: Option<UnitCell>) -> Frame {
+        let mut frame = Frame::new();
+        if let Some(cell) = cell {
+            frame.set_cell(&cell);
+        }
+        // deterministic pseudo-random positions
+        let mut state = 12345_u32;
+        let mut random = move || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            f64::from(state >> 8) / f64::from(1_u32 << 24)
+        };
+        for _ in 0..200 {
+            let position = [15.0 * random(), 15.0 * random(), 15.0 * random()];
+            frame.add_atom(&Atom::new("Ar"), position, None);
+        }
+        return frame;
+    }
+
+    #[test]
+    fn grid() {
+        assert_eq!(grid_shape([10.0, 10.0, 10.0], 1000), [10, 10, 10]);
+        assert_eq!(grid_shape([0.0, f64::NAN, f64::INFINITY], 1000), [1, 1, 1]);
+
+        let shape = grid_shape([1e6, 1e6, 1e6], 10);
+        assert!(shape.iter().product::<usize>() <= 20);
+        let shape = grid_shape([1e6, 1.0, 1.0], 1);
+        assert!(shape.iter().product::<usize>() <= 2);
+
+        // sparse system, where a grid with bins as large as the cutoff would
+        // contain 10^9 bins
+        let positions = [[0.0, 0.0, 0.0], [1000.0, 1000.0, 1000.0], [1000.5, 1000.0, 1000.0]];
+        let grid = CellGrid::open(&positions, 1.0);
+        assert!(grid.bins_count() <= 6);
+        let mut points = (0..grid.bins_count())
+            .flat_map(|bin| grid.bin(bin).to_vec())
+            .collect::<Vec<_>>();
+        points.sort_unstable();
+        assert_eq!(points, [0, 1, 2]);
+
+        let matrix = [[1000.0, 0.0, 0.0], [0.0, 1000.0, 0.0], [0.0, 0.0, 1000.0]];
+        let inverse = linalg::inverse(&matrix).unwrap();
+        let grid = CellGrid::periodic(&positions, &matrix, &inverse, 1.0);
+        assert!(grid.bins_count() <= 6);
+
+        // neighboring bins are unique, including in directions with a
+        // single bin
+        let grid = CellGrid::new([1, 3, 4], true, std::iter::empty());
+        for bin in 0..grid.bins_count() {
+            let (neighbors, count) = grid.neighbor_bins(bin);
+            let mut neighbors = neighbors[..count].to_vec();
+            assert_eq!(count, 9);
+            assert!(neighbors.contains(&bin));
+            neighbors.sort_unstable();
+            neighbors.dedup();
+            assert_eq!(neighbors.len(), 9);
+        }
+
+        let grid = CellGrid::new([3, 3, 3], false, std::iter::empty());
+        assert_eq!(grid.neighbor_bins(0).1, 8);
+        assert_eq!(grid.neighbor_bins(13).1, 27);
+    }
+
+    #[test]
+    fn pairs() {
+        let cells = [
+            None,
+            Some(UnitCell::new([15.0, 15.0, 15.0])),
+            Some(UnitCell::new([15.0, 15.0, 4.0])),
+            Some(UnitCell::triclinic([15.0, 16.0, 15.0], [90.0, 80.0, 100.0])),
+        ];
+        for cell in cells {
+            let frame = lattice_frame(cell);
+            let mut neighbors = NeighborList::new(3.0);
+            neighbors.compute(&frame);
+
+            let expected = brute_force(&frame, 3.0);
+            assert!(!expected.is_empty());
+            assert_eq!(neighbors.pairs().len(), expected.len());
+            for (pair, &(i, j, distance)) in neighbors.pairs().iter().zip(&expected) {
+                assert_eq!((pair.first, pair.second), (i, j));
+                approx::assert_ulps_eq!(pair.distance, distance, epsilon = 1e-9);
+                approx::assert_ulps_eq!(linalg::norm2(pair.vector).sqrt(), distance, epsilon = 1e-9);
+                assert!(neighbors.neighbors(j).contains(&i));
+            }
+        }
+    }
+
+    #[test]
+    fn update() {
+        let cells = [None, Some(UnitCell::new([15.0, 15.0, 15.0]))];
+        for cell in cells {
+            let mut frame = lattice_frame(cell);
+            let mut neighbors = NeighborList::new(3.0).with_skin(1.0);
+
+            for step in 0..10 {
+                // small displacements, up to 0.1 A per step in each direction
+                for (i, position) in frame.positions_mut().iter_mut().enumerate() {
+                    #[allow(clippy::cast_precision_loss)]
+                    let phase = (i + step) as f64;
+                    position[0] += 0.1 * phase.sin();
+                    position[1] += 0.1 * phase.cos();
+                }
+                neighbors.update(&frame);
+
+                let expected = brute_force(&frame, 3.0);
+                assert_eq!(neighbors.pairs().len(), expected.len());
+                for (pair, &(i, j, distance)) in neighbors.pairs().iter().zip(&expected) {
+                    assert_eq!((pair.first, pair.second), (i, j));
+                    approx::assert_ulps_eq!(pair.distance, distance, epsilon = 1e-9);
+                }
+            }
+            assert!(neighbors.rebuilds() > 1);
+            assert!(neighbors.rebuilds() < 10);
+
+            // changing the cell or the number of atoms forces a rebuild
+            let rebuilds = neighbors.rebuilds();
+            frame.set_cell(&UnitCell::new([16.0, 16.0, 16.0]));
+            neighbors.update(&frame);
+            assert_eq!(neighbors.rebuilds(), rebuilds + 1);
+
+            frame.resize(150);
+            neighbors.update(&frame);
+            assert_eq!(neighbors.rebuilds(), rebuilds + 2);
+            assert_eq!(neighbors.pairs().len(), brute_force(&frame, 3.0).len());
+        }
+    }
+
+    #[test]
+    #[should_panic = "the neighbor list skin must be a positive number, got -1"]
+    fn negative_skin() {
+        let _ = NeighborList::new(3.0).with_skin(-1.0);
+    }
+
+    #[test]
+    fn empty() {
+        let mut neighbors = NeighborList::new(3.0);
+        neighbors.compute(&Frame::new());
+        assert!(neighbors.pairs().is_empty());
+
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Ar"), [1.0, 1.0, 1.0], None);
+        frame.add_atom(&Atom::new("Ar"), [1.0, 1.0, 1.0], None);
+        neighbors.compute(&frame);
+        assert_eq!(neighbors.pairs().len(), 1);
+        assert_eq!(neighbors.pairs()[0].distance, 0.0);
+    }
+
+    #[test]
+    fn results() {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("Ar"), [1.0, 2.0, 2.0], None);
+        frame.add_atom(&Atom::new("Ar"), [50.0, 0.0, 0.0], None);
+
+        let mut neighbors = NeighborList::new(5.0);
+        neighbors.compute(&frame);
+        assert_eq!(neighbors.columns(), ["first", "second", "distance", "x", "y", "z"]);
+        assert_eq!(neighbors.rows(), [[0.0, 1.0, 3.0, 1.0, 2.0, 2.0]]);
+    }
+
+    #[test]
+    #[should_panic = "the neighbor list cutoff must be a positive number, got -1"]
+    fn negative_cutoff() {
+        let _ = NeighborList::new(-1.0);
+    }
+}
@@ -0,0 +1,262 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::path::Path;
+
+use crate::errors::Error;
+use crate::{Frame, Trajectory};
+
+/// A `FrameAnalyzer` computes some quantity from individual frames, and is
+/// used with `analysis::run_parallel` to analyze multiple trajectories using
+/// multiple threads.
+///
+/// Each thread uses its own analyzer, created with `FrameAnalyzer::fork`.
+/// The analyzer accumulates partial results from all the frames it sees in
+/// `FrameAnalyzer::analyze`, and these partial results are then merged
+/// according to the `Reduce` operation.
+pub trait FrameAnalyzer: Send {
+    /// Create a new analyzer with the same settings as this one, and without
+    /// any accumulated results.
+    fn fork(&self) -> Box<dyn FrameAnalyzer>;
+
+    /// Analyze a single `frame`, accumulating the corresponding results.
+    /// Frames are given to each analyzer in the same order as in the
+    /// trajectories.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by this function stops the whole analysis.
+    fn analyze(&mut self, frame: &Frame) -> Result<(), Error>;
+
+    /// Get the results accumulated by this analyzer
+    fn results(&self) -> Vec<f64>;
+}
+
+/// How to merge the partial results of multiple `FrameAnalyzer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Reduce {
+    /// Concatenate the partial results, following the order of the frames.
+    /// This is useful for time series, where an analyzer produces one or
+    /// more values for each frame.
+    Concatenate,
+    /// Sum the partial results element by element. This is useful for
+    /// histograms and other quantities accumulated over all frames.
+    Sum,
+    /// Sum the partial results element by element, and divide by the total
+    /// number of frames.
+    Mean,
+}
+
+/// Analyze all the frames in the trajectories at `paths` with multiple
+/// threads, running all the `analyzers` on each frame. The partial results
+/// from each thread are then merged according to `reduce`, and this function
+/// returns the final results for each analyzer, in the same order as
+/// `analyzers`. The `analyzers` themselves are only used to create new
+/// analyzers with `FrameAnalyzer::fork`, and are left untouched.
+///
+/// The frames are split in contiguous blocks (following the order of `paths`
+/// and then the order of steps inside each trajectory), one for each
+/// available CPU. Each thread opens the trajectories it needs, and runs forks
+/// of the `analyzers` on the frames in its block.
+///
+/// # Errors
+///
+/// This function fails if any trajectory can not be opened or any frame can
+/// not be read, or if any of the analyzers returns an error.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Error, Frame};
+/// # use chemfiles::analysis::{self, FrameAnalyzer, Reduce};
+/// #[derive(Default)]
+/// struct AtomsCount {
+///     counts: Vec<f64>,
+/// }
+///
+/// impl FrameAnalyzer for AtomsCount {
+///     fn fork(&self) -> Box<dyn FrameAnalyzer> {
+///         Box::new(AtomsCount::default())
+///     }
+///
+///     fn analyze(&mut self, frame: &Frame) -> Result<(), Error> {
+///         self.counts.push(frame.size() as f64);
+///         Ok(())
+///     }
+///
+///     fn results(&self) -> Vec<f64> {
+///         self.counts.clone()
+///     }
+/// }
+///
+/// let paths = ["run-1.xyz", "run-2.xyz"];
+/// let analyzers: Vec<Box<dyn FrameAnalyzer>> = vec![Box::new(AtomsCount::default())];
+/// let results = analysis::run_parallel(&paths, &analyzers, Reduce::Concatenate).unwrap();
+/// println!("atoms count in all frames: {:?}", results[0]);
+/// ```
+pub fn run_parallel<P>(
+    paths: &[P],
+    analyzers: &[Box<dyn FrameAnalyzer>],
+    reduce: Reduce,
+) -> Result<Vec<Vec<f64>>, Error>
+where
+    P: AsRef<Path> + Sync,
+{
+    let mut frames = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        let mut trajectory = Trajectory::open(path, 'r')?;
+        frames.extend((0..trajectory.try_nsteps()?).map(|step| (index, step)));
+    }
+
+    let threads = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+    let block_size = usize::max(frames.len() / threads + usize::from(frames.len() % threads != 0), 1);
+
+    let partials = std::thread::scope(|scope| {
+        let handles = frames
+            .chunks(block_size)
+            .map(|block| {
+                let mut forks = analyzers.iter().map(|analyzer| analyzer.fork()).collect::<Vec<_>>();
+                scope.spawn(move || -> Result<Vec<Vec<f64>>, Error> {
+                    analyze_block(paths, block, &mut forks)?;
+                    return Ok(forks.iter().map(|analyzer| analyzer.results()).collect());
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| match handle.join() {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+
+    let mut results = vec![Vec::new(); analyzers.len()];
+    for partial in partials {
+        for (result, values) in results.iter_mut().zip(partial) {
+            match reduce {
+                Reduce::Concatenate => result.extend(values),
+                Reduce::Sum | Reduce::Mean => {
+                    if result.len() < values.len() {
+                        result.resize(values.len(), 0.0);
+                    }
+                    for (value, partial) in result.iter_mut().zip(values) {
+                        *value += partial;
+                    }
+                }
+            }
+        }
+    }
+
+    if reduce == Reduce::Mean && !frames.is_empty() {
+        #[allow(clippy::cast_precision_loss)]
+        let count = frames.len() as f64;
+        for value in results.iter_mut().flatten() {
+            *value /= count;
+        }
+    }
+
+    return Ok(results);
+}
+
+/// Run all the `analyzers` on the frames in `block`, given as pairs of
+/// (index in `paths`, step).
+fn analyze_block<P: AsRef<Path>>(
+    paths: &[P],
+    block: &[(usize, usize)],
+    analyzers: &mut [Box<dyn FrameAnalyzer>],
+) -> Result<(), Error> {
+    let mut current: Option<(usize, Trajectory)> = None;
+    let mut frame = Frame::new();
+    for &(index, step) in block {
+        let reopen = match &current {
+            Some((opened, _)) => *opened != index,
+            None => true,
+        };
+        if reopen {
+            current = Some((index, Trajectory::open(&paths[index], 'r')?));
+        }
+
+        if let Some((_, trajectory)) = &mut current {
+            trajectory.read_step(step, &mut frame)?;
+        }
+
+        for analyzer in analyzers.iter_mut() {
+            analyzer.analyze(&frame)?;
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Oxygens {
+        values: Vec<f64>,
+    }
+
+    impl FrameAnalyzer for Oxygens {
+        fn fork(&self) -> Box<dyn FrameAnalyzer> {
+            Box::new(Oxygens { values: Vec::new() })
+        }
+
+        fn analyze(&mut self, frame: &Frame) -> Result<(), Error> {
+            let mut count = 0.0;
+            for atom in frame.iter_atoms() {
+                if atom.name() == "O" {
+                    count += 1.0;
+                }
+            }
+            self.values.push(count);
+            Ok(())
+        }
+
+        fn results(&self) -> Vec<f64> {
+            self.values.clone()
+        }
+    }
+
+    struct Frames {
+        count: f64,
+    }
+
+    impl FrameAnalyzer for Frames {
+        fn fork(&self) -> Box<dyn FrameAnalyzer> {
+            Box::new(Frames { count: 0.0 })
+        }
+
+        fn analyze(&mut self, frame: &Frame) -> Result<(), Error> {
+            assert!(frame.size() > 0);
+            self.count += 1.0;
+            Ok(())
+        }
+
+        fn results(&self) -> Vec<f64> {
+            vec![self.count]
+        }
+    }
+
+    #[test]
+    fn parallel() {
+        let paths = ["data/water.xyz", "data/water.xyz"];
+        let analyzers: Vec<Box<dyn FrameAnalyzer>> = vec![
+            Box::new(Oxygens { values: Vec::new() }),
+            Box::new(Frames { count: 0.0 }),
+        ];
+
+        let results = run_parallel(&paths, &analyzers, Reduce::Concatenate).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].len(), 200);
+        assert!(results[0].iter().all(|&count| count == 99.0));
+
+        let analyzers: Vec<Box<dyn FrameAnalyzer>> = vec![Box::new(Frames { count: 0.0 })];
+        let results = run_parallel(&paths, &analyzers, Reduce::Sum).unwrap();
+        assert_eq!(results, [vec![200.0]]);
+
+        let results = run_parallel(&paths, &analyzers, Reduce::Mean).unwrap();
+        assert_eq!(results, [vec![1.0]]);
+
+        assert!(run_parallel(&["not-here.xyz"], &analyzers, Reduce::Sum).is_err());
+    }
+}
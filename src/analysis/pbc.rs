@@ -0,0 +1,87 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Helpers dealing with molecules split across periodic boundaries
+use std::collections::VecDeque;
+
+use crate::linalg::{self, Vector3D};
+use crate::{CellShape, Frame, Topology};
+
+/// Get the list of bonded neighbors of each atom in the `topology`
+pub(crate) fn bonded_neighbors(topology: &Topology) -> Vec<Vec<usize>> {
+    let mut neighbors = vec![Vec::new(); topology.size()];
+    for [i, j] in topology.bonds() {
+        neighbors[i].push(j);
+        neighbors[j].push(i);
+    }
+    return neighbors;
+}
+
+/// Get the positions of the atoms in `molecule` (a sorted list of atomic
+/// indexes), making the molecule whole by following the bonds from its first
+/// atom and using the minimum image convention for each bond.
+pub(crate) fn unwrapped_positions(frame: &Frame, molecule: &[usize], neighbors: &[Vec<usize>]) -> Vec<Vector3D> {
+    let positions = frame.positions();
+    let mut unwrapped = molecule.iter().map(|&i| positions[i]).collect::<Vec<_>>();
+
+    let cell = frame.cell();
+    if cell.shape() == CellShape::Infinite || molecule.is_empty() {
+        return unwrapped;
+    }
+
+    let mut visited = vec![false; molecule.len()];
+    let mut queue = VecDeque::new();
+    for start in 0..molecule.len() {
+        // the molecule might not be connected if the caller gave a
+        // selection instead of a bonded molecule
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        queue.push_back(start);
+
+        while let Some(current) = queue.pop_front() {
+            for &neighbor in &neighbors[molecule[current]] {
+                if let Ok(local) = molecule.binary_search(&neighbor) {
+                    if visited[local] {
+                        continue;
+                    }
+                    visited[local] = true;
+
+                    let mut bond = linalg::sub(positions[neighbor], positions[molecule[current]]);
+                    cell.wrap(&mut bond);
+                    unwrapped[local] = linalg::add(unwrapped[current], bond);
+                    queue.push_back(local);
+                }
+            }
+        }
+    }
+
+    return unwrapped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, UnitCell};
+
+    #[test]
+    fn unwrap() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        frame.add_atom(&Atom::new("C"), [9.5, 5.0, 5.0], None);
+        frame.add_atom(&Atom::new("C"), [0.5, 5.0, 5.0], None);
+        frame.add_atom(&Atom::new("C"), [1.5, 5.0, 5.0], None);
+        frame.add_atom(&Atom::new("O"), [5.0, 5.0, 5.0], None);
+        frame.add_bond(0, 1);
+        frame.add_bond(1, 2);
+
+        let neighbors = bonded_neighbors(&frame.topology());
+        assert_eq!(neighbors[1], [0, 2]);
+
+        let positions = unwrapped_positions(&frame, &[0, 1, 2], &neighbors);
+        crate::assert_vector3d_eq(&positions[0], &[9.5, 5.0, 5.0], 1e-12);
+        crate::assert_vector3d_eq(&positions[1], &[10.5, 5.0, 5.0], 1e-12);
+        crate::assert_vector3d_eq(&positions[2], &[11.5, 5.0, 5.0], 1e-12);
+    }
+}
@@ -0,0 +1,276 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Analysis of linear polymer chains.
+//!
+//! The functions in this module take a selection matching the backbone atoms
+//! of the chains. The chains are then found by following the bonds between
+//! the selected atoms in the first frame, and must be linear: each backbone
+//! atom can only be bonded to one or two other backbone atoms. Chains
+//! crossing periodic boundaries are unwrapped by following the backbone
+//! bonds, using the minimum image convention.
+use crate::errors::{Error, Status};
+use crate::linalg::{self, Vector3D};
+use crate::{CellShape, Frame, Selection, Trajectory};
+
+/// Compute the end-to-end distance of each polymer chain in each frame of
+/// the `trajectory`, returning a matrix with one row per frame and one
+/// column per chain. The chains are made of the atoms matching
+/// `chain_selection`, see the module documentation for more information.
+///
+/// # Errors
+///
+/// This function fails if the selection is invalid, if the selected atoms do
+/// not form linear chains, if any frame can not be read, or if the frames do
+/// not all contain the same number of atoms.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis::polymer};
+/// let mut trajectory = Trajectory::open("polyethylene.pdb", 'r').unwrap();
+/// let distances = polymer::end_to_end(&mut trajectory, "name C").unwrap();
+/// println!("first chain at first step: {}", distances[0][0]);
+/// ```
+pub fn end_to_end(trajectory: &mut Trajectory, chain_selection: &str) -> Result<Vec<Vec<f64>>, Error> {
+    let mut distances = Vec::new();
+    for_each_chain(trajectory, chain_selection, |chains| {
+        let values = chains
+            .iter()
+            .map(|chain| linalg::norm2(linalg::sub(chain[chain.len() - 1], chain[0])).sqrt())
+            .collect();
+        distances.push(values);
+    })?;
+    return Ok(distances);
+}
+
+/// Estimate the persistence length of the polymer chains matching
+/// `chain_selection` in the `trajectory`, in Angstroms.
+///
+/// This uses the decay of the orientation correlation between consecutive
+/// backbone bonds, `<cos θ> = exp(-l / Lp)` where `l` is the average bond
+/// length, averaged over all chains and frames. Fully flexible chains (with
+/// `<cos θ> <= 0`) give a persistence length of zero, and perfectly rigid
+/// chains give an infinite persistence length.
+///
+/// # Errors
+///
+/// This function fails if the selection is invalid, if the selected atoms do
+/// not form linear chains with at least two bonds, if any frame can not be
+/// read, or if the frames do not all contain the same number of atoms.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis::polymer};
+/// let mut trajectory = Trajectory::open("polyethylene.pdb", 'r').unwrap();
+/// let length = polymer::persistence_length(&mut trajectory, "name C").unwrap();
+/// println!("persistence length: {} A", length);
+/// ```
+pub fn persistence_length(trajectory: &mut Trajectory, chain_selection: &str) -> Result<f64, Error> {
+    let mut cosines = 0.0;
+    let mut angles_count = 0_usize;
+    let mut lengths = 0.0;
+    let mut bonds_count = 0_usize;
+
+    for_each_chain(trajectory, chain_selection, |chains| {
+        for chain in chains {
+            let bonds = chain
+                .windows(2)
+                .map(|pair| linalg::sub(pair[1], pair[0]))
+                .collect::<Vec<_>>();
+            for bond in &bonds {
+                lengths += linalg::norm2(*bond).sqrt();
+                bonds_count += 1;
+            }
+            for pair in bonds.windows(2) {
+                let norms = (linalg::norm2(pair[0]) * linalg::norm2(pair[1])).sqrt();
+                if norms > 0.0 {
+                    cosines += linalg::dot(pair[0], pair[1]) / norms;
+                    angles_count += 1;
+                }
+            }
+        }
+    })?;
+
+    if angles_count == 0 {
+        return Err(Error::new(
+            Status::SelectionError,
+            "persistence length requires chains with at least two bonds",
+        ));
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let average_cosine = cosines / angles_count as f64;
+    #[allow(clippy::cast_precision_loss)]
+    let bond_length = lengths / bonds_count as f64;
+
+    if average_cosine <= 0.0 {
+        return Ok(0.0);
+    } else if average_cosine >= 1.0 {
+        return Ok(f64::INFINITY);
+    }
+    return Ok(-bond_length / average_cosine.ln());
+}
+
+/// Read all frames in the `trajectory`, and call `function` with the
+/// unwrapped positions of the atoms in each chain, ordered from one end of
+/// the chain to the other.
+fn for_each_chain<F>(trajectory: &mut Trajectory, chain_selection: &str, mut function: F) -> Result<(), Error>
+where
+    F: FnMut(&[Vec<Vector3D>]),
+{
+    let mut selection = Selection::new(chain_selection)?;
+    if selection.size() != 1 {
+        return Err(Error::new(
+            Status::SelectionError,
+            "polymer analysis requires a selection matching single atoms",
+        ));
+    }
+
+    let mut chains = Vec::new();
+    let mut natoms = 0;
+    let mut frame = Frame::new();
+    for step in 0..trajectory.try_nsteps()? {
+        trajectory.read_step(step, &mut frame)?;
+        if step == 0 {
+            chains = find_chains(&frame, &selection.list(&frame))?;
+            natoms = frame.size();
+        } else if frame.size() != natoms {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!(
+                    "frame at step {} contains {} atoms, expected {}",
+                    step,
+                    frame.size(),
+                    natoms
+                ),
+            ));
+        }
+
+        let positions = frame.positions();
+        let cell = frame.cell();
+        let periodic = cell.shape() != CellShape::Infinite;
+        let unwrapped = chains
+            .iter()
+            .map(|chain| {
+                let mut unwrapped = Vec::with_capacity(chain.len());
+                unwrapped.push(positions[chain[0]]);
+                for pair in chain.windows(2) {
+                    let mut bond = linalg::sub(positions[pair[1]], positions[pair[0]]);
+                    if periodic {
+                        cell.wrap(&mut bond);
+                    }
+                    let previous = unwrapped[unwrapped.len() - 1];
+                    unwrapped.push(linalg::add(previous, bond));
+                }
+                unwrapped
+            })
+            .collect::<Vec<_>>();
+
+        function(&unwrapped);
+    }
+    return Ok(());
+}
+
+/// Find the linear chains formed by bonds between the `selected` atoms,
+/// with atoms ordered from one end of the chain to the other.
+fn find_chains(frame: &Frame, selected: &[usize]) -> Result<Vec<Vec<usize>>, Error> {
+    let mut is_selected = vec![false; frame.size()];
+    for &atom in selected {
+        is_selected[atom] = true;
+    }
+
+    let mut neighbors = vec![Vec::new(); frame.size()];
+    for [i, j] in frame.topology().bonds() {
+        if is_selected[i] && is_selected[j] {
+            neighbors[i].push(j);
+            neighbors[j].push(i);
+        }
+    }
+
+    if let Some(&atom) = selected.iter().find(|&&atom| neighbors[atom].len() > 2) {
+        return Err(Error::new(
+            Status::SelectionError,
+            format!("the selected atoms do not form linear chains: atom {atom} is a branching point"),
+        ));
+    }
+
+    let mut visited = vec![false; frame.size()];
+    let mut chains = Vec::new();
+    // start from chain ends first, and then from the remaining atoms for
+    // cyclic chains
+    let ends = selected.iter().filter(|&&atom| neighbors[atom].len() < 2);
+    let others = selected.iter().filter(|&&atom| neighbors[atom].len() == 2);
+    for &start in ends.chain(others) {
+        if visited[start] {
+            continue;
+        }
+
+        let mut chain = vec![start];
+        visited[start] = true;
+        let mut current = start;
+        while let Some(&next) = neighbors[current].iter().find(|&&next| !visited[next]) {
+            visited[next] = true;
+            chain.push(next);
+            current = next;
+        }
+        chains.push(chain);
+    }
+
+    return Ok(chains);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, UnitCell};
+
+    fn chain() -> Frame {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        // zig-zag chain crossing the periodic boundary
+        frame.add_atom(&Atom::new("C"), [8.0, 5.0, 5.0], None);
+        frame.add_atom(&Atom::new("C"), [9.0, 6.0, 5.0], None);
+        frame.add_atom(&Atom::new("C"), [0.0, 5.0, 5.0], None);
+        frame.add_atom(&Atom::new("C"), [1.0, 6.0, 5.0], None);
+        frame.add_atom(&Atom::new("H"), [1.0, 7.0, 5.0], None);
+        frame.add_bond(0, 1);
+        frame.add_bond(2, 1);
+        frame.add_bond(2, 3);
+        frame.add_bond(3, 4);
+        return frame;
+    }
+
+    #[test]
+    fn chains() {
+        let frame = chain();
+        let mut trajectory = Trajectory::memory_writer("XYZ").unwrap();
+        trajectory.write(&frame).unwrap();
+        let data = trajectory.into_bytes().unwrap();
+
+        let mut trajectory = Trajectory::memory_reader(&data, "XYZ").unwrap();
+        trajectory.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        trajectory.set_topology(&frame.topology());
+
+        let distances = end_to_end(&mut trajectory, "name C").unwrap();
+        assert_eq!(distances.len(), 1);
+        approx::assert_ulps_eq!(distances[0][0], f64::sqrt(10.0), epsilon = 1e-12);
+
+        // the angle between consecutive bonds is 90°
+        let length = persistence_length(&mut trajectory, "name C").unwrap();
+        assert_eq!(length, 0.0);
+
+        let error = persistence_length(&mut trajectory, "index 0 or index 1").unwrap_err();
+        assert_eq!(error.status, Status::SelectionError);
+    }
+
+    #[test]
+    fn branches() {
+        let mut frame = Frame::new();
+        frame.resize(4);
+        frame.add_bond(0, 1);
+        frame.add_bond(0, 2);
+        frame.add_bond(0, 3);
+        assert!(find_chains(&frame, &[0, 1, 2, 3]).is_err());
+        assert_eq!(find_chains(&frame, &[0, 1, 2]).unwrap(), vec![vec![1, 0, 2]]);
+    }
+}
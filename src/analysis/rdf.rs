@@ -0,0 +1,385 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::{Error, Status};
+use crate::linalg;
+use crate::{CellShape, Frame, Selection};
+
+use super::{Accumulator, NeighborList, ProgressTracker, Results};
+
+/// Radial distribution function g(r) between two groups of atoms, created by
+/// `analysis::rdf`. The functions computed on different parts of a
+/// trajectory can be combined with `Accumulator::merge`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Rdf {
+    /// Distance at the center of each bin, in Angstroms
+    pub distances: Vec<f64>,
+    /// Value of g(r) in each bin
+    pub values: Vec<f64>,
+    /// Number of frames used to compute the distribution
+    pub frames: usize,
+}
+
+impl Accumulator for Rdf {
+    fn merge(&mut self, other: &Rdf) -> Result<(), Error> {
+        if self.distances != other.distances {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                "can not merge radial distribution functions with different r_max or number of bins",
+            ));
+        }
+
+        let total = self.frames + other.frames;
+        if total == 0 {
+            return Ok(());
+        }
+
+        // the values are averaged over frames, weight them accordingly
+        #[allow(clippy::cast_precision_loss)]
+        let (weight, other_weight) = (self.frames as f64 / total as f64, other.frames as f64 / total as f64);
+        for (value, other) in self.values.iter_mut().zip(&other.values) {
+            *value = weight * *value + other_weight * other;
+        }
+        self.frames = total;
+        return Ok(());
+    }
+}
+
+impl Results for Rdf {
+    fn columns(&self) -> Vec<String> {
+        vec!["r".into(), "g(r)".into()]
+    }
+
+    fn rows(&self) -> Vec<Vec<f64>> {
+        self.distances
+            .iter()
+            .zip(&self.values)
+            .map(|(&distance, &value)| vec![distance, value])
+            .collect()
+    }
+}
+
+/// Compute the radial distribution function g(r) between the atoms matching
+/// `selection_a` and the atoms matching `selection_b`, for distances up to
+/// `r_max` (in Angstroms) divided in `nbins` bins.
+///
+/// The frames are processed one at a time, so this function can be used with
+/// `Trajectory::frames` to analyze trajectories which do not fit in memory.
+/// The selections are evaluated again for each frame. Pairs of atoms are
+/// counted with the minimum image convention, and the histogram of each frame
+/// is normalized with the volume of its own unit cell and the number of
+/// pairs of distinct atoms, so g(r) tends to 1 at large distances in
+/// homogeneous systems, even when the volume changes during the simulation.
+/// Frames where the selections do not form any pair of distinct atoms are
+/// ignored.
+///
+/// # Errors
+///
+/// This function fails if `r_max` is not positive or `nbins` is zero, if a
+/// selection is invalid or matches multiple atoms (for example
+/// `"pairs: ..."`), if a frame can not be read, does not have a periodic
+/// unit cell, or if `r_max` is larger than half the width of the unit cell.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis};
+/// let mut trajectory = Trajectory::open("water.xtc", 'r').unwrap();
+/// let rdf = analysis::rdf(trajectory.frames(), "name O", "name H", 8.0, 200).unwrap();
+///
+/// for (r, g) in rdf.distances.iter().zip(&rdf.values) {
+///     println!("{} {}", r, g);
+/// }
+/// ```
+pub fn rdf<I>(frames: I, selection_a: &str, selection_b: &str, r_max: f64, nbins: usize) -> Result<Rdf, Error>
+where
+    I: IntoIterator<Item = Result<Frame, Error>>,
+{
+    return compute_rdf(frames, selection_a, selection_b, r_max, nbins, None);
+}
+
+/// Compute the radial distribution function as with `analysis::rdf`,
+/// reporting the progress to the given `tracker` after each frame.
+///
+/// # Errors
+///
+/// This function fails in the same cases as `analysis::rdf`.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis::{self, Progress, ProgressTracker}};
+/// let mut trajectory = Trajectory::open("water.xtc", 'r').unwrap();
+/// let tracker = ProgressTracker::new(
+///     |progress: &Progress| eprintln!("RDF: {} frames", progress.frames),
+///     Some(trajectory.nsteps()),
+/// );
+///
+/// let rdf = analysis::rdf_with_progress(trajectory.frames(), "name O", "name H", 8.0, 200, tracker).unwrap();
+/// ```
+pub fn rdf_with_progress<I>(
+    frames: I,
+    selection_a: &str,
+    selection_b: &str,
+    r_max: f64,
+    nbins: usize,
+    tracker: ProgressTracker,
+) -> Result<Rdf, Error>
+where
+    I: IntoIterator<Item = Result<Frame, Error>>,
+{
+    return compute_rdf(frames, selection_a, selection_b, r_max, nbins, Some(tracker));
+}
+
+/// Implementation of `rdf` and `rdf_with_progress`
+fn compute_rdf<I>(
+    frames: I,
+    selection_a: &str,
+    selection_b: &str,
+    r_max: f64,
+    nbins: usize,
+    mut progress: Option<ProgressTracker>,
+) -> Result<Rdf, Error>
+where
+    I: IntoIterator<Item = Result<Frame, Error>>,
+{
+    if !(r_max > 0.0 && r_max.is_finite()) || nbins == 0 {
+        return Err(Error::new(
+            Status::ChemfilesError,
+            format!("invalid parameters for the radial distribution function: r_max = {r_max}, nbins = {nbins}"),
+        ));
+    }
+
+    let mut selection_a = single_atoms_selection(selection_a)?;
+    let mut selection_b = single_atoms_selection(selection_b)?;
+
+    #[allow(clippy::cast_precision_loss)]
+    let width = r_max / nbins as f64;
+    let mut neighbors = NeighborList::new(r_max);
+    let mut values = vec![0.0; nbins];
+    let mut frames_count = 0;
+
+    if let Some(progress) = &mut progress {
+        progress.start();
+    }
+
+    for frame in frames {
+        let frame = frame?;
+        let volume = check_cell(&frame, r_max)?;
+
+        let mut in_a = vec![false; frame.size()];
+        let mut in_b = vec![false; frame.size()];
+        for i in selection_a.list(&frame) {
+            in_a[i] = true;
+        }
+        for i in selection_b.list(&frame) {
+            in_b[i] = true;
+        }
+
+        let count_a = in_a.iter().filter(|&&selected| selected).count();
+        let count_b = in_b.iter().filter(|&&selected| selected).count();
+        let common = in_a.iter().zip(&in_b).filter(|(&a, &b)| a && b).count();
+        let pairs = count_a * count_b - common;
+        // frames without any pair of distinct atoms are ignored
+        if pairs != 0 {
+            let mut histogram = vec![0_usize; nbins];
+            neighbors.compute(&frame);
+            for pair in neighbors.pairs() {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let bin = usize::min((pair.distance / width) as usize, nbins - 1);
+                if in_a[pair.first] && in_b[pair.second] {
+                    histogram[bin] += 1;
+                }
+                if in_a[pair.second] && in_b[pair.first] {
+                    histogram[bin] += 1;
+                }
+            }
+
+            #[allow(clippy::cast_precision_loss)]
+            let density = pairs as f64 / volume;
+            for (value, &count) in values.iter_mut().zip(&histogram) {
+                #[allow(clippy::cast_precision_loss)]
+                let count = count as f64;
+                *value += count / density;
+            }
+            frames_count += 1;
+        }
+
+        if let Some(progress) = &mut progress {
+            progress.tick();
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let distances = (0..nbins).map(|i| (i as f64 + 0.5) * width).collect::<Vec<_>>();
+    if frames_count != 0 {
+        for (i, value) in values.iter_mut().enumerate() {
+            #[allow(clippy::cast_precision_loss)]
+            let (inner, outer) = (i as f64 * width, (i + 1) as f64 * width);
+            let shell = 4.0 / 3.0 * std::f64::consts::PI * (outer.powi(3) - inner.powi(3));
+            #[allow(clippy::cast_precision_loss)]
+            let frames = frames_count as f64;
+            *value /= shell * frames;
+        }
+    }
+
+    return Ok(Rdf {
+        distances,
+        values,
+        frames: frames_count,
+    });
+}
+
+/// Create a selection from `selection`, checking that it matches single atoms
+fn single_atoms_selection(selection: &str) -> Result<Selection, Error> {
+    let selection = Selection::new(selection)?;
+    if selection.size() != 1 {
+        return Err(Error::new(
+            Status::SelectionError,
+            "the radial distribution function requires selections matching single atoms",
+        ));
+    }
+    return Ok(selection);
+}
+
+/// Check that the cell of `frame` is periodic and large enough to use the
+/// minimum image convention up to `r_max`, and return its volume
+fn check_cell(frame: &Frame, r_max: f64) -> Result<f64, Error> {
+    let cell = frame.cell();
+    let volume = cell.volume();
+    if cell.shape() == CellShape::Infinite || volume <= 0.0 {
+        return Err(Error::new(
+            Status::ChemfilesError,
+            format!(
+                "the radial distribution function requires a periodic unit cell, frame at step {} does not have one",
+                frame.step()
+            ),
+        ));
+    }
+
+    // the width of the cell along each vector is the distance between the
+    // two faces defined by the other vectors
+    let matrix = cell.matrix();
+    let column = |i: usize| [matrix[0][i], matrix[1][i], matrix[2][i]];
+    for i in 0..3 {
+        let face = linalg::cross(column((i + 1) % 3), column((i + 2) % 3));
+        let width = volume / linalg::norm2(face).sqrt();
+        if 2.0 * r_max > width {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!(
+                    "r_max ({r_max}) is larger than half the width of the unit cell ({width}) at step {}",
+                    frame.step()
+                ),
+            ));
+        }
+    }
+
+    return Ok(volume);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, Trajectory, UnitCell};
+
+    fn lattice() -> Frame {
+        // simple cubic lattice with two types of atoms
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([8.0, 8.0, 8.0]));
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    let name = if (i + j + k) % 2 == 0 { "Na" } else { "Cl" };
+                    let position = [2.0 * f64::from(i), 2.0 * f64::from(j), 2.0 * f64::from(k)];
+                    frame.add_atom(&Atom::new(name), position, None);
+                }
+            }
+        }
+        return frame;
+    }
+
+    #[test]
+    fn rdf() {
+        let frames = vec![Ok(lattice()), Ok(lattice())];
+        let rdf = super::rdf(frames, "all", "all", 2.5, 5).unwrap();
+        assert_eq!(rdf.frames, 2);
+        assert_eq!(rdf.distances, [0.25, 0.75, 1.25, 1.75, 2.25]);
+        assert_eq!(rdf.values[..4], [0.0; 4]);
+
+        // each atom has 6 first neighbors at 2.0 A
+        let shell = 4.0 / 3.0 * std::f64::consts::PI * (2.5_f64.powi(3) - 8.0);
+        let expected = 64.0 * 6.0 * 512.0 / (64.0 * 63.0) / shell;
+        approx::assert_ulps_eq!(rdf.values[4], expected, epsilon = 1e-12);
+
+        // all the first neighbors of Na are Cl
+        let frames = std::iter::once(Ok(lattice()));
+        let na_cl = super::rdf(frames, "name Na", "name Cl", 2.5, 5).unwrap();
+        let expected = 32.0 * 6.0 * 512.0 / (32.0 * 32.0) / shell;
+        approx::assert_ulps_eq!(na_cl.values[4], expected, epsilon = 1e-12);
+
+        let frames = std::iter::once(Ok(lattice()));
+        let na_na = super::rdf(frames, "name Na", "name Na", 2.5, 5).unwrap();
+        assert_eq!(na_na.values, [0.0; 5]);
+
+        let rows = rdf.rows();
+        assert_eq!(rdf.columns(), ["r", "g(r)"]);
+        assert_eq!(rows[4], [2.25, rdf.values[4]]);
+
+        let empty = super::rdf(Vec::new(), "all", "all", 2.5, 5).unwrap();
+        assert_eq!(empty.frames, 0);
+        assert_eq!(empty.values, [0.0; 5]);
+    }
+
+    #[test]
+    fn progress() {
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collected = std::sync::Arc::clone(&reports);
+        let tracker = ProgressTracker::new(
+            move |progress: &crate::analysis::Progress| collected.lock().unwrap().push(progress.frames),
+            Some(3),
+        );
+
+        let frames = vec![Ok(lattice()), Ok(lattice()), Ok(lattice())];
+        let rdf = super::rdf_with_progress(frames, "all", "all", 2.5, 5, tracker).unwrap();
+        assert_eq!(rdf.frames, 3);
+        assert_eq!(*reports.lock().unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn merge() {
+        let mut first = super::rdf(vec![Ok(lattice())], "all", "all", 2.5, 5).unwrap();
+        let second = super::rdf(vec![Ok(lattice()), Ok(lattice())], "all", "all", 2.5, 5).unwrap();
+        let expected = first.values.clone();
+
+        first.merge(&second).unwrap();
+        assert_eq!(first.frames, 3);
+        for (value, expected) in first.values.iter().zip(&expected) {
+            approx::assert_ulps_eq!(value, expected, epsilon = 1e-12);
+        }
+
+        let other = super::rdf(vec![Ok(lattice())], "all", "all", 3.0, 5).unwrap();
+        assert!(first.merge(&other).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let frames = || std::iter::once(Ok(lattice()));
+        assert!(super::rdf(frames(), "all", "all", 0.0, 5).is_err());
+        assert!(super::rdf(frames(), "all", "all", 2.0, 0).is_err());
+        assert!(super::rdf(frames(), "pairs: all", "all", 2.0, 5).is_err());
+        assert!(super::rdf(frames(), "all", "name", 2.0, 5).is_err());
+
+        let error = super::rdf(frames(), "all", "all", 4.5, 5).unwrap_err();
+        assert!(error.message.contains("larger than half the width"));
+
+        let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let error = super::rdf(trajectory.frames(), "name O", "name H", 2.0, 5).unwrap_err();
+        assert!(error.message.contains("requires a periodic unit cell"));
+
+        trajectory.set_cell(&UnitCell::new([15.0, 15.0, 15.0]));
+        let rdf = super::rdf(trajectory.frames(), "name O", "name H", 5.0, 50).unwrap();
+        assert_eq!(rdf.frames, 100);
+        // the highest peak comes from covalent O-H bonds, around 1 A
+        let peak = rdf.values.iter().copied().fold(0.0, f64::max);
+        let position = rdf.values.iter().position(|&value| value == peak).unwrap();
+        assert!(rdf.distances[position] < 1.2);
+    }
+}
@@ -0,0 +1,144 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::{Frame, Property};
+
+/// Average the `"bfactor"` property of the atoms in each residue of the
+/// `frame`, and store the result in the `"bfactor"` property of the residue.
+///
+/// The returned vector contains one value for each residue in the topology,
+/// in the same order as `Topology::residue`. Atoms without a numeric
+/// `"bfactor"` property are ignored, and residues where no atom has this
+/// property get `None` and are left unchanged.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, Frame, Property, Residue};
+/// let mut frame = Frame::new();
+/// let mut residue = Residue::new("ALA");
+/// for (i, bfactor) in [10.0, 20.0, 30.0].iter().enumerate() {
+///     frame.add_atom(&Atom::new("C"), [0.0; 3], None);
+///     frame.atom_mut(i).set("bfactor", *bfactor);
+///     residue.add_atom(i);
+/// }
+/// frame.add_residue(&residue).unwrap();
+///
+/// let bfactors = chemfiles::analysis::residue_bfactors(&mut frame);
+/// assert_eq!(bfactors, [Some(20.0)]);
+///
+/// let residue = frame.topology().residue(0).unwrap().get("bfactor");
+/// assert_eq!(residue, Some(Property::Double(20.0)));
+/// ```
+pub fn residue_bfactors(frame: &mut Frame) -> Vec<Option<f64>> {
+    residue_averages(frame, "bfactor")
+}
+
+/// Average the `"occupancy"` property of the atoms in each residue of the
+/// `frame`, and store the result in the `"occupancy"` property of the
+/// residue. See `residue_bfactors` for more information.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, Frame, Residue};
+/// let mut frame = Frame::new();
+/// let mut residue = Residue::new("HOH");
+/// for (i, occupancy) in [1.0, 0.5].iter().enumerate() {
+///     frame.add_atom(&Atom::new("H"), [0.0; 3], None);
+///     frame.atom_mut(i).set("occupancy", *occupancy);
+///     residue.add_atom(i);
+/// }
+/// frame.add_residue(&residue).unwrap();
+/// frame.add_residue(&Residue::new("empty")).unwrap();
+///
+/// let occupancies = chemfiles::analysis::residue_occupancies(&mut frame);
+/// assert_eq!(occupancies, [Some(0.75), None]);
+/// ```
+pub fn residue_occupancies(frame: &mut Frame) -> Vec<Option<f64>> {
+    residue_averages(frame, "occupancy")
+}
+
+/// Compute the average of the atomic property `name` in each residue, and
+/// store it in the residue property with the same name.
+fn residue_averages(frame: &mut Frame, name: &str) -> Vec<Option<f64>> {
+    let topology = frame.topology();
+    let mut averages = Vec::new();
+
+    #[allow(clippy::cast_possible_truncation)]
+    for i in 0..topology.residues_count() as usize {
+        let residue = topology.residue(i).expect("residue should exist");
+        let mut sum = 0.0;
+        let mut count = 0;
+        for atom in residue.atoms() {
+            if let Some(Property::Double(value)) = topology.atom(atom).get(name) {
+                sum += value;
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            averages.push(None);
+        } else {
+            averages.push(Some(sum / f64::from(count)));
+        }
+    }
+
+    let updated = topology.with_updated_residues(|i, residue| {
+        if let Some(value) = averages[i] {
+            residue.set(name, value);
+        }
+    });
+    std::mem::drop(topology);
+
+    frame
+        .set_topology(&updated)
+        .expect("topology should have the same size as the frame");
+    return averages;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, BondOrder, Residue};
+
+    #[test]
+    fn bfactors() {
+        let mut frame = Frame::new();
+        for (i, &bfactor) in [0.0, 10.0, 20.0, 30.0, 40.0].iter().enumerate() {
+            frame.add_atom(&Atom::new("C"), [0.0; 3], None);
+            frame.atom_mut(i).set("bfactor", bfactor);
+        }
+        frame.add_bond_with_order(0, 1, BondOrder::Double);
+
+        let mut first = Residue::with_id("ALA", 3);
+        first.add_atom(0);
+        first.add_atom(1);
+        first.set("chainid", "A");
+        frame.add_residue(&first).unwrap();
+
+        let mut second = Residue::with_id("GLY", 4);
+        second.add_atom(2);
+        second.add_atom(3);
+        second.add_atom(4);
+        frame.add_residue(&second).unwrap();
+
+        let bfactors = residue_bfactors(&mut frame);
+        assert_eq!(bfactors, [Some(5.0), Some(30.0)]);
+
+        let topology = frame.topology();
+        let first = topology.residue(0).unwrap();
+        assert_eq!(first.id(), Some(3));
+        assert_eq!(first.get("bfactor"), Some(Property::Double(5.0)));
+        assert_eq!(first.get("chainid"), Some(Property::String("A".into())));
+        assert_eq!(first.atoms(), [0, 1]);
+        assert_eq!(
+            topology.residue(1).unwrap().get("bfactor"),
+            Some(Property::Double(30.0))
+        );
+
+        // the rest of the topology is unchanged
+        assert_eq!(topology.bond_orders(), [BondOrder::Double]);
+        assert_eq!(topology.atom(4).get("bfactor"), Some(Property::Double(40.0)));
+
+        // no occupancy in this frame
+        assert_eq!(residue_occupancies(&mut frame), [None, None]);
+    }
+}
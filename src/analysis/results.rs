@@ -0,0 +1,204 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::fmt::Write as _;
+use std::io::Write;
+
+use crate::errors::Error;
+
+/// Common interface to export the results of an analysis as a table, with
+/// named columns of numeric values. This allows to send the output of all
+/// analyses to plotting tools, spreadsheets or notebooks in the same way.
+///
+/// Implementations only need to provide `Results::columns` and
+/// `Results::rows`, the export functions are implemented on top of them.
+///
+/// This trait is implemented by the types returned by analyses: `Adf`,
+/// `CellSeries`, `NeighborList`, `Rdf` and `Rmsf`. The other analyses
+/// (`average_structure`, `per_molecule`, the `polymer` functions,
+/// `residue_bfactors`, `residue_occupancies`, `rmsd`, `align`,
+/// `rmsd_symmetric`, `voronoi_neighbors` and `radical_voronoi_neighbors`)
+/// return frames or plain values, which can be exported by building a
+/// `Table` from them.
+pub trait Results {
+    /// Get the names of the columns in this table
+    fn columns(&self) -> Vec<String>;
+
+    /// Get the rows of this table. All rows should contain one value for
+    /// each column.
+    fn rows(&self) -> Vec<Vec<f64>>;
+
+    /// Write these results to `writer` in CSV format, with a header line
+    /// containing the column names.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if writing to `writer` fails.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::{Results, Table};
+    /// let table = Table::new(vec!["step".into(), "energy".into()], vec![vec![0.0, -3.5], vec![1.0, -4.25]]);
+    ///
+    /// let mut output = Vec::new();
+    /// table.to_csv(&mut output).unwrap();
+    /// assert_eq!(String::from_utf8(output).unwrap(), "step,energy\n0,-3.5\n1,-4.25\n");
+    /// ```
+    fn to_csv<W: Write>(&self, mut writer: W) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        let columns = self.columns().iter().map(|name| csv_escape(name)).collect::<Vec<_>>();
+        writeln!(writer, "{}", columns.join(","))?;
+        for row in self.rows() {
+            let row = row.iter().map(f64::to_string).collect::<Vec<_>>();
+            writeln!(writer, "{}", row.join(","))?;
+        }
+        return Ok(());
+    }
+
+    /// Get these results as a JSON object, mapping each column name to the
+    /// array of values in this column. Non-finite values are represented by
+    /// `null`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::{Results, Table};
+    /// let table = Table::new(vec!["step".into(), "energy".into()], vec![vec![0.0, -3.5], vec![1.0, f64::NAN]]);
+    /// assert_eq!(table.to_json(), r#"{"step":[0,1],"energy":[-3.5,null]}"#);
+    /// ```
+    fn to_json(&self) -> String {
+        let rows = self.rows();
+        let mut json = String::from("{");
+        for (i, name) in self.columns().iter().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+            json.push_str(&json_escape(name));
+            json.push_str(":[");
+            for (j, row) in rows.iter().enumerate() {
+                if j != 0 {
+                    json.push(',');
+                }
+                match row.get(i) {
+                    Some(value) if value.is_finite() => {
+                        let _ = write!(json, "{value}");
+                    }
+                    _ => json.push_str("null"),
+                }
+            }
+            json.push(']');
+        }
+        json.push('}');
+        return json;
+    }
+}
+
+/// A generic table of results, with named columns. This can be used to
+/// export the output of analyses returning plain vectors with the `Results`
+/// trait.
+///
+/// # Example
+/// ```
+/// # use chemfiles::analysis::{Results, Table};
+/// let table = Table::new(vec!["x".into(), "y".into()], vec![vec![1.0, 2.0]]);
+/// assert_eq!(table.columns(), ["x", "y"]);
+/// assert_eq!(table.rows(), [[1.0, 2.0]]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    /// Names of the columns
+    pub columns: Vec<String>,
+    /// Values in the table, one vector for each row
+    pub rows: Vec<Vec<f64>>,
+}
+
+impl Table {
+    /// Create a new table with the given `columns` names and `rows`.
+    ///
+    /// # Panics
+    ///
+    /// If any row does not contain one value for each column.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::Table;
+    /// let table = Table::new(vec!["distance".into()], vec![vec![1.2], vec![1.5]]);
+    /// assert_eq!(table.rows.len(), 2);
+    /// ```
+    pub fn new(columns: Vec<String>, rows: Vec<Vec<f64>>) -> Table {
+        for row in &rows {
+            assert_eq!(
+                row.len(),
+                columns.len(),
+                "all the rows in a table must have one value per column"
+            );
+        }
+        return Table { columns, rows };
+    }
+}
+
+impl Results for Table {
+    fn columns(&self) -> Vec<String> {
+        self.columns.clone()
+    }
+
+    fn rows(&self) -> Vec<Vec<f64>> {
+        self.rows.clone()
+    }
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+    return value.into();
+}
+
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    return escaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export() {
+        let table = Table::new(
+            vec!["a, b".into(), "say \"hi\"".into()],
+            vec![vec![1.5, f64::INFINITY], vec![-2.0, 1e-3]],
+        );
+
+        let mut csv = Vec::new();
+        table.to_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "\"a, b\",\"say \"\"hi\"\"\"\n1.5,inf\n-2,0.001\n"
+        );
+
+        assert_eq!(table.to_json(), r#"{"a, b":[1.5,-2],"say \"hi\"":[null,0.001]}"#);
+        assert_eq!(Table::default().to_json(), "{}");
+    }
+
+    #[test]
+    #[should_panic(expected = "all the rows in a table must have one value per column")]
+    fn invalid_table() {
+        let _ = Table::new(vec!["x".into()], vec![vec![1.0, 2.0]]);
+    }
+}
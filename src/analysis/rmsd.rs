@@ -0,0 +1,396 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::{Error, Status};
+use crate::linalg::{self, Vector3D};
+use crate::{Frame, Selection};
+
+/// Compute the root mean square deviation (in Angstroms) between the atoms
+/// matching `selection` in the frames `a` and `b`, after superposing them
+/// with the optimal rotation and translation (Kabsch algorithm).
+///
+/// The selection is evaluated on `a`, and the atoms with the same indexes are
+/// used in `b`. If `mass_weighted` is `true`, the superposition and the RMSD
+/// are weighted by the atomic masses, ignoring virtual sites.
+///
+/// # Errors
+///
+/// This function fails if the selection is invalid, matches multiple atoms
+/// (for example `"pairs: ..."`) or does not match any atom, or if the two
+/// frames do not contain the same number of atoms.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, Frame, analysis};
+/// let mut a = Frame::new();
+/// a.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+/// a.add_atom(&Atom::new("O"), [1.2, 0.0, 0.0], None);
+///
+/// // rotated and translated copy
+/// let mut b = Frame::new();
+/// b.add_atom(&Atom::new("C"), [5.0, 5.0, 5.0], None);
+/// b.add_atom(&Atom::new("O"), [5.0, 6.2, 5.0], None);
+///
+/// let rmsd = analysis::rmsd(&a, &b, "all", false).unwrap();
+/// assert!(rmsd < 1e-6);
+/// ```
+pub fn rmsd(a: &Frame, b: &Frame, selection: &str, mass_weighted: bool) -> Result<f64, Error> {
+    let atoms = selected_atoms(a, b, selection)?;
+    let (reference, mobile, weights) = superposition_data(a, b, &atoms, mass_weighted);
+    return Ok(linalg::superpose(&reference, &mobile, weights.as_deref()).rmsd);
+}
+
+/// Superpose `frame` onto `reference`, using the atoms matching `selection`
+/// to find the optimal rotation and translation (Kabsch algorithm), and
+/// return the RMSD between the selected atoms after the superposition.
+///
+/// The selection is evaluated on `reference`, and the atoms with the same
+/// indexes are used in `frame`. All the atoms of `frame` are then moved, and
+/// velocities (if any) are rotated accordingly. If `mass_weighted` is `true`,
+/// the superposition is weighted by the atomic masses, ignoring virtual
+/// sites.
+///
+/// # Errors
+///
+/// This function fails if the selection is invalid, matches multiple atoms
+/// (for example `"pairs: ..."`) or does not match any atom, or if the two
+/// frames do not contain the same number of atoms.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Frame, Trajectory, analysis};
+/// let mut trajectory = Trajectory::open("protein.pdb", 'r').unwrap();
+/// let mut reference = Frame::new();
+/// trajectory.read(&mut reference).unwrap();
+///
+/// let mut output = Trajectory::open("aligned.pdb", 'w').unwrap();
+/// for frame in trajectory.frames() {
+///     let mut frame = frame.unwrap();
+///     let rmsd = analysis::align(&mut frame, &reference, "name CA", true).unwrap();
+///     println!("step {}: RMSD = {:.3} A", frame.step(), rmsd);
+///     output.write(&frame).unwrap();
+/// }
+/// ```
+pub fn align(frame: &mut Frame, reference: &Frame, selection: &str, mass_weighted: bool) -> Result<f64, Error> {
+    let atoms = selected_atoms(reference, frame, selection)?;
+    let (reference, mobile, weights) = superposition_data(reference, frame, &atoms, mass_weighted);
+    let superposition = linalg::superpose(&reference, &mobile, weights.as_deref());
+
+    for position in frame.positions_mut() {
+        *position = superposition.apply(*position);
+    }
+    if let Some(velocities) = frame.velocities_mut() {
+        for velocity in velocities {
+            *velocity = linalg::mat_vec(&superposition.rotation, *velocity);
+        }
+    }
+
+    return Ok(superposition.rmsd);
+}
+
+/// Maximal number of combinations of permutations checked by
+/// `rmsd_symmetric`
+const MAX_PERMUTATIONS: usize = 100_000;
+
+/// Compute the root mean square deviation (in Angstroms) between all the
+/// atoms in the frames `a` and `b`, minimized over the permutations of
+/// chemically equivalent atoms, such as the two oxygens of a carboxylate
+/// group or the hydrogens of a methyl group.
+///
+/// Each entry in `equivalent_groups` contains the indexes of a group of
+/// equivalent atoms, and the RMSD is computed (after optimal superposition)
+/// for all the ways to match the atoms in each group of `a` with the atoms of
+/// the same group in `b`. The groups must not overlap. If `mass_weighted` is
+/// `true`, the superposition and the RMSD are weighted by the atomic masses
+/// of the atoms in `a`, ignoring virtual sites.
+///
+/// All combinations of permutations are checked, so this function is only
+/// usable with small groups: it fails if there are more than 100 000
+/// combinations (for example more than 8 atoms in a single group).
+///
+/// # Errors
+///
+/// This function fails if the two frames do not contain the same number of
+/// atoms, if an index in `equivalent_groups` is out of bounds or appears
+/// multiple times, or if there are too many permutations to check.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, Frame, analysis};
+/// let mut a = Frame::new();
+/// a.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+/// a.add_atom(&Atom::new("O"), [1.0, 1.0, 0.0], None);
+/// a.add_atom(&Atom::new("O"), [1.0, -1.0, 0.0], None);
+/// a.add_atom(&Atom::new("H"), [-1.0, 0.5, 0.8], None);
+///
+/// // same molecule, with the two oxygens swapped
+/// let mut b = a.clone();
+/// b.positions_mut().swap(1, 2);
+///
+/// assert!(analysis::rmsd(&a, &b, "all", false).unwrap() > 0.1);
+/// let rmsd = analysis::rmsd_symmetric(&a, &b, &[vec![1, 2]], false).unwrap();
+/// assert!(rmsd < 1e-6);
+/// ```
+pub fn rmsd_symmetric(
+    a: &Frame,
+    b: &Frame,
+    equivalent_groups: &[Vec<usize>],
+    mass_weighted: bool,
+) -> Result<f64, Error> {
+    let atoms = selected_atoms(a, b, "all")?;
+
+    let mut seen = vec![false; a.size()];
+    let mut combinations = 1_usize;
+    for group in equivalent_groups {
+        for &atom in group {
+            if atom >= a.size() {
+                return Err(Error::new(
+                    Status::OutOfBounds,
+                    format!(
+                        "atom index {atom} in equivalent groups is out of bounds for a frame with {} atoms",
+                        a.size()
+                    ),
+                ));
+            }
+            if seen[atom] {
+                return Err(Error::new(
+                    Status::ChemfilesError,
+                    format!("atom {atom} appears in multiple equivalent groups"),
+                ));
+            }
+            seen[atom] = true;
+        }
+
+        for count in 2..=group.len() {
+            combinations = combinations.saturating_mul(count);
+        }
+    }
+
+    if combinations > MAX_PERMUTATIONS {
+        return Err(Error::new(
+            Status::ChemfilesError,
+            format!(
+                "too many permutations of equivalent atoms to check ({combinations}), the limit is {MAX_PERMUTATIONS}"
+            ),
+        ));
+    }
+
+    let (reference, mut mobile, weights) = superposition_data(a, b, &atoms, mass_weighted);
+    let original = mobile.clone();
+    let mut permutations = equivalent_groups
+        .iter()
+        .map(|group| (0..group.len()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let mut best = f64::INFINITY;
+    loop {
+        for (group, permutation) in equivalent_groups.iter().zip(&permutations) {
+            for (&atom, &other) in group.iter().zip(permutation) {
+                mobile[atom] = original[group[other]];
+            }
+        }
+        best = f64::min(best, linalg::superpose(&reference, &mobile, weights.as_deref()).rmsd);
+
+        // go to the next combination of permutations, like an odometer
+        let mut done = true;
+        for permutation in &mut permutations {
+            if next_permutation(permutation) {
+                done = false;
+                break;
+            }
+        }
+        if done {
+            break;
+        }
+    }
+
+    return Ok(best);
+}
+
+/// Transform `values` into the next permutation in lexicographic order,
+/// returning `false` (and resetting `values` to the first permutation) if
+/// this was the last one
+fn next_permutation(values: &mut [usize]) -> bool {
+    if let Some(pivot) = values.windows(2).rposition(|pair| pair[0] < pair[1]) {
+        let successor = values
+            .iter()
+            .rposition(|&value| value > values[pivot])
+            .expect("there should be a larger value after the pivot");
+        values.swap(pivot, successor);
+        values[(pivot + 1)..].reverse();
+        return true;
+    }
+
+    values.reverse();
+    return false;
+}
+
+/// Get the indexes of the atoms matching `selection` in `reference`, checking
+/// that `frame` contains the same number of atoms
+fn selected_atoms(reference: &Frame, frame: &Frame, selection: &str) -> Result<Vec<usize>, Error> {
+    let mut selection = Selection::new(selection)?;
+    if selection.size() != 1 {
+        return Err(Error::new(
+            Status::SelectionError,
+            "RMSD requires a selection matching single atoms",
+        ));
+    }
+
+    if frame.size() != reference.size() {
+        return Err(Error::new(
+            Status::ChemfilesError,
+            format!(
+                "can not compute RMSD between frames with {} and {} atoms",
+                reference.size(),
+                frame.size()
+            ),
+        ));
+    }
+
+    let atoms = selection.list(reference);
+    if atoms.is_empty() {
+        return Err(Error::new(
+            Status::SelectionError,
+            "the selection used for RMSD does not match any atom",
+        ));
+    }
+    return Ok(atoms);
+}
+
+/// Get the positions of the `atoms` in `reference` and `frame`, and the
+/// corresponding weights if `mass_weighted` is `true`
+fn superposition_data(
+    reference: &Frame,
+    frame: &Frame,
+    atoms: &[usize],
+    mass_weighted: bool,
+) -> (Vec<Vector3D>, Vec<Vector3D>, Option<Vec<f64>>) {
+    let reference_positions = reference.positions();
+    let positions = frame.positions();
+    let weights = if mass_weighted {
+        let masses = reference.analysis_masses();
+        Some(atoms.iter().map(|&i| masses[i]).collect())
+    } else {
+        None
+    };
+
+    return (
+        atoms.iter().map(|&i| reference_positions[i]).collect(),
+        atoms.iter().map(|&i| positions[i]).collect(),
+        weights,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Atom;
+
+    fn molecule() -> Frame {
+        let mut frame = Frame::new();
+        frame.add_velocities();
+        frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        frame.add_atom(&Atom::new("O"), [1.2, 0.0, 0.0], [0.0, 0.0, 0.0]);
+        frame.add_atom(&Atom::new("H"), [-0.5, 0.9, 0.0], [0.0, 0.0, 0.0]);
+        frame.add_atom(&Atom::new("H"), [-0.5, -0.9, 0.3], [0.0, 0.0, 0.0]);
+        return frame;
+    }
+
+    #[test]
+    fn rotated() {
+        let reference = molecule();
+
+        // rotation by 90° around z, and translation
+        let mut frame = reference.clone();
+        for position in frame.positions_mut() {
+            *position = [3.0 - position[1], 2.0 + position[0], 1.0 + position[2]];
+        }
+        frame.velocities_mut().unwrap()[0] = [0.0, 1.0, 0.0];
+
+        approx::assert_ulps_eq!(rmsd(&reference, &frame, "all", false).unwrap(), 0.0, epsilon = 1e-9);
+        approx::assert_ulps_eq!(rmsd(&reference, &frame, "all", true).unwrap(), 0.0, epsilon = 1e-9);
+
+        let value = align(&mut frame, &reference, "name C O H", true).unwrap();
+        approx::assert_ulps_eq!(value, 0.0, epsilon = 1e-9);
+        for (position, expected) in frame.positions().iter().zip(reference.positions()) {
+            crate::assert_vector3d_eq(position, expected, 1e-9);
+        }
+        crate::assert_vector3d_eq(&frame.velocities().unwrap()[0], &[1.0, 0.0, 0.0], 1e-9);
+    }
+
+    #[test]
+    fn weights() {
+        let reference = molecule();
+        let mut frame = reference.clone();
+        frame.positions_mut()[2][2] += 0.5;
+
+        let uniform = rmsd(&reference, &frame, "all", false).unwrap();
+        let weighted = rmsd(&reference, &frame, "all", true).unwrap();
+        assert!(uniform > 0.0);
+        // the displaced hydrogen is lighter than the other atoms
+        assert!(weighted < uniform);
+
+        // only the selected atoms are used
+        approx::assert_ulps_eq!(
+            rmsd(&reference, &frame, "not index 2", false).unwrap(),
+            0.0,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn permutations() {
+        let mut values = [0, 1, 2];
+        let mut all = vec![values];
+        while next_permutation(&mut values) {
+            all.push(values);
+        }
+        assert_eq!(all, [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]]);
+        assert_eq!(values, [0, 1, 2]);
+
+        let mut empty: [usize; 0] = [];
+        assert!(!next_permutation(&mut empty));
+    }
+
+    #[test]
+    fn symmetric() {
+        let reference = molecule();
+        let mut frame = reference.clone();
+        // swap the two hydrogens, and rotate the frame
+        frame.positions_mut().swap(2, 3);
+        for position in frame.positions_mut() {
+            *position = [-position[1], position[0], position[2]];
+        }
+
+        assert!(rmsd(&reference, &frame, "all", false).unwrap() > 0.05);
+        let value = rmsd_symmetric(&reference, &frame, &[vec![2, 3]], false).unwrap();
+        approx::assert_ulps_eq!(value, 0.0, epsilon = 1e-9);
+
+        // without equivalent groups, this is the same as the usual RMSD
+        let value = rmsd_symmetric(&reference, &frame, &[], true).unwrap();
+        approx::assert_ulps_eq!(value, rmsd(&reference, &frame, "all", true).unwrap(), epsilon = 1e-12);
+
+        assert!(rmsd_symmetric(&reference, &frame, &[vec![2, 4]], false).is_err());
+        assert!(rmsd_symmetric(&reference, &frame, &[vec![1, 2], vec![2, 3]], false).is_err());
+
+        let mut large = Frame::new();
+        for i in 0..10 {
+            large.add_atom(&Atom::new("H"), [f64::from(i), 0.0, 0.0], None);
+        }
+        let group = (0..10).collect::<Vec<_>>();
+        assert!(rmsd_symmetric(&large, &large, &[group], false).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let reference = molecule();
+        let mut frame = reference.clone();
+
+        assert!(rmsd(&reference, &frame, "pairs: all", false).is_err());
+        assert!(rmsd(&reference, &frame, "name Zn", false).is_err());
+        assert!(rmsd(&reference, &frame, "name (", false).is_err());
+
+        frame.resize(2);
+        assert!(rmsd(&reference, &frame, "all", false).is_err());
+        assert!(align(&mut frame, &reference, "all", false).is_err());
+    }
+}
@@ -0,0 +1,733 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::{Error, Status};
+use crate::linalg::{self, Vector3D};
+use crate::{Frame, Topology, Trajectory};
+
+use super::{Accumulator, ProgressTracker, Results};
+
+/// `Rmsf` computes the root mean square fluctuation of each atom over a
+/// trajectory, i.e. the standard deviation of the atomic positions around
+/// their average.
+///
+/// Each frame is aligned onto a reference structure before accumulating the
+/// positions, to remove global rotations and translations. The fluctuations
+/// are computed with respect to the average of the aligned structures, in a
+/// single pass over the trajectory.
+///
+/// By default, the reference structure is the first frame. Use
+/// `Rmsf::with_reference` to align the frames onto another structure, for
+/// example to merge the results computed on different parts of a trajectory
+/// with `Accumulator::merge`, and `Rmsf::refine` or `analysis::rmsf` to align
+/// the frames onto the average structure instead.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis::Rmsf};
+/// let mut trajectory = Trajectory::open("protein.pdb", 'r').unwrap();
+///
+/// let mut rmsf = Rmsf::new();
+/// for frame in trajectory.frames() {
+///     rmsf.add_frame(&frame.unwrap()).unwrap();
+/// }
+///
+/// for (i, value) in rmsf.values().iter().enumerate() {
+///     println!("atom {}: {:.3} A", i, value);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Rmsf {
+    /// Positions of the reference structure, used to align the frames
+    reference: Vec<Vector3D>,
+    /// Running average of the aligned positions
+    average: Vec<Vector3D>,
+    /// Running sum of squared distances to the average
+    squared: Vec<f64>,
+    count: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    progress: Option<ProgressTracker>,
+}
+
+/// Serialized data of an `Rmsf`, checked before creating the `Rmsf`
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RmsfData {
+    reference: Vec<Vector3D>,
+    average: Vec<Vector3D>,
+    squared: Vec<f64>,
+    count: usize,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RmsfData> for Rmsf {
+    type Error = Error;
+
+    fn try_from(data: RmsfData) -> Result<Rmsf, Error> {
+        // the average and squared distances are only allocated when adding
+        // the first frame
+        let natoms = if data.count == 0 { 0 } else { data.reference.len() };
+        if data.average.len() != natoms || data.squared.len() != natoms {
+            return Err(Error::new(
+                Status::FormatError,
+                format!(
+                    "inconsistent RMSF data: got {} reference positions, {} average positions \
+                    and {} squared distances for {} frames",
+                    data.reference.len(),
+                    data.average.len(),
+                    data.squared.len(),
+                    data.count
+                ),
+            ));
+        }
+
+        if data
+            .squared
+            .iter()
+            .any(|&squared| !(squared >= 0.0 && squared.is_finite()))
+        {
+            return Err(Error::new(
+                Status::FormatError,
+                "the RMSF squared distances must be positive numbers",
+            ));
+        }
+
+        return Ok(Rmsf {
+            reference: data.reference,
+            average: data.average,
+            squared: data.squared,
+            count: data.count,
+            progress: None,
+        });
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Rmsf {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Rmsf, D::Error> {
+        let data = RmsfData::deserialize(deserializer)?;
+        Rmsf::try_from(data).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Rmsf {
+    /// Create a new empty `Rmsf` accumulator.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::Rmsf;
+    /// let rmsf = Rmsf::new();
+    /// assert_eq!(rmsf.frames_count(), 0);
+    /// ```
+    pub fn new() -> Rmsf {
+        Rmsf::default()
+    }
+
+    /// Create a new empty `Rmsf` accumulator, aligning all frames onto the
+    /// positions of the atoms in `reference` instead of the first frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::Rmsf};
+    /// let mut reference = Frame::new();
+    /// reference.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    ///
+    /// let rmsf = Rmsf::with_reference(&reference);
+    /// assert_eq!(rmsf.frames_count(), 0);
+    /// ```
+    pub fn with_reference(reference: &Frame) -> Rmsf {
+        Rmsf {
+            reference: reference.positions().to_vec(),
+            ..Rmsf::default()
+        }
+    }
+
+    /// Add the positions of the atoms in `frame` to this accumulator.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the frame does not contain the same number of
+    /// atoms as the first frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::Rmsf};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    ///
+    /// let mut rmsf = Rmsf::new();
+    /// rmsf.add_frame(&frame).unwrap();
+    /// assert_eq!(rmsf.frames_count(), 1);
+    ///
+    /// frame.add_atom(&Atom::new("C"), [1.0, 0.0, 0.0], None);
+    /// assert!(rmsf.add_frame(&frame).is_err());
+    /// ```
+    pub fn add_frame(&mut self, frame: &Frame) -> Result<(), Error> {
+        return self.add_positions(frame.positions());
+    }
+
+    /// Add the given atomic `positions` to this accumulator
+    fn add_positions(&mut self, positions: &[Vector3D]) -> Result<(), Error> {
+        if self.count == 0 && self.reference.is_empty() {
+            self.reference = positions.to_vec();
+        }
+
+        if positions.len() != self.reference.len() {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!(
+                    "can not add a frame with {} atoms to RMSF computed for {} atoms",
+                    positions.len(),
+                    self.reference.len()
+                ),
+            ));
+        }
+
+        if self.count == 0 {
+            self.average = vec![[0.0; 3]; positions.len()];
+            self.squared = vec![0.0; positions.len()];
+        }
+
+        let superposition = linalg::superpose(&self.reference, positions, None);
+        self.count += 1;
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.count as f64;
+        for (i, &position) in positions.iter().enumerate() {
+            // Welford's online algorithm for the variance
+            let aligned = superposition.apply(position);
+            let delta = linalg::sub(aligned, self.average[i]);
+            self.average[i] = linalg::add(self.average[i], linalg::scale(delta, 1.0 / count));
+            self.squared[i] += linalg::dot(delta, linalg::sub(aligned, self.average[i]));
+        }
+
+        if let Some(progress) = &mut self.progress {
+            progress.tick();
+        }
+        return Ok(());
+    }
+
+    /// Create a new empty `Rmsf` accumulator, aligning the frames onto the
+    /// average structure computed by this one. Adding the same frames again
+    /// to the new accumulator gives fluctuations around the average
+    /// structure, instead of the reference used by this accumulator.
+    /// Repeating the process until the average structure does not change
+    /// anymore gives the fluctuations after alignment to the average, see
+    /// `analysis::rmsf`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::Rmsf};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [1.0, 2.0, 3.0], None);
+    ///
+    /// let mut rmsf = Rmsf::new();
+    /// rmsf.add_frame(&frame).unwrap();
+    ///
+    /// let mut refined = rmsf.refine();
+    /// assert_eq!(refined.frames_count(), 0);
+    /// refined.add_frame(&frame).unwrap();
+    /// assert_eq!(refined.values(), [0.0]);
+    /// ```
+    #[must_use]
+    pub fn refine(&self) -> Rmsf {
+        Rmsf {
+            reference: self.average.clone(),
+            ..Rmsf::default()
+        }
+    }
+
+    /// Report the progress of this analysis to the given `tracker` after
+    /// each frame successfully added with `Rmsf::add_frame`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Trajectory, analysis::{Progress, ProgressTracker, Rmsf}};
+    /// let mut trajectory = Trajectory::open("protein.pdb", 'r').unwrap();
+    ///
+    /// let mut rmsf = Rmsf::new();
+    /// rmsf.set_progress(ProgressTracker::new(
+    ///     |progress: &Progress| {
+    ///         if let Some(remaining) = progress.remaining() {
+    ///             eprintln!("RMSF: {:.0?} remaining", remaining);
+    ///         }
+    ///     },
+    ///     Some(trajectory.nsteps()),
+    /// ));
+    ///
+    /// for frame in trajectory.frames() {
+    ///     rmsf.add_frame(&frame.unwrap()).unwrap();
+    /// }
+    /// ```
+    pub fn set_progress(&mut self, tracker: ProgressTracker) {
+        self.progress = Some(tracker);
+    }
+
+    /// Get the number of frames added to this accumulator.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::Rmsf;
+    /// let rmsf = Rmsf::new();
+    /// assert_eq!(rmsf.frames_count(), 0);
+    /// ```
+    pub fn frames_count(&self) -> usize {
+        self.count
+    }
+
+    /// Get the average structure, aligned onto the reference structure.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::Rmsf};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [1.0, 2.0, 3.0], None);
+    ///
+    /// let mut rmsf = Rmsf::new();
+    /// rmsf.add_frame(&frame).unwrap();
+    /// assert_eq!(rmsf.average(), [[1.0, 2.0, 3.0]]);
+    /// ```
+    pub fn average(&self) -> &[[f64; 3]] {
+        &self.average
+    }
+
+    /// Get the root mean square fluctuation of each atom, in Angstroms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, analysis::Rmsf};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    ///
+    /// let mut rmsf = Rmsf::new();
+    /// rmsf.add_frame(&frame).unwrap();
+    /// assert_eq!(rmsf.values(), [0.0]);
+    /// ```
+    pub fn values(&self) -> Vec<f64> {
+        if self.count == 0 {
+            return Vec::new();
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.count as f64;
+        return self.squared.iter().map(|squared| (squared / count).sqrt()).collect();
+    }
+
+    /// Get the average root mean square fluctuation of the atoms in each
+    /// residue of the `topology`, in the same order as `Topology::residue`.
+    /// Residues without atoms get `None`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, Residue, analysis::Rmsf};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    /// let mut residue = Residue::new("ALA");
+    /// residue.add_atom(0);
+    /// frame.add_residue(&residue).unwrap();
+    ///
+    /// let mut rmsf = Rmsf::new();
+    /// rmsf.add_frame(&frame).unwrap();
+    /// assert_eq!(rmsf.residue_values(&frame.topology()), [Some(0.0)]);
+    /// ```
+    pub fn residue_values(&self, topology: &Topology) -> Vec<Option<f64>> {
+        let values = self.values();
+        let mut residues = Vec::new();
+
+        #[allow(clippy::cast_possible_truncation)]
+        for i in 0..topology.residues_count() as usize {
+            let residue = topology.residue(i).expect("residue should exist");
+            let atoms = residue
+                .atoms()
+                .into_iter()
+                .filter_map(|atom| values.get(atom))
+                .collect::<Vec<_>>();
+
+            if atoms.is_empty() {
+                residues.push(None);
+            } else {
+                #[allow(clippy::cast_precision_loss)]
+                let count = atoms.len() as f64;
+                residues.push(Some(atoms.into_iter().sum::<f64>() / count));
+            }
+        }
+
+        return residues;
+    }
+
+    /// Store the fluctuations in the `"bfactor"` property of the atoms in
+    /// `frame`, using the isotropic B-factor definition `B = 8π²/3 RMSF²`.
+    /// The values can then be written to a PDB file, or averaged by residue
+    /// with `analysis::residue_bfactors`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the frame does not contain the same number of
+    /// atoms as the frames used to compute the fluctuations.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, Property, analysis::Rmsf};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    ///
+    /// let mut rmsf = Rmsf::new();
+    /// rmsf.add_frame(&frame).unwrap();
+    /// rmsf.write_bfactors(&mut frame).unwrap();
+    /// assert_eq!(frame.atom(0).get("bfactor"), Some(Property::Double(0.0)));
+    /// ```
+    pub fn write_bfactors(&self, frame: &mut Frame) -> Result<(), Error> {
+        let values = self.values();
+        if values.len() != frame.size() {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!(
+                    "can not write RMSF for {} atoms in a frame with {} atoms",
+                    values.len(),
+                    frame.size()
+                ),
+            ));
+        }
+
+        let factor = 8.0 * std::f64::consts::PI * std::f64::consts::PI / 3.0;
+        for (i, value) in values.iter().enumerate() {
+            frame.atom_mut(i).set("bfactor", factor * value * value);
+        }
+        return Ok(());
+    }
+}
+
+/// Largest number of passes over the trajectory used by `analysis::rmsf`
+const MAX_RMSF_PASSES: usize = 10;
+
+/// Compute the root mean square fluctuation of each atom in the `trajectory`,
+/// after aligning all the frames onto the average structure.
+///
+/// Since the average structure depends on the alignment, it is computed
+/// iteratively: the first pass aligns the frames onto the first one, and each
+/// following pass aligns them onto the average structure of the previous
+/// pass (see `Rmsf::refine`), until the average structure moves by less than
+/// 10<sup>-4</sup> Å, and for at most 10 passes. Each pass reads the whole
+/// trajectory again, use `Rmsf` directly to compute the fluctuations in a
+/// single pass, aligning the frames onto a fixed structure.
+///
+/// # Errors
+///
+/// This function fails if the trajectory can not be read, or if the frames
+/// do not all contain the same number of atoms.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Trajectory, analysis};
+/// let mut trajectory = Trajectory::open("protein.pdb", 'r').unwrap();
+/// let rmsf = analysis::rmsf(&mut trajectory).unwrap();
+///
+/// for (i, value) in rmsf.values().iter().enumerate() {
+///     println!("atom {}: {:.3} A", i, value);
+/// }
+/// ```
+pub fn rmsf(trajectory: &mut Trajectory) -> Result<Rmsf, Error> {
+    return aligned_to_average(|rmsf| {
+        for frame in trajectory.frames() {
+            rmsf.add_frame(&frame?)?;
+        }
+        return Ok(());
+    });
+}
+
+/// Iteratively compute the RMSF of a set of frames, aligning them onto the
+/// average structure. `add_frames` is called for each pass, and should add
+/// all the frames to the given accumulator.
+fn aligned_to_average<F>(mut add_frames: F) -> Result<Rmsf, Error>
+where
+    F: FnMut(&mut Rmsf) -> Result<(), Error>,
+{
+    let mut rmsf = Rmsf::new();
+    add_frames(&mut rmsf)?;
+
+    for _ in 1..MAX_RMSF_PASSES {
+        let mut refined = rmsf.refine();
+        add_frames(&mut refined)?;
+
+        let change = refined
+            .average
+            .iter()
+            .zip(&rmsf.average)
+            .map(|(&a, &b)| linalg::norm2(linalg::sub(a, b)))
+            .fold(0.0, f64::max);
+        rmsf = refined;
+        if change.sqrt() < 1e-4 {
+            break;
+        }
+    }
+
+    return Ok(rmsf);
+}
+
+impl Accumulator for Rmsf {
+    fn merge(&mut self, other: &Rmsf) -> Result<(), Error> {
+        if other.count == 0 {
+            return Ok(());
+        }
+
+        if self.count == 0 && self.reference.is_empty() {
+            self.reference.clone_from(&other.reference);
+        } else if self.reference != other.reference {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                "can not merge RMSF aligned onto different reference structures, use Rmsf::with_reference",
+            ));
+        }
+
+        if self.count == 0 {
+            self.average.clone_from(&other.average);
+            self.squared.clone_from(&other.squared);
+            self.count = other.count;
+            return Ok(());
+        }
+
+        // combine the running averages and variances (Chan et al. algorithm)
+        #[allow(clippy::cast_precision_loss)]
+        let (count, other_count) = (self.count as f64, other.count as f64);
+        let total = count + other_count;
+        for i in 0..self.average.len() {
+            let delta = linalg::sub(other.average[i], self.average[i]);
+            self.average[i] = linalg::add(self.average[i], linalg::scale(delta, other_count / total));
+            self.squared[i] += other.squared[i] + linalg::norm2(delta) * count * other_count / total;
+        }
+        self.count += other.count;
+        return Ok(());
+    }
+}
+
+impl Results for Rmsf {
+    fn columns(&self) -> Vec<String> {
+        vec!["atom".into(), "rmsf".into()]
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn rows(&self) -> Vec<Vec<f64>> {
+        self.values()
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| vec![i as f64, value])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, Property, Residue};
+
+    #[test]
+    fn rmsf() {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [10.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [0.0, 10.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [0.0, 0.0, 10.0], None);
+        frame.add_atom(&Atom::new("O"), [3.0, 3.0, 3.0], None);
+
+        let mut residue = Residue::new("core");
+        for i in 0..4 {
+            residue.add_atom(i);
+        }
+        frame.add_residue(&residue).unwrap();
+        let mut residue = Residue::new("mobile");
+        residue.add_atom(4);
+        frame.add_residue(&residue).unwrap();
+
+        let mut rmsf = Rmsf::new();
+        for &shift in &[-0.1, 0.1, -0.1, 0.1] {
+            let mut copy = frame.clone();
+            copy.positions_mut()[4][0] += shift;
+            // global translation, removed by the alignment
+            for position in copy.positions_mut() {
+                position[1] += 5.0;
+            }
+            rmsf.add_frame(&copy).unwrap();
+        }
+        assert_eq!(rmsf.frames_count(), 4);
+
+        // the mobile atom slightly perturbs the alignment, so the values
+        // are not exact
+        let values = rmsf.values();
+        for &value in &values[..4] {
+            assert!(value < 0.05);
+        }
+        approx::assert_ulps_eq!(values[4], 0.1, epsilon = 0.03);
+
+        let residues = rmsf.residue_values(&frame.topology());
+        assert!(residues[0].unwrap() < 0.05);
+        approx::assert_ulps_eq!(residues[1].unwrap(), 0.1, epsilon = 0.03);
+
+        let rows = rmsf.rows();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[4], [4.0, values[4]]);
+        assert!(rmsf.to_json().starts_with(r#"{"atom":[0,1,2,3,4],"rmsf":["#));
+
+        rmsf.write_bfactors(&mut frame).unwrap();
+        let bfactor = frame.atom(4).get("bfactor").and_then(|p| p.as_double()).unwrap();
+        approx::assert_ulps_eq!(bfactor, 8.0 * std::f64::consts::PI.powi(2) / 3.0 * values[4].powi(2));
+        assert!(matches!(frame.atom(0).get("bfactor"), Some(Property::Double(_))));
+
+        frame.resize(2);
+        assert!(rmsf.write_bfactors(&mut frame).is_err());
+        assert!(rmsf.add_frame(&frame).is_err());
+    }
+
+    fn shifted_frames() -> Vec<Frame> {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [10.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [0.0, 10.0, 0.0], None);
+        frame.add_atom(&Atom::new("O"), [3.0, 3.0, 3.0], None);
+
+        [0.1, -0.2, 0.3, 0.0, -0.1]
+            .iter()
+            .map(|&shift| {
+                let mut copy = frame.clone();
+                copy.positions_mut()[3][2] += shift;
+                copy
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge() {
+        let frames = shifted_frames();
+
+        let mut full = Rmsf::with_reference(&frames[0]);
+        let mut first = Rmsf::with_reference(&frames[0]);
+        let mut second = Rmsf::with_reference(&frames[0]);
+        for (i, frame) in frames.iter().enumerate() {
+            full.add_frame(frame).unwrap();
+            if i < 2 {
+                first.add_frame(frame).unwrap();
+            } else {
+                second.add_frame(frame).unwrap();
+            }
+        }
+
+        first.merge(&second).unwrap();
+        assert_eq!(first.frames_count(), 5);
+        for (merged, expected) in first.values().iter().zip(full.values()) {
+            approx::assert_ulps_eq!(*merged, expected, epsilon = 1e-12);
+        }
+
+        let mut empty = Rmsf::new();
+        empty.merge(&first).unwrap();
+        assert_eq!(empty.values(), first.values());
+        first.merge(&Rmsf::new()).unwrap();
+        assert_eq!(first.frames_count(), 5);
+
+        // different references can not be merged
+        let mut other = Rmsf::new();
+        other.add_frame(&frames[1]).unwrap();
+        assert!(first.merge(&other).is_err());
+    }
+
+    #[test]
+    fn average_alignment() {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [4.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [0.0, 4.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [0.0, 0.0, 4.0], None);
+        frame.add_atom(&Atom::new("O"), [2.0, 2.0, 2.0], None);
+
+        let perturbations = [
+            [0.5, 0.0, 0.0, -0.2],
+            [-0.1, 0.2, 0.0, 0.1],
+            [0.0, -0.1, 0.3, 0.0],
+            [0.1, 0.0, -0.2, 0.2],
+        ];
+        let frames = perturbations
+            .iter()
+            .zip(&[0.0_f64, 0.3, 0.6, 0.9])
+            .map(|(shifts, &angle)| {
+                let mut copy = frame.clone();
+                for (atom, &shift) in shifts.iter().enumerate() {
+                    copy.positions_mut()[atom + 1][atom % 3] += shift;
+                }
+                // global rotation around the z axis, and translation
+                for position in copy.positions_mut() {
+                    let [x, y, z] = *position;
+                    *position = [
+                        angle.cos() * x - angle.sin() * y + 1.0,
+                        angle.sin() * x + angle.cos() * y,
+                        z - 2.0,
+                    ];
+                }
+                copy
+            })
+            .collect::<Vec<_>>();
+
+        let average = aligned_to_average(|rmsf| frames.iter().try_for_each(|frame| rmsf.add_frame(frame))).unwrap();
+        assert_eq!(average.frames_count(), 4);
+
+        // the average structure is the reference used for the alignment
+        for (position, reference) in average.average().iter().zip(&average.reference) {
+            crate::assert_vector3d_eq(position, reference, 1e-4);
+        }
+
+        // aligning onto the first frame gives different fluctuations, which
+        // depend on the order of the frames
+        let mut first = Rmsf::new();
+        for frame in &frames {
+            first.add_frame(frame).unwrap();
+        }
+        let mut last = Rmsf::new();
+        for frame in frames.iter().rev() {
+            last.add_frame(frame).unwrap();
+        }
+        assert!(first
+            .values()
+            .iter()
+            .zip(last.values())
+            .any(|(a, b)| (a - b).abs() > 1e-4));
+
+        // while the alignment onto the average does not
+        let reversed =
+            aligned_to_average(|rmsf| frames.iter().rev().try_for_each(|frame| rmsf.add_frame(frame))).unwrap();
+        for (value, expected) in reversed.values().iter().zip(average.values()) {
+            approx::assert_ulps_eq!(*value, expected, epsilon = 1e-5);
+        }
+
+        let error = aligned_to_average(|rmsf| {
+            rmsf.add_frame(&frame)?;
+            rmsf.add_frame(&Frame::new())
+        })
+        .unwrap_err();
+        assert!(error.message.contains("can not add a frame"));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde() {
+        let frames = shifted_frames();
+        let mut rmsf = Rmsf::new();
+        rmsf.add_frame(&frames[0]).unwrap();
+        rmsf.add_frame(&frames[1]).unwrap();
+
+        let json = serde_json::to_string(&rmsf).unwrap();
+        let mut restored: Rmsf = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.values(), rmsf.values());
+
+        // resume the analysis from the checkpoint
+        restored.add_frame(&frames[2]).unwrap();
+        rmsf.add_frame(&frames[2]).unwrap();
+        assert_eq!(restored.values(), rmsf.values());
+
+        let empty: Rmsf = serde_json::from_str(&serde_json::to_string(&Rmsf::new()).unwrap()).unwrap();
+        assert_eq!(empty.frames_count(), 0);
+
+        let mismatched =
+            r#"{"reference": [[0, 0, 0], [1, 0, 0]], "average": [[0, 0, 0]], "squared": [0, 0], "count": 1}"#;
+        assert!(serde_json::from_str::<Rmsf>(mismatched).is_err());
+
+        let negative = r#"{"reference": [[0, 0, 0]], "average": [[0, 0, 0]], "squared": [-1], "count": 1}"#;
+        assert!(serde_json::from_str::<Rmsf>(negative).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::{Error, Status};
+use crate::linalg::{self, Vector3D};
+use crate::{CellShape, Frame};
+
+/// `Unwrapper` removes the jumps of atoms crossing periodic boundaries
+/// between consecutive frames, giving continuous trajectories for each atom.
+/// This is required to compute diffusion coefficients or mean square
+/// displacements from wrapped trajectories.
+///
+/// The unwrapper keeps track of the periodic image of each atom: when the
+/// fractional coordinates of an atom change by more than half a cell between
+/// two consecutive frames, the atom is assumed to have crossed a periodic
+/// boundary. Frames must then be given in order, without skipping frames
+/// where atoms could move by more than half a cell. Using fractional
+/// coordinates makes this work with fluctuating cells in NPT simulations.
+///
+/// See also `Trajectory::unwrapped_frames` to unwrap all the frames of a
+/// trajectory.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, Frame, UnitCell, analysis::Unwrapper};
+/// let mut frame = Frame::new();
+/// frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+/// frame.add_atom(&Atom::new("Ar"), [9.5, 5.0, 5.0], None);
+///
+/// let mut unwrapper = Unwrapper::new();
+/// unwrapper.unwrap(&mut frame).unwrap();
+///
+/// // the atom crosses the boundary of the cell
+/// frame.positions_mut()[0] = [0.5, 5.0, 5.0];
+/// unwrapper.unwrap(&mut frame).unwrap();
+/// assert_eq!(frame.positions()[0], [10.5, 5.0, 5.0]);
+/// assert_eq!(unwrapper.images(), [[1, 0, 0]]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Unwrapper {
+    /// Wrapped fractional coordinates of the atoms in the previous frame
+    previous: Vec<Vector3D>,
+    /// Periodic image of each atom
+    images: Vec<[i64; 3]>,
+}
+
+impl Unwrapper {
+    /// Create a new `Unwrapper`, using the next frame given to
+    /// `Unwrapper::unwrap` as the starting point.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::Unwrapper;
+    /// let unwrapper = Unwrapper::new();
+    /// assert!(unwrapper.images().is_empty());
+    /// ```
+    pub fn new() -> Unwrapper {
+        Unwrapper::default()
+    }
+
+    /// Get the periodic image of each atom in the last frame given to
+    /// `Unwrapper::unwrap`, as the number of cell vectors added to the
+    /// wrapped position along each direction.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, UnitCell, analysis::Unwrapper};
+    /// let mut frame = Frame::new();
+    /// frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+    /// frame.add_atom(&Atom::new("Ar"), [5.0, 5.0, 5.0], None);
+    ///
+    /// let mut unwrapper = Unwrapper::new();
+    /// unwrapper.unwrap(&mut frame).unwrap();
+    /// assert_eq!(unwrapper.images(), [[0, 0, 0]]);
+    /// ```
+    pub fn images(&self) -> &[[i64; 3]] {
+        &self.images
+    }
+
+    /// Forget about all the frames given to this unwrapper, using the next
+    /// frame as the new starting point.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, UnitCell, analysis::Unwrapper};
+    /// let mut frame = Frame::new();
+    /// frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+    /// frame.add_atom(&Atom::new("Ar"), [5.0, 5.0, 5.0], None);
+    ///
+    /// let mut unwrapper = Unwrapper::new();
+    /// unwrapper.unwrap(&mut frame).unwrap();
+    /// unwrapper.reset();
+    /// assert!(unwrapper.images().is_empty());
+    /// ```
+    pub fn reset(&mut self) {
+        self.previous.clear();
+        self.images.clear();
+    }
+
+    /// Unwrap the positions of the atoms in `frame`, using the positions of
+    /// the atoms in the previous frame to detect periodic boundary
+    /// crossings. The first frame is left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the unit cell of the frame is infinite, or if
+    /// the frame does not contain the same number of atoms as the previous
+    /// frames.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Trajectory, analysis::Unwrapper};
+    /// let mut trajectory = Trajectory::open("water.nc", 'r').unwrap();
+    /// let mut unwrapper = Unwrapper::new();
+    /// for frame in trajectory.frames() {
+    ///     let mut frame = frame.unwrap();
+    ///     unwrapper.unwrap(&mut frame).unwrap();
+    ///     println!("{:?}", frame.positions()[0]);
+    /// }
+    /// ```
+    pub fn unwrap(&mut self, frame: &mut Frame) -> Result<(), Error> {
+        let cell = frame.cell();
+        let matrix = cell.matrix();
+        let inverse = match linalg::inverse(&matrix) {
+            Some(inverse) if cell.shape() != CellShape::Infinite => inverse,
+            _ => {
+                return Err(Error::new(
+                    Status::ChemfilesError,
+                    format!(
+                        "can not unwrap frame at step {}: the unit cell is infinite",
+                        frame.step()
+                    ),
+                ))
+            }
+        };
+        std::mem::drop(cell);
+
+        if self.previous.is_empty() && self.images.is_empty() {
+            self.images = vec![[0; 3]; frame.size()];
+        } else if frame.size() != self.images.len() {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!(
+                    "can not unwrap frame at step {} with {} atoms, previous frames contained {} atoms",
+                    frame.step(),
+                    frame.size(),
+                    self.images.len()
+                ),
+            ));
+        }
+
+        let fractional = frame
+            .positions()
+            .iter()
+            .map(|&position| linalg::mat_vec(&inverse, position))
+            .collect::<Vec<_>>();
+
+        if !self.previous.is_empty() {
+            for ((image, current), previous) in self.images.iter_mut().zip(&fractional).zip(&self.previous) {
+                for d in 0..3 {
+                    #[allow(clippy::cast_possible_truncation)]
+                    let jump = (current[d] - previous[d]).round() as i64;
+                    image[d] -= jump;
+                }
+            }
+        }
+
+        for ((position, current), image) in frame.positions_mut().iter_mut().zip(&fractional).zip(&self.images) {
+            #[allow(clippy::cast_precision_loss)]
+            let image = [image[0] as f64, image[1] as f64, image[2] as f64];
+            *position = linalg::mat_vec(&matrix, linalg::add(*current, image));
+        }
+
+        self.previous = fractional;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, UnitCell};
+
+    #[test]
+    fn unwrap() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        frame.add_atom(&Atom::new("Ar"), [9.0, 5.0, 1.0], None);
+        frame.add_atom(&Atom::new("Ar"), [5.0, 5.0, 5.0], None);
+
+        let mut unwrapper = Unwrapper::new();
+        // the first atom moves by +2 A along x and -1.5 A along z at each
+        // step, crossing the boundaries of the cell
+        for step in 0..8 {
+            let expected = [9.0 + 2.0 * f64::from(step), 5.0, 1.0 - 1.5 * f64::from(step)];
+            let wrapped = [expected[0].rem_euclid(10.0), expected[1], expected[2].rem_euclid(10.0)];
+            frame.positions_mut()[0] = wrapped;
+
+            unwrapper.unwrap(&mut frame).unwrap();
+            crate::assert_vector3d_eq(&frame.positions()[0], &expected, 1e-12);
+            assert_eq!(frame.positions()[1], [5.0, 5.0, 5.0]);
+        }
+        assert_eq!(unwrapper.images()[0], [2, 0, -1]);
+        assert_eq!(unwrapper.images()[1], [0, 0, 0]);
+
+        // changes in the cell are taken into account
+        frame.set_cell(&UnitCell::new([11.0, 10.0, 10.0]));
+        frame.positions_mut()[0] = [3.3, 5.0, 0.5];
+        unwrapper.unwrap(&mut frame).unwrap();
+        crate::assert_vector3d_eq(&frame.positions()[0], &[25.3, 5.0, -9.5], 1e-12);
+
+        frame.resize(3);
+        assert!(unwrapper.unwrap(&mut frame).is_err());
+        unwrapper.reset();
+        unwrapper.unwrap(&mut frame).unwrap();
+        assert_eq!(unwrapper.images(), [[0, 0, 0]; 3]);
+
+        frame.set_cell(&UnitCell::infinite());
+        assert!(unwrapper.unwrap(&mut frame).is_err());
+    }
+}
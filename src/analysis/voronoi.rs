@@ -0,0 +1,437 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::linalg::{self, Matrix3, Vector3D};
+use crate::{CellShape, Frame};
+
+/// Find the Voronoi neighbors of all the atoms in `frame`. Two atoms are
+/// neighbors if their Voronoi cells share a face with a non-zero area. This
+/// gives a parameter-free definition of the first coordination shell, which
+/// is more meaningful than a distance cutoff in liquids and amorphous
+/// systems.
+///
+/// Periodic boundary conditions are used if the frame has a unit cell. For
+/// frames without unit cell, the Voronoi cells of atoms at the surface are
+/// clipped to a box around the system, about twice as large as the system
+/// itself.
+///
+/// The returned vector contains the sorted list of neighbors of each atom.
+/// The cost of this function grows with the square of the number of atoms.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{analysis, Atom, Frame, UnitCell};
+/// let mut frame = Frame::new();
+/// frame.set_cell(&UnitCell::new([6.0, 6.0, 6.0]));
+/// for &x in &[0.0, 2.0, 4.0] {
+///     for &y in &[0.0, 2.0, 4.0] {
+///         for &z in &[0.0, 2.0, 4.0] {
+///             frame.add_atom(&Atom::new("Ar"), [x, y, z], None);
+///         }
+///     }
+/// }
+///
+/// // simple cubic lattice: six neighbors for each atom
+/// let neighbors = analysis::voronoi_neighbors(&frame);
+/// assert!(neighbors.iter().all(|list| list.len() == 6));
+/// ```
+pub fn voronoi_neighbors(frame: &Frame) -> Vec<Vec<usize>> {
+    return compute_neighbors(frame, None);
+}
+
+/// Find the neighbors of all the atoms in `frame` using a radical Voronoi
+/// tessellation (also called power diagram or Laguerre tessellation), where
+/// the plane separating two atoms is moved according to their `radii`. This
+/// gives a better description of mixtures of atoms with different sizes than
+/// `voronoi_neighbors`, which is equivalent to using the same radius for all
+/// atoms.
+///
+/// The same conventions as `voronoi_neighbors` apply for periodic boundary
+/// conditions and frames without unit cell.
+///
+/// # Panics
+///
+/// If `radii` does not contain one value for each atom in the frame.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{analysis, Atom, Frame};
+/// let mut frame = Frame::new();
+/// frame.add_atom(&Atom::new("Na"), [0.0, 0.0, 0.0], None);
+/// frame.add_atom(&Atom::new("Cl"), [2.8, 0.0, 0.0], None);
+///
+/// let neighbors = analysis::radical_voronoi_neighbors(&frame, &[1.0, 1.8]);
+/// assert_eq!(neighbors, [vec![1], vec![0]]);
+/// ```
+pub fn radical_voronoi_neighbors(frame: &Frame, radii: &[f64]) -> Vec<Vec<usize>> {
+    assert_eq!(
+        radii.len(),
+        frame.size(),
+        "radical Voronoi tessellation requires one radius per atom"
+    );
+    return compute_neighbors(frame, Some(radii));
+}
+
+fn compute_neighbors(frame: &Frame, radii: Option<&[f64]>) -> Vec<Vec<usize>> {
+    let cell = frame.cell();
+    let matrix = if cell.shape() == CellShape::Infinite {
+        None
+    } else {
+        Some(cell.matrix())
+    };
+    return tessellate(frame.positions(), matrix, radii);
+}
+
+/// Compute the (radical) Voronoi neighbors of all the `positions`, using the
+/// periodic boundary conditions defined by the cell `matrix` if any.
+fn tessellate(positions: &[Vector3D], matrix: Option<Matrix3>, radii: Option<&[f64]>) -> Vec<Vec<usize>> {
+    let inverse = matrix.as_ref().and_then(linalg::inverse);
+
+    // periodic images to consider for each pair of atoms
+    let mut shifts = Vec::new();
+    if let Some(matrix) = matrix {
+        for a in &[-1.0, 0.0, 1.0] {
+            for b in &[-1.0, 0.0, 1.0] {
+                for c in &[-1.0, 0.0, 1.0] {
+                    shifts.push(linalg::mat_vec(&matrix, [*a, *b, *c]));
+                }
+            }
+        }
+    } else {
+        shifts.push([0.0; 3]);
+    }
+
+    // bounding box used as the starting point for the Voronoi cells. With
+    // periodic boundary conditions, the box is relative to each atom since
+    // the atoms can be anywhere in space (unwrapped or shifted coordinates).
+    // Without unit cell, the box is in absolute coordinates around the system.
+    let (lower, upper) = if let Some(matrix) = matrix {
+        let half_size = (0..3)
+            .map(|k| linalg::norm2([matrix[0][k], matrix[1][k], matrix[2][k]]).sqrt())
+            .sum::<f64>();
+        ([-half_size; 3], [half_size; 3])
+    } else {
+        let mut lower = [f64::INFINITY; 3];
+        let mut upper = [f64::NEG_INFINITY; 3];
+        for position in positions {
+            for k in 0..3 {
+                lower[k] = f64::min(lower[k], position[k]);
+                upper[k] = f64::max(upper[k], position[k]);
+            }
+        }
+        let margin = (0..3).map(|k| upper[k] - lower[k]).fold(1.0, f64::max);
+        (linalg::sub(lower, [margin; 3]), linalg::add(upper, [margin; 3]))
+    };
+
+    // the planes separating two atoms in a radical tessellation are at most
+    // `(max(R^2) - min(R^2)) / (2 r)` closer to the center than in a standard
+    // Voronoi tessellation
+    let radii_spread = radii.map_or(0.0, |radii| {
+        let max = radii.iter().map(|r| r * r).fold(f64::NEG_INFINITY, f64::max);
+        let min = radii.iter().map(|r| r * r).fold(f64::INFINITY, f64::min);
+        max - min
+    });
+
+    let mut neighbors = Vec::with_capacity(positions.len());
+    for (i, &center) in positions.iter().enumerate() {
+        let mut candidates = Vec::new();
+        for (j, &position) in positions.iter().enumerate() {
+            let mut delta = linalg::sub(position, center);
+            if let (Some(matrix), Some(inverse)) = (&matrix, &inverse) {
+                delta = linalg::minimum_image(matrix, inverse, delta);
+            }
+
+            for &shift in &shifts {
+                let vector = linalg::add(delta, shift);
+                let distance2 = linalg::norm2(vector);
+                if distance2 > 1e-20 {
+                    candidates.push((distance2, j, vector));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut polyhedron = if matrix.is_some() {
+            Polyhedron::cuboid(lower, upper)
+        } else {
+            Polyhedron::cuboid(linalg::sub(lower, center), linalg::sub(upper, center))
+        };
+        for (distance2, j, vector) in candidates {
+            let distance = distance2.sqrt();
+            let max_radius = polyhedron.max_radius();
+            if (distance2 - radii_spread) / (2.0 * distance) > max_radius {
+                // all the remaining planes are outside of the cell
+                break;
+            }
+
+            let offset = match radii {
+                Some(radii) => (distance2 + radii[i] * radii[i] - radii[j] * radii[j]) / (2.0 * distance),
+                None => 0.5 * distance,
+            };
+            polyhedron.clip(linalg::scale(vector, 1.0 / distance), offset, j);
+        }
+
+        let mut list = polyhedron.neighbors();
+        list.retain(|&j| j != i);
+        neighbors.push(list);
+    }
+
+    return neighbors;
+}
+
+/// A face of a convex polyhedron, created by the plane separating the
+/// center of the polyhedron from `neighbor`.
+struct Face {
+    neighbor: Option<usize>,
+    vertices: Vec<Vector3D>,
+}
+
+/// A convex polyhedron, represented by the list of its faces
+struct Polyhedron {
+    faces: Vec<Face>,
+    /// tolerance used when deciding on which side of a plane a point is
+    epsilon: f64,
+}
+
+impl Polyhedron {
+    /// Create a rectangular cuboid polyhedron from its `lower` and `upper`
+    /// corners.
+    fn cuboid(lower: Vector3D, upper: Vector3D) -> Polyhedron {
+        let corner = |x: usize, y: usize, z: usize| {
+            [
+                [lower[0], upper[0]][x],
+                [lower[1], upper[1]][y],
+                [lower[2], upper[2]][z],
+            ]
+        };
+
+        let faces = vec![
+            [corner(0, 0, 0), corner(0, 1, 0), corner(0, 1, 1), corner(0, 0, 1)],
+            [corner(1, 0, 0), corner(1, 1, 0), corner(1, 1, 1), corner(1, 0, 1)],
+            [corner(0, 0, 0), corner(1, 0, 0), corner(1, 0, 1), corner(0, 0, 1)],
+            [corner(0, 1, 0), corner(1, 1, 0), corner(1, 1, 1), corner(0, 1, 1)],
+            [corner(0, 0, 0), corner(1, 0, 0), corner(1, 1, 0), corner(0, 1, 0)],
+            [corner(0, 0, 1), corner(1, 0, 1), corner(1, 1, 1), corner(0, 1, 1)],
+        ];
+
+        let size = linalg::norm2(linalg::sub(upper, lower)).sqrt();
+        return Polyhedron {
+            faces: faces
+                .into_iter()
+                .map(|vertices| Face {
+                    neighbor: None,
+                    vertices: vertices.to_vec(),
+                })
+                .collect(),
+            epsilon: 1e-10 * size,
+        };
+    }
+
+    /// Get the largest distance between the origin and a vertex
+    fn max_radius(&self) -> f64 {
+        let max = self
+            .faces
+            .iter()
+            .flat_map(|face| &face.vertices)
+            .map(|&vertex| linalg::norm2(vertex))
+            .fold(0.0, f64::max);
+        return max.sqrt();
+    }
+
+    /// Cut this polyhedron with the plane `normal · x = offset`, keeping the
+    /// part containing the origin. If the plane intersects the polyhedron, the
+    /// corresponding new face is associated with `neighbor`.
+    fn clip(&mut self, normal: Vector3D, offset: f64, neighbor: usize) {
+        let epsilon = self.epsilon;
+        let max = self
+            .faces
+            .iter()
+            .flat_map(|face| &face.vertices)
+            .map(|&vertex| linalg::dot(vertex, normal))
+            .fold(f64::NEG_INFINITY, f64::max);
+        if max <= offset + epsilon {
+            return;
+        }
+
+        let mut new_vertices: Vec<Vector3D> = Vec::new();
+        for face in &mut self.faces {
+            let count = face.vertices.len();
+            let mut clipped = Vec::with_capacity(count + 1);
+            for k in 0..count {
+                let a = face.vertices[k];
+                let b = face.vertices[(k + 1) % count];
+                let distance_a = linalg::dot(a, normal) - offset;
+                let distance_b = linalg::dot(b, normal) - offset;
+
+                let a_inside = distance_a <= epsilon;
+                if a_inside {
+                    clipped.push(a);
+                    if distance_a.abs() <= epsilon {
+                        new_vertices.push(a);
+                    }
+                }
+
+                if a_inside != (distance_b <= epsilon) {
+                    let t = distance_a / (distance_a - distance_b);
+                    let intersection = linalg::add(a, linalg::scale(linalg::sub(b, a), t));
+                    clipped.push(intersection);
+                    new_vertices.push(intersection);
+                }
+            }
+            face.vertices = clipped;
+        }
+        self.faces.retain(|face| face.vertices.len() >= 3);
+
+        let mut vertices: Vec<Vector3D> = Vec::with_capacity(new_vertices.len());
+        for vertex in new_vertices {
+            let duplicated = vertices
+                .iter()
+                .any(|&other| linalg::norm2(linalg::sub(vertex, other)) <= 100.0 * epsilon * epsilon);
+            if !duplicated {
+                vertices.push(vertex);
+            }
+        }
+
+        if vertices.len() < 3 {
+            return;
+        }
+
+        // sort the vertices of the new face by angle around its center
+        let center = linalg::weighted_center(&vertices, None);
+        let axis = if normal[0].abs() < 0.9 {
+            [1.0, 0.0, 0.0]
+        } else {
+            [0.0, 1.0, 0.0]
+        };
+        let u = linalg::cross(normal, axis);
+        let v = linalg::cross(normal, u);
+        let angle = |vertex: &Vector3D| {
+            let delta = linalg::sub(*vertex, center);
+            return f64::atan2(linalg::dot(delta, v), linalg::dot(delta, u));
+        };
+        vertices.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
+
+        self.faces.push(Face {
+            neighbor: Some(neighbor),
+            vertices,
+        });
+    }
+
+    /// Get the sorted list of neighbors associated with a face of non-zero
+    /// area in this polyhedron
+    fn neighbors(&self) -> Vec<usize> {
+        let mut neighbors = Vec::new();
+        for face in &self.faces {
+            if let Some(neighbor) = face.neighbor {
+                let count = face.vertices.len();
+                let mut area = [0.0; 3];
+                for k in 0..count {
+                    area = linalg::add(area, linalg::cross(face.vertices[k], face.vertices[(k + 1) % count]));
+                }
+
+                if linalg::norm2(area).sqrt() > 1e3 * self.epsilon * self.epsilon {
+                    neighbors.push(neighbor);
+                }
+            }
+        }
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        return neighbors;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lattices() {
+        let mut positions = Vec::new();
+        for &x in &[0.0, 3.0, 6.0] {
+            for &y in &[0.0, 3.0, 6.0] {
+                for &z in &[0.0, 3.0, 6.0] {
+                    positions.push([x, y, z]);
+                }
+            }
+        }
+        let cell = [[9.0, 0.0, 0.0], [0.0, 9.0, 0.0], [0.0, 0.0, 9.0]];
+
+        let neighbors = tessellate(&positions, Some(cell), None);
+        assert_eq!(neighbors.len(), 27);
+        for list in &neighbors {
+            assert_eq!(list.len(), 6);
+        }
+        // atom 0 is at the origin, its neighbors are (0, 0, 3), (0, 0, 6)
+        // through the periodic boundaries, etc.
+        assert_eq!(neighbors[0], [1, 2, 3, 6, 9, 18]);
+
+        // the same periodic system, described with a triclinic cell
+        let cell = [[9.0, 0.0, 9.0], [0.0, 9.0, 0.0], [0.0, 0.0, 9.0]];
+        assert_eq!(tessellate(&positions, Some(cell), None), neighbors);
+
+        // face-centered cubic lattice: twelve neighbors for each atom
+        let mut positions = Vec::new();
+        for &x in &[0.0, 4.0] {
+            for &y in &[0.0, 4.0] {
+                for &z in &[0.0, 4.0] {
+                    positions.push([x, y, z]);
+                    positions.push([x + 2.0, y + 2.0, z]);
+                    positions.push([x + 2.0, y, z + 2.0]);
+                    positions.push([x, y + 2.0, z + 2.0]);
+                }
+            }
+        }
+        let cell = [[8.0, 0.0, 0.0], [0.0, 8.0, 0.0], [0.0, 0.0, 8.0]];
+
+        for list in tessellate(&positions, Some(cell), None) {
+            assert_eq!(list.len(), 12);
+        }
+    }
+
+    #[test]
+    fn translated() {
+        let mut positions = Vec::new();
+        for &x in &[0.0, 3.0, 6.0] {
+            for &y in &[0.0, 3.1, 6.0] {
+                for &z in &[0.5, 3.0, 6.2] {
+                    positions.push([x, y, z]);
+                }
+            }
+        }
+        let cell = [[9.0, 0.0, 0.0], [0.0, 9.0, 0.0], [3.0, 0.0, 9.0]];
+        let expected = tessellate(&positions, Some(cell), None);
+
+        // translating every atom by some cell vectors (as in unwrapped
+        // trajectories) does not change the neighbors
+        let translated = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &position)| {
+                #[allow(clippy::cast_precision_loss)]
+                let shift = [(i % 5) as f64 * 4.0 - 8.0, 11.0, -3.0];
+                linalg::add(position, linalg::mat_vec(&cell, shift))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tessellate(&translated, Some(cell), None), expected);
+
+        // translating the whole system far from the origin
+        let shifted = positions
+            .iter()
+            .map(|&position| linalg::add(position, [100.0, -250.0, 37.0]))
+            .collect::<Vec<_>>();
+        assert_eq!(tessellate(&shifted, Some(cell), None), expected);
+    }
+
+    #[test]
+    fn infinite() {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+        let neighbors = tessellate(&positions, None, None);
+        assert_eq!(neighbors, [vec![1, 3], vec![0, 2, 3], vec![1, 3], vec![0, 1, 2]]);
+
+        // a large radius for the first atom makes its cell grow until the
+        // cell of the second atom disappears
+        let neighbors = tessellate(&positions, None, Some(&[2.0, 1.0, 1.0, 1.0]));
+        assert_eq!(neighbors[0], [2, 3]);
+        assert!(neighbors[1].is_empty());
+    }
+}
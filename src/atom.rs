@@ -1,12 +1,16 @@
 // Chemfiles, a modern library for chemistry file reading and writing
 // Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
-use std::marker::PhantomData;
+use std::sync::Arc;
 
 use chemfiles_sys as ffi;
 
-use crate::errors::{check_not_null, check_success};
+use crate::borrowed::{Borrowed, BorrowedMut};
+use crate::errors::{check_allocation, check_not_null, check_success, install_log_bridge, Error};
+use crate::intern::intern;
+use crate::perf;
 use crate::property::{PropertiesIter, Property, RawProperty};
 use crate::strings;
+use crate::Element;
 
 /// An `Atom` is a particle in the current `Frame`. It stores the following
 /// atomic properties:
@@ -24,46 +28,66 @@ pub struct Atom {
     handle: *mut ffi::CHFL_ATOM,
 }
 
-/// An analog to a reference to an atom (`&Atom`)
-#[derive(Debug)]
-pub struct AtomRef<'a> {
-    inner: Atom,
-    marker: PhantomData<&'a Atom>,
-}
+/// An analog to a reference to an atom (`&Atom`), for atoms owned by a
+/// `Frame` or a `Topology`. See `Borrowed` for more information.
+pub type AtomRef<'a> = Borrowed<'a, Atom>;
 
-impl<'a> std::ops::Deref for AtomRef<'a> {
-    type Target = Atom;
-    fn deref(&self) -> &Atom {
-        &self.inner
+/// An analog to a mutable reference to an atom (`&mut Atom`), for atoms
+/// owned by a `Frame` or a `Topology`. See `BorrowedMut` for more
+/// information.
+///
+/// The borrowed atom can not be moved out of its frame or topology:
+///
+/// ```compile_fail
+/// # use chemfiles::{Atom, Topology};
+/// let mut topology = Topology::new();
+/// topology.resize(1);
+/// let mut atom = Atom::new("He");
+/// std::mem::swap(&mut *topology.atom_mut(0), &mut atom);
+/// ```
+pub type AtomMut<'a> = BorrowedMut<'a, Atom>;
+
+impl AtomMut<'_> {
+    /// Set the mass of this atom, see `Atom::set_mass`.
+    pub fn set_mass(&mut self, mass: f64) {
+        self.inner_mut().set_mass(mass);
     }
-}
 
-/// An analog to a mutable reference to an atom (`&mut Atom`)
-#[derive(Debug)]
-pub struct AtomMut<'a> {
-    inner: Atom,
-    marker: PhantomData<&'a mut Atom>,
-}
+    /// Set the charge of this atom, see `Atom::set_charge`.
+    pub fn set_charge(&mut self, charge: f64) {
+        self.inner_mut().set_charge(charge);
+    }
 
-impl<'a> std::ops::Deref for AtomMut<'a> {
-    type Target = Atom;
-    fn deref(&self) -> &Atom {
-        &self.inner
+    /// Set the name of this atom, see `Atom::set_name`.
+    pub fn set_name<'b>(&mut self, name: impl Into<&'b str>) {
+        self.inner_mut().set_name(name);
     }
-}
 
-impl<'a> std::ops::DerefMut for AtomMut<'a> {
-    fn deref_mut(&mut self) -> &mut Atom {
-        &mut self.inner
+    /// Set the type of this atom, see `Atom::set_atomic_type`.
+    pub fn set_atomic_type<'b>(&mut self, atomic_type: impl Into<&'b str>) {
+        self.inner_mut().set_atomic_type(atomic_type);
+    }
+
+    /// Add a new `property` with the given `name` to this atom, see
+    /// `Atom::set`.
+    pub fn set(&mut self, name: &str, property: impl Into<Property>) {
+        self.inner_mut().set(name, property);
+    }
+
+    /// Mark this atom as virtual or not, see `Atom::set_virtual`.
+    pub fn set_virtual(&mut self, is_virtual: bool) {
+        self.inner_mut().set_virtual(is_virtual);
+    }
+
+    /// Set the molecule id of this atom, see `Atom::set_molecule_id`.
+    pub fn set_molecule_id(&mut self, id: usize) {
+        self.inner_mut().set_molecule_id(id);
     }
 }
 
 impl Clone for Atom {
     fn clone(&self) -> Atom {
-        unsafe {
-            let new_handle = ffi::chfl_atom_copy(self.as_ptr());
-            Atom::from_ptr(new_handle)
-        }
+        self.try_clone().expect("failed to copy atom")
     }
 }
 
@@ -85,10 +109,7 @@ impl Atom {
     #[inline]
     #[allow(clippy::ptr_cast_constness)]
     pub(crate) unsafe fn ref_from_ptr<'a>(ptr: *const ffi::CHFL_ATOM) -> AtomRef<'a> {
-        AtomRef {
-            inner: Atom::from_ptr(ptr as *mut ffi::CHFL_ATOM),
-            marker: PhantomData,
-        }
+        Borrowed::new(Atom::from_ptr(ptr as *mut ffi::CHFL_ATOM))
     }
 
     /// Create a mutably borrowed `Atom` from a C pointer.
@@ -97,10 +118,7 @@ impl Atom {
     /// pointer, and the caller is responsible for setting the right lifetime
     #[inline]
     pub(crate) unsafe fn ref_mut_from_ptr<'a>(ptr: *mut ffi::CHFL_ATOM) -> AtomMut<'a> {
-        AtomMut {
-            inner: Atom::from_ptr(ptr),
-            marker: PhantomData,
-        }
+        BorrowedMut::new(Atom::from_ptr(ptr))
     }
 
     /// Get the underlying C pointer as a const pointer.
@@ -115,6 +133,28 @@ impl Atom {
         self.handle
     }
 
+    /// Create a copy of this atom, returning an error instead of panicking
+    /// if the memory allocation fails. `Clone::clone` panics in this case.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the C++ library can not allocate memory for
+    /// the copy.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let atom = Atom::new("He");
+    /// let copy = atom.try_clone().unwrap();
+    /// assert_eq!(copy.name(), "He");
+    /// ```
+    pub fn try_clone(&self) -> Result<Atom, Error> {
+        unsafe {
+            let new_handle = check_allocation(ffi::chfl_atom_copy(self.as_ptr()))?;
+            return Ok(Atom::from_ptr(new_handle));
+        }
+    }
+
     /// Create an atom with the given `name`, and set the atom type to `name`.
     ///
     /// # Example
@@ -124,6 +164,7 @@ impl Atom {
     /// assert_eq!(atom.name(), "He");
     /// ```
     pub fn new<'a>(name: impl Into<&'a str>) -> Atom {
+        install_log_bridge();
         let buffer = strings::to_c(name.into());
         unsafe {
             let handle = ffi::chfl_atom(buffer.as_ptr());
@@ -131,6 +172,30 @@ impl Atom {
         }
     }
 
+    /// Create an atom for the element with the given atomic `number`, using
+    /// the element symbol as both the atom name and type. The mass and radii
+    /// of the atom come from the periodic table of chemfiles. If no element
+    /// have this atomic number, returns `None`.
+    ///
+    /// This is useful to give a chemical meaning to atoms read from formats
+    /// only storing numeric types, such as LAMMPS trajectories.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let atom = Atom::from_atomic_number(8).unwrap();
+    /// assert_eq!(atom.name(), "O");
+    /// assert_eq!(atom.atomic_type(), "O");
+    /// assert_eq!(atom.mass(), 15.999);
+    ///
+    /// assert!(Atom::from_atomic_number(0).is_none());
+    /// assert!(Atom::from_atomic_number(300).is_none());
+    /// ```
+    pub fn from_atomic_number(number: u64) -> Option<Atom> {
+        let element = Element::from_atomic_number(u8::try_from(number).ok()?)?;
+        return Some(Atom::new(element.symbol()));
+    }
+
     /// Get the atom mass, in atomic mass units.
     ///
     /// # Example
@@ -205,7 +270,7 @@ impl Atom {
     /// ```
     pub fn name(&self) -> String {
         let get_name = |ptr, len| unsafe { ffi::chfl_atom_name(self.as_ptr(), ptr, len) };
-        let name = strings::call_autogrow_buffer(10, get_name).expect("getting name failed");
+        let name = strings::call_autogrow_buffer(perf::atom_string_buffer(), get_name).expect("getting name failed");
         return strings::from_c(name.as_ptr());
     }
 
@@ -219,10 +284,49 @@ impl Atom {
     /// ```
     pub fn atomic_type(&self) -> String {
         let get_type = |ptr, len| unsafe { ffi::chfl_atom_type(self.as_ptr(), ptr, len) };
-        let buffer = strings::call_autogrow_buffer(10, get_type).expect("getting type failed");
+        let buffer = strings::call_autogrow_buffer(perf::atom_string_buffer(), get_type).expect("getting type failed");
         return strings::from_c(buffer.as_ptr());
     }
 
+    /// Get the atom name as a shared string.
+    ///
+    /// All the calls to this function with the same name return the same
+    /// shared allocation, which avoids allocating one `String` per atom when
+    /// loading large systems where most atoms share a few names.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// # use std::sync::Arc;
+    /// let first = Atom::new("CA");
+    /// let second = Atom::new("CA");
+    /// assert_eq!(&*first.name_interned(), "CA");
+    /// assert!(Arc::ptr_eq(&first.name_interned(), &second.name_interned()));
+    /// ```
+    pub fn name_interned(&self) -> Arc<str> {
+        let get_name = |ptr, len| unsafe { ffi::chfl_atom_name(self.as_ptr(), ptr, len) };
+        let name = strings::call_autogrow_buffer(perf::atom_string_buffer(), get_name).expect("getting name failed");
+        return intern(strings::as_str(&name));
+    }
+
+    /// Get the atom type as a shared string.
+    ///
+    /// All the calls to this function with the same type return the same
+    /// shared allocation, see `Atom::name_interned` for more information.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let mut atom = Atom::new("CA");
+    /// atom.set_atomic_type("C");
+    /// assert_eq!(&*atom.atomic_type_interned(), "C");
+    /// ```
+    pub fn atomic_type_interned(&self) -> Arc<str> {
+        let get_type = |ptr, len| unsafe { ffi::chfl_atom_type(self.as_ptr(), ptr, len) };
+        let buffer = strings::call_autogrow_buffer(perf::atom_string_buffer(), get_type).expect("getting type failed");
+        return intern(strings::as_str(&buffer));
+    }
+
     /// Set the atom name to `name`.
     ///
     /// # Example
@@ -269,7 +373,8 @@ impl Atom {
     /// ```
     pub fn full_name(&self) -> String {
         let get_full_name = |ptr, len| unsafe { ffi::chfl_atom_full_name(self.as_ptr(), ptr, len) };
-        let name = strings::call_autogrow_buffer(10, get_full_name).expect("getting full name failed");
+        let name =
+            strings::call_autogrow_buffer(perf::atom_string_buffer(), get_full_name).expect("getting full name failed");
         return strings::from_c(name.as_ptr());
     }
 
@@ -324,6 +429,21 @@ impl Atom {
         return number;
     }
 
+    /// Try to get the chemical element of the atom from the atomic type,
+    /// using the same element table as `Atom::atomic_number`. If the element
+    /// can not be found, returns `None`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Element};
+    /// assert_eq!(Atom::new("He").element(), Element::from_symbol("He"));
+    /// assert_eq!(Atom::new("Xxx").element(), None);
+    /// ```
+    pub fn element(&self) -> Option<Element> {
+        let number = u8::try_from(self.atomic_number()).ok()?;
+        return Element::from_atomic_number(number);
+    }
+
     /// Add a new `property` with the given `name` to this atom.
     ///
     /// If a property with the same name already exists, this function override
@@ -376,6 +496,107 @@ impl Atom {
         }
     }
 
+    /// Get the alternate location identifier of this atom, as read from the
+    /// `"altloc"` property set by the PDB and mmCIF readers. Atoms without
+    /// alternate locations return `None`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let mut atom = Atom::new("CA");
+    /// assert_eq!(atom.altloc(), None);
+    ///
+    /// atom.set("altloc", "B");
+    /// assert_eq!(atom.altloc(), Some('B'));
+    /// ```
+    pub fn altloc(&self) -> Option<char> {
+        self.get("altloc").and_then(|altloc| altloc.as_code())
+    }
+
+    /// Check if this atom is a virtual site, *i.e.* a massless interaction
+    /// site such as the M site of TIP4P water or some coarse-grained beads.
+    /// This is stored in the `"is_virtual"` boolean property.
+    ///
+    /// Virtual sites are excluded from mass-weighted analyses, such as the
+    /// center of mass or radius of gyration of molecules.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let mut atom = Atom::new("MW");
+    /// assert!(!atom.is_virtual());
+    ///
+    /// atom.set_virtual(true);
+    /// assert!(atom.is_virtual());
+    /// ```
+    pub fn is_virtual(&self) -> bool {
+        self.get("is_virtual").and_then(|property| property.as_bool()) == Some(true)
+    }
+
+    /// Mark this atom as a virtual site (if `is_virtual` is `true`) or as a
+    /// regular atom, by setting the `"is_virtual"` property.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let mut atom = Atom::new("MW");
+    /// atom.set_virtual(true);
+    /// assert!(atom.is_virtual());
+    ///
+    /// atom.set_virtual(false);
+    /// assert!(!atom.is_virtual());
+    /// ```
+    pub fn set_virtual(&mut self, is_virtual: bool) {
+        self.set("is_virtual", is_virtual);
+    }
+
+    /// Get the index of the molecule containing this atom, stored in the
+    /// `"molecule_id"` property. This is `None` if the property is not set,
+    /// or is not a non-negative integer.
+    ///
+    /// Molecule ids can be computed from the bonds with
+    /// `Topology::assign_molecule_ids`. The readers in chemfiles never set
+    /// this property: formats storing the molecule of each atom (such as the
+    /// molecule-ID column of LAMMPS data files) create one residue per
+    /// molecule instead, with the molecule id as the residue id. These are
+    /// available through `Topology::residue_for_atom` and `Residue::id`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let mut atom = Atom::new("C");
+    /// assert_eq!(atom.molecule_id(), None);
+    ///
+    /// atom.set_molecule_id(3);
+    /// assert_eq!(atom.molecule_id(), Some(3));
+    ///
+    /// atom.set("molecule_id", 2.5);
+    /// assert_eq!(atom.molecule_id(), None);
+    /// ```
+    pub fn molecule_id(&self) -> Option<usize> {
+        let id = self.get("molecule_id").and_then(|property| property.as_double())?;
+        if id < 0.0 || id.fract() != 0.0 || id > 2.0_f64.powi(53) {
+            return None;
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        return Some(id as usize);
+    }
+
+    /// Set the index of the molecule containing this atom, in the
+    /// `"molecule_id"` property.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let mut atom = Atom::new("C");
+    /// atom.set_molecule_id(12);
+    /// assert_eq!(atom.molecule_id(), Some(12));
+    /// ```
+    pub fn set_molecule_id(&mut self, id: usize) {
+        #[allow(clippy::cast_precision_loss)]
+        self.set("molecule_id", id as f64);
+    }
+
     /// Get an iterator over all (name, property) pairs for this atom
     ///
     /// # Examples
@@ -394,31 +615,59 @@ impl Atom {
     /// }
     /// ```
     pub fn properties(&self) -> PropertiesIter {
+        PropertiesIter {
+            names: self.list_properties().into_iter(),
+            getter: Box::new(move |name| self.get(name).expect("failed to get property")),
+        }
+    }
+
+    /// Get the number of properties associated with this atom.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let mut atom = Atom::new("He");
+    /// assert_eq!(atom.properties_count(), 0);
+    ///
+    /// atom.set("foo", 22.2);
+    /// atom.set("bar", false);
+    /// assert_eq!(atom.properties_count(), 2);
+    /// ```
+    pub fn properties_count(&self) -> usize {
         let mut count = 0;
         unsafe {
             check_success(ffi::chfl_atom_properties_count(self.as_ptr(), &mut count));
         }
-
         #[allow(clippy::cast_possible_truncation)]
-        let size = count as usize;
-        let mut c_names = vec![std::ptr::null_mut(); size];
+        return count as usize;
+    }
+
+    /// Get the names of all the properties associated with this atom,
+    /// without getting the corresponding values.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let mut atom = Atom::new("He");
+    /// atom.set("foo", 22.2);
+    /// atom.set("bar", false);
+    ///
+    /// let mut names = atom.list_properties();
+    /// names.sort();
+    /// assert_eq!(names, ["bar", "foo"]);
+    /// ```
+    pub fn list_properties(&self) -> Vec<String> {
+        let count = self.properties_count();
+        let mut c_names = vec![std::ptr::null_mut(); count];
         unsafe {
             check_success(ffi::chfl_atom_list_properties(
                 self.as_ptr(),
                 c_names.as_mut_ptr(),
-                count,
+                count as u64,
             ));
         }
 
-        let mut names = Vec::new();
-        for ptr in c_names {
-            names.push(strings::from_c(ptr));
-        }
-
-        PropertiesIter {
-            names: names.into_iter(),
-            getter: Box::new(move |name| self.get(name).expect("failed to get property")),
-        }
+        return c_names.into_iter().map(|ptr| strings::from_c(ptr)).collect();
     }
 }
 
@@ -430,6 +679,13 @@ impl Drop for Atom {
     }
 }
 
+// SAFETY: an `Atom` owns its C++ object, which is not tied to the thread
+// which created it. The C++ atom is only modified through `&mut self`, and
+// creating or releasing handles to atoms inside frames or topologies is
+// protected by a lock in the C++ library.
+unsafe impl Send for Atom {}
+unsafe impl Sync for Atom {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -445,6 +701,9 @@ mod test {
         atom.set_name("Na");
         assert_eq!(atom.name(), "Na");
         assert_eq!(copy.name(), "He");
+
+        let copy = atom.try_clone().unwrap();
+        assert_eq!(copy.name(), "Na");
     }
 
     #[test]
@@ -469,6 +728,34 @@ mod test {
         assert_eq!(atom.name(), "He");
         atom.set_name("Zn-12");
         assert_eq!(atom.name(), "Zn-12");
+
+        // longer than the initial buffer
+        let long = "a-very-long-atom-name-".repeat(10);
+        atom.set_name(long.as_str());
+        assert_eq!(atom.name(), long);
+        assert_eq!(&*atom.name_interned(), long);
+
+        atom.set_atomic_type("opls_1158");
+        assert_eq!(atom.atomic_type(), "opls_1158");
+        assert_eq!(atom.full_name(), "");
+    }
+
+    #[test]
+    fn interned() {
+        let first = Atom::new("Zn");
+        let mut second = Atom::new("Zn");
+        assert_eq!(&*first.name_interned(), "Zn");
+        assert!(Arc::ptr_eq(&first.name_interned(), &second.name_interned()));
+
+        second.set_name("Fe");
+        assert_eq!(&*second.name_interned(), "Fe");
+        assert!(!Arc::ptr_eq(&first.name_interned(), &second.name_interned()));
+
+        second.set_atomic_type("Zn");
+        assert!(Arc::ptr_eq(
+            &first.atomic_type_interned(),
+            &second.atomic_type_interned()
+        ));
     }
 
     #[test]
@@ -511,6 +798,47 @@ mod test {
         assert_eq!(atom.atomic_number(), 0);
     }
 
+    #[test]
+    fn from_atomic_number() {
+        let atom = Atom::from_atomic_number(26).unwrap();
+        assert_eq!(atom.name(), "Fe");
+        assert_eq!(atom.atomic_type(), "Fe");
+        assert_eq!(atom.atomic_number(), 26);
+        assert_eq!(atom.mass(), Atom::new("Fe").mass());
+        assert_eq!(atom.vdw_radius(), Atom::new("Fe").vdw_radius());
+
+        assert!(Atom::from_atomic_number(0).is_none());
+        assert!(Atom::from_atomic_number(119).is_none());
+        assert!(Atom::from_atomic_number(u64::MAX).is_none());
+    }
+
+    #[test]
+    fn virtual_site() {
+        let mut atom = Atom::new("MW");
+        assert!(!atom.is_virtual());
+        atom.set_virtual(true);
+        assert!(atom.is_virtual());
+        assert_eq!(atom.get("is_virtual"), Some(Property::Bool(true)));
+
+        atom.set("is_virtual", "yes");
+        assert!(!atom.is_virtual());
+    }
+
+    #[test]
+    fn molecule_id() {
+        let mut atom = Atom::new("C");
+        assert_eq!(atom.molecule_id(), None);
+
+        atom.set_molecule_id(0);
+        assert_eq!(atom.molecule_id(), Some(0));
+        assert_eq!(atom.get("molecule_id"), Some(Property::Double(0.0)));
+
+        atom.set("molecule_id", -1.0);
+        assert_eq!(atom.molecule_id(), None);
+        atom.set("molecule_id", "3");
+        assert_eq!(atom.molecule_id(), None);
+    }
+
     #[test]
     fn property() {
         let mut atom = Atom::new("F");
@@ -527,5 +855,10 @@ mod test {
                 assert_eq!(property, Property::String("here".into()));
             }
         }
+
+        assert_eq!(atom.properties_count(), 2);
+        let mut names = atom.list_properties();
+        names.sort();
+        assert_eq!(names, ["bar", "foo"]);
     }
 }
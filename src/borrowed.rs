@@ -0,0 +1,117 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+/// A view on an object owned by another one, analog to a reference `&'a T`.
+///
+/// This is used for the atoms, unit cell, residues and topology obtained from
+/// a `Frame` or a `Topology` (see `AtomRef`, `UnitCellRef`, `ResidueRef` and
+/// `TopologyRef`). The handle inside a `Borrowed` is a reference counted
+/// borrow of the object in the C library: dropping the `Borrowed` releases
+/// this reference without freeing the object itself.
+///
+/// `Borrowed` only gives access to `&T` through `Deref`, which means the
+/// borrowed object can never be moved out of its parent.
+#[derive(Debug)]
+pub struct Borrowed<'a, T> {
+    inner: T,
+    marker: PhantomData<&'a T>,
+}
+
+impl<T> Borrowed<'_, T> {
+    /// Create a new `Borrowed` from a `T` containing a borrowed handle.
+    ///
+    /// This function is unsafe because the caller is responsible for setting
+    /// the right lifetime.
+    #[inline]
+    pub(crate) unsafe fn new(inner: T) -> Self {
+        Borrowed {
+            inner,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for Borrowed<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// A mutable view on an object owned by another one, analog to a mutable
+/// reference `&'a mut T`.
+///
+/// This is used for the atoms and unit cell obtained from a `Frame` or a
+/// `Topology` (see `AtomMut` and `UnitCellMut`), and releases the borrowed
+/// handle when dropped in the same way as `Borrowed`.
+///
+/// `BorrowedMut` gives access to the methods of `T` taking `&self` through
+/// `Deref`, and provides the methods modifying the object directly. It does
+/// not implement `DerefMut`, since getting a `&mut T` would allow to move the
+/// borrowed object out of its parent (with `std::mem::swap` for example),
+/// where it would outlive the memory it points to.
+#[derive(Debug)]
+pub struct BorrowedMut<'a, T> {
+    inner: T,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<T> BorrowedMut<'_, T> {
+    /// Create a new `BorrowedMut` from a `T` containing a borrowed handle.
+    ///
+    /// This function is unsafe because the caller is responsible for setting
+    /// the right lifetime.
+    #[inline]
+    pub(crate) unsafe fn new(inner: T) -> Self {
+        BorrowedMut {
+            inner,
+            marker: PhantomData,
+        }
+    }
+
+    /// Get mutable access to the borrowed object, for the implementation of
+    /// the modifying methods.
+    #[inline]
+    pub(crate) fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T> Deref for BorrowedMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Atom, CellShape, Frame};
+
+    #[test]
+    fn views() {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("He"), [0.0, 0.0, 0.0], None);
+
+        frame.atom_mut(0).set_name("Ne");
+        frame.atom_mut(0).set_mass(42.0);
+        assert_eq!(frame.atom(0).name(), "Ne");
+        assert_eq!(frame.topology().atom(0).mass(), 42.0);
+
+        {
+            let mut cell = frame.cell_mut();
+            cell.set_shape(CellShape::Orthorhombic).unwrap();
+            cell.set_lengths([10.0, 11.0, 12.0]).unwrap();
+        }
+        assert_eq!(frame.cell().lengths(), [10.0, 11.0, 12.0]);
+
+        // dropping the views only releases the borrowed handles
+        let views = (0..10).map(|_| frame.atom(0)).collect::<Vec<_>>();
+        drop(views);
+        drop(frame.topology());
+        assert_eq!(frame.size(), 1);
+        assert_eq!(frame.atom(0).name(), "Ne");
+    }
+}
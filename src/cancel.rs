@@ -0,0 +1,54 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::errors::{Error, Status};
+
+/// A `CancelToken` allows to cooperatively cancel long-running operations,
+/// such as reading all the frames of a large trajectory, from another thread.
+///
+/// All the clones of a token share the same state: calling `cancel` on any of
+/// them cancels the operations using any other clone. Operations check the
+/// token before processing each frame, and return an error with
+/// `Status::Cancelled` once the token has been cancelled.
+///
+/// # Example
+/// ```
+/// # use chemfiles::CancelToken;
+/// let token = CancelToken::new();
+/// let clone = token.clone();
+/// assert!(!token.is_cancelled());
+///
+/// clone.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Create a new token, which is not cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Request cancellation of all operations using this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Check if cancellation was requested for this token.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Return an error if cancellation was requested for this token.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            return Err(Error::new(Status::Cancelled, "the operation was cancelled"));
+        }
+        return Ok(());
+    }
+}
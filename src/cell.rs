@@ -1,14 +1,16 @@
 // Chemfiles, a modern library for chemistry file reading and writing
 // Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
-use std::marker::PhantomData;
 
 #[allow(clippy::wildcard_imports)]
 use chemfiles_sys as ffi;
 
-use crate::errors::{check, check_not_null, check_success, Error};
+use crate::borrowed::{Borrowed, BorrowedMut};
+use crate::errors::{check, check_allocation, check_not_null, check_success, install_log_bridge, Error, Status};
+use crate::linalg;
 
 /// Available unit cell shapes.
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CellShape {
     /// Orthorhombic cell, with the three angles equals to 90°.
     Orthorhombic,
@@ -58,46 +60,57 @@ pub struct UnitCell {
     handle: *mut ffi::CHFL_CELL,
 }
 
-/// An analog to a reference to an unit cell (`&UnitCell`)
-#[derive(Debug)]
-pub struct UnitCellRef<'a> {
-    inner: UnitCell,
-    marker: PhantomData<&'a UnitCell>,
-}
+/// An analog to a reference to an unit cell (`&UnitCell`), for the cell of a
+/// `Frame`. See `Borrowed` for more information.
+pub type UnitCellRef<'a> = Borrowed<'a, UnitCell>;
 
-impl<'a> std::ops::Deref for UnitCellRef<'a> {
-    type Target = UnitCell;
-    fn deref(&self) -> &UnitCell {
-        &self.inner
-    }
-}
+/// An analog to a mutable reference to an unit cell (`&mut UnitCell`), for
+/// the cell of a `Frame`. See `BorrowedMut` for more information.
+///
+/// The borrowed cell can not be moved out of its frame:
+///
+/// ```compile_fail
+/// # use chemfiles::{Frame, UnitCell};
+/// let mut frame = Frame::new();
+/// let mut cell = UnitCell::new([10.0, 10.0, 10.0]);
+/// std::mem::swap(&mut *frame.cell_mut(), &mut cell);
+/// ```
+pub type UnitCellMut<'a> = BorrowedMut<'a, UnitCell>;
 
-/// An analog to a mutable reference to an unit cell (`&mut UnitCell`)
-#[derive(Debug)]
-pub struct UnitCellMut<'a> {
-    inner: UnitCell,
-    marker: PhantomData<&'a mut UnitCell>,
-}
+impl UnitCellMut<'_> {
+    /// Set the lengths of the cell, see `UnitCell::set_lengths`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the unit cell is infinite, or if one of the
+    /// lengths is negative.
+    pub fn set_lengths(&mut self, lengths: [f64; 3]) -> Result<(), Error> {
+        self.inner_mut().set_lengths(lengths)
+    }
 
-impl<'a> std::ops::Deref for UnitCellMut<'a> {
-    type Target = UnitCell;
-    fn deref(&self) -> &UnitCell {
-        &self.inner
+    /// Set the angles of the cell, see `UnitCell::set_angles`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the unit cell is not `Triclinic`.
+    pub fn set_angles(&mut self, angles: [f64; 3]) -> Result<(), Error> {
+        self.inner_mut().set_angles(angles)
     }
-}
 
-impl<'a> std::ops::DerefMut for UnitCellMut<'a> {
-    fn deref_mut(&mut self) -> &mut UnitCell {
-        &mut self.inner
+    /// Set the shape of the cell, see `UnitCell::set_shape`.
+    ///
+    /// # Errors
+    ///
+    /// This can fail if the cell length or angles are incompatible with the
+    /// new shape.
+    pub fn set_shape(&mut self, shape: CellShape) -> Result<(), Error> {
+        self.inner_mut().set_shape(shape)
     }
 }
 
 impl Clone for UnitCell {
     fn clone(&self) -> UnitCell {
-        unsafe {
-            let new_handle = ffi::chfl_cell_copy(self.as_ptr());
-            UnitCell::from_ptr(new_handle)
-        }
+        self.try_clone().expect("failed to copy unit cell")
     }
 }
 
@@ -118,10 +131,7 @@ impl UnitCell {
     #[inline]
     #[allow(clippy::ptr_cast_constness)]
     pub(crate) unsafe fn ref_from_ptr<'a>(ptr: *const ffi::CHFL_CELL) -> UnitCellRef<'a> {
-        UnitCellRef {
-            inner: UnitCell::from_ptr(ptr as *mut ffi::CHFL_CELL),
-            marker: PhantomData,
-        }
+        Borrowed::new(UnitCell::from_ptr(ptr as *mut ffi::CHFL_CELL))
     }
 
     /// Create a borrowed `UnitCell` from a C pointer.
@@ -131,10 +141,7 @@ impl UnitCell {
     /// setting the right lifetime
     #[inline]
     pub(crate) unsafe fn ref_mut_from_ptr<'a>(ptr: *mut ffi::CHFL_CELL) -> UnitCellMut<'a> {
-        UnitCellMut {
-            inner: UnitCell::from_ptr(ptr),
-            marker: PhantomData,
-        }
+        BorrowedMut::new(UnitCell::from_ptr(ptr))
     }
 
     /// Get the underlying C pointer as a const pointer.
@@ -149,6 +156,28 @@ impl UnitCell {
         self.handle
     }
 
+    /// Create a copy of this unit cell, returning an error instead of panicking
+    /// if the memory allocation fails. `Clone::clone` panics in this case.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the C++ library can not allocate memory for
+    /// the copy.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([2.0, 3.0, 4.0]);
+    /// let copy = cell.try_clone().unwrap();
+    /// assert_eq!(copy.lengths(), [2.0, 3.0, 4.0]);
+    /// ```
+    pub fn try_clone(&self) -> Result<UnitCell, Error> {
+        unsafe {
+            let new_handle = check_allocation(ffi::chfl_cell_copy(self.as_ptr()))?;
+            return Ok(UnitCell::from_ptr(new_handle));
+        }
+    }
+
     /// Create an `Orthorhombic` `UnitCell` from the three lengths, in Angstroms.
     ///
     /// # Example
@@ -161,6 +190,7 @@ impl UnitCell {
     /// assert_eq!(cell.shape(), CellShape::Orthorhombic);
     /// ```
     pub fn new(lengths: [f64; 3]) -> UnitCell {
+        install_log_bridge();
         unsafe {
             let handle = ffi::chfl_cell(lengths.as_ptr(), std::ptr::null());
             UnitCell::from_ptr(handle)
@@ -202,6 +232,7 @@ impl UnitCell {
     /// assert_eq!(cell.shape(), CellShape::Triclinic);
     /// ```
     pub fn triclinic(lengths: [f64; 3], angles: [f64; 3]) -> UnitCell {
+        install_log_bridge();
         unsafe {
             let handle = ffi::chfl_cell(lengths.as_ptr(), angles.as_ptr());
             UnitCell::from_ptr(handle)
@@ -230,12 +261,26 @@ impl UnitCell {
     /// assert_eq!(cell.shape(), CellShape::Orthorhombic);
     /// ```
     pub fn from_matrix(mut matrix: [[f64; 3]; 3]) -> UnitCell {
+        install_log_bridge();
         unsafe {
             let handle = ffi::chfl_cell_from_matrix(matrix.as_mut_ptr());
             UnitCell::from_ptr(handle)
         }
     }
 
+    /// Create a `UnitCell` from the three lengths (in Angstroms) and three
+    /// angles (in degree), returning an error instead of panicking if these
+    /// parameters do not represent a valid cell.
+    #[cfg(feature = "serde")]
+    pub(crate) fn try_triclinic(lengths: [f64; 3], angles: [f64; 3]) -> Result<UnitCell, Error> {
+        install_log_bridge();
+        let handle = unsafe { ffi::chfl_cell(lengths.as_ptr(), angles.as_ptr()) };
+        if handle.is_null() {
+            return Err(Error::new(crate::Status::ChemfilesError, Error::last_error()));
+        }
+        return Ok(unsafe { UnitCell::from_ptr(handle) });
+    }
+
     /// Get the three lengths of the cell, in Angstroms.
     ///
     /// # Example
@@ -418,6 +463,235 @@ impl UnitCell {
             check_success(ffi::chfl_cell_wrap(self.as_ptr(), vector.as_mut_ptr()));
         }
     }
+
+    /// Wrap all the `vectors` in this unit cell, in place. This gives the
+    /// same result as calling `UnitCell::wrap` on each vector, but is a lot
+    /// faster for large number of vectors.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([10.0, 20.0, 30.0]);
+    ///
+    /// let mut vectors = [[12.0, 5.0, -45.0], [1.0, 2.0, 3.0]];
+    /// cell.wrap_all(&mut vectors);
+    /// assert_eq!(vectors, [[2.0, 5.0, 15.0], [1.0, 2.0, 3.0]]);
+    /// ```
+    pub fn wrap_all(&self, vectors: &mut [[f64; 3]]) {
+        if self.shape() == CellShape::Infinite {
+            return;
+        }
+
+        let matrix = self.matrix();
+        if let Some(inverse) = linalg::inverse(&matrix) {
+            for vector in vectors {
+                *vector = linalg::wrap(&matrix, &inverse, *vector);
+            }
+        }
+    }
+
+    /// Replace `vector` by its periodic image with the smallest norm in this
+    /// unit cell, following the minimum image convention. This works for
+    /// orthorhombic and triclinic cells, but only searches the images in the
+    /// neighboring cells: for strongly skewed triclinic cells, the result
+    /// might not be the closest image. `vector` is unchanged for infinite
+    /// cells and for cells with a zero volume.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([10.0, 20.0, 30.0]);
+    ///
+    /// let mut vector = [8.0, -12.0, 5.0];
+    /// cell.minimum_image(&mut vector);
+    /// assert_eq!(vector, [-2.0, 8.0, 5.0]);
+    /// ```
+    pub fn minimum_image(&self, vector: &mut [f64; 3]) {
+        if self.shape() == CellShape::Infinite {
+            return;
+        }
+
+        let matrix = self.matrix();
+        if let Some(inverse) = linalg::inverse(&matrix) {
+            *vector = linalg::minimum_image(&matrix, &inverse, *vector);
+        }
+    }
+
+    /// Get the distance between the points `a` and `b`, using the minimum
+    /// image convention in this unit cell.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([10.0, 10.0, 10.0]);
+    /// assert_eq!(cell.distance(&[1.0, 0.0, 0.0], &[9.0, 0.0, 0.0]), 2.0);
+    ///
+    /// let infinite = UnitCell::infinite();
+    /// assert_eq!(infinite.distance(&[1.0, 0.0, 0.0], &[9.0, 0.0, 0.0]), 8.0);
+    /// ```
+    pub fn distance(&self, a: &[f64; 3], b: &[f64; 3]) -> f64 {
+        let mut vector = linalg::sub(*b, *a);
+        self.minimum_image(&mut vector);
+        return linalg::norm2(vector).sqrt();
+    }
+
+    /// Get the inverse of the cell matrix, if it exists
+    fn inverse_matrix(&self) -> Result<[[f64; 3]; 3], Error> {
+        match linalg::inverse(&self.matrix()) {
+            Some(inverse) => Ok(inverse),
+            None => Err(Error::new(
+                Status::ChemfilesError,
+                "fractional coordinates are not defined for infinite cells or cells with a zero volume",
+            )),
+        }
+    }
+
+    /// Convert the cartesian `vector` to fractional coordinates in this unit
+    /// cell, *i.e.* to coordinates in the basis of the cell vectors.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if this cell is infinite, or if it has a zero
+    /// volume (for example if one of the lengths is zero).
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([10.0, 20.0, 30.0]);
+    /// assert_eq!(cell.fractional(&[5.0, 5.0, 3.0]).unwrap(), [0.5, 0.25, 0.1]);
+    ///
+    /// assert!(UnitCell::infinite().fractional(&[5.0, 5.0, 3.0]).is_err());
+    /// ```
+    pub fn fractional(&self, vector: &[f64; 3]) -> Result<[f64; 3], Error> {
+        let inverse = self.inverse_matrix()?;
+        return Ok(linalg::mat_vec(&inverse, *vector));
+    }
+
+    /// Convert the `fractional` coordinates in this unit cell to a cartesian
+    /// vector.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([10.0, 20.0, 30.0]);
+    /// assert_eq!(cell.cartesian(&[0.5, 0.25, 0.1]), [5.0, 5.0, 3.0]);
+    /// ```
+    pub fn cartesian(&self, fractional: &[f64; 3]) -> [f64; 3] {
+        linalg::mat_vec(&self.matrix(), *fractional)
+    }
+
+    /// Convert all the cartesian `vectors` to fractional coordinates in this
+    /// unit cell, in place.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if this cell is infinite or has a zero volume,
+    /// in which case `vectors` are not modified.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([10.0, 20.0, 30.0]);
+    ///
+    /// let mut vectors = [[5.0, 5.0, 3.0], [10.0, 0.0, 15.0]];
+    /// cell.fractional_all(&mut vectors).unwrap();
+    /// assert_eq!(vectors, [[0.5, 0.25, 0.1], [1.0, 0.0, 0.5]]);
+    /// ```
+    pub fn fractional_all(&self, vectors: &mut [[f64; 3]]) -> Result<(), Error> {
+        let inverse = self.inverse_matrix()?;
+        for vector in vectors {
+            *vector = linalg::mat_vec(&inverse, *vector);
+        }
+        return Ok(());
+    }
+
+    /// Convert all the `fractional` coordinates in this unit cell to
+    /// cartesian vectors, in place.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([10.0, 20.0, 30.0]);
+    ///
+    /// let mut vectors = [[0.5, 0.25, 0.1], [1.0, 0.0, 0.5]];
+    /// cell.cartesian_all(&mut vectors);
+    /// assert_eq!(vectors, [[5.0, 5.0, 3.0], [10.0, 0.0, 15.0]]);
+    /// ```
+    pub fn cartesian_all(&self, fractional: &mut [[f64; 3]]) {
+        let matrix = self.matrix();
+        for vector in fractional {
+            *vector = linalg::mat_vec(&matrix, *vector);
+        }
+    }
+}
+
+#[cfg(feature = "ndarray")]
+impl UnitCell {
+    /// Get the unit cell matricial representation as a 3x3 `ndarray` array,
+    /// with the same layout as `UnitCell::matrix`.
+    ///
+    /// This function is only available with the `ndarray` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([10.0, 20.0, 30.0]);
+    ///
+    /// let matrix = cell.matrix_array();
+    /// assert_eq!(matrix.shape(), [3, 3]);
+    /// assert_eq!(matrix[[1, 1]], 20.0);
+    /// ```
+    pub fn matrix_array(&self) -> ndarray::Array2<f64> {
+        ndarray::Array2::from(self.matrix().to_vec())
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl UnitCell {
+    /// Get the unit cell matricial representation as a `nalgebra` matrix,
+    /// with the same layout as `UnitCell::matrix`: the cell vectors are the
+    /// columns of the matrix.
+    ///
+    /// This function is only available with the `nalgebra` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::UnitCell;
+    /// let cell = UnitCell::new([10.0, 20.0, 30.0]);
+    ///
+    /// let matrix = cell.matrix_na();
+    /// assert_eq!(matrix.determinant(), 6000.0);
+    /// assert_eq!(matrix[(1, 1)], 20.0);
+    /// ```
+    pub fn matrix_na(&self) -> nalgebra::Matrix3<f64> {
+        let matrix = self.matrix();
+        return nalgebra::Matrix3::from_fn(|i, j| matrix[i][j]);
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl TryFrom<nalgebra::Matrix3<f64>> for UnitCell {
+    type Error = Error;
+
+    /// Create an `UnitCell` from a `nalgebra` cell matrix, with the same
+    /// conventions as `UnitCell::from_matrix`. Contrary to
+    /// `UnitCell::from_matrix`, this returns an error instead of panicking if
+    /// the matrix does not represent a unit cell.
+    fn try_from(matrix: nalgebra::Matrix3<f64>) -> Result<UnitCell, Error> {
+        install_log_bridge();
+        let mut raw = [[0.0; 3]; 3];
+        for (i, row) in raw.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = matrix[(i, j)];
+            }
+        }
+
+        let handle = unsafe { ffi::chfl_cell_from_matrix(raw.as_mut_ptr()) };
+        if handle.is_null() {
+            return Err(Error::new(crate::Status::ChemfilesError, Error::last_error()));
+        }
+        return Ok(unsafe { UnitCell::from_ptr(handle) });
+    }
 }
 
 impl Drop for UnitCell {
@@ -428,6 +702,11 @@ impl Drop for UnitCell {
     }
 }
 
+// SAFETY: the C++ unit cell only contains plain values, and is only modified
+// through `&mut self`.
+unsafe impl Send for UnitCell {}
+unsafe impl Sync for UnitCell {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -443,6 +722,9 @@ mod test {
         cell.set_lengths([10.0, 12.0, 11.0]).unwrap();
         assert_eq!(cell.lengths(), [10.0, 12.0, 11.0]);
         assert_eq!(copy.lengths(), [2.0, 3.0, 4.0]);
+
+        let copy = cell.try_clone().unwrap();
+        assert_eq!(copy.lengths(), [10.0, 12.0, 11.0]);
     }
 
     #[test]
@@ -481,6 +763,105 @@ mod test {
         crate::assert_vector3d_eq(&vector, &[2.0, 5.2, 14.7], 1e-6);
     }
 
+    #[test]
+    fn wrap_all() {
+        let cell = UnitCell::triclinic([10.0, 11.0, 12.0], [80.0, 95.0, 110.0]);
+        let mut vectors = [[12.0, 5.2, -45.3], [-3.0, 18.0, 7.5], [0.0; 3]];
+        let mut expected = vectors;
+        for vector in &mut expected {
+            cell.wrap(vector);
+        }
+
+        cell.wrap_all(&mut vectors);
+        for (vector, expected) in vectors.iter().zip(&expected) {
+            crate::assert_vector3d_eq(vector, expected, 1e-12);
+        }
+
+        let mut vectors = [[12.0, 5.2, -45.3]];
+        UnitCell::infinite().wrap_all(&mut vectors);
+        assert_eq!(vectors, [[12.0, 5.2, -45.3]]);
+    }
+
+    #[test]
+    fn minimum_image() {
+        let cell = UnitCell::new([10.0, 20.0, 30.0]);
+        let mut vector = [12.0, -15.0, 14.0];
+        cell.minimum_image(&mut vector);
+        crate::assert_vector3d_eq(&vector, &[2.0, 5.0, 14.0], 1e-12);
+        approx::assert_ulps_eq!(cell.distance(&[1.0, 1.0, 1.0], &[9.0, 19.0, 29.0]), f64::sqrt(12.0));
+
+        let cell = UnitCell::infinite();
+        let mut vector = [12.0, -15.0, 14.0];
+        cell.minimum_image(&mut vector);
+        assert_eq!(vector, [12.0, -15.0, 14.0]);
+
+        // cell with a zero volume
+        let cell = UnitCell::new([10.0, 10.0, 0.0]);
+        let mut vector = [12.0, -15.0, 14.0];
+        cell.minimum_image(&mut vector);
+        assert_eq!(vector, [12.0, -15.0, 14.0]);
+        assert_eq!(cell.distance(&[0.0; 3], &[0.0, 3.0, 4.0]), 5.0);
+
+        // strongly skewed cell, where rounding fractional coordinates alone
+        // does not give the closest image
+        let cell = UnitCell::from_matrix([[10.0, 9.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 10.0]]);
+        let matrix = cell.matrix();
+        let original = [-5.0, -1.0, 0.0];
+        let mut vector = original;
+        cell.minimum_image(&mut vector);
+        crate::assert_vector3d_eq(&vector, &[4.0, 1.0, 0.0], 1e-12);
+
+        let norm = linalg::norm2(vector);
+        for i in -3..=3 {
+            for j in -3..=3 {
+                let shift = linalg::mat_vec(&matrix, [f64::from(i), f64::from(j), 0.0]);
+                assert!(norm <= linalg::norm2(linalg::add(original, shift)) + 1e-12);
+            }
+        }
+        approx::assert_ulps_eq!(cell.distance(&[0.0; 3], &original), norm.sqrt());
+    }
+
+    #[test]
+    fn fractional() {
+        let cell = UnitCell::triclinic([10.0, 11.0, 12.0], [80.0, 95.0, 110.0]);
+        let vector = [3.0, -4.5, 18.0];
+        let fractional = cell.fractional(&vector).unwrap();
+        crate::assert_vector3d_eq(&cell.cartesian(&fractional), &vector, 1e-12);
+
+        let mut vectors = [vector, [0.0; 3]];
+        cell.fractional_all(&mut vectors).unwrap();
+        assert_eq!(vectors[0], fractional);
+        assert_eq!(vectors[1], [0.0; 3]);
+
+        cell.cartesian_all(&mut vectors);
+        crate::assert_vector3d_eq(&vectors[0], &vector, 1e-12);
+
+        // fractional coordinates along the cell vectors
+        let matrix = cell.matrix();
+        let a = [matrix[0][0], matrix[1][0], matrix[2][0]];
+        crate::assert_vector3d_eq(&cell.fractional(&a).unwrap(), &[1.0, 0.0, 0.0], 1e-12);
+    }
+
+    #[test]
+    fn fractional_singular() {
+        let vector = [1.0, 2.0, 3.0];
+        assert!(UnitCell::infinite().fractional(&vector).is_err());
+
+        let cell = UnitCell::new([10.0, 10.0, 0.0]);
+        assert!(cell.fractional(&vector).is_err());
+
+        let mut vectors = [vector];
+        assert!(cell.fractional_all(&mut vectors).is_err());
+        assert_eq!(vectors, [vector]);
+
+        // wrapping and minimum image leave the vectors unchanged
+        cell.wrap_all(&mut vectors);
+        assert_eq!(vectors, [vector]);
+        let mut image = vector;
+        cell.minimum_image(&mut image);
+        assert_eq!(image, vector);
+    }
+
     #[test]
     fn matrix() {
         let cell = UnitCell::new([2.0, 3.0, 4.0]);
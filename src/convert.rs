@@ -0,0 +1,455 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+use crate::errors::{Error, Status};
+use crate::{Frame, OpenMode, Selection, Trajectory};
+
+/// A `Converter` reads frames from one trajectory and writes them to another
+/// one, optionally keeping only some of the frames and some of the atoms.
+/// See also `chemfiles::convert` for a conversion using the default settings.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::Converter;
+/// // convert DCD + PSF to a multi-frame PDB file of the protein only,
+/// // keeping one frame out of 10 in the first 1000 frames
+/// let count = Converter::new()
+///     .topology_file("system.psf")
+///     .selection("resname ALA or resname GLY or resname LYS")
+///     .frames(..1000)
+///     .stride(10)
+///     .run("trajectory.dcd", "protein.pdb")
+///     .unwrap();
+/// println!("converted {} frames", count);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Converter {
+    input_format: String,
+    output_format: String,
+    topology: Option<PathBuf>,
+    selection: Option<String>,
+    start: usize,
+    stop: Option<usize>,
+    stride: usize,
+}
+
+impl Default for Converter {
+    fn default() -> Converter {
+        Converter {
+            input_format: String::new(),
+            output_format: String::new(),
+            topology: None,
+            selection: None,
+            start: 0,
+            stop: None,
+            stride: 1,
+        }
+    }
+}
+
+impl Converter {
+    /// Create a new `Converter` with the default settings: all the frames and
+    /// all the atoms are converted, and both formats are guessed from the
+    /// file extensions.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Converter;
+    /// Converter::new().run("water.nc", "water.xyz").unwrap();
+    /// ```
+    pub fn new() -> Converter {
+        Converter::default()
+    }
+
+    /// Use the given `format` to read the input file, instead of guessing it
+    /// from the extension.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Converter;
+    /// Converter::new().input_format("XYZ").run("water.txt", "water.pdb").unwrap();
+    /// ```
+    #[must_use]
+    pub fn input_format(mut self, format: &str) -> Converter {
+        self.input_format = format.into();
+        self
+    }
+
+    /// Use the given `format` to write the output file, instead of guessing
+    /// it from the extension.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Converter;
+    /// Converter::new().output_format("XYZ / GZ").run("water.pdb", "water.dat").unwrap();
+    /// ```
+    #[must_use]
+    pub fn output_format(mut self, format: &str) -> Converter {
+        self.output_format = format.into();
+        self
+    }
+
+    /// Read the topology of the input from the first frame of the file at
+    /// `path`, for example when converting from formats without topology
+    /// information such as DCD.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Converter;
+    /// Converter::new().topology_file("system.psf").run("trajectory.dcd", "output.pdb").unwrap();
+    /// ```
+    #[must_use]
+    pub fn topology_file<P: AsRef<Path>>(mut self, path: P) -> Converter {
+        self.topology = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Only keep the atoms matching the given `selection` in the output.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Converter;
+    /// Converter::new().selection("not name H").run("water.xyz", "oxygens.xyz").unwrap();
+    /// ```
+    #[must_use]
+    pub fn selection(mut self, selection: &str) -> Converter {
+        self.selection = Some(selection.into());
+        self
+    }
+
+    /// Only convert the frames with a step in the given `range`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Converter;
+    /// Converter::new().frames(10..20).run("water.xyz", "part.xyz").unwrap();
+    /// ```
+    #[must_use]
+    pub fn frames<R: RangeBounds<usize>>(mut self, range: R) -> Converter {
+        self.start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        self.stop = match range.end_bound() {
+            Bound::Included(&stop) => Some(stop.saturating_add(1)),
+            Bound::Excluded(&stop) => Some(stop),
+            Bound::Unbounded => None,
+        };
+        self
+    }
+
+    /// Only convert one frame every `stride` frames, starting with the first
+    /// frame in the range given to `Converter::frames`.
+    ///
+    /// # Panics
+    ///
+    /// If `stride` is zero.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Converter;
+    /// Converter::new().stride(10).run("water.xyz", "sparse.xyz").unwrap();
+    /// ```
+    #[must_use]
+    pub fn stride(mut self, stride: usize) -> Converter {
+        assert!(stride > 0, "the conversion stride must be positive");
+        self.stride = stride;
+        self
+    }
+
+    /// Convert the trajectory at `input` and write the result to `output`,
+    /// returning the number of frames written.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the selection is invalid or matches multiple
+    /// atoms (for example `"pairs: ..."`), if the input or the topology file
+    /// can not be read, or if the output can not be written.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Converter;
+    /// let count = Converter::new().stride(2).run("water.xyz", "water.pdb").unwrap();
+    /// ```
+    pub fn run<P, Q>(&self, input: P, output: Q) -> Result<usize, Error>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let mut selection = match &self.selection {
+            Some(selection) => Some(Selection::new(selection.as_str())?),
+            None => None,
+        };
+        if let Some(selection) = &selection {
+            if selection.size() != 1 {
+                return Err(Error::new(
+                    Status::SelectionError,
+                    "trajectory conversion requires a selection matching single atoms",
+                ));
+            }
+        }
+
+        let mut builder = Trajectory::builder().format(&self.input_format);
+        if let Some(topology) = &self.topology {
+            builder = builder.topology_file(topology);
+        }
+        let mut input = builder.open(input)?;
+
+        let mut output = Trajectory::builder()
+            .mode(OpenMode::Write)
+            .format(&self.output_format)
+            .open(output)?;
+
+        let nsteps = input.try_nsteps()?;
+        let stop = self.stop.map_or(nsteps, |stop| stop.min(nsteps));
+
+        let mut count = 0;
+        let mut frame = Frame::new();
+        for step in (self.start..stop).step_by(self.stride) {
+            input.read_step(step, &mut frame)?;
+            match &mut selection {
+                Some(selection) => output.write(&frame.subset(&selection.list(&frame)))?,
+                None => output.write(&frame)?,
+            }
+            count += 1;
+        }
+
+        output.close()?;
+        return Ok(count);
+    }
+}
+
+/// Convert the trajectory at `input` to the file at `output`, guessing both
+/// formats from the file extensions, and return the number of frames
+/// written. Use a `Converter` to only convert some frames or atoms.
+///
+/// # Errors
+///
+/// This function fails if the input can not be read, or if the output can
+/// not be written.
+///
+/// # Example
+/// ```no_run
+/// let count = chemfiles::convert("water.nc", "water.xyz").unwrap();
+/// println!("converted {} frames", count);
+/// ```
+pub fn convert<P, Q>(input: P, output: Q) -> Result<usize, Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    Converter::new().run(input, output)
+}
+
+/// How to split a trajectory in multiple files with `chemfiles::split`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitBy {
+    /// Write this number of frames in each file
+    Frames(usize),
+    /// Write the frames covering this duration in each file, using the time
+    /// associated with each frame (see `Frame::time`). The duration uses the
+    /// same unit as the time in the trajectory, usually picoseconds.
+    Time(f64),
+}
+
+/// Split the trajectory at `input` into multiple files containing the
+/// consecutive frames of the trajectory, and return the paths of the files
+/// written. The path of each file is created by replacing `{}` in
+/// `output_template` with the index of the file, starting at 0, and the
+/// format of the files is guessed from the extension. Each frame is written
+/// with its own topology and unit cell.
+///
+/// When splitting by time, the first file contains the frames with a time
+/// in `[t0, t0 + duration)` where `t0` is the time of the first frame, the
+/// second file the frames with a time in `[t0 + duration, t0 + 2 * duration)`,
+/// and so on. Files which would not contain any frame are not created.
+///
+/// # Errors
+///
+/// This function fails if `output_template` does not contain `{}`, if the
+/// chunk size is not positive, if the input can not be read or the outputs
+/// can not be written, or when splitting by time if a frame does not have an
+/// associated time.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::SplitBy;
+/// // write files with 1000 frames each: part-0.xtc, part-1.xtc, ...
+/// let files = chemfiles::split("trajectory.xtc", "part-{}.xtc", SplitBy::Frames(1000)).unwrap();
+/// println!("wrote {} files", files.len());
+///
+/// // write one file for each nanosecond of simulation
+/// chemfiles::split("trajectory.xtc", "ns-{}.xtc", SplitBy::Time(1000.0)).unwrap();
+/// ```
+pub fn split<P: AsRef<Path>>(input: P, output_template: &str, chunk: SplitBy) -> Result<Vec<PathBuf>, Error> {
+    if !output_template.contains("{}") {
+        return Err(Error::new(
+            Status::ChemfilesError,
+            format!("the output template '{output_template}' must contain '{{}}'"),
+        ));
+    }
+
+    let valid = match chunk {
+        SplitBy::Frames(count) => count > 0,
+        SplitBy::Time(duration) => duration > 0.0 && duration.is_finite(),
+    };
+    if !valid {
+        return Err(Error::new(
+            Status::ChemfilesError,
+            format!("the size of the chunks must be positive, got {chunk:?}"),
+        ));
+    }
+
+    let mut input = Trajectory::open(input, 'r')?;
+    let mut paths = Vec::new();
+    let mut output: Option<(usize, Trajectory)> = None;
+    let mut start_time = None;
+
+    for (index, frame) in input.frames().enumerate() {
+        let frame = frame?;
+        let chunk_index = match chunk {
+            SplitBy::Frames(count) => index / count,
+            SplitBy::Time(duration) => {
+                let time = frame.time().ok_or_else(|| {
+                    Error::new(
+                        Status::ChemfilesError,
+                        format!("frame at step {} does not have an associated time", frame.step()),
+                    )
+                })?;
+                let start = *start_time.get_or_insert(time);
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let chunk_index = ((time - start) / duration).floor().max(0.0) as usize;
+                chunk_index
+            }
+        };
+
+        let needs_new_file = match &output {
+            Some((current, _)) => *current != chunk_index,
+            None => true,
+        };
+        if needs_new_file {
+            if let Some((_, trajectory)) = output.take() {
+                trajectory.close()?;
+            }
+            let path = PathBuf::from(output_template.replace("{}", &paths.len().to_string()));
+            output = Some((chunk_index, Trajectory::open(&path, 'w')?));
+            paths.push(path);
+        }
+
+        if let Some((_, trajectory)) = &mut output {
+            trajectory.write(&frame)?;
+        }
+    }
+
+    if let Some((_, trajectory)) = output {
+        trajectory.close()?;
+    }
+    return Ok(paths);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert() {
+        let output = "test-tmp-convert.xyz";
+        let count = Converter::new()
+            .selection("name O")
+            .frames(10..=50)
+            .stride(10)
+            .run("data/water.xyz", output)
+            .unwrap();
+        assert_eq!(count, 5);
+
+        let mut trajectory = Trajectory::open(output, 'r').unwrap();
+        assert_eq!(trajectory.nsteps(), 5);
+        let mut frame = Frame::new();
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.size(), 99);
+
+        let mut water = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let mut reference = Frame::new();
+        water.read_step(10, &mut reference).unwrap();
+        assert_eq!(frame.positions()[0], reference.positions()[0]);
+        std::mem::drop(trajectory);
+
+        assert_eq!(super::convert("data/water.xyz", output).unwrap(), 100);
+        std::fs::remove_file(output).unwrap();
+
+        let converter = Converter::new().selection("name O and");
+        assert!(converter.run("data/water.xyz", output).is_err());
+
+        let converter = Converter::new().selection("pairs: all");
+        let error = converter.run("data/water.xyz", output).unwrap_err();
+        assert_eq!(error.status, Status::SelectionError);
+    }
+
+    #[test]
+    fn split() {
+        let files = super::split("data/water.xyz", "test-tmp-split-{}.xyz", SplitBy::Frames(30)).unwrap();
+        assert_eq!(files.len(), 4);
+        assert_eq!(files[1], Path::new("test-tmp-split-1.xyz"));
+
+        let mut reference = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let mut expected = Frame::new();
+        reference.read_step(30, &mut expected).unwrap();
+
+        let counts = files
+            .iter()
+            .map(|path| Trajectory::open(path, 'r').unwrap().nsteps())
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [30, 30, 30, 10]);
+
+        let mut trajectory = Trajectory::open(&files[1], 'r').unwrap();
+        let mut frame = Frame::new();
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.positions(), expected.positions());
+        std::mem::drop(trajectory);
+
+        for path in files {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert!(super::split("data/water.xyz", "no-template.xyz", SplitBy::Frames(30)).is_err());
+        assert!(super::split("data/water.xyz", "test-tmp-split-{}.xyz", SplitBy::Frames(0)).is_err());
+        assert!(super::split("data/water.xyz", "test-tmp-split-{}.xyz", SplitBy::Time(-1.0)).is_err());
+
+        // XYZ files do not contain the time of the frames
+        let error = super::split("data/water.xyz", "test-tmp-split-time-{}.xyz", SplitBy::Time(1.0)).unwrap_err();
+        assert!(error.message.contains("does not have an associated time"));
+    }
+
+    #[test]
+    fn split_time() {
+        let mut input = Trajectory::open("test-tmp-split-input.xyz", 'w').unwrap();
+        let mut frame = Frame::new();
+        frame.add_atom(&crate::Atom::new("Zn"), [0.0; 3], None);
+        for &time in &[0.0, 0.4, 1.0, 1.5, 3.2] {
+            frame.set("time", time);
+            input.write(&frame).unwrap();
+        }
+        input.close().unwrap();
+
+        let files = super::split(
+            "test-tmp-split-input.xyz",
+            "test-tmp-split-time-{}.xyz",
+            SplitBy::Time(1.0),
+        )
+        .unwrap();
+        let counts = files
+            .iter()
+            .map(|path| Trajectory::open(path, 'r').unwrap().nsteps())
+            .collect::<Vec<_>>();
+        // there are no frames between 2.0 and 3.0
+        assert_eq!(counts, [2, 2, 1]);
+
+        for path in files {
+            std::fs::remove_file(path).unwrap();
+        }
+        std::fs::remove_file("test-tmp-split-input.xyz").unwrap();
+    }
+}
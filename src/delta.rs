@@ -0,0 +1,486 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Experimental archival format storing only the atoms which moved since the
+//! previous frame.
+//!
+//! The files are text files starting with a `CHEMFILES-DELTA 1` header line,
+//! followed by a sequence of records. Key frames store all the atoms, while
+//! delta frames only store the atoms which moved by more than the tolerance:
+//!
+//! ```text
+//! KEYFRAME <step> <natoms>
+//! CELL <9 values for the cell matrix>
+//! <x> <y> <z> <atom name>     (natoms times)
+//! DELTA <step> <count>
+//! CELL <9 values for the cell matrix>
+//! <index> <x> <y> <z>         (count times)
+//! ```
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::errors::{Error, Status};
+use crate::linalg::{norm2, sub, Vector3D};
+use crate::{Atom, Frame, UnitCell};
+
+const HEADER: &str = "CHEMFILES-DELTA 1";
+
+/// `DeltaTrajectoryWriter` writes frames in an experimental archival format,
+/// storing only the atoms whose positions changed by more than a given
+/// tolerance since the last stored frame. This is useful to archive
+/// simulations where most atoms do not move, like molecules adsorbed on a
+/// frozen substrate.
+///
+/// A key frame containing all atoms is stored every `keyframe_interval`
+/// frames, and every time the number of atoms changes. Only the positions,
+/// the atom names, the step and the unit cell are stored; velocities, bonds,
+/// residues and properties are not.
+///
+/// Positions are compared with the positions a reader would reconstruct, so
+/// errors do not accumulate over successive delta frames: each atom in the
+/// reconstructed frames is always within `tolerance` of its actual position.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, DeltaTrajectoryReader, DeltaTrajectoryWriter, Frame};
+/// let mut frame = Frame::new();
+/// frame.add_atom(&Atom::new("Pt"), [0.0, 0.0, 0.0], None);
+/// frame.add_atom(&Atom::new("CO"), [0.0, 0.0, 2.0], None);
+///
+/// let mut writer = DeltaTrajectoryWriter::new(Vec::new(), 1e-3, 10);
+/// writer.write(&frame).unwrap();
+/// frame.positions_mut()[1][2] = 2.5;
+/// writer.write(&frame).unwrap();
+/// let data = writer.finish().unwrap();
+///
+/// let reader = DeltaTrajectoryReader::new(data.as_slice());
+/// let frames = reader.collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(frames.len(), 2);
+/// assert_eq!(frames[1].positions()[1], [0.0, 0.0, 2.5]);
+/// ```
+#[derive(Debug)]
+pub struct DeltaTrajectoryWriter<W: Write> {
+    writer: W,
+    tolerance: f64,
+    keyframe_interval: usize,
+    /// Positions as they will be reconstructed by a reader
+    reference: Vec<Vector3D>,
+    /// Number of frames written since the last key frame
+    since_keyframe: usize,
+    header_written: bool,
+}
+
+impl DeltaTrajectoryWriter<BufWriter<File>> {
+    /// Create a new file at `path` and write frames to it, using the given
+    /// `tolerance` (in Angstroms) and `keyframe_interval`. See
+    /// `DeltaTrajectoryWriter::new` for more information on the parameters.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the file can not be created.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::DeltaTrajectoryWriter;
+    /// let writer = DeltaTrajectoryWriter::create("adsorption.delta", 1e-3, 100).unwrap();
+    /// ```
+    pub fn create<P: AsRef<Path>>(path: P, tolerance: f64, keyframe_interval: usize) -> Result<Self, Error> {
+        let file = File::create(path)?;
+        return Ok(DeltaTrajectoryWriter::new(
+            BufWriter::new(file),
+            tolerance,
+            keyframe_interval,
+        ));
+    }
+}
+
+impl<W: Write> DeltaTrajectoryWriter<W> {
+    /// Create a writer sending data to `writer`. Atoms moving by more than
+    /// `tolerance` (in Angstroms) are stored in each frame, and a key frame
+    /// with all atoms is stored every `keyframe_interval` frames. A
+    /// `keyframe_interval` of 0 only stores a key frame for the first frame,
+    /// or when the number of atoms changes.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::DeltaTrajectoryWriter;
+    /// let writer = DeltaTrajectoryWriter::new(Vec::new(), 1e-3, 100);
+    /// ```
+    pub fn new(writer: W, tolerance: f64, keyframe_interval: usize) -> DeltaTrajectoryWriter<W> {
+        DeltaTrajectoryWriter {
+            writer,
+            tolerance,
+            keyframe_interval,
+            reference: Vec::new(),
+            since_keyframe: 0,
+            header_written: false,
+        }
+    }
+
+    /// Write a `frame` to this writer.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the underlying writer fails.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{DeltaTrajectoryWriter, Frame};
+    /// let mut writer = DeltaTrajectoryWriter::new(Vec::new(), 1e-3, 100);
+    /// writer.write(&Frame::new()).unwrap();
+    /// ```
+    pub fn write(&mut self, frame: &Frame) -> Result<(), Error> {
+        if !self.header_written {
+            writeln!(self.writer, "{HEADER}")?;
+            self.header_written = true;
+        }
+
+        let positions = frame.positions();
+        // the first frame is always a key frame, even without atoms
+        let keyframe = self.since_keyframe == 0
+            || positions.len() != self.reference.len()
+            || (self.keyframe_interval != 0 && self.since_keyframe >= self.keyframe_interval);
+
+        let matrix = frame.cell().matrix();
+        let cell = matrix
+            .iter()
+            .flatten()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if keyframe {
+            writeln!(self.writer, "KEYFRAME {} {}", frame.step(), positions.len())?;
+            writeln!(self.writer, "CELL {cell}")?;
+            for (i, &[x, y, z]) in positions.iter().enumerate() {
+                writeln!(self.writer, "{x} {y} {z} {}", frame.atom(i).name())?;
+            }
+            self.reference = positions.to_vec();
+            self.since_keyframe = 1;
+        } else {
+            let tolerance2 = self.tolerance * self.tolerance;
+            let moved = positions
+                .iter()
+                .zip(&self.reference)
+                .enumerate()
+                .filter(|(_, (&position, &reference))| norm2(sub(position, reference)) > tolerance2)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+
+            writeln!(self.writer, "DELTA {} {}", frame.step(), moved.len())?;
+            writeln!(self.writer, "CELL {cell}")?;
+            for i in moved {
+                let [x, y, z] = positions[i];
+                writeln!(self.writer, "{i} {x} {y} {z}")?;
+                self.reference[i] = positions[i];
+            }
+            self.since_keyframe += 1;
+        }
+
+        return Ok(());
+    }
+
+    /// Flush all data and get back the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the underlying writer can not be flushed.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{DeltaTrajectoryWriter, Frame};
+    /// let mut writer = DeltaTrajectoryWriter::new(Vec::new(), 1e-3, 100);
+    /// writer.write(&Frame::new()).unwrap();
+    /// let data = writer.finish().unwrap();
+    /// assert!(data.starts_with(b"CHEMFILES-DELTA"));
+    /// ```
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.writer.flush()?;
+        return Ok(self.writer);
+    }
+}
+
+/// `DeltaTrajectoryReader` reads files created by `DeltaTrajectoryWriter`,
+/// reconstructing full frames from the key frames and the following delta
+/// frames. It is an iterator over the frames in the file.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::DeltaTrajectoryReader;
+/// let reader = DeltaTrajectoryReader::open("adsorption.delta").unwrap();
+/// for frame in reader {
+///     let frame = frame.unwrap();
+///     println!("step {} contains {} atoms", frame.step(), frame.size());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DeltaTrajectoryReader<R: BufRead> {
+    reader: R,
+    line: String,
+    /// Last reconstructed frame, used as a starting point for delta frames
+    current: Option<Frame>,
+    header_read: bool,
+    finished: bool,
+}
+
+impl DeltaTrajectoryReader<BufReader<File>> {
+    /// Open the file at `path` for reading.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the file can not be opened.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::DeltaTrajectoryReader;
+    /// let reader = DeltaTrajectoryReader::open("adsorption.delta").unwrap();
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        return Ok(DeltaTrajectoryReader::new(BufReader::new(file)));
+    }
+}
+
+impl<R: BufRead> DeltaTrajectoryReader<R> {
+    /// Create a reader getting data from `reader`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::DeltaTrajectoryReader;
+    /// let data = "CHEMFILES-DELTA 1\n";
+    /// let mut reader = DeltaTrajectoryReader::new(data.as_bytes());
+    /// assert!(reader.next().is_none());
+    /// ```
+    pub fn new(reader: R) -> DeltaTrajectoryReader<R> {
+        DeltaTrajectoryReader {
+            reader,
+            line: String::new(),
+            current: None,
+            header_read: false,
+            finished: false,
+        }
+    }
+
+    /// Read the next line in `self.line`, returning `false` at the end of
+    /// the data
+    fn next_line(&mut self) -> Result<bool, Error> {
+        self.line.clear();
+        let count = self.reader.read_line(&mut self.line)?;
+        return Ok(count != 0);
+    }
+
+    /// Read the next line, failing at the end of the data
+    fn expect_line(&mut self) -> Result<&str, Error> {
+        if !self.next_line()? {
+            return Err(format_error("unexpected end of file"));
+        }
+        return Ok(self.line.trim_end_matches(['\n', '\r']));
+    }
+
+    fn read_cell(&mut self) -> Result<UnitCell, Error> {
+        let line = self.expect_line()?;
+        let values = line
+            .strip_prefix("CELL ")
+            .ok_or_else(|| format_error(format!("expected a CELL record, got '{line}'")))?;
+
+        let values = values.split_whitespace().map(parse).collect::<Result<Vec<f64>, _>>()?;
+        if values.len() != 9 {
+            return Err(format_error(format!(
+                "expected 9 values in CELL record, got {}",
+                values.len()
+            )));
+        }
+
+        let mut matrix = [[0.0; 3]; 3];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row.copy_from_slice(&values[3 * i..3 * i + 3]);
+        }
+        return Ok(UnitCell::from_matrix(matrix));
+    }
+
+    fn read_keyframe(&mut self, step: usize, natoms: usize) -> Result<Frame, Error> {
+        let mut frame = Frame::new();
+        frame.set_step(step);
+        frame.set_cell(&self.read_cell()?);
+        for _ in 0..natoms {
+            let line = self.expect_line()?;
+            let mut fields = line.splitn(4, ' ');
+            let mut position = [0.0; 3];
+            for value in &mut position {
+                *value = parse(fields.next().unwrap_or_default())?;
+            }
+            let name = fields.next().unwrap_or_default();
+            frame.add_atom(&Atom::new(name), position, None);
+        }
+        return Ok(frame);
+    }
+
+    fn read_delta(&mut self, step: usize, count: usize) -> Result<Frame, Error> {
+        let mut frame = match self.current {
+            Some(ref frame) => frame.clone(),
+            None => return Err(format_error("DELTA record before the first KEYFRAME")),
+        };
+        frame.set_step(step);
+        frame.set_cell(&self.read_cell()?);
+
+        let natoms = frame.size();
+        for _ in 0..count {
+            let line = self.expect_line()?;
+            let mut fields = line.split_whitespace();
+            let index: usize = parse(fields.next().unwrap_or_default())?;
+            if index >= natoms {
+                return Err(format_error(format!(
+                    "atom index {index} is out of bounds for a frame with {natoms} atoms"
+                )));
+            }
+            let mut position = [0.0; 3];
+            for value in &mut position {
+                *value = parse(fields.next().unwrap_or_default())?;
+            }
+            frame.positions_mut()[index] = position;
+        }
+        return Ok(frame);
+    }
+
+    fn read_next(&mut self) -> Result<Option<Frame>, Error> {
+        if !self.header_read {
+            if !self.next_line()? {
+                return Ok(None);
+            }
+            if self.line.trim_end() != HEADER {
+                return Err(format_error("missing CHEMFILES-DELTA header"));
+            }
+            self.header_read = true;
+        }
+
+        if !self.next_line()? {
+            return Ok(None);
+        }
+
+        let line = self.line.trim_end().to_owned();
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 3 {
+            return Err(format_error(format!("invalid record '{line}'")));
+        }
+        let step = parse(fields[1])?;
+        let count = parse(fields[2])?;
+
+        let frame = match fields[0] {
+            "KEYFRAME" => self.read_keyframe(step, count)?,
+            "DELTA" => self.read_delta(step, count)?,
+            _ => return Err(format_error(format!("unknown record '{}'", fields[0]))),
+        };
+        self.current = Some(frame.clone());
+        return Ok(Some(frame));
+    }
+}
+
+impl<R: BufRead> Iterator for DeltaTrajectoryReader<R> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let result = self.read_next().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            // stop at the end of the data or after the first error
+            self.finished = true;
+        }
+        return result;
+    }
+}
+
+fn format_error(message: impl Into<String>) -> Error {
+    Error::new(Status::FormatError, message)
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| format_error(format!("could not parse '{value}' in delta trajectory")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> Frame {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 11.0, 12.0]));
+        frame.add_atom(&Atom::new("Pt"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("Pt"), [2.5, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [1.25, 0.0, 2.0], None);
+        frame.add_atom(&Atom::new("O"), [1.25, 0.0, 3.15], None);
+        return frame;
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut frame = frame();
+        let mut writer = DeltaTrajectoryWriter::new(Vec::new(), 0.01, 3);
+        for step in 0..5 {
+            frame.set_step(step);
+            frame.positions_mut()[2][1] += 0.1;
+            frame.positions_mut()[3][1] += 0.1;
+            // below the tolerance
+            frame.positions_mut()[0][0] += 0.001;
+            writer.write(&frame).unwrap();
+        }
+        let data = writer.finish().unwrap();
+        let text = String::from_utf8(data.clone()).unwrap();
+        assert_eq!(text.matches("KEYFRAME").count(), 2);
+        assert_eq!(text.matches("DELTA 1 2").count(), 1);
+
+        let frames = DeltaTrajectoryReader::new(data.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(frames.len(), 5);
+        for (step, read) in frames.iter().enumerate() {
+            assert_eq!(read.step(), step);
+            assert_eq!(read.size(), 4);
+            assert_eq!(read.atom(3).name(), "O");
+            assert_eq!(read.cell().lengths(), [10.0, 11.0, 12.0]);
+        }
+
+        let last = &frames[4];
+        for (read, expected) in last.positions().iter().zip(frame.positions()) {
+            crate::assert_vector3d_eq(read, expected, 0.01);
+        }
+        crate::assert_vector3d_eq(&last.positions()[2], &frame.positions()[2], 1e-12);
+    }
+
+    #[test]
+    fn resized_frame() {
+        let mut frame = frame();
+        let mut writer = DeltaTrajectoryWriter::new(Vec::new(), 0.01, 0);
+        writer.write(&frame).unwrap();
+        frame.add_atom(&Atom::new("H"), [5.0, 5.0, 5.0], None);
+        writer.write(&frame).unwrap();
+        let data = writer.finish().unwrap();
+
+        let frames = DeltaTrajectoryReader::new(data.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(frames[0].size(), 4);
+        assert_eq!(frames[1].size(), 5);
+        assert_eq!(frames[1].atom(4).name(), "H");
+    }
+
+    #[test]
+    fn errors() {
+        let mut reader = DeltaTrajectoryReader::new("not a delta file\n".as_bytes());
+        assert_eq!(reader.next().unwrap().unwrap_err().status, Status::FormatError);
+        assert!(reader.next().is_none());
+
+        let data = "CHEMFILES-DELTA 1\nDELTA 0 0\nCELL 0 0 0 0 0 0 0 0 0\n";
+        let mut reader = DeltaTrajectoryReader::new(data.as_bytes());
+        assert_eq!(reader.next().unwrap().unwrap_err().status, Status::FormatError);
+
+        let data = "CHEMFILES-DELTA 1\nKEYFRAME 0 2\nCELL 0 0 0 0 0 0 0 0 0\n0 0 0 H\n";
+        let mut reader = DeltaTrajectoryReader::new(data.as_bytes());
+        assert_eq!(reader.next().unwrap().unwrap_err().status, Status::FormatError);
+    }
+}
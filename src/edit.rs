@@ -0,0 +1,280 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::{Error, Status};
+use crate::{Atom, BondOrder, Frame};
+
+/// A single structural modification of a `Frame`, recorded in an `EditLog`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Edit {
+    /// Add an atom at the end of the frame, with the given position and
+    /// optional velocity
+    AddAtom {
+        /// The atom to add
+        atom: Atom,
+        /// Position of the new atom
+        position: [f64; 3],
+        /// Velocity of the new atom, if any
+        velocity: Option<[f64; 3]>,
+    },
+    /// Remove the atom at the given index
+    RemoveAtom(usize),
+    /// Add a bond with the given order between two atoms
+    AddBond(usize, usize, BondOrder),
+    /// Remove the bond between two atoms, if it exists
+    RemoveBond(usize, usize),
+    /// Remove all the bonds in the frame
+    ClearBonds,
+    /// Change the name of the atom at the given index
+    RenameAtom(usize, String),
+    /// Change the type of the atom at the given index
+    SetAtomicType(usize, String),
+}
+
+impl Edit {
+    /// Apply this edit to the `frame`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the edit refers to atoms which are not in the
+    /// frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Edit, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    ///
+    /// Edit::RenameAtom(0, "CA".into()).apply(&mut frame).unwrap();
+    /// assert_eq!(frame.atom(0).name(), "CA");
+    ///
+    /// assert!(Edit::RemoveAtom(3).apply(&mut frame).is_err());
+    /// ```
+    pub fn apply(&self, frame: &mut Frame) -> Result<(), Error> {
+        let size = frame.size();
+        let check = |index: usize| {
+            if index < size {
+                Ok(())
+            } else {
+                Err(Error::new(
+                    Status::OutOfBounds,
+                    format!("can not apply {self:?}: atom {index} is out of bounds for a frame with {size} atoms"),
+                ))
+            }
+        };
+
+        match self {
+            Edit::AddAtom {
+                atom,
+                position,
+                velocity,
+            } => frame.add_atom(atom, *position, *velocity),
+            &Edit::RemoveAtom(i) => {
+                check(i)?;
+                frame.remove(i);
+            }
+            &Edit::AddBond(i, j, order) => {
+                check(i)?;
+                check(j)?;
+                frame.add_bond_with_order(i, j, order);
+            }
+            &Edit::RemoveBond(i, j) => {
+                check(i)?;
+                check(j)?;
+                frame.remove_bond(i, j);
+            }
+            Edit::ClearBonds => frame.clear_bonds(),
+            Edit::RenameAtom(i, name) => {
+                check(*i)?;
+                frame.atom_mut(*i).set_name(name.as_str());
+            }
+            Edit::SetAtomicType(i, atomic_type) => {
+                check(*i)?;
+                frame.atom_mut(*i).set_atomic_type(atomic_type.as_str());
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// An `EditLog` records a sequence of structural edits applied to a frame,
+/// and can replay them onto other frames. This allows to define a cleanup
+/// recipe (removing solvent atoms, renaming atoms, fixing bonds, ...) on the
+/// first frame of a trajectory, and then apply it consistently to all the
+/// other frames.
+///
+/// Edits are replayed in the order they were recorded, and use atomic
+/// indexes: they should be replayed on frames with the same topology as the
+/// frame used to record them.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, BondOrder, Edit, EditLog, Frame};
+/// let mut first = Frame::new();
+/// first.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+/// first.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+/// first.add_atom(&Atom::new("Na"), [5.0, 0.0, 0.0], None);
+/// let mut second = first.clone();
+///
+/// let mut log = EditLog::new();
+/// log.apply(&mut first, Edit::RemoveAtom(2)).unwrap();
+/// log.apply(&mut first, Edit::AddBond(0, 1, BondOrder::Single)).unwrap();
+/// assert_eq!(log.len(), 2);
+///
+/// log.replay(&mut second).unwrap();
+/// assert_eq!(second.size(), 2);
+/// assert_eq!(second.topology().bonds(), [[0, 1]]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EditLog {
+    edits: Vec<Edit>,
+}
+
+impl EditLog {
+    /// Create a new empty `EditLog`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::EditLog;
+    /// let log = EditLog::new();
+    /// assert!(log.is_empty());
+    /// ```
+    pub fn new() -> EditLog {
+        EditLog::default()
+    }
+
+    /// Apply the `edit` to `frame`, and record it in this log if it was
+    /// successfully applied.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the edit refers to atoms which are not in the
+    /// frame. The edit is not recorded in this case.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Edit, EditLog, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    ///
+    /// let mut log = EditLog::new();
+    /// log.apply(&mut frame, Edit::SetAtomicType(0, "CT".into())).unwrap();
+    /// assert_eq!(frame.atom(0).atomic_type(), "CT");
+    ///
+    /// assert!(log.apply(&mut frame, Edit::RenameAtom(1, "H".into())).is_err());
+    /// assert_eq!(log.len(), 1);
+    /// ```
+    pub fn apply(&mut self, frame: &mut Frame, edit: Edit) -> Result<(), Error> {
+        edit.apply(frame)?;
+        self.edits.push(edit);
+        return Ok(());
+    }
+
+    /// Record the `edit` in this log without applying it to any frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Edit, EditLog};
+    /// let mut log = EditLog::new();
+    /// log.push(Edit::ClearBonds);
+    /// assert_eq!(log.len(), 1);
+    /// ```
+    pub fn push(&mut self, edit: Edit) {
+        self.edits.push(edit);
+    }
+
+    /// Apply all the edits in this log to `frame`, in the order they were
+    /// recorded.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if any of the edits refers to atoms which are not
+    /// in the frame. The edits before the failing one are still applied to
+    /// the frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Edit, EditLog, Frame};
+    /// let mut log = EditLog::new();
+    /// log.push(Edit::RenameAtom(0, "OW".into()));
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// log.replay(&mut frame).unwrap();
+    /// assert_eq!(frame.atom(0).name(), "OW");
+    ///
+    /// assert!(log.replay(&mut Frame::new()).is_err());
+    /// ```
+    pub fn replay(&self, frame: &mut Frame) -> Result<(), Error> {
+        for edit in &self.edits {
+            edit.apply(frame)?;
+        }
+        return Ok(());
+    }
+
+    /// Get the edits recorded in this log.
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    /// Get the number of edits recorded in this log.
+    pub fn len(&self) -> usize {
+        self.edits.len()
+    }
+
+    /// Check if this log is empty.
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Remove all the edits recorded in this log.
+    pub fn clear(&mut self) {
+        self.edits.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Trajectory;
+
+    #[test]
+    fn replay() {
+        let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let mut frame = Frame::new();
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.size(), 297);
+
+        let mut log = EditLog::new();
+        log.apply(&mut frame, Edit::RemoveAtom(296)).unwrap();
+        log.apply(&mut frame, Edit::RemoveAtom(295)).unwrap();
+        log.apply(&mut frame, Edit::ClearBonds).unwrap();
+        log.apply(&mut frame, Edit::AddBond(0, 1, BondOrder::Single)).unwrap();
+        log.apply(&mut frame, Edit::RenameAtom(0, "OW".into())).unwrap();
+        log.apply(
+            &mut frame,
+            Edit::AddAtom {
+                atom: Atom::new("Na"),
+                position: [1.0, 2.0, 3.0],
+                velocity: None,
+            },
+        )
+        .unwrap();
+        assert!(log.apply(&mut frame, Edit::RemoveBond(0, 3000)).is_err());
+        assert_eq!(log.len(), 6);
+
+        let mut other = Frame::new();
+        trajectory.read(&mut other).unwrap();
+        log.replay(&mut other).unwrap();
+
+        assert_eq!(other.size(), 296);
+        assert_eq!(other.atom(0).name(), "OW");
+        assert_eq!(other.atom(295).name(), "Na");
+        assert_eq!(other.positions()[295], [1.0, 2.0, 3.0]);
+        assert_eq!(other.topology().bonds(), [[0, 1]]);
+        assert_eq!(other.topology().bond_order(0, 1), BondOrder::Single);
+
+        log.clear();
+        assert!(log.is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::fmt;
+
+static SYMBOLS: [&str; 118] = [
+    "H", "He", "Li", "Be", "B", "C", "N", "O", "F", "Ne", "Na", "Mg", "Al", "Si", "P", "S", "Cl", "Ar", "K", "Ca",
+    "Sc", "Ti", "V", "Cr", "Mn", "Fe", "Co", "Ni", "Cu", "Zn", "Ga", "Ge", "As", "Se", "Br", "Kr", "Rb", "Sr", "Y",
+    "Zr", "Nb", "Mo", "Tc", "Ru", "Rh", "Pd", "Ag", "Cd", "In", "Sn", "Sb", "Te", "I", "Xe", "Cs", "Ba", "La", "Ce",
+    "Pr", "Nd", "Pm", "Sm", "Eu", "Gd", "Tb", "Dy", "Ho", "Er", "Tm", "Yb", "Lu", "Hf", "Ta", "W", "Re", "Os", "Ir",
+    "Pt", "Au", "Hg", "Tl", "Pb", "Bi", "Po", "At", "Rn", "Fr", "Ra", "Ac", "Th", "Pa", "U", "Np", "Pu", "Am", "Cm",
+    "Bk", "Cf", "Es", "Fm", "Md", "No", "Lr", "Rf", "Db", "Sg", "Bh", "Hs", "Mt", "Ds", "Rg", "Cn", "Nh", "Fl", "Mc",
+    "Lv", "Ts", "Og",
+];
+
+/// A chemical element, identified by its atomic number.
+///
+/// Elements are used for typed queries on atoms, such as
+/// `Frame::indices_of_element` or `AtomGroup::filter_element`, which compare
+/// the atomic number of atoms (as given by `Atom::atomic_number`) without
+/// going through the selection language.
+///
+/// # Example
+/// ```
+/// # use chemfiles::Element;
+/// let oxygen = Element::from_symbol("O").unwrap();
+/// assert_eq!(oxygen.atomic_number(), 8);
+/// assert_eq!(oxygen.symbol(), "O");
+/// assert_eq!(oxygen, Element::from_atomic_number(8).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Element {
+    number: u8,
+}
+
+impl Element {
+    /// Get the element with the given atomic `number`, or `None` if no element
+    /// with this atomic number exists.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Element;
+    /// assert_eq!(Element::from_atomic_number(6).unwrap().symbol(), "C");
+    /// assert!(Element::from_atomic_number(0).is_none());
+    /// assert!(Element::from_atomic_number(200).is_none());
+    /// ```
+    pub fn from_atomic_number(number: u8) -> Option<Element> {
+        if number == 0 || usize::from(number) > SYMBOLS.len() {
+            return None;
+        }
+        return Some(Element { number });
+    }
+
+    /// Get the element with the given `symbol`, or `None` if no element with
+    /// this symbol exists. The comparison ignores case, so both `"Na"` and
+    /// `"NA"` give sodium.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Element;
+    /// assert_eq!(Element::from_symbol("Zn").unwrap().atomic_number(), 30);
+    /// assert_eq!(Element::from_symbol("ZN").unwrap().atomic_number(), 30);
+    /// assert!(Element::from_symbol("Xxx").is_none());
+    /// ```
+    pub fn from_symbol(symbol: &str) -> Option<Element> {
+        let index = SYMBOLS.iter().position(|s| s.eq_ignore_ascii_case(symbol))?;
+        let number = u8::try_from(index + 1).expect("too many elements");
+        return Some(Element { number });
+    }
+
+    /// Get the atomic number of this element.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Element;
+    /// assert_eq!(Element::from_symbol("He").unwrap().atomic_number(), 2);
+    /// ```
+    pub fn atomic_number(self) -> u8 {
+        self.number
+    }
+
+    /// Get the symbol of this element, with the usual capitalization.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Element;
+    /// assert_eq!(Element::from_symbol("FE").unwrap().symbol(), "Fe");
+    /// ```
+    pub fn symbol(self) -> &'static str {
+        SYMBOLS[usize::from(self.number) - 1]
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols() {
+        for number in 1..=118 {
+            let element = Element::from_atomic_number(number).unwrap();
+            assert_eq!(Element::from_symbol(element.symbol()), Some(element));
+            assert_eq!(element.to_string(), element.symbol());
+        }
+
+        assert_eq!(Element::from_symbol("og").unwrap().atomic_number(), 118);
+        assert!(Element::from_atomic_number(119).is_none());
+        assert!(Element::from_symbol("").is_none());
+    }
+}
@@ -0,0 +1,344 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::path::Path;
+
+use crate::errors::{Error, Status};
+use crate::linalg;
+use crate::{Frame, Property, Topology, Trajectory};
+
+/// An `Ensemble` is a set of conformers of the same molecule: multiple frames
+/// sharing a single topology, as found in multi-structure SDF or XYZ files
+/// produced by conformer generators or docking software.
+///
+/// All the frames in an ensemble contain the same number of atoms, and the
+/// ensemble topology is used for all of them when writing the ensemble.
+#[derive(Debug, Clone)]
+pub struct Ensemble {
+    topology: Topology,
+    frames: Vec<Frame>,
+}
+
+impl Ensemble {
+    /// Create a new empty ensemble, where all the frames will share the given
+    /// `topology`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Ensemble, Topology};
+    /// let mut topology = Topology::new();
+    /// topology.add_atom(&Atom::new("O"));
+    ///
+    /// let ensemble = Ensemble::new(topology);
+    /// assert!(ensemble.is_empty());
+    /// assert_eq!(ensemble.topology().size(), 1);
+    /// ```
+    pub fn new(topology: Topology) -> Ensemble {
+        Ensemble {
+            topology,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Read all the frames in the trajectory at `path` into a new ensemble.
+    /// The topology of the first frame is used as the ensemble topology.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the file can not be read, or if the frames in
+    /// the file do not all contain the same number of atoms.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Ensemble;
+    /// let ensemble = Ensemble::read("conformers.sdf").unwrap();
+    /// println!("there are {} conformers", ensemble.len());
+    /// ```
+    pub fn read<P>(path: P) -> Result<Ensemble, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut trajectory = Trajectory::open(path, 'r')?;
+        let mut ensemble: Option<Ensemble> = None;
+        for _ in 0..trajectory.try_nsteps()? {
+            let mut frame = Frame::new();
+            trajectory.read(&mut frame)?;
+
+            if let Some(ref mut ensemble) = ensemble {
+                ensemble.push(frame)?;
+            } else {
+                let mut new = Ensemble::new(frame.topology().clone());
+                new.push(frame)?;
+                ensemble = Some(new);
+            }
+        }
+
+        return Ok(ensemble.unwrap_or_else(|| Ensemble::new(Topology::new())));
+    }
+
+    /// Add a new `frame` to this ensemble.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the frame does not contain the same number of
+    /// atoms as the ensemble topology.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Ensemble, Frame, Topology};
+    /// let mut topology = Topology::new();
+    /// topology.add_atom(&Atom::new("O"));
+    /// let mut ensemble = Ensemble::new(topology);
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.resize(1);
+    /// ensemble.push(frame).unwrap();
+    /// assert_eq!(ensemble.len(), 1);
+    ///
+    /// assert!(ensemble.push(Frame::new()).is_err());
+    /// ```
+    pub fn push(&mut self, frame: Frame) -> Result<(), Error> {
+        if frame.size() != self.topology.size() {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!(
+                    "can not add a frame with {} atoms to an ensemble with {} atoms",
+                    frame.size(),
+                    self.topology.size()
+                ),
+            ));
+        }
+        self.frames.push(frame);
+        return Ok(());
+    }
+
+    /// Get the topology shared by all the frames in this ensemble.
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Get the frames in this ensemble.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Get the number of frames in this ensemble.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Check if this ensemble is empty.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Get the matrix of pairwise RMSD between all the frames in this
+    /// ensemble, after optimal superposition of each pair of frames. The
+    /// result is expressed in Angstroms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Ensemble, Frame, Topology};
+    /// let mut topology = Topology::new();
+    /// topology.add_atom(&Atom::new("C"));
+    /// topology.add_atom(&Atom::new("O"));
+    /// let mut ensemble = Ensemble::new(topology);
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("O"), [1.2, 0.0, 0.0], None);
+    /// ensemble.push(frame.clone()).unwrap();
+    ///
+    /// frame.positions_mut()[1] = [0.0, 1.4, 0.0];
+    /// ensemble.push(frame).unwrap();
+    ///
+    /// let rmsd = ensemble.rmsd_matrix();
+    /// assert_eq!(rmsd[0][0], 0.0);
+    /// assert!((rmsd[0][1] - 0.1).abs() < 1e-6);
+    /// ```
+    #[allow(clippy::needless_range_loop)]
+    pub fn rmsd_matrix(&self) -> Vec<Vec<f64>> {
+        let n = self.frames.len();
+        let mut matrix = vec![vec![0.0; n]; n];
+        for i in 0..n {
+            let reference = self.frames[i].positions();
+            for j in (i + 1)..n {
+                let mobile = self.frames[j].positions();
+                let rmsd = linalg::superpose(reference, mobile, None).rmsd;
+                matrix[i][j] = rmsd;
+                matrix[j][i] = rmsd;
+            }
+        }
+        return matrix;
+    }
+
+    /// Get the frame with the lowest value for the numeric property with the
+    /// given `name`, typically the energy of the conformer. Frames without
+    /// this property, or where this property is not a number are ignored.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Ensemble, Frame, Property, Topology};
+    /// let mut ensemble = Ensemble::new(Topology::new());
+    /// for energy in &[-12.0, -15.3, -8.2] {
+    ///     let mut frame = Frame::new();
+    ///     frame.set("energy", *energy);
+    ///     ensemble.push(frame).unwrap();
+    /// }
+    ///
+    /// let lowest = ensemble.lowest_energy("energy").unwrap();
+    /// assert_eq!(lowest.get("energy"), Some(Property::Double(-15.3)));
+    /// assert!(ensemble.lowest_energy("missing").is_none());
+    /// ```
+    pub fn lowest_energy(&self, name: &str) -> Option<&Frame> {
+        self.frames
+            .iter()
+            .filter_map(|frame| match frame.get(name) {
+                Some(Property::Double(value)) if !value.is_nan() => Some((value, frame)),
+                _ => None,
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, frame)| frame)
+    }
+
+    /// Align all the frames in this ensemble onto the first one, using the
+    /// rotation and translation minimizing the RMSD between them. Velocities
+    /// are rotated together with the positions.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Ensemble, Frame, Topology};
+    /// let mut topology = Topology::new();
+    /// topology.add_atom(&Atom::new("C"));
+    /// topology.add_atom(&Atom::new("O"));
+    /// let mut ensemble = Ensemble::new(topology);
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("O"), [1.2, 0.0, 0.0], None);
+    /// ensemble.push(frame.clone()).unwrap();
+    ///
+    /// frame.positions_mut()[0] = [2.0, 2.0, 2.0];
+    /// frame.positions_mut()[1] = [2.0, 3.2, 2.0];
+    /// ensemble.push(frame).unwrap();
+    ///
+    /// ensemble.align_all();
+    /// let positions = ensemble.frames()[1].positions();
+    /// assert!((positions[1][0] - 1.2).abs() < 1e-6);
+    /// ```
+    pub fn align_all(&mut self) {
+        if self.frames.is_empty() {
+            return;
+        }
+
+        let (first, others) = self.frames.split_at_mut(1);
+        let reference = first[0].positions();
+        for frame in others {
+            let superposition = linalg::superpose(reference, frame.positions(), None);
+            for position in frame.positions_mut() {
+                *position = superposition.apply(*position);
+            }
+            if let Some(velocities) = frame.velocities_mut() {
+                for velocity in velocities {
+                    *velocity = linalg::mat_vec(&superposition.rotation, *velocity);
+                }
+            }
+        }
+    }
+
+    /// Write all the frames in this ensemble to the file at `path`, using the
+    /// ensemble topology for all frames. The format is guessed from the file
+    /// extension.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the file can not be opened or written.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Ensemble;
+    /// let mut ensemble = Ensemble::read("conformers.sdf").unwrap();
+    /// ensemble.align_all();
+    /// ensemble.write("aligned.sdf").unwrap();
+    /// ```
+    pub fn write<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut trajectory = Trajectory::open(path, 'w')?;
+        trajectory.set_topology(&self.topology);
+        for frame in &self.frames {
+            trajectory.write(frame)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, MemoryTrajectoryReader};
+
+    use approx::assert_ulps_eq;
+
+    fn water(positions: &[[f64; 3]; 3], energy: f64) -> Frame {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("O"), positions[0], None);
+        frame.add_atom(&Atom::new("H"), positions[1], None);
+        frame.add_atom(&Atom::new("H"), positions[2], None);
+        frame.set("energy", energy);
+        frame
+    }
+
+    #[test]
+    fn ensemble() {
+        let first = water(&[[0.0, 0.0, 0.0], [0.96, 0.0, 0.0], [-0.24, 0.93, 0.0]], -3.0);
+        let mut ensemble = Ensemble::new(first.topology().clone());
+        ensemble.push(first).unwrap();
+        // the same molecule, rotated and translated
+        ensemble
+            .push(water(&[[5.0, 5.0, 5.0], [5.0, 5.96, 5.0], [4.07, 4.76, 5.0]], -4.0))
+            .unwrap();
+        ensemble
+            .push(water(&[[0.0, 0.0, 0.0], [1.2, 0.0, 0.0], [-0.24, 0.93, 0.0]], 2.0))
+            .unwrap();
+
+        let rmsd = ensemble.rmsd_matrix();
+        assert_eq!(rmsd.len(), 3);
+        assert_ulps_eq!(rmsd[0][1], 0.0, epsilon = 1e-6);
+        assert_ulps_eq!(rmsd[1][0], rmsd[0][1]);
+        assert!(rmsd[0][2] > 0.05);
+
+        let lowest = ensemble.lowest_energy("energy").unwrap();
+        assert_eq!(lowest.get("energy"), Some(Property::Double(-4.0)));
+
+        ensemble.align_all();
+        let positions = ensemble.frames()[1].positions();
+        crate::assert_vector3d_eq(&positions[0], &[0.0, 0.0, 0.0], 1e-6);
+        crate::assert_vector3d_eq(&positions[1], &[0.96, 0.0, 0.0], 1e-6);
+
+        let mut wrong = Frame::new();
+        wrong.resize(4);
+        let error = ensemble.push(wrong).unwrap_err();
+        assert_eq!(error.status, Status::ChemfilesError);
+    }
+
+    #[test]
+    fn write() {
+        let frame = water(&[[0.0, 0.0, 0.0], [0.96, 0.0, 0.0], [-0.24, 0.93, 0.0]], -3.0);
+        let mut ensemble = Ensemble::new(frame.topology().clone());
+        ensemble.push(frame.clone()).unwrap();
+        ensemble.push(frame).unwrap();
+
+        let filename = "test-ensemble.xyz";
+        ensemble.write(filename).unwrap();
+        let content = std::fs::read_to_string(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+
+        let mut trajectory = MemoryTrajectoryReader::new(content.as_bytes(), "XYZ").unwrap();
+        assert_eq!(trajectory.nsteps(), 2);
+
+        let ensemble = Ensemble::read("src/../data/water.xyz").unwrap();
+        assert_eq!(ensemble.len(), 100);
+        assert_eq!(ensemble.topology().size(), 297);
+    }
+}
@@ -1,20 +1,98 @@
 // Chemfiles, a modern library for chemistry file reading and writing
 // Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::ffi::CStr;
 use std::os::raw::c_char;
-use std::panic::{self, RefUnwindSafe};
-use std::path::Path;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Once, PoisonError};
 
 use chemfiles_sys as ffi;
 
 use crate::strings;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
 /// Error type for Chemfiles.
+///
+/// This struct is marked `#[non_exhaustive]`, use `Error::new` to create
+/// errors outside of this crate. Errors are compared by `status` and
+/// `message` only, ignoring their context.
+#[non_exhaustive]
+#[derive(Clone, Debug)]
 pub struct Error {
     /// The error status code
     pub status: Status,
     /// A message describing the error cause
     pub message: String,
+    /// Where the error happened, see `Error::context`
+    context: Option<ErrorContext>,
+}
+
+impl PartialEq for Error {
+    fn eq(&self, other: &Error) -> bool {
+        self.status == other.status && self.message == other.message
+    }
+}
+
+impl Eq for Error {}
+
+/// Operation on a `Trajectory` which created an error, see `ErrorContext`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TrajectoryOperation {
+    /// Opening the trajectory
+    Open,
+    /// Reading a frame from the trajectory
+    Read,
+    /// Writing a frame to the trajectory
+    Write,
+}
+
+/// Additional information about an error created by a `Trajectory`
+/// operation, to identify the corresponding file and frame.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Frame, TrajectoryOperation, Trajectory};
+/// let data = "1\n\nO 0 0 xxx\n";
+/// let mut trajectory = Trajectory::memory_reader(data, "XYZ").unwrap();
+///
+/// let error = trajectory.read(&mut Frame::new()).unwrap_err();
+/// let context = error.context().unwrap();
+/// assert_eq!(context.operation, TrajectoryOperation::Read);
+/// assert_eq!(context.step, Some(0));
+/// // this trajectory is in memory
+/// assert_eq!(context.path, None);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// The operation which failed
+    pub operation: TrajectoryOperation,
+    /// Path of the trajectory file, or `None` for in-memory trajectories
+    pub path: Option<PathBuf>,
+    /// Step of the frame being read or written, if any
+    pub step: Option<usize>,
+}
+
+impl std::fmt::Display for ErrorContext {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        let operation = match self.operation {
+            TrajectoryOperation::Open => "opening",
+            TrajectoryOperation::Read => "reading",
+            TrajectoryOperation::Write => "writing",
+        };
+        write!(fmt, "while {operation}")?;
+
+        if let Some(step) = self.step {
+            write!(fmt, " step {step}")?;
+        }
+
+        match (&self.path, self.operation) {
+            (Some(path), TrajectoryOperation::Read) if self.step.is_some() => write!(fmt, " of '{}'", path.display()),
+            (Some(path), TrajectoryOperation::Write) if self.step.is_some() => write!(fmt, " to '{}'", path.display()),
+            (Some(path), _) => write!(fmt, " '{}'", path.display()),
+            (None, _) => write!(fmt, " in memory"),
+        }
+    }
 }
 
 #[repr(C)]
@@ -45,6 +123,8 @@ pub enum Status {
     /// The given path is not valid UTF8
     // TODO: rename this to UTF8Error in the next breaking release
     UTF8PathError,
+    /// The operation was cancelled using a `CancelToken`
+    Cancelled,
 }
 
 impl From<ffi::chfl_status> for Error {
@@ -63,7 +143,11 @@ impl From<ffi::chfl_status> for Error {
         };
 
         let message = Error::last_error();
-        Error { status, message }
+        Error {
+            status,
+            message,
+            context: None,
+        }
     }
 }
 
@@ -72,22 +156,73 @@ impl From<std::str::Utf8Error> for Error {
         Error {
             status: Status::UTF8PathError,
             message: "failed to convert data to UTF8 string".into(),
+            context: None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error {
+            status: Status::FileError,
+            message: error.to_string(),
+            context: None,
         }
     }
 }
 
 impl Error {
+    /// Create a new error with the given `status` and `message`, and without
+    /// context.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Error, Status};
+    /// let error = Error::new(Status::FormatError, "missing atom count");
+    /// assert_eq!(error.status, Status::FormatError);
+    /// assert!(error.context().is_none());
+    /// ```
+    pub fn new(status: Status, message: impl Into<String>) -> Error {
+        Error {
+            status,
+            message: message.into(),
+            context: None,
+        }
+    }
+
     /// Create a new error because the given `path` is invalid UTF-8 data
+    #[cfg_attr(unix, allow(dead_code))]
     pub(crate) fn utf8_path_error(path: &Path) -> Error {
         Error {
             status: Status::UTF8PathError,
             message: format!("Could not convert '{}' to UTF8", path.display()),
+            context: None,
         }
     }
 
+    /// Attach the given `context` to this error
+    pub(crate) fn with_context(mut self, context: ErrorContext) -> Error {
+        self.context = Some(context);
+        self
+    }
+
+    /// Get the file and frame where this error happened, for errors coming
+    /// from `Trajectory` operations.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Trajectory, TrajectoryOperation};
+    /// let error = Trajectory::open("not-a-file.xyz", 'r').unwrap_err();
+    /// let context = error.context().unwrap();
+    /// assert_eq!(context.operation, TrajectoryOperation::Open);
+    /// ```
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_ref()
+    }
+
     /// Get the last error message from the C++ library.
     pub fn last_error() -> String {
-        unsafe { strings::from_c(ffi::chfl_last_error()) }
+        unsafe { strings::from_c_lossy(ffi::chfl_last_error()) }
     }
 
     /// Clear any error from the C++ library
@@ -121,48 +256,262 @@ pub(crate) fn check_not_null<T>(ptr: *const T) {
     assert!(!ptr.is_null(), "unexpected null pointer: {}", Error::last_error());
 }
 
-pub trait WarningCallback: RefUnwindSafe + Fn(&str) {}
-impl<T> WarningCallback for T where T: RefUnwindSafe + Fn(&str) {}
+/// Check the pointer returned by a C function allocating a new object,
+/// getting the error if the allocation failed.
+pub(crate) fn check_allocation<T>(ptr: *mut T) -> Result<*mut T, Error> {
+    if ptr.is_null() {
+        return Err(Error::new(Status::MemoryError, Error::last_error()));
+    }
+    return Ok(ptr);
+}
+
+pub trait WarningCallback: Send + FnMut(&str) {}
+impl<T> WarningCallback for T where T: Send + FnMut(&str) {}
+
+pub(crate) type SharedCallback = Arc<Mutex<Box<dyn WarningCallback<Output = ()>>>>;
 
-static mut LOGGING_CALLBACK: Option<*mut dyn WarningCallback<Output = ()>> = None;
+/// The user-provided warning callback, if any. The callback is stored behind
+/// an `Arc` so it can be called without holding the global lock, allowing
+/// the callback itself to set a new callback.
+static LOGGING_CALLBACK: Mutex<Option<SharedCallback>> = Mutex::new(None);
+static INSTALL_CALLBACK: Once = Once::new();
+
+thread_local! {
+    /// Warnings captured by `capture_warnings` on the current thread
+    static CAPTURED_WARNINGS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+    /// Are we currently running the user callback on this thread?
+    static IN_CALLBACK: Cell<bool> = const { Cell::new(false) };
+}
 
 extern "C" fn warning_callback(message: *const c_char) {
-    unsafe {
-        let callback = &*LOGGING_CALLBACK.expect("No callback provided, this is an internal bug");
-        // ignore result. If a panic happened, everything is going badly anyway
-        let _result = panic::catch_unwind(|| {
-            callback(&strings::from_c(message));
+    // unwinding through the C++ library is undefined behavior, so any panic
+    // must be caught before returning to C++
+    abort_on_panic("chemfiles warning callback", || {
+        let message = unsafe { CStr::from_ptr(message).to_string_lossy().into_owned() };
+        send_warning(&message);
+    });
+}
+
+/// Send a warning `message` to the capture and the user callback
+pub(crate) fn send_warning(message: &str) {
+    CAPTURED_WARNINGS.with(|captured| {
+        if let Some(ref mut captured) = *captured.borrow_mut() {
+            captured.push(message.to_owned());
+        }
+    });
+
+    let callback = LOGGING_CALLBACK.lock().unwrap_or_else(PoisonError::into_inner).clone();
+
+    // warnings emitted while running the callback (for example if the
+    // callback uses chemfiles) can not be sent to the callback again
+    let reentrant = IN_CALLBACK.with(Cell::get);
+    match callback {
+        Some(callback) if !reentrant => {
+            IN_CALLBACK.with(|flag| flag.set(true));
+            let mut callback = callback.lock().unwrap_or_else(PoisonError::into_inner);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                (callback)(message);
+            }));
+            IN_CALLBACK.with(|flag| flag.set(false));
+
+            if let Err(payload) = result {
+                // the warning is not lost, and the panic message is reported
+                // instead of unwinding into C++
+                report_error(&format!(
+                    "the warning callback panicked ({}) while handling: {message}",
+                    panic_message(&*payload)
+                ));
+            }
+        }
+        _ => default_warning(message),
+    }
+}
+
+/// Report a warning `message` when no callback is set, either with the `log`
+/// crate or on the standard error
+fn default_warning(message: &str) {
+    #[cfg(feature = "log")]
+    log::warn!(target: "chemfiles", "{message}");
+
+    // same behavior as the default callback in the C++ library
+    #[cfg(not(feature = "log"))]
+    eprintln!("[chemfiles] {message}");
+}
+
+/// Report an error `message` which can not be returned to the user, either
+/// with the `log` crate or on the standard error
+fn report_error(message: &str) {
+    #[cfg(feature = "log")]
+    log::error!(target: "chemfiles", "{message}");
+
+    #[cfg(not(feature = "log"))]
+    eprintln!("[chemfiles] {message}");
+}
+
+/// Get the message associated with a panic `payload`
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return (*message).to_owned();
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+    return "unknown panic payload".into();
+}
+
+/// Run `function` in a callback called by the C++ library. Unwinding across
+/// the FFI boundary is undefined behavior, so if `function` panics, this
+/// prints the panic message and aborts the process instead of unwinding.
+pub(crate) fn abort_on_panic<T>(context: &str, function: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(function)) {
+        Ok(value) => value,
+        Err(payload) => {
+            eprintln!(
+                "[chemfiles] unexpected panic in {context}: {}. Aborting since unwinding into C++ is not possible",
+                panic_message(&*payload)
+            );
+            std::process::abort();
+        }
+    }
+}
+
+/// Make sure the C library sends all warnings to the Rust side
+fn install_warning_callback() {
+    INSTALL_CALLBACK.call_once(|| unsafe {
+        check_success(ffi::chfl_set_warning_callback(warning_callback));
+    });
+}
+
+/// With the `log` feature, make sure the warnings from the C library are sent
+/// to the `log` crate, even if `set_warning_callback` was never called. This
+/// does nothing without the `log` feature.
+///
+/// This is called at the start of every function which can be the first call
+/// to the C library (constructors, `add_configuration`, `guess_format`, ...),
+/// before calling into C, so that errors from this first call are logged too.
+#[inline]
+pub(crate) fn install_log_bridge() {
+    #[cfg(feature = "log")]
+    install_warning_callback();
+}
+
+/// Restore the previous state of warnings capture when dropped
+struct CaptureGuard {
+    previous: Option<Vec<String>>,
+}
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CAPTURED_WARNINGS.with(|captured| {
+            let mut captured = captured.borrow_mut();
+            let current = std::mem::replace(&mut *captured, previous);
+            // forward warnings to the outer capture, if any
+            if let (Some(outer), Some(current)) = (captured.as_mut(), current) {
+                outer.extend(current);
+            }
         });
     }
 }
 
+/// Call `function` and collect all the warnings emitted by chemfiles on the
+/// current thread while it runs. The warnings are still sent to the user
+/// callback as usual.
+pub(crate) fn capture_warnings<T>(function: impl FnOnce() -> T) -> (T, Vec<String>) {
+    install_warning_callback();
+    let previous = CAPTURED_WARNINGS.with(|captured| captured.borrow_mut().replace(Vec::new()));
+    let guard = CaptureGuard { previous };
+
+    let result = function();
+    let warnings = CAPTURED_WARNINGS
+        .with(|captured| captured.borrow_mut().take())
+        .unwrap_or_default();
+    std::mem::drop(guard);
+
+    // forward warnings to the outer capture, if any
+    CAPTURED_WARNINGS.with(|captured| {
+        if let Some(ref mut outer) = *captured.borrow_mut() {
+            outer.extend(warnings.iter().cloned());
+        }
+    });
+
+    return (result, warnings);
+}
+
 /// Use `callback` for every chemfiles warning. The callback will be passed
 /// the warning message. This will drop any previous warning callback.
+///
+/// The callback can be called from any thread using chemfiles, but never
+/// concurrently. Warnings emitted by chemfiles while the callback is running
+/// on the same thread are printed to the standard error instead.
+///
+/// The callback is called from inside the C++ library, where unwinding is
+/// not possible. If the callback panics, the panic is caught and its message
+/// is printed to the standard error together with the warning, and the
+/// callback will still be used for the next warnings.
+///
+/// Without a callback, warnings are printed to the standard error. With the
+/// `log` feature, they are sent to the `log` crate instead, using
+/// `log::warn!` with the `"chemfiles"` target; and panics of the callback
+/// are reported with `log::error!`.
+///
+/// # Example
+/// ```
+/// # use std::sync::{Arc, Mutex};
+/// let warnings = Arc::new(Mutex::new(Vec::new()));
+/// let collected = Arc::clone(&warnings);
+/// chemfiles::set_warning_callback(move |message| {
+///     collected.lock().unwrap().push(message.to_owned());
+/// });
+///
+/// // ... use chemfiles, warnings end up in `warnings`
+///
+/// chemfiles::reset_warning_callback();
+/// ```
 pub fn set_warning_callback<F>(callback: F)
 where
     F: WarningCallback + 'static,
 {
-    // box callback to ensure it stays accessible
-    let callback = Box::into_raw(Box::new(callback));
-    unsafe {
-        if let Some(previous) = LOGGING_CALLBACK {
-            // drop the previous callback
-            let previous = Box::from_raw(previous);
-            std::mem::drop(previous);
-            // set the LOGGING_CALLBACK to the new one
-            LOGGING_CALLBACK = Some(callback);
-        } else {
-            // set the LOGGING_CALLBACK
-            LOGGING_CALLBACK = Some(callback);
-            // Tell C code to use Rust-provided callback
-            check_success(ffi::chfl_set_warning_callback(warning_callback));
-        }
-    }
+    let callback: SharedCallback = Arc::new(Mutex::new(Box::new(callback)));
+    let previous = LOGGING_CALLBACK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .replace(callback);
+    // drop the previous callback outside of the lock
+    std::mem::drop(previous);
+
+    // Tell C code to use Rust-provided callback
+    install_warning_callback();
+}
+
+/// Replace the current warning callback with `callback`, returning the
+/// previous one. `None` corresponds to the default behavior of printing
+/// warnings to the standard error.
+pub(crate) fn replace_warning_callback(callback: Option<SharedCallback>) -> Option<SharedCallback> {
+    install_warning_callback();
+    let mut current = LOGGING_CALLBACK.lock().unwrap_or_else(PoisonError::into_inner);
+    return std::mem::replace(&mut *current, callback);
+}
+
+/// Remove any callback set with `set_warning_callback`, and go back to the
+/// default behavior of printing warnings to the standard error.
+///
+/// # Example
+/// ```
+/// chemfiles::set_warning_callback(|message| println!("{}", message));
+/// chemfiles::reset_warning_callback();
+/// ```
+pub fn reset_warning_callback() {
+    let previous = LOGGING_CALLBACK.lock().unwrap_or_else(PoisonError::into_inner).take();
+    std::mem::drop(previous);
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        write!(fmt, "{}", self.message)
+        write!(fmt, "{}", self.message)?;
+        if let Some(context) = &self.context {
+            write!(fmt, " ({context})")?;
+        }
+        Ok(())
     }
 }
 
@@ -180,6 +529,7 @@ impl std::error::Error for Error {
             Status::ConfigurationError => "Error in configuration files",
             Status::OutOfBounds => "Out of bounds indexing",
             Status::PropertyError => "Error in property",
+            Status::Cancelled => "The operation was cancelled",
         }
     }
 }
@@ -202,6 +552,159 @@ mod test {
         assert_eq!(Error::last_error(), "");
     }
 
+    #[test]
+    fn context() {
+        let error = Error::new(Status::FormatError, "invalid data");
+        assert_eq!(error.to_string(), "invalid data");
+
+        let error = error.with_context(ErrorContext {
+            operation: TrajectoryOperation::Read,
+            path: Some("water.xyz".into()),
+            step: Some(3),
+        });
+        assert_eq!(error.to_string(), "invalid data (while reading step 3 of 'water.xyz')");
+        // the context is not used when comparing errors
+        assert_eq!(error, Error::new(Status::FormatError, "invalid data"));
+
+        let context = ErrorContext {
+            operation: TrajectoryOperation::Open,
+            path: Some("water.xyz".into()),
+            step: None,
+        };
+        assert_eq!(context.to_string(), "while opening 'water.xyz'");
+
+        let context = ErrorContext {
+            operation: TrajectoryOperation::Write,
+            path: Some("water.xyz".into()),
+            step: Some(0),
+        };
+        assert_eq!(context.to_string(), "while writing step 0 to 'water.xyz'");
+
+        let context = ErrorContext {
+            operation: TrajectoryOperation::Write,
+            path: None,
+            step: Some(0),
+        };
+        assert_eq!(context.to_string(), "while writing step 0 in memory");
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn log_bridge() {
+        struct Logger;
+        static LOGGED: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
+        impl log::Log for Logger {
+            fn enabled(&self, metadata: &log::Metadata) -> bool {
+                metadata.target() == "chemfiles"
+            }
+
+            fn log(&self, record: &log::Record) {
+                if self.enabled(record.metadata()) {
+                    LOGGED.lock().unwrap().push((record.level(), record.args().to_string()));
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: Logger = Logger;
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Warn);
+
+        crate::Options::scoped()
+            .apply(|| {
+                // errors from the C library are sent to the log, even before
+                // any other function of this crate was called
+                assert!(crate::Trajectory::open("not-a-file.xyz", 'r').is_err());
+
+                reset_warning_callback();
+                send_warning("first warning");
+
+                set_warning_callback(|_| panic!("oops"));
+                send_warning("second warning");
+                reset_warning_callback();
+            })
+            .unwrap();
+
+        let logged = LOGGED.lock().unwrap();
+        assert!(logged
+            .iter()
+            .any(|(level, message)| *level == log::Level::Warn && message.contains("not-a-file.xyz")));
+        assert!(logged.contains(&(log::Level::Warn, "first warning".into())));
+        let error = "the warning callback panicked (oops) while handling: second warning";
+        assert!(logged.contains(&(log::Level::Error, error.into())));
+    }
+
+    #[test]
+    fn warning_callback() {
+        // prevent other tests from changing the callback concurrently
+        crate::Options::scoped()
+            .apply(|| {
+                let warnings = Arc::new(Mutex::new(Vec::new()));
+                let collected = Arc::clone(&warnings);
+                let mut count = 0;
+                set_warning_callback(move |message: &str| {
+                    count += 1;
+                    collected.lock().unwrap().push(format!("{count}: {message}"));
+                });
+
+                let data = "FOOBAR this is not a PDB record\nEND\n";
+                let mut trajectory = crate::Trajectory::memory_reader(data, "PDB").unwrap();
+                let mut frame = crate::Frame::new();
+                let _ = trajectory.read(&mut frame);
+
+                reset_warning_callback();
+                let _ = trajectory.read_step(0, &mut frame);
+
+                let warnings = warnings.lock().unwrap();
+                assert!(!warnings.is_empty());
+                assert!(warnings[0].starts_with("1: "));
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn panicking_callback() {
+        crate::Options::scoped()
+            .apply(|| {
+                let count = Arc::new(Mutex::new(0));
+                let counter = Arc::clone(&count);
+                set_warning_callback(move |_: &str| {
+                    *counter.lock().unwrap() += 1;
+                    panic!("this callback always panics");
+                });
+
+                // the panic does not unwind through C++, and reading continues
+                let data = "FOOBAR this is not a PDB record\nEND\n";
+                let mut trajectory = crate::Trajectory::memory_reader(data, "PDB").unwrap();
+                let mut frame = crate::Frame::new();
+                trajectory.read(&mut frame).unwrap();
+                let first = *count.lock().unwrap();
+                assert!(first > 0);
+
+                // the callback is still used after panicking
+                let _ = trajectory.read_step(0, &mut frame);
+                assert!(*count.lock().unwrap() > first);
+                reset_warning_callback();
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn panic_messages() {
+        let payload = panic::catch_unwind(|| panic!("static message")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "static message");
+
+        let value = 42;
+        let payload = panic::catch_unwind(|| panic!("formatted {value}")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "formatted 42");
+
+        let payload = panic::catch_unwind(|| panic::panic_any(3)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "unknown panic payload");
+
+        assert_eq!(abort_on_panic("test", || 3), 3);
+    }
+
     #[test]
     fn codes() {
         assert_eq!(Error::from(ffi::chfl_status::CHFL_SUCCESS).status, Status::Success);
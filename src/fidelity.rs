@@ -0,0 +1,296 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::errors::{Error, Status};
+use crate::{Frame, Trajectory};
+
+/// Data lost when writing a frame with a given format and reading it back,
+/// created by `chemfiles::roundtrip_check`.
+///
+/// Boolean fields are `true` when the corresponding data was preserved, or
+/// when there was nothing to preserve (for example `velocities` for a frame
+/// without velocities).
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FidelityReport {
+    /// Name of the format used for the round-trip
+    pub format: String,
+    /// Are the atomic names and types preserved?
+    pub atoms: bool,
+    /// Are the atomic masses preserved?
+    pub masses: bool,
+    /// Are the atomic charges preserved?
+    pub charges: bool,
+    /// Is the unit cell (shape, lengths and angles) preserved?
+    pub cell: bool,
+    /// Are the velocities preserved?
+    pub velocities: bool,
+    /// Are the bonds preserved?
+    pub bonds: bool,
+    /// Are the bond orders preserved? This is always `false` if the bonds
+    /// themselves are not preserved.
+    pub bond_orders: bool,
+    /// Are the residues (name, id and atoms) preserved?
+    pub residues: bool,
+    /// Names of the frame properties missing after the round-trip
+    pub lost_properties: Vec<String>,
+    /// Names of the atomic properties missing on at least one atom after the
+    /// round-trip
+    pub lost_atom_properties: Vec<String>,
+    /// Largest difference on the coordinates of the positions, in Angstroms
+    pub position_error: f64,
+    /// Largest difference on the coordinates of the velocities, if both the
+    /// initial and re-read frames have velocities
+    pub velocity_error: Option<f64>,
+}
+
+impl FidelityReport {
+    /// Check if all the data was preserved by the round-trip, allowing a
+    /// difference of `tolerance` on positions and velocities coming from
+    /// the limited precision of the format.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+    ///
+    /// let report = chemfiles::roundtrip_check(&frame, "XYZ").unwrap();
+    /// assert!(report.is_lossless(1e-5));
+    /// ```
+    pub fn is_lossless(&self, tolerance: f64) -> bool {
+        return self.atoms
+            && self.masses
+            && self.charges
+            && self.cell
+            && self.velocities
+            && self.bonds
+            && self.bond_orders
+            && self.residues
+            && self.lost_properties.is_empty()
+            && self.lost_atom_properties.is_empty()
+            && self.position_error <= tolerance
+            && self.velocity_error.unwrap_or(0.0) <= tolerance;
+    }
+}
+
+/// Counter used to create unique names for the temporary files
+static ROUNDTRIP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Write `frame` with the given `format` to a temporary file, read it back and
+/// report which data was lost in the process. This allows to check which
+/// output format can store all the data of a given system, and the precision
+/// of the stored coordinates.
+///
+/// The temporary file is created in `std::env::temp_dir()`, and removed
+/// before this function returns.
+///
+/// # Errors
+///
+/// This function fails if the format can not be used to write and then read
+/// the frame, or if the re-read frame does not contain the same number of
+/// atoms as `frame`.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, Frame};
+/// let mut frame = Frame::new();
+/// frame.add_velocities();
+/// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+/// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+/// frame.add_bond(0, 1);
+///
+/// // XYZ files do not store bonds
+/// let report = chemfiles::roundtrip_check(&frame, "XYZ").unwrap();
+/// assert!(report.atoms);
+/// assert!(!report.bonds);
+/// ```
+pub fn roundtrip_check(frame: &Frame, format: &str) -> Result<FidelityReport, Error> {
+    let path = std::env::temp_dir().join(format!(
+        "chemfiles-roundtrip-{}-{}",
+        std::process::id(),
+        ROUNDTRIP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = roundtrip(frame, format, &path);
+    if path.exists() {
+        // ignore errors, the file is in the temporary directory anyway
+        let _ = std::fs::remove_file(&path);
+    }
+    let reread = result?;
+
+    if reread.size() != frame.size() {
+        return Err(Error::new(
+            Status::FormatError,
+            format!(
+                "the frame read with the {format} format contains {} atoms instead of {}",
+                reread.size(),
+                frame.size()
+            ),
+        ));
+    }
+
+    return Ok(compare(frame, &reread, format));
+}
+
+/// Write `frame` to `path` with the given `format`, and read it back
+fn roundtrip(frame: &Frame, format: &str, path: &Path) -> Result<Frame, Error> {
+    let mut trajectory = Trajectory::open_with_format(path, 'w', format)?;
+    trajectory.write(frame)?;
+    trajectory.close()?;
+
+    let mut trajectory = Trajectory::open_with_format(path, 'r', format)?;
+    let mut reread = Frame::new();
+    trajectory.read(&mut reread)?;
+    return Ok(reread);
+}
+
+/// Compare the `initial` frame with the `reread` one, which must contain the
+/// same number of atoms
+#[allow(clippy::float_cmp)]
+fn compare(initial: &Frame, reread: &Frame, format: &str) -> FidelityReport {
+    let initial_atoms = initial.iter_atoms().collect::<Vec<_>>();
+    let reread_atoms = reread.iter_atoms().collect::<Vec<_>>();
+
+    let atoms = initial.names() == reread.names() && initial.types() == reread.types();
+    let masses = initial.masses() == reread.masses();
+    let charges = initial.charges() == reread.charges();
+
+    let (cell, reread_cell) = (initial.cell(), reread.cell());
+    let close = |a: [f64; 3], b: [f64; 3]| a.iter().zip(&b).all(|(a, b)| (a - b).abs() <= 1e-3 * a.abs().max(1.0));
+    let cell = cell.shape() == reread_cell.shape()
+        && close(cell.lengths(), reread_cell.lengths())
+        && close(cell.angles(), reread_cell.angles());
+
+    let position_error = max_difference(initial.positions(), reread.positions());
+    let (velocities, velocity_error) = match (initial.velocities(), reread.velocities()) {
+        (None, _) => (true, None),
+        (Some(_), None) => (false, None),
+        (Some(initial), Some(reread)) => (true, Some(max_difference(initial, reread))),
+    };
+
+    let (topology, reread_topology) = (initial.topology(), reread.topology());
+    let bonds = topology.bonds() == reread_topology.bonds();
+    let bond_orders = bonds && topology.bond_orders() == reread_topology.bond_orders();
+
+    let residues = topology.residues_count() == reread_topology.residues_count()
+        && topology
+            .iter_residues()
+            .zip(reread_topology.iter_residues())
+            .all(|(a, b)| a.name() == b.name() && a.id() == b.id() && a.atoms() == b.atoms());
+
+    let reread_properties = reread.list_properties();
+    let lost_properties = initial
+        .list_properties()
+        .into_iter()
+        .filter(|name| !reread_properties.contains(name))
+        .collect::<BTreeSet<_>>();
+
+    let mut lost_atom_properties = BTreeSet::new();
+    for (atom, reread_atom) in initial_atoms.iter().zip(&reread_atoms) {
+        let reread_properties = reread_atom.list_properties();
+        for name in atom.list_properties() {
+            if !reread_properties.contains(&name) {
+                let _ = lost_atom_properties.insert(name);
+            }
+        }
+    }
+
+    return FidelityReport {
+        format: format.into(),
+        atoms,
+        masses,
+        charges,
+        cell,
+        velocities,
+        bonds,
+        bond_orders,
+        residues,
+        lost_properties: lost_properties.into_iter().collect(),
+        lost_atom_properties: lost_atom_properties.into_iter().collect(),
+        position_error,
+        velocity_error,
+    };
+}
+
+/// Get the largest difference between the coordinates of `a` and `b`
+fn max_difference(a: &[[f64; 3]], b: &[[f64; 3]]) -> f64 {
+    let mut error = 0.0;
+    for (a, b) in a.iter().zip(b) {
+        for d in 0..3 {
+            error = f64::max(error, (a[d] - b[d]).abs());
+        }
+    }
+    return error;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Atom, BondOrder, Residue, UnitCell};
+
+    fn frame() -> Frame {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 11.0, 12.0]));
+        frame.set("name", "water");
+        frame.add_velocities();
+        frame.add_atom(&Atom::new("O"), [1.0, 2.0, 3.0], [0.1, 0.2, 0.3]);
+        frame.add_atom(&Atom::new("H"), [1.957_123_4, 2.0, 3.0], [0.0, 0.0, 0.0]);
+        frame.add_atom(&Atom::new("H"), [1.0, 2.957_123_4, 3.0], [0.0, 0.0, 0.0]);
+        frame.add_bond_with_order(0, 1, BondOrder::Single);
+        frame.add_bond_with_order(0, 2, BondOrder::Single);
+        frame.atom_mut(0).set("is_virtual", false);
+
+        let mut residue = Residue::with_id("HOH", 1);
+        for i in 0..3 {
+            residue.add_atom(i);
+        }
+        frame.add_residue(&residue).unwrap();
+        return frame;
+    }
+
+    #[test]
+    fn xyz() {
+        let report = roundtrip_check(&frame(), "XYZ").unwrap();
+        assert_eq!(report.format, "XYZ");
+        assert!(report.atoms);
+        assert!(!report.bonds);
+        assert!(!report.bond_orders);
+        assert!(!report.residues);
+        assert!(report.position_error < 1e-5);
+        assert!(!report.is_lossless(1e-3));
+    }
+
+    #[test]
+    fn pdb() {
+        let report = roundtrip_check(&frame(), "PDB").unwrap();
+        assert!(report.cell);
+        assert!(report.bonds);
+        assert!(report.residues);
+        // PDB stores coordinates with 3 decimal places
+        assert!(report.position_error > 1e-5);
+        assert!(report.position_error < 1e-3);
+        assert_eq!(report.lost_atom_properties, ["is_virtual"]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(roundtrip_check(&frame(), "not a format").is_err());
+    }
+
+    #[test]
+    fn lossless() {
+        let mut report = roundtrip_check(&Frame::new(), "XYZ").unwrap();
+        assert!(report.is_lossless(0.0));
+
+        report.position_error = 1e-4;
+        assert!(report.is_lossless(1e-3));
+        assert!(!report.is_lossless(1e-5));
+
+        report.lost_properties.push("name".into());
+        assert!(!report.is_lossless(1e-3));
+    }
+}
@@ -1,13 +1,18 @@
 // Chemfiles, a modern library for chemistry file reading and writing
 // Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
 #[allow(clippy::wildcard_imports)]
 use chemfiles_sys as ffi;
 
-use crate::{Atom, AtomMut, AtomRef};
-use crate::{BondOrder, Residue, Topology, TopologyRef};
-use crate::{UnitCell, UnitCellMut, UnitCellRef};
+use crate::{Atom, AtomMut, AtomRef, Element};
+use crate::{BondOrder, Residue, Selection, SpaceGroup, Topology, TopologyRef};
+use crate::{CellShape, UnitCell, UnitCellMut, UnitCellRef};
 
-use crate::errors::{check, check_not_null, check_success, Error};
+use crate::errors::{check, check_allocation, check_not_null, check_success, install_log_bridge, Error, Status};
+use crate::linalg;
 use crate::property::{PropertiesIter, Property, RawProperty};
 use crate::strings;
 
@@ -22,10 +27,7 @@ pub struct Frame {
 
 impl Clone for Frame {
     fn clone(&self) -> Frame {
-        unsafe {
-            let new_handle = ffi::chfl_frame_copy(self.as_ptr());
-            Frame::from_ptr(new_handle)
-        }
+        self.try_clone().expect("failed to copy frame")
     }
 }
 
@@ -70,6 +72,29 @@ impl Frame {
         self.handle
     }
 
+    /// Create a copy of this frame, returning an error instead of panicking
+    /// if the memory allocation fails. `Clone::clone` panics in this case.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the C++ library can not allocate memory for
+    /// the copy.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Frame;
+    /// let mut frame = Frame::new();
+    /// frame.resize(42);
+    /// let copy = frame.try_clone().unwrap();
+    /// assert_eq!(copy.size(), 42);
+    /// ```
+    pub fn try_clone(&self) -> Result<Frame, Error> {
+        unsafe {
+            let new_handle = check_allocation(ffi::chfl_frame_copy(self.as_ptr()))?;
+            return Ok(Frame::from_ptr(new_handle));
+        }
+    }
+
     /// Create an empty frame. It will be resized by the library as needed.
     ///
     /// # Example
@@ -80,6 +105,7 @@ impl Frame {
     /// assert_eq!(frame.size(), 0);
     /// ```
     pub fn new() -> Frame {
+        install_log_bridge();
         unsafe { Frame::from_ptr(ffi::chfl_frame()) }
     }
 
@@ -216,6 +242,48 @@ impl Frame {
         }
     }
 
+    /// Remove all the atoms matched by `selection` in this frame, and return
+    /// the number of removed atoms. For selections with more than one atom
+    /// (pairs, angles, ...), all the atoms in all matches are removed.
+    ///
+    /// This is done in a single pass, renumbering the remaining atoms in
+    /// bonds and residues, and keeping positions, velocities, cell and
+    /// properties. This is much faster than removing atoms one by one with
+    /// `Frame::remove`, and does not require to care about indexes changing
+    /// after each removal.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, Selection};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+    /// frame.add_bond(0, 2);
+    ///
+    /// let mut selection = Selection::new("name H and index 1").unwrap();
+    /// assert_eq!(frame.remove_matching(&mut selection), 1);
+    ///
+    /// assert_eq!(frame.size(), 2);
+    /// assert_eq!(frame.positions()[1], [0.0, 1.0, 0.0]);
+    /// assert_eq!(frame.topology().bonds(), [[0, 1]]);
+    /// ```
+    pub fn remove_matching(&mut self, selection: &mut Selection) -> usize {
+        let mut removed = vec![false; self.size()];
+        for matched in selection.evaluate(self) {
+            for &atom in &matched {
+                removed[atom] = true;
+            }
+        }
+
+        let kept = (0..self.size()).filter(|&i| !removed[i]).collect::<Vec<_>>();
+        let count = self.size() - kept.len();
+        if count != 0 {
+            *self = self.subset(&kept);
+        }
+        return count;
+    }
+
     /// Add a bond between the atoms at indexes `i` and `j` in the frame.
     ///
     /// The bond order is set to `BondOrder::Unknown`.
@@ -438,6 +506,9 @@ impl Frame {
 
     /// Get a view into the positions of this frame.
     ///
+    /// The returned slice directly borrows the memory used by chemfiles, no
+    /// data is copied.
+    ///
     /// # Example
     /// ```
     /// # use chemfiles::Frame;
@@ -461,6 +532,11 @@ impl Frame {
 
         #[allow(clippy::cast_possible_truncation)]
         let size = natoms as usize;
+        if size == 0 || ptr.is_null() {
+            // the pointer can be null for empty frames, which is not allowed
+            // in `slice::from_raw_parts`
+            return &[];
+        }
         unsafe {
             return std::slice::from_raw_parts(ptr, size);
         }
@@ -468,6 +544,9 @@ impl Frame {
 
     /// Get a mutable view into the positions of this frame.
     ///
+    /// The returned slice directly borrows the memory used by chemfiles, so
+    /// modifications are visible in the frame without any copy.
+    ///
     /// # Example
     /// ```
     /// # use chemfiles::Frame;
@@ -490,12 +569,19 @@ impl Frame {
         }
         #[allow(clippy::cast_possible_truncation)]
         let size = natoms as usize;
+        if size == 0 || ptr.is_null() {
+            return &mut [];
+        }
         unsafe {
             return std::slice::from_raw_parts_mut(ptr, size);
         }
     }
 
-    /// Get a view into the velocities of this frame.
+    /// Get a view into the velocities of this frame, or `None` if the frame
+    /// does not contain velocities.
+    ///
+    /// The returned slice directly borrows the memory used by chemfiles, no
+    /// data is copied.
     ///
     /// # Example
     /// ```
@@ -524,12 +610,16 @@ impl Frame {
         }
         #[allow(clippy::cast_possible_truncation)]
         let size = natoms as usize;
+        if size == 0 || ptr.is_null() {
+            return Some(&[]);
+        }
         unsafe {
             return Some(std::slice::from_raw_parts(ptr, size));
         }
     }
 
-    /// Get a mutable view into the velocities of this frame.
+    /// Get a mutable view into the velocities of this frame, or `None` if
+    /// the frame does not contain velocities.
     ///
     /// # Example
     /// ```
@@ -558,6 +648,9 @@ impl Frame {
         }
         #[allow(clippy::cast_possible_truncation)]
         let size = natoms as usize;
+        if size == 0 || ptr.is_null() {
+            return Some(&mut []);
+        }
         unsafe {
             return Some(std::slice::from_raw_parts_mut(ptr, size));
         }
@@ -583,7 +676,10 @@ impl Frame {
     }
 
     /// Add velocity data to this frame. If the frame already have velocities,
-    /// this does nothing.
+    /// this does nothing. The new velocities are initialized to zero.
+    ///
+    /// The C++ library does not allow to remove velocities from a frame once
+    /// they have been added.
     ///
     /// # Example
     /// ```
@@ -602,6 +698,9 @@ impl Frame {
 
     /// Get a reference to the `UnitCell` from this frame.
     ///
+    /// The cell is not copied, and the reference can not outlive the frame.
+    /// Use `frame.cell().clone()` to get an owned copy of the cell.
+    ///
     /// # Example
     /// ```
     /// # use chemfiles::{Frame, CellShape};
@@ -655,8 +754,34 @@ impl Frame {
         }
     }
 
+    /// Wrap the positions of all the atoms in this frame inside the frame's
+    /// unit cell. This does nothing for frames with an infinite cell.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, UnitCell};
+    /// let mut frame = Frame::new();
+    /// frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+    /// frame.add_atom(&Atom::new("Zn"), [12.0, -3.0, 4.0], None);
+    ///
+    /// frame.wrap();
+    /// assert_eq!(frame.positions(), [[2.0, -3.0, 4.0]]);
+    /// ```
+    pub fn wrap(&mut self) {
+        let cell = self.cell().clone();
+        cell.wrap_all(self.positions_mut());
+    }
+
     /// Get a reference to the `Topology` of this frame.
     ///
+    /// The topology gives access to the connectivity of the system, including
+    /// the bonds read from files such as PDB or PSF, and the angles and
+    /// dihedral angles derived from these bonds: see `Topology::bonds`,
+    /// `Topology::angles`, `Topology::dihedrals` and `Topology::impropers`.
+    ///
+    /// The topology is not copied, and the reference can not outlive the
+    /// frame. Use `frame.topology().clone()` to get an owned copy.
+    ///
     /// # Example
     /// ```
     /// # use chemfiles::Frame;
@@ -666,6 +791,17 @@ impl Frame {
     /// let topology = frame.topology();
     /// assert_eq!(topology.size(), 42);
     /// ```
+    ///
+    /// ```no_run
+    /// # use chemfiles::{Frame, Trajectory};
+    /// let mut trajectory = Trajectory::open("protein.pdb", 'r').unwrap();
+    /// let mut frame = Frame::new();
+    /// trajectory.read(&mut frame).unwrap();
+    ///
+    /// for [i, j] in frame.topology().bonds() {
+    ///     println!("{} is bonded to {}", frame.atom(i).name(), frame.atom(j).name());
+    /// }
+    /// ```
     pub fn topology(&self) -> TopologyRef {
         unsafe {
             let handle = ffi::chfl_topology_from_frame(self.as_ptr());
@@ -758,6 +894,87 @@ impl Frame {
         unsafe { check(ffi::chfl_frame_guess_bonds(self.as_mut_ptr())) }
     }
 
+    /// Guess the bonds in this `frame` using a distance criterion with an
+    /// adjustable `tolerance`, replacing any existing bond. Angles and
+    /// dihedrals are then guessed from the bonds.
+    ///
+    /// Two atoms are considered bonded if their distance (accounting for
+    /// periodic boundary conditions) is smaller than the sum of their covalent
+    /// radii multiplied by `tolerance`. Values around 1.2 work for most
+    /// organic molecules, larger values can be used for unusual structures
+    /// such as transition states or coarse-grained systems.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if `tolerance` is not a positive number, or if the
+    /// covalent radius is unknown for some atoms in the frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Frame, Atom};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("C"), [1.9, 0.0, 0.0], None);
+    ///
+    /// frame.guess_bonds_with_tolerance(1.2).unwrap();
+    /// assert_eq!(frame.topology().bonds_count(), 0);
+    ///
+    /// // stretched bonds are found with a larger tolerance
+    /// frame.guess_bonds_with_tolerance(1.4).unwrap();
+    /// assert_eq!(frame.topology().bonds(), [[0, 1]]);
+    /// ```
+    pub fn guess_bonds_with_tolerance(&mut self, tolerance: f64) -> Result<(), Error> {
+        if !(tolerance > 0.0 && tolerance.is_finite()) {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!("the tolerance for bonds guessing must be a positive number, got {tolerance}"),
+            ));
+        }
+
+        let mut radii = Vec::with_capacity(self.size());
+        for atom in self.iter_atoms() {
+            let radius = atom.covalent_radius();
+            if radius <= 0.0 {
+                return Err(Error::new(
+                    Status::ChemfilesError,
+                    format!("missing covalent radius for atom of type '{}'", atom.atomic_type()),
+                ));
+            }
+            radii.push(radius);
+        }
+
+        let cell = self.cell();
+        let matrix = cell.matrix();
+        let inverse = if cell.shape() == CellShape::Infinite {
+            None
+        } else {
+            linalg::inverse(&matrix)
+        };
+        std::mem::drop(cell);
+
+        let positions = self.positions();
+        let mut bonds = Vec::new();
+        for i in 0..positions.len() {
+            for j in (i + 1)..positions.len() {
+                let mut delta = linalg::sub(positions[j], positions[i]);
+                if let Some(inverse) = &inverse {
+                    delta = linalg::minimum_image(&matrix, inverse, delta);
+                }
+
+                let distance = linalg::norm2(delta).sqrt();
+                if distance > 0.03 && distance < tolerance * (radii[i] + radii[j]) {
+                    bonds.push((i, j));
+                }
+            }
+        }
+
+        self.clear_bonds();
+        for (i, j) in bonds {
+            self.add_bond(i, j);
+        }
+        return Ok(());
+    }
+
     /// Remove all existing bonds, angles, dihedral angles and improper
     /// dihedral angles in the topology of the frame.
     ///
@@ -854,122 +1071,2336 @@ impl Frame {
     /// }
     /// ```
     pub fn properties(&self) -> PropertiesIter {
+        PropertiesIter {
+            names: self.list_properties().into_iter(),
+            getter: Box::new(move |name| self.get(name).expect("failed to get property")),
+        }
+    }
+
+    /// Get the number of properties associated with this frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Frame;
+    /// let mut frame = Frame::new();
+    /// assert_eq!(frame.properties_count(), 0);
+    ///
+    /// frame.set("time", 12.5);
+    /// assert_eq!(frame.properties_count(), 1);
+    /// ```
+    pub fn properties_count(&self) -> usize {
         let mut count = 0;
         unsafe {
             check_success(ffi::chfl_frame_properties_count(self.as_ptr(), &mut count));
         }
-
         #[allow(clippy::cast_possible_truncation)]
-        let size = count as usize;
-        let mut c_names = vec![std::ptr::null_mut(); size];
+        return count as usize;
+    }
+
+    /// Get the names of all the properties associated with this frame,
+    /// without getting the corresponding values.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Frame;
+    /// let mut frame = Frame::new();
+    /// frame.set("time", 12.5);
+    /// frame.set("lambda", 0.3);
+    ///
+    /// let mut names = frame.list_properties();
+    /// names.sort();
+    /// assert_eq!(names, ["lambda", "time"]);
+    /// ```
+    pub fn list_properties(&self) -> Vec<String> {
+        let count = self.properties_count();
+        let mut c_names = vec![std::ptr::null_mut(); count];
         unsafe {
             check_success(ffi::chfl_frame_list_properties(
                 self.as_ptr(),
                 c_names.as_mut_ptr(),
-                count,
+                count as u64,
             ));
         }
 
-        let mut names = Vec::new();
-        for ptr in c_names {
-            names.push(strings::from_c(ptr));
-        }
+        return c_names.into_iter().map(|ptr| strings::from_c(ptr)).collect();
+    }
 
-        PropertiesIter {
-            names: names.into_iter(),
-            getter: Box::new(move |name| self.get(name).expect("failed to get property")),
-        }
+    /// Get the simulation time associated with this frame, if any. This is
+    /// the `"time"` property, set when reading formats storing the time of
+    /// each step such as TRR, XTC or Amber `NetCDF`. The unit of the time
+    /// depends on the format, usually picoseconds.
+    ///
+    /// # Examples
+    /// ```
+    /// # use chemfiles::Frame;
+    /// let mut frame = Frame::new();
+    /// assert_eq!(frame.time(), None);
+    ///
+    /// frame.set("time", 12.5);
+    /// assert_eq!(frame.time(), Some(12.5));
+    /// ```
+    pub fn time(&self) -> Option<f64> {
+        return self.get("time").and_then(|property| property.as_double());
     }
 
-    /// Gets an iterator over atoms
+    /// Get the SDF data fields (the `> <tag>` blocks) associated with this
+    /// frame, as a map from tag name to tag value.
+    ///
+    /// Chemfiles stores these data fields as frame properties when reading
+    /// SDF files, and writes all frame properties back as data fields when
+    /// writing SDF files. This function collects them as strings, formatting
+    /// numeric, boolean and vector properties the same way the SDF writer
+    /// does. The `"name"` property is the title of the molecule and not a
+    /// data field, so it is not included in the map.
     ///
     /// # Example
     /// ```
-    /// # use chemfiles::{Atom, AtomRef, Frame};
+    /// # use chemfiles::Frame;
     /// let mut frame = Frame::new();
+    /// frame.set("name", "aspirin");
+    /// frame.set("IC50", 12.5);
+    /// frame.set("ID", "CHEMBL25");
+    ///
+    /// let tags = frame.sdf_tags();
+    /// assert_eq!(tags.len(), 2);
+    /// assert_eq!(tags["IC50"], "12.5");
+    /// assert_eq!(tags["ID"], "CHEMBL25");
+    /// ```
+    pub fn sdf_tags(&self) -> BTreeMap<String, String> {
+        let mut tags = BTreeMap::new();
+        for (name, property) in self.properties() {
+            if name == "name" {
+                continue;
+            }
+
+            let value = match property {
+                Property::Bool(value) => value.to_string(),
+                Property::Double(value) => value.to_string(),
+                Property::String(value) => value,
+                Property::Vector3D(value) => format!("{} {} {}", value[0], value[1], value[2]),
+            };
+            let _ = tags.insert(name, value);
+        }
+        return tags;
+    }
+
+    /// Store the group of atoms at `indexes` as a string property with the
+    /// given `name` in this frame, so that the group can be written along
+    /// with the frame (for example in extended XYZ or SDF files) and later
+    /// retrieved with `Frame::load_group`.
     ///
-    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
-    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    /// The indexes are stored as a whitespace-separated list. Any existing
+    /// property with the same name is replaced.
     ///
-    /// let mut atoms: Vec<AtomRef> = Vec::new();
+    /// # Panics
     ///
-    /// for atom in frame.iter_atoms() {
-    ///     atoms.push(atom);
-    /// }
+    /// If any of the indexes is out of bounds.
     ///
-    /// assert_eq!(atoms.len(), 2);
+    /// # Example
     /// ```
-    pub fn iter_atoms(&self) -> AtomIter<'_> {
-        AtomIter {
-            frame: self,
-            index: 0,
-            size: self.size(),
+    /// # use chemfiles::{Atom, Frame, Property};
+    /// let mut frame = Frame::new();
+    /// for _ in 0..4 {
+    ///     frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    /// }
+    ///
+    /// frame.save_group("backbone", &[0, 1, 3]);
+    /// assert_eq!(frame.get("backbone"), Some(Property::String("0 1 3".into())));
+    /// ```
+    pub fn save_group(&mut self, name: &str, indexes: &[usize]) {
+        let size = self.size();
+        let mut group = String::new();
+        for &index in indexes {
+            assert!(
+                index < size,
+                "out of bounds atomic index in group '{name}': we have {size} atoms, but the index is {index}"
+            );
+            if !group.is_empty() {
+                group.push(' ');
+            }
+            group.push_str(&index.to_string());
         }
+        self.set(name, group);
     }
-}
 
-impl Drop for Frame {
-    fn drop(&mut self) {
-        unsafe {
-            let _ = ffi::chfl_free(self.as_ptr().cast());
+    /// Get the group of atoms stored with `Frame::save_group` in the property
+    /// with the given `name`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if there is no property with this name, if the
+    /// property is not a string containing a whitespace-separated list of
+    /// integers, or if any of these integers is out of bounds for this frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// for _ in 0..4 {
+    ///     frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+    /// }
+    ///
+    /// frame.save_group("ends", &[0, 3]);
+    /// assert_eq!(frame.load_group("ends").unwrap(), [0, 3]);
+    /// assert!(frame.load_group("missing").is_err());
+    /// ```
+    pub fn load_group(&self, name: &str) -> Result<Vec<usize>, Error> {
+        let group = match self.get(name) {
+            Some(Property::String(group)) => group,
+            Some(_) => {
+                return Err(Error::new(
+                    Status::PropertyError,
+                    format!("the '{name}' property does not contain a group of atoms"),
+                ))
+            }
+            None => {
+                return Err(Error::new(
+                    Status::PropertyError,
+                    format!("there is no '{name}' property in this frame"),
+                ))
+            }
+        };
+
+        let size = self.size();
+        let mut indexes = Vec::new();
+        for value in group.split_whitespace() {
+            let index = value.parse::<usize>().map_err(|_| {
+                Error::new(
+                    Status::PropertyError,
+                    format!("invalid atomic index '{value}' in the '{name}' group"),
+                )
+            })?;
+            if index >= size {
+                return Err(Error::new(
+                    Status::OutOfBounds,
+                    format!(
+                        "out of bounds atomic index in group '{name}': we have {size} atoms, but the index is {index}"
+                    ),
+                ));
+            }
+            indexes.push(index);
         }
+        return Ok(indexes);
     }
-}
 
-impl<'a> Iterator for AtomIter<'a> {
-    type Item = AtomRef<'a>;
+    /// Get the list of alternate location identifiers used by the atoms in
+    /// this frame (see `Atom::altloc`), sorted and without duplicates.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// for altloc in ["B", "", "A", "B"] {
+    ///     let mut atom = Atom::new("C");
+    ///     atom.set("altloc", altloc);
+    ///     frame.add_atom(&atom, [0.0; 3], None);
+    /// }
+    ///
+    /// assert_eq!(frame.altlocs(), ['A', 'B']);
+    /// ```
+    pub fn altlocs(&self) -> Vec<char> {
+        let mut altlocs = self.iter_atoms().filter_map(|atom| atom.altloc()).collect::<Vec<_>>();
+        altlocs.sort_unstable();
+        altlocs.dedup();
+        return altlocs;
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.size <= self.index {
-            return None;
+    /// Keep a single alternate conformation in this frame, removing all the
+    /// atoms with an alternate location identifier different from `altloc`.
+    /// Atoms without alternate location are kept. The occupancy of the
+    /// remaining atoms with alternate location `altloc` is set to 1, since
+    /// they now form the only conformation.
+    ///
+    /// Bonds, residues (including their insertion codes), velocities and
+    /// properties of the remaining atoms are preserved. This function returns
+    /// the number of removed atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, Property};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("N"), [0.0; 3], None);
+    /// for (altloc, occupancy) in [("A", 0.6), ("B", 0.4)] {
+    ///     let mut atom = Atom::new("CA");
+    ///     atom.set("altloc", altloc);
+    ///     atom.set("occupancy", occupancy);
+    ///     frame.add_atom(&atom, [1.0, 0.0, 0.0], None);
+    /// }
+    ///
+    /// assert_eq!(frame.select_altloc('A'), 1);
+    /// assert_eq!(frame.size(), 2);
+    /// assert_eq!(frame.atom(1).altloc(), Some('A'));
+    /// assert_eq!(frame.atom(1).get("occupancy"), Some(Property::Double(1.0)));
+    /// ```
+    pub fn select_altloc(&mut self, altloc: char) -> usize {
+        let mut kept = Vec::new();
+        for (i, atom) in self.iter_atoms().enumerate() {
+            match atom.altloc() {
+                Some(current) if current != altloc => {}
+                _ => kept.push(i),
+            }
         }
-        let atom = self.frame.atom(self.index);
-        self.index += 1;
-        Some(atom)
-    }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        let removed = self.size() - kept.len();
+        if removed != 0 {
+            *self = self.subset(&kept);
+        }
 
-    #[test]
-    fn clone() {
-        let mut frame = Frame::new();
-        assert_eq!(frame.size(), 0);
-        let copy = frame.clone();
-        assert_eq!(copy.size(), 0);
+        for i in 0..self.size() {
+            if self.atom(i).altloc() == Some(altloc) {
+                self.atom_mut(i).set("occupancy", 1.0);
+            }
+        }
 
-        frame.resize(42);
-        assert_eq!(frame.size(), 42);
-        assert_eq!(copy.size(), 0);
+        return removed;
     }
 
-    #[test]
-    fn size() {
-        let mut frame = Frame::new();
-        assert_eq!(frame.size(), 0);
+    /// Get a human-readable table describing the first `limit` atoms in this
+    /// frame, with their index, name, residue name and id, position and
+    /// charge. This is intended for logs and debugging, and the exact format
+    /// might change in the future. Use `Topology::describe` to get a summary
+    /// of the topology.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [0.757, 0.586, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [-0.757, 0.586, 0.0], None);
+    ///
+    /// let table = frame.describe(2);
+    /// println!("{}", table);
+    /// assert!(table.starts_with("frame at step 0 with 3 atoms"));
+    /// assert!(table.contains("0.757"));
+    /// assert!(table.ends_with("... and 1 more atoms\n"));
+    /// ```
+    pub fn describe(&self, limit: usize) -> String {
+        let size = self.size();
+        let topology = self.topology();
+        let positions = self.positions();
+
+        let mut description = String::new();
+        let _ = writeln!(
+            description,
+            "frame at step {} with {} atoms and {:?} cell",
+            self.step(),
+            size,
+            self.cell().shape()
+        );
+        let _ = writeln!(
+            description,
+            "{:>6} {:<8} {:<8} {:>6} {:>10} {:>10} {:>10} {:>8}",
+            "index", "name", "resname", "resid", "x", "y", "z", "charge"
+        );
+
+        for (i, [x, y, z]) in positions.iter().enumerate().take(limit) {
+            let atom = topology.atom(i);
+            let (resname, resid) = match topology.residue_for_atom(i) {
+                Some(residue) => (
+                    residue.name(),
+                    residue.id().map_or_else(|| "-".into(), |id| id.to_string()),
+                ),
+                None => ("-".into(), "-".into()),
+            };
+            let _ = writeln!(
+                description,
+                "{:>6} {:<8} {:<8} {:>6} {:>10.3} {:>10.3} {:>10.3} {:>8.3}",
+                i,
+                atom.name(),
+                resname,
+                resid,
+                x,
+                y,
+                z,
+                atom.charge()
+            );
+        }
 
-        frame.resize(12);
-        assert_eq!(frame.size(), 12);
+        if size > limit {
+            let _ = writeln!(description, "... and {} more atoms", size - limit);
+        }
+        return description;
     }
 
-    #[test]
-    fn add_atom() {
-        let mut frame = Frame::new();
+    /// Check the internal consistency of this frame, and panic with a
+    /// description of the first problem found. The checks are:
+    ///
+    /// - the positions, the velocities (if any) and the topology all contain
+    ///   the same number of atoms;
+    /// - all positions and velocities are finite;
+    /// - all atomic masses are finite and non-negative;
+    /// - all bonds and residues refer to atoms inside the frame.
+    ///
+    /// This is intended to be used during development, to detect corrupted
+    /// frames (for example `NaN` written through `Frame::positions_mut`) as
+    /// close as possible to their origin. With the `strict-debug` feature,
+    /// this function is also called on all frames read from or written to a
+    /// `Trajectory` in builds with debug assertions.
+    ///
+    /// # Panics
+    ///
+    /// If any of the checks fails.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    /// frame.add_bond(0, 1);
+    ///
+    /// frame.debug_validate();
+    /// ```
+    pub fn debug_validate(&self) {
+        if let Some(problem) = self.integrity_problem() {
+            panic!("invalid frame at step {}: {}", self.step(), problem);
+        }
+    }
 
-        frame.add_atom(&Atom::new("U"), [1.0, 1.0, 2.0], None);
-        assert_eq!(frame.size(), 1);
-        assert_eq!(frame.atom(0).name(), "U");
+    /// Get a description of the first broken invariant in this frame, if any
+    fn integrity_problem(&self) -> Option<String> {
+        let size = self.size();
+        let positions = self.positions();
+        if positions.len() != size {
+            return Some(format!("{} positions for {} atoms", positions.len(), size));
+        }
 
-        let positions = &[[1.0, 1.0, 2.0]];
-        assert_eq!(frame.positions(), positions);
+        if let Some(i) = positions.iter().position(|p| !p.iter().all(|x| x.is_finite())) {
+            return Some(format!("position of atom {} is not finite: {:?}", i, positions[i]));
+        }
 
-        frame.add_velocities();
-        frame.add_atom(&Atom::new("F"), [1.0, 1.0, 2.0], [4.0, 3.0, 2.0]);
-        assert_eq!(frame.size(), 2);
-        assert_eq!(frame.atom(0).name(), "U");
-        assert_eq!(frame.atom(1).name(), "F");
+        if let Some(velocities) = self.velocities() {
+            if velocities.len() != size {
+                return Some(format!("{} velocities for {} atoms", velocities.len(), size));
+            }
+
+            if let Some(i) = velocities.iter().position(|v| !v.iter().all(|x| x.is_finite())) {
+                return Some(format!("velocity of atom {} is not finite: {:?}", i, velocities[i]));
+            }
+        }
+
+        let topology = self.topology();
+        if topology.size() != size {
+            return Some(format!(
+                "topology contains {} atoms instead of {}",
+                topology.size(),
+                size
+            ));
+        }
+
+        if let Some((i, mass)) = self
+            .masses()
+            .into_iter()
+            .enumerate()
+            .find(|(_, mass)| !(mass.is_finite() && *mass >= 0.0))
+        {
+            return Some(format!("mass of atom {i} is invalid: {mass}"));
+        }
+
+        if let Some(bond) = topology.bonds().into_iter().find(|bond| bond[1] >= size) {
+            return Some(format!("bond {bond:?} is out of bounds for {size} atoms"));
+        }
+
+        for residue in topology.iter_residues() {
+            if let Some(i) = residue.atoms().into_iter().find(|&i| i >= size) {
+                return Some(format!(
+                    "residue '{}' contains atom {i}, out of bounds for {size} atoms",
+                    residue.name()
+                ));
+            }
+        }
+
+        return None;
+    }
+
+    /// Get the indexes of all the atoms of the given `element` in this frame.
+    /// The element of an atom is determined from its type, using
+    /// `Atom::atomic_number`.
+    ///
+    /// This is a faster alternative to selections for the common case of
+    /// finding all atoms of a given element.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Element, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+    ///
+    /// let hydrogen = Element::from_symbol("H").unwrap();
+    /// assert_eq!(frame.indices_of_element(hydrogen), [1, 2]);
+    /// ```
+    pub fn indices_of_element(&self, element: Element) -> Vec<usize> {
+        let number = u64::from(element.atomic_number());
+        return self
+            .iter_atoms()
+            .enumerate()
+            .filter(|(_, atom)| atom.atomic_number() == number)
+            .map(|(i, _)| i)
+            .collect();
+    }
+
+    /// Get the names of all the atoms in this frame.
+    ///
+    /// This is a convenience function, and is not faster than calling
+    /// `Atom::name` for each atom: the C API does not give access to the data
+    /// of all atoms at once, so this still needs a few calls to the C library
+    /// for each atom.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H1"), [1.0, 0.0, 0.0], None);
+    ///
+    /// assert_eq!(frame.names(), ["O", "H1"]);
+    /// ```
+    pub fn names(&self) -> Vec<String> {
+        self.topology().map_atoms(Atom::name)
+    }
+
+    /// Get the atomic types of all the atoms in this frame. Like
+    /// `Frame::names`, this is not faster than going through the atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// let mut atom = Atom::new("H1");
+    /// atom.set_atomic_type("H");
+    /// frame.add_atom(&atom, [0.0, 0.0, 0.0], None);
+    ///
+    /// assert_eq!(frame.types(), ["H"]);
+    /// ```
+    pub fn types(&self) -> Vec<String> {
+        self.topology().map_atoms(Atom::atomic_type)
+    }
+
+    /// Get the masses of all the atoms in this frame. Like `Frame::names`,
+    /// this is not faster than going through the atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("H"), [0.0, 0.0, 0.0], None);
+    ///
+    /// assert_eq!(frame.masses(), [1.008]);
+    /// ```
+    pub fn masses(&self) -> Vec<f64> {
+        self.topology().map_atoms(Atom::mass)
+    }
+
+    /// Get the charges of all the atoms in this frame. Like `Frame::names`,
+    /// this is not faster than going through the atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Na"), [0.0, 0.0, 0.0], None);
+    ///
+    /// assert_eq!(frame.charges(), [0.0]);
+    /// ```
+    pub fn charges(&self) -> Vec<f64> {
+        self.topology().map_atoms(Atom::charge)
+    }
+
+    /// Set the names of all the atoms in this frame. This is a convenience
+    /// function, which modifies the atoms one by one.
+    ///
+    /// # Panics
+    ///
+    /// If the number of `names` is not the same as the number of atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Frame;
+    /// let mut frame = Frame::new();
+    /// frame.resize(2);
+    ///
+    /// frame.set_names(&["OW", "HW"]);
+    /// assert_eq!(frame.names(), ["OW", "HW"]);
+    /// ```
+    pub fn set_names<S: AsRef<str>>(&mut self, names: &[S]) {
+        self.check_bulk_size(names.len(), "names");
+        for (i, name) in names.iter().enumerate() {
+            self.atom_mut(i).set_name(name.as_ref());
+        }
+    }
+
+    /// Set the atomic types of all the atoms in this frame, one atom at the
+    /// time (see `Frame::set_names`).
+    ///
+    /// # Panics
+    ///
+    /// If the number of `types` is not the same as the number of atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Frame;
+    /// let mut frame = Frame::new();
+    /// frame.resize(2);
+    ///
+    /// frame.set_types(&["O", "H"]);
+    /// assert_eq!(frame.types(), ["O", "H"]);
+    /// ```
+    pub fn set_types<S: AsRef<str>>(&mut self, types: &[S]) {
+        self.check_bulk_size(types.len(), "types");
+        for (i, atomic_type) in types.iter().enumerate() {
+            self.atom_mut(i).set_atomic_type(atomic_type.as_ref());
+        }
+    }
+
+    /// Set the masses of all the atoms in this frame, one atom at the
+    /// time (see `Frame::set_names`).
+    ///
+    /// # Panics
+    ///
+    /// If the number of `masses` is not the same as the number of atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Frame;
+    /// let mut frame = Frame::new();
+    /// frame.resize(2);
+    ///
+    /// frame.set_masses(&[16.0, 1.0]);
+    /// assert_eq!(frame.masses(), [16.0, 1.0]);
+    /// ```
+    pub fn set_masses(&mut self, masses: &[f64]) {
+        self.check_bulk_size(masses.len(), "masses");
+        for (i, &mass) in masses.iter().enumerate() {
+            self.atom_mut(i).set_mass(mass);
+        }
+    }
+
+    /// Set the charges of all the atoms in this frame, one atom at the
+    /// time (see `Frame::set_names`).
+    ///
+    /// # Panics
+    ///
+    /// If the number of `charges` is not the same as the number of atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Frame;
+    /// let mut frame = Frame::new();
+    /// frame.resize(2);
+    ///
+    /// frame.set_charges(&[-0.8, 0.4]);
+    /// assert_eq!(frame.charges(), [-0.8, 0.4]);
+    /// ```
+    pub fn set_charges(&mut self, charges: &[f64]) {
+        self.check_bulk_size(charges.len(), "charges");
+        for (i, &charge) in charges.iter().enumerate() {
+            self.atom_mut(i).set_charge(charge);
+        }
+    }
+
+    /// Get the names of the per-atom columns stored in this frame, *i.e.* the
+    /// atomic properties with a numeric value defined for all the atoms.
+    ///
+    /// This is how additional columns of LAMMPS dump files (forces, per-atom
+    /// energies, custom computes such as `c_pe` or `f_ave[1]`, ...) are
+    /// stored when reading a trajectory: each column becomes an atomic
+    /// property using the column name from the `ITEM: ATOMS` header. The
+    /// names are sorted alphabetically, and the values can be accessed with
+    /// `Frame::dump_column`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("Ar"), [1.0, 0.0, 0.0], None);
+    ///
+    /// frame.set_dump_column("c_pe", &[-1.2, -1.3]);
+    /// frame.atom_mut(0).set("flag", 1.0);
+    ///
+    /// // "flag" is not defined for all atoms
+    /// assert_eq!(frame.dump_columns(), ["c_pe"]);
+    /// ```
+    pub fn dump_columns(&self) -> Vec<String> {
+        if self.size() == 0 {
+            return Vec::new();
+        }
+
+        let mut columns = self.atom(0).list_properties();
+        columns.retain(|name| {
+            self.iter_atoms()
+                .all(|atom| matches!(atom.get(name), Some(Property::Double(_))))
+        });
+        columns.sort();
+        return columns;
+    }
+
+    /// Get the values of the per-atom column `name` for all the atoms in this
+    /// frame, or `None` if some atoms do not have a numeric property with
+    /// this name. See `Frame::dump_columns` for more information.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("Ar"), [1.0, 0.0, 0.0], None);
+    ///
+    /// frame.set_dump_column("fx", &[0.5, -0.5]);
+    /// assert_eq!(frame.dump_column("fx"), Some(vec![0.5, -0.5]));
+    /// assert_eq!(frame.dump_column("fy"), None);
+    /// ```
+    pub fn dump_column(&self, name: &str) -> Option<Vec<f64>> {
+        return self
+            .iter_atoms()
+            .map(|atom| atom.get(name).and_then(|property| property.as_double()))
+            .collect();
+    }
+
+    /// Set the values of the per-atom column `name` for all the atoms in
+    /// this frame, replacing any existing atomic property with this name.
+    /// The values are stored as atomic properties, which are written by
+    /// formats supporting custom per-atom data: LAMMPS dump files written by
+    /// a `Trajectory` contain one additional column for each property
+    /// defined on all atoms.
+    ///
+    /// # Panics
+    ///
+    /// If the number of `values` is not the same as the number of atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Frame;
+    /// let mut frame = Frame::new();
+    /// frame.resize(2);
+    ///
+    /// frame.set_dump_column("c_pe", &[-1.2, -1.3]);
+    /// assert_eq!(frame.dump_column("c_pe"), Some(vec![-1.2, -1.3]));
+    /// ```
+    pub fn set_dump_column(&mut self, name: &str, values: &[f64]) {
+        self.check_bulk_size(values.len(), "values");
+        for (i, &value) in values.iter().enumerate() {
+            self.atom_mut(i).set(name, value);
+        }
+    }
+
+    /// Get the masses of all the atoms to use in mass-weighted analyses,
+    /// where virtual sites have a mass of zero
+    pub(crate) fn analysis_masses(&self) -> Vec<f64> {
+        self.iter_atoms()
+            .map(|atom| if atom.is_virtual() { 0.0 } else { atom.mass() })
+            .collect()
+    }
+
+    /// Check that `count` values were given to one of the bulk setters
+    fn check_bulk_size(&self, count: usize, values: &str) {
+        let size = self.size();
+        assert!(count == size, "expected {size} {values} for this frame, got {count}");
+    }
+
+    /// Gets an iterator over atoms
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, AtomRef, Frame};
+    /// let mut frame = Frame::new();
+    ///
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    ///
+    /// let mut atoms: Vec<AtomRef> = Vec::new();
+    ///
+    /// for atom in frame.iter_atoms() {
+    ///     atoms.push(atom);
+    /// }
+    ///
+    /// assert_eq!(atoms.len(), 2);
+    /// ```
+    pub fn iter_atoms(&self) -> AtomIter<'_> {
+        AtomIter {
+            frame: self,
+            index: 0,
+            size: self.size(),
+        }
+    }
+
+    /// Get the center of mass of the atoms matching `selection` in this
+    /// frame. Virtual sites (see `Atom::is_virtual`) are ignored.
+    ///
+    /// If `periodic` is `true` and the frame has a periodic unit cell, all
+    /// the atoms are first unwrapped around the first selected atom using
+    /// the minimum image convention, which gives the right result for groups
+    /// of atoms split across the periodic boundaries, as long as the group
+    /// is smaller than half of the cell. The center is then wrapped inside
+    /// the unit cell. The center is always the (arithmetic) weighted mean of
+    /// the (unwrapped) positions.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the selection is invalid, matches multiple
+    /// atoms (for example `"pairs: ..."`) or does not match any atom, or if
+    /// the total mass of the selected atoms is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, UnitCell};
+    /// let mut frame = Frame::new();
+    /// frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+    /// frame.add_atom(&Atom::new("C"), [9.5, 5.0, 5.0], None);
+    /// frame.add_atom(&Atom::new("C"), [0.5, 5.0, 5.0], None);
+    ///
+    /// let center = frame.center_of_mass("all", false).unwrap();
+    /// assert!((center[0] - 5.0).abs() < 1e-12);
+    ///
+    /// // the two atoms are close to each other with periodic boundaries
+    /// let center = frame.center_of_mass("all", true).unwrap();
+    /// assert!(center[0].abs() < 1e-12 || (center[0] - 10.0).abs() < 1e-12);
+    /// ```
+    pub fn center_of_mass(&self, selection: &str, periodic: bool) -> Result<[f64; 3], Error> {
+        let (atoms, masses) = self.selected_masses(selection, "center of mass")?;
+        return Ok(self.weighted_center(&atoms, Some(&masses), periodic));
+    }
+
+    /// Get the geometric center (centroid) of the atoms matching `selection`
+    /// in this frame, giving the same weight to all atoms. See
+    /// `Frame::center_of_mass` for the meaning of `periodic`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the selection is invalid, matches multiple
+    /// atoms (for example `"pairs: ..."`) or does not match any atom.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+    ///
+    /// assert_eq!(frame.centroid("name H", false).unwrap(), [0.5, 0.5, 0.0]);
+    /// ```
+    pub fn centroid(&self, selection: &str, periodic: bool) -> Result<[f64; 3], Error> {
+        let atoms = self.selected_atoms(selection)?;
+        return Ok(self.weighted_center(&atoms, None, periodic));
+    }
+
+    /// Translate all the atoms in this frame by `vector`. This can be used
+    /// with `Frame::center_of_mass` to recenter the system.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+    /// frame.add_atom(&Atom::new("Zn"), [3.0, 2.0, 1.0], None);
+    ///
+    /// let center = frame.centroid("all", false).unwrap();
+    /// frame.translate([-center[0], -center[1], -center[2]]);
+    /// assert_eq!(frame.positions(), [[-1.0, 0.0, 1.0], [1.0, 0.0, -1.0]]);
+    /// ```
+    pub fn translate(&mut self, vector: [f64; 3]) {
+        for position in self.positions_mut() {
+            *position = linalg::add(*position, vector);
+        }
+    }
+
+    /// Get the mass-weighted radius of gyration of the atoms matching
+    /// `selection` in this frame, *i.e.* the square root of the mass-weighted
+    /// mean squared distance of the atoms to their center of mass. Virtual
+    /// sites (see `Atom::is_virtual`) are ignored.
+    ///
+    /// The positions are used as-is, without accounting for periodic
+    /// boundary conditions: molecules split across the boundaries should be
+    /// made whole first, for example with `analysis::Unwrapper`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the selection is invalid, matches multiple
+    /// atoms (for example `"pairs: ..."`) or does not match any atom, or if
+    /// the total mass of the selected atoms is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [-1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("C"), [1.0, 0.0, 0.0], None);
+    ///
+    /// assert_eq!(frame.gyration_radius("all").unwrap(), 1.0);
+    /// ```
+    pub fn gyration_radius(&self, selection: &str) -> Result<f64, Error> {
+        let (atoms, masses) = self.selected_masses(selection, "radius of gyration")?;
+        let positions = self.positions();
+        let center = self.weighted_center(&atoms, Some(&masses), false);
+
+        let mut sum = 0.0;
+        for (&i, &mass) in atoms.iter().zip(&masses) {
+            sum += mass * linalg::norm2(linalg::sub(positions[i], center));
+        }
+        return Ok((sum / masses.iter().sum::<f64>()).sqrt());
+    }
+
+    /// Get the inertia tensor of the atoms matching `selection` in this
+    /// frame, relative to their center of mass. The tensor uses the atomic
+    /// masses (in Dalton) and positions (in Angstroms), and virtual sites
+    /// (see `Atom::is_virtual`) are ignored. As for `Frame::gyration_radius`,
+    /// periodic boundary conditions are not taken into account.
+    ///
+    /// The eigenvalues of this tensor are the principal moments of inertia,
+    /// which can be used to characterize the shape of molecules.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the selection is invalid, matches multiple
+    /// atoms (for example `"pairs: ..."`) or does not match any atom, or if
+    /// the total mass of the selected atoms is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [-1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("C"), [1.0, 0.0, 0.0], None);
+    ///
+    /// let tensor = frame.inertia_tensor("all").unwrap();
+    /// // no inertia around the axis of the molecule
+    /// assert_eq!(tensor[0][0], 0.0);
+    /// assert_eq!(tensor[1][1], 2.0 * frame.atom(0).mass());
+    /// ```
+    pub fn inertia_tensor(&self, selection: &str) -> Result<[[f64; 3]; 3], Error> {
+        let (atoms, masses) = self.selected_masses(selection, "inertia tensor")?;
+        let positions = self.positions();
+        let center = self.weighted_center(&atoms, Some(&masses), false);
+
+        let mut tensor = [[0.0; 3]; 3];
+        for (&i, &mass) in atoms.iter().zip(&masses) {
+            let r = linalg::sub(positions[i], center);
+            let r2 = linalg::norm2(r);
+            for a in 0..3 {
+                for b in 0..3 {
+                    let diagonal = if a == b { r2 } else { 0.0 };
+                    tensor[a][b] += mass * (diagonal - r[a] * r[b]);
+                }
+            }
+        }
+        return Ok(tensor);
+    }
+
+    /// Get the indexes and analysis masses of the atoms matching the
+    /// `selection` string, checking that the total mass is not zero. `what`
+    /// is the name of the computed quantity, used in error messages.
+    fn selected_masses(&self, selection: &str, what: &str) -> Result<(Vec<usize>, Vec<f64>), Error> {
+        let atoms = self.selected_atoms(selection)?;
+        let all_masses = self.analysis_masses();
+        let masses = atoms.iter().map(|&i| all_masses[i]).collect::<Vec<_>>();
+        if masses.iter().sum::<f64>() == 0.0 {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!("can not compute the {what} of atoms without mass"),
+            ));
+        }
+        return Ok((atoms, masses));
+    }
+
+    /// Get the indexes of the atoms matching the `selection` string, checking
+    /// that the selection matches single atoms and at least one atom
+    fn selected_atoms(&self, selection: &str) -> Result<Vec<usize>, Error> {
+        let mut selection = Selection::new(selection)?;
+        if selection.size() != 1 {
+            return Err(Error::new(
+                Status::SelectionError,
+                "this function requires a selection matching single atoms",
+            ));
+        }
+
+        let atoms = selection.list(self);
+        if atoms.is_empty() {
+            return Err(Error::new(
+                Status::SelectionError,
+                "the selection does not match any atom",
+            ));
+        }
+        return Ok(atoms);
+    }
+
+    /// Get the center of the `atoms`, weighted by `weights` if given. If
+    /// `periodic` is `true` and the cell is not infinite, the atoms are
+    /// unwrapped around the first one before computing the center, and the
+    /// center is wrapped inside the cell.
+    fn weighted_center(&self, atoms: &[usize], weights: Option<&[f64]>, periodic: bool) -> [f64; 3] {
+        let frame_positions = self.positions();
+        let mut positions = atoms.iter().map(|&i| frame_positions[i]).collect::<Vec<_>>();
+
+        let cell = self.cell();
+        let matrix = cell.matrix();
+        let inverse = match linalg::inverse(&matrix) {
+            Some(inverse) if periodic && cell.shape() != CellShape::Infinite => inverse,
+            _ => return linalg::weighted_center(&positions, weights),
+        };
+
+        let reference = positions[0];
+        for position in &mut positions {
+            let image = linalg::minimum_image(&matrix, &inverse, linalg::sub(*position, reference));
+            *position = linalg::add(reference, image);
+        }
+
+        let center = linalg::weighted_center(&positions, weights);
+        let fractional = linalg::mat_vec(&inverse, center);
+        let fractional = [
+            fractional[0] - fractional[0].floor(),
+            fractional[1] - fractional[1].floor(),
+            fractional[2] - fractional[2].floor(),
+        ];
+        return linalg::mat_vec(&matrix, fractional);
+    }
+
+    /// Reflect all the atoms in this frame through the plane containing
+    /// `point` and perpendicular to `normal`. Velocities, if any, are
+    /// reflected as well.
+    ///
+    /// A reflection changes the handedness of chiral centers: the
+    /// `"chirality"` property of atoms (set by the SMILES reader) is updated
+    /// by swapping `"CW"` and `"CCW"`. Other chirality annotations are not
+    /// modified.
+    ///
+    /// # Panics
+    ///
+    /// If `normal` is the zero vector.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [1.0, 2.0, 3.0], None);
+    ///
+    /// // reflection through the yz plane
+    /// frame.mirror([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+    /// assert_eq!(frame.positions()[0], [-1.0, 2.0, 3.0]);
+    /// ```
+    pub fn mirror(&mut self, point: [f64; 3], normal: [f64; 3]) {
+        let norm = linalg::norm2(normal).sqrt();
+        assert!(norm > 0.0, "the normal of the mirror plane can not be zero");
+        let normal = linalg::scale(normal, 1.0 / norm);
+
+        for position in self.positions_mut() {
+            let distance = linalg::dot(linalg::sub(*position, point), normal);
+            *position = linalg::sub(*position, linalg::scale(normal, 2.0 * distance));
+        }
+
+        if let Some(velocities) = self.velocities_mut() {
+            for velocity in velocities {
+                let projection = linalg::dot(*velocity, normal);
+                *velocity = linalg::sub(*velocity, linalg::scale(normal, 2.0 * projection));
+            }
+        }
+
+        self.swap_chirality();
+    }
+
+    /// Invert all the atoms in this frame through `center`, sending each
+    /// position `r` to `2 * center - r`. Velocities, if any, are inverted as
+    /// well.
+    ///
+    /// Like `Frame::mirror`, this changes the handedness of chiral centers
+    /// and updates the `"chirality"` property of atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("C"), [1.0, 2.0, 3.0], None);
+    ///
+    /// frame.invert([1.0, 1.0, 1.0]);
+    /// assert_eq!(frame.positions()[0], [1.0, 0.0, -1.0]);
+    /// ```
+    pub fn invert(&mut self, center: [f64; 3]) {
+        for position in self.positions_mut() {
+            *position = linalg::sub(linalg::scale(center, 2.0), *position);
+        }
+
+        if let Some(velocities) = self.velocities_mut() {
+            for velocity in velocities {
+                *velocity = linalg::scale(*velocity, -1.0);
+            }
+        }
+
+        self.swap_chirality();
+    }
+
+    /// Split this frame into one frame per molecule, *i.e.* per group of
+    /// atoms connected together by bonds (see `Topology::molecules`). The
+    /// molecules are in the order of their first atom, and atoms keep their
+    /// relative order inside each molecule.
+    ///
+    /// Each new frame contains the positions, velocities, bonds and residues
+    /// (restricted to the atoms in the molecule) of the corresponding atoms,
+    /// as well as a copy of the unit cell, step and properties of this frame.
+    /// Atoms without bonds are split into frames containing a single atom.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("Na"), [5.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+    /// frame.add_bond(0, 2);
+    /// frame.add_bond(0, 3);
+    ///
+    /// let molecules = frame.split_molecules();
+    /// assert_eq!(molecules.len(), 2);
+    /// assert_eq!(molecules[0].size(), 3);
+    /// assert_eq!(molecules[0].topology().bonds(), [[0, 1], [0, 2]]);
+    /// assert_eq!(molecules[1].atom(0).name(), "Na");
+    /// ```
+    pub fn split_molecules(&self) -> Vec<Frame> {
+        let topology = self.topology();
+        let molecules = topology.molecules();
+
+        // molecule containing each atom, and index of the atom in this molecule
+        let mut locations = vec![(0, 0); self.size()];
+        for (molecule, atoms) in molecules.iter().enumerate() {
+            for (local, &atom) in atoms.iter().enumerate() {
+                locations[atom] = (molecule, local);
+            }
+        }
+
+        let positions = self.positions();
+        let velocities = self.velocities();
+        let mut frames = molecules
+            .iter()
+            .map(|atoms| {
+                let mut frame = self.empty_copy();
+                for &atom in atoms {
+                    frame.add_atom(&self.atom(atom), positions[atom], velocities.map(|v| v[atom]));
+                }
+                frame
+            })
+            .collect::<Vec<_>>();
+
+        for ([i, j], order) in topology.bonds().into_iter().zip(topology.bond_orders()) {
+            // bonded atoms are always in the same molecule
+            let (molecule, local_i) = locations[i];
+            let (_, local_j) = locations[j];
+            frames[molecule].add_bond_with_order(local_i, local_j, order);
+        }
+
+        for residue in topology.iter_residues() {
+            let mut atoms_by_molecule = BTreeMap::<usize, Vec<usize>>::new();
+            for atom in residue.atoms() {
+                let (molecule, local) = locations[atom];
+                atoms_by_molecule.entry(molecule).or_default().push(local);
+            }
+
+            for (molecule, atoms) in atoms_by_molecule {
+                frames[molecule]
+                    .add_residue(&restricted_residue(&residue, &atoms))
+                    .expect("residues from a valid frame should be valid");
+            }
+        }
+
+        return frames;
+    }
+
+    /// Extract the chain with the given `chain_id` into a new frame. The
+    /// chain is made of all the atoms in residues with a `"chainid"` string
+    /// property equal to `chain_id`, as set by the PDB and mmCIF readers.
+    /// Atoms keep their relative order in the new frame.
+    ///
+    /// The new frame contains the positions, velocities, bonds and residues
+    /// of the atoms in the chain, as well as a copy of the unit cell, step and
+    /// properties of this frame. If no residue belongs to this chain, the new
+    /// frame does not contain any atom.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, Residue};
+    /// let mut frame = Frame::new();
+    /// for (i, chain) in ["A", "B"].iter().enumerate() {
+    ///     frame.add_atom(&Atom::new("N"), [0.0, 0.0, 0.0], None);
+    ///     frame.add_atom(&Atom::new("C"), [1.0, 0.0, 0.0], None);
+    ///     frame.add_bond(2 * i, 2 * i + 1);
+    ///
+    ///     let mut residue = Residue::with_id("GLY", i as i64 + 1);
+    ///     residue.add_atom(2 * i);
+    ///     residue.add_atom(2 * i + 1);
+    ///     residue.set("chainid", *chain);
+    ///     frame.add_residue(&residue).unwrap();
+    /// }
+    ///
+    /// let chain = frame.extract_chain("B");
+    /// assert_eq!(chain.size(), 2);
+    /// assert_eq!(chain.topology().bonds(), [[0, 1]]);
+    /// assert_eq!(chain.topology().residue(0).unwrap().id(), Some(2));
+    ///
+    /// assert_eq!(frame.extract_chain("C").size(), 0);
+    /// ```
+    #[must_use]
+    pub fn extract_chain(&self, chain_id: &str) -> Frame {
+        let mut atoms = Vec::new();
+        for residue in self.topology().iter_residues() {
+            if let Some(Property::String(id)) = residue.get("chainid") {
+                if id == chain_id {
+                    atoms.extend(residue.atoms());
+                }
+            }
+        }
+        atoms.sort_unstable();
+        atoms.dedup();
+
+        return self.subset(&atoms);
+    }
+
+    /// Build the full content of a unit cell from the `atoms` in the
+    /// asymmetric unit of a crystal, given with their fractional coordinates,
+    /// by applying all the symmetry operations of the `spacegroup`.
+    ///
+    /// The new frame contains all the images of the first atom, then all the
+    /// images of the second atom, and so on. All positions are wrapped inside
+    /// the unit cell, and images closer than 0.01 Å from another image of the
+    /// same atom (for atoms on special positions) are only included once.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if `cell` is infinite.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, SpaceGroup, UnitCell};
+    /// // rock salt structure
+    /// let atoms = [
+    ///     (Atom::new("Na"), [0.0, 0.0, 0.0]),
+    ///     (Atom::new("Cl"), [0.5, 0.5, 0.5]),
+    /// ];
+    /// let cell = UnitCell::new([5.64, 5.64, 5.64]);
+    /// let spacegroup = SpaceGroup::from_symbol("F m -3 m").unwrap();
+    ///
+    /// let frame = Frame::from_asymmetric_unit(&atoms, &cell, &spacegroup).unwrap();
+    /// assert_eq!(frame.size(), 8);
+    /// assert_eq!(frame.atom(3).name(), "Na");
+    /// assert_eq!(frame.atom(4).name(), "Cl");
+    /// ```
+    pub fn from_asymmetric_unit(
+        atoms: &[(Atom, [f64; 3])],
+        cell: &UnitCell,
+        spacegroup: &SpaceGroup,
+    ) -> Result<Frame, Error> {
+        const TOLERANCE: f64 = 1e-2;
+
+        if cell.shape() == CellShape::Infinite {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                "can not build a crystal structure with an infinite unit cell",
+            ));
+        }
+
+        let mut frame = Frame::new();
+        frame.set_cell(cell);
+        for (atom, fractional) in atoms {
+            let mut unique: Vec<[f64; 3]> = Vec::new();
+            for image in spacegroup.images(*fractional) {
+                let duplicated = unique.iter().any(|&existing| {
+                    let mut delta = linalg::sub(image, existing);
+                    for value in &mut delta {
+                        *value -= value.round();
+                    }
+                    linalg::norm2(cell.cartesian(&delta)) < TOLERANCE * TOLERANCE
+                });
+                if !duplicated {
+                    unique.push(image);
+                }
+            }
+
+            for image in unique {
+                frame.add_atom(atom, cell.cartesian(&image), None);
+            }
+        }
+
+        return Ok(frame);
+    }
+
+    /// Build a supercell by replicating this frame `nx`, `ny` and `nz` times
+    /// along the first, second and third cell vectors respectively.
+    ///
+    /// The new frame contains all the atoms of the first replica, followed by
+    /// all the atoms of the next replica, and so on, with the replicas
+    /// ordered by increasing shift along the third, then second, then first
+    /// cell vector. Bonds are replicated as well, and bonds crossing the
+    /// periodic boundaries are connected to the atom in the neighboring
+    /// replica. Residues are replicated in each replica, and residue ids are
+    /// shifted to remain unique. The unit cell of the new frame is scaled
+    /// accordingly, and the step and properties of this frame are copied.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the unit cell of this frame is infinite, or if
+    /// any of `nx`, `ny` or `nz` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, UnitCell};
+    /// let mut frame = Frame::new();
+    /// frame.set_cell(&UnitCell::new([3.0, 3.0, 3.0]));
+    /// frame.add_atom(&Atom::new("Na"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("Cl"), [1.5, 1.5, 1.5], None);
+    ///
+    /// let supercell = frame.supercell(2, 2, 2).unwrap();
+    /// assert_eq!(supercell.size(), 16);
+    /// assert_eq!(supercell.cell().lengths(), [6.0, 6.0, 6.0]);
+    /// assert_eq!(supercell.positions()[3], [0.0, 0.0, 3.0]);
+    /// ```
+    pub fn supercell(&self, nx: usize, ny: usize, nz: usize) -> Result<Frame, Error> {
+        let cell = self.cell();
+        let matrix = cell.matrix();
+        let inverse = match linalg::inverse(&matrix) {
+            Some(inverse) if cell.shape() != CellShape::Infinite => inverse,
+            _ => {
+                return Err(Error::new(
+                    Status::ChemfilesError,
+                    "can not build a supercell for a frame with an infinite unit cell",
+                ))
+            }
+        };
+
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!("supercell replications must be positive, got {nx}x{ny}x{nz}"),
+            ));
+        }
+
+        let counts = [nx, ny, nz];
+        let mut images = Vec::with_capacity(nx * ny * nz);
+        for i in 0..nx {
+            for j in 0..ny {
+                for k in 0..nz {
+                    images.push([i, j, k]);
+                }
+            }
+        }
+        // index of the replica with the given shift along each cell vector
+        let image_index = |[i, j, k]: [usize; 3]| (i * ny + j) * nz + k;
+
+        let mut supercell = self.empty_copy();
+        let mut supercell_matrix = matrix;
+        for row in &mut supercell_matrix {
+            for (value, &count) in row.iter_mut().zip(&counts) {
+                #[allow(clippy::cast_precision_loss)]
+                let count = count as f64;
+                *value *= count;
+            }
+        }
+        supercell.set_cell(&UnitCell::from_matrix(supercell_matrix));
+
+        let positions = self.positions();
+        let velocities = self.velocities();
+        for image in &images {
+            #[allow(clippy::cast_precision_loss)]
+            let shift = linalg::mat_vec(&matrix, [image[0] as f64, image[1] as f64, image[2] as f64]);
+            for (i, &position) in positions.iter().enumerate() {
+                let velocity = velocities.map(|v| v[i]);
+                supercell.add_atom(&self.atom(i), linalg::add(position, shift), velocity);
+            }
+        }
+
+        let size = self.size();
+        let topology = self.topology();
+        for ([i, j], order) in topology.bonds().into_iter().zip(topology.bond_orders()) {
+            // find in which neighboring replica the bonded atom is, using the
+            // minimum image convention
+            let vector = linalg::minimum_image(&matrix, &inverse, linalg::sub(positions[j], positions[i]));
+            let partner = linalg::sub(linalg::add(positions[i], vector), positions[j]);
+            let offset = linalg::mat_vec(&inverse, partner);
+
+            for image in &images {
+                let mut other = [0; 3];
+                for d in 0..3 {
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+                    let shifted = (image[d] as i64 + offset[d].round() as i64).rem_euclid(counts[d] as i64) as usize;
+                    other[d] = shifted;
+                }
+                supercell.add_bond_with_order(image_index(*image) * size + i, image_index(other) * size + j, order);
+            }
+        }
+
+        let residues = topology.iter_residues().collect::<Vec<_>>();
+        let ids = residues.iter().filter_map(|residue| residue.id());
+        let span = match (ids.clone().min(), ids.max()) {
+            (Some(min), Some(max)) => max - min + 1,
+            _ => 0,
+        };
+        for image in &images {
+            let index = image_index(*image);
+            for residue in &residues {
+                let atoms = residue
+                    .atoms()
+                    .into_iter()
+                    .map(|atom| index * size + atom)
+                    .collect::<Vec<_>>();
+                #[allow(clippy::cast_possible_wrap)]
+                let id = residue.id().map(|id| id + index as i64 * span);
+                supercell
+                    .add_residue(&renumbered_residue(residue, id, &atoms))
+                    .expect("replicated residues should have unique ids");
+            }
+        }
+
+        return Ok(supercell);
+    }
+
+    /// Turn this frame into an immutable snapshot which can be shared between
+    /// threads. Use `frame.clone().freeze()` to keep the original frame
+    /// around, and `FrozenFrame::thaw` to get back a mutable frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+    ///
+    /// let frozen = frame.freeze();
+    /// assert_eq!(frozen.size(), 1);
+    /// ```
+    pub fn freeze(self) -> Arc<FrozenFrame> {
+        // compute and cache the angles, dihedrals and impropers now, since
+        // doing it later could happen concurrently from multiple threads
+        let _ = self.topology().angles_count();
+        Arc::new(FrozenFrame { frame: self })
+    }
+
+    /// Swap clockwise and counter-clockwise chirality annotations after an
+    /// improper rotation
+    fn swap_chirality(&mut self) {
+        for i in 0..self.size() {
+            let swapped = match self.atom(i).get("chirality") {
+                Some(Property::String(ref chirality)) if chirality == "CW" => "CCW",
+                Some(Property::String(ref chirality)) if chirality == "CCW" => "CW",
+                _ => continue,
+            };
+            self.atom_mut(i).set("chirality", swapped);
+        }
+    }
+
+    /// Create a new frame containing only the atoms at the given `indexes`
+    /// in this frame, in the same order. Positions, velocities, bonds between
+    /// the selected atoms, residues (restricted to the selected atoms), the
+    /// unit cell, the step and the frame properties are copied.
+    pub(crate) fn subset(&self, indexes: &[usize]) -> Frame {
+        let mut subset = self.empty_copy();
+        let velocities = self.velocities();
+        let positions = self.positions();
+        let mut new_indexes = vec![None; self.size()];
+        for (new, &old) in indexes.iter().enumerate() {
+            new_indexes[old] = Some(new);
+            subset.add_atom(&self.atom(old), positions[old], velocities.map(|v| v[old]));
+        }
+
+        let topology = self.topology();
+        for (bond, order) in topology.bonds().into_iter().zip(topology.bond_orders()) {
+            if let (Some(i), Some(j)) = (new_indexes[bond[0]], new_indexes[bond[1]]) {
+                subset.add_bond_with_order(i, j, order);
+            }
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        for i in 0..topology.residues_count() as usize {
+            let residue = topology.residue(i).expect("residue should exist");
+            let atoms = residue
+                .atoms()
+                .into_iter()
+                .filter_map(|atom| new_indexes[atom])
+                .collect::<Vec<_>>();
+            if atoms.is_empty() {
+                continue;
+            }
+
+            subset
+                .add_residue(&restricted_residue(&residue, &atoms))
+                .expect("residues from a valid frame should be valid");
+        }
+
+        return subset;
+    }
+
+    /// Create a new frame without atoms, with the same unit cell, step and
+    /// properties as this frame, and velocities if this frame has them.
+    fn empty_copy(&self) -> Frame {
+        let mut copy = Frame::new();
+        copy.set_cell(&self.cell());
+        copy.set_step(self.step());
+        for (name, property) in self.properties() {
+            copy.set(&name, property);
+        }
+        if self.has_velocities() {
+            copy.add_velocities();
+        }
+        return copy;
+    }
+}
+
+/// Create a copy of `residue` (with the same name, id and properties)
+/// containing the given `atoms` instead of the residue atoms.
+fn restricted_residue(residue: &Residue, atoms: &[usize]) -> Residue {
+    return renumbered_residue(residue, residue.id(), atoms);
+}
+
+/// Create a copy of `residue` (with the same name and properties) with the
+/// given `id` and containing the given `atoms` instead of the residue atoms.
+fn renumbered_residue(residue: &Residue, id: Option<i64>, atoms: &[usize]) -> Residue {
+    let name = residue.name();
+    let mut copy = match id {
+        Some(id) => Residue::with_id(name.as_str(), id),
+        None => Residue::new(name.as_str()),
+    };
+    for &atom in atoms {
+        copy.add_atom(atom);
+    }
+    for (name, property) in residue.properties() {
+        copy.set(&name, property);
+    }
+    return copy;
+}
+
+#[cfg(feature = "ndarray")]
+impl Frame {
+    /// Get a view of the positions of the atoms in this frame as a 2D
+    /// `ndarray` array with shape `(size, 3)`, without copying the data.
+    ///
+    /// This function is only available with the `ndarray` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [1.0, 2.0, 3.0], None);
+    /// frame.add_atom(&Atom::new("H"), [4.0, 5.0, 6.0], None);
+    ///
+    /// let positions = frame.positions_array();
+    /// assert_eq!(positions.shape(), [2, 3]);
+    /// assert_eq!(positions[[1, 2]], 6.0);
+    /// ```
+    pub fn positions_array(&self) -> ndarray::ArrayView2<'_, f64> {
+        ndarray::ArrayView2::from(self.positions())
+    }
+
+    /// Get a mutable view of the positions of the atoms in this frame as a
+    /// 2D `ndarray` array with shape `(size, 3)`, without copying the data.
+    ///
+    /// This function is only available with the `ndarray` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [1.0, 2.0, 3.0], None);
+    ///
+    /// let mut positions = frame.positions_array_mut();
+    /// positions += 1.0;
+    /// assert_eq!(frame.positions(), [[2.0, 3.0, 4.0]]);
+    /// ```
+    pub fn positions_array_mut(&mut self) -> ndarray::ArrayViewMut2<'_, f64> {
+        ndarray::ArrayViewMut2::from(self.positions_mut())
+    }
+
+    /// Get a view of the velocities of the atoms in this frame as a 2D
+    /// `ndarray` array with shape `(size, 3)`, without copying the data, or
+    /// `None` if the frame does not contain velocity data.
+    ///
+    /// This function is only available with the `ndarray` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// assert!(frame.velocities_array().is_none());
+    ///
+    /// frame.add_velocities();
+    /// frame.add_atom(&Atom::new("O"), [0.0; 3], [1.0, 2.0, 3.0]);
+    /// assert_eq!(frame.velocities_array().unwrap()[[0, 1]], 2.0);
+    /// ```
+    pub fn velocities_array(&self) -> Option<ndarray::ArrayView2<'_, f64>> {
+        self.velocities().map(ndarray::ArrayView2::from)
+    }
+
+    /// Get a mutable view of the velocities of the atoms in this frame as a
+    /// 2D `ndarray` array with shape `(size, 3)`, without copying the data,
+    /// or `None` if the frame does not contain velocity data.
+    ///
+    /// This function is only available with the `ndarray` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_velocities();
+    /// frame.add_atom(&Atom::new("O"), [0.0; 3], [1.0, 2.0, 3.0]);
+    ///
+    /// frame.velocities_array_mut().unwrap().fill(0.0);
+    /// assert_eq!(frame.velocities().unwrap(), [[0.0; 3]]);
+    /// ```
+    pub fn velocities_array_mut(&mut self) -> Option<ndarray::ArrayViewMut2<'_, f64>> {
+        self.velocities_mut().map(ndarray::ArrayViewMut2::from)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+const _: () = {
+    // the slice casts below rely on nalgebra points and vectors having the
+    // same layout as `[f64; 3]`
+    assert!(std::mem::size_of::<nalgebra::Point3<f64>>() == std::mem::size_of::<[f64; 3]>());
+    assert!(std::mem::align_of::<nalgebra::Point3<f64>>() == std::mem::align_of::<[f64; 3]>());
+    assert!(std::mem::size_of::<nalgebra::Vector3<f64>>() == std::mem::size_of::<[f64; 3]>());
+    assert!(std::mem::align_of::<nalgebra::Vector3<f64>>() == std::mem::align_of::<[f64; 3]>());
+};
+
+#[cfg(feature = "nalgebra")]
+impl Frame {
+    /// Get a view of the positions of the atoms in this frame as `nalgebra`
+    /// points, without copying the data.
+    ///
+    /// This function is only available with the `nalgebra` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// # use nalgebra::Point3;
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [1.0, 2.0, 3.0], None);
+    /// frame.add_atom(&Atom::new("H"), [4.0, 5.0, 6.0], None);
+    ///
+    /// let positions = frame.positions_na();
+    /// assert_eq!(nalgebra::distance(&positions[0], &positions[1]), f64::sqrt(27.0));
+    /// assert_eq!(positions[1], Point3::new(4.0, 5.0, 6.0));
+    /// ```
+    pub fn positions_na(&self) -> &[nalgebra::Point3<f64>] {
+        let positions = self.positions();
+        // SAFETY: `Point3<f64>` is a `#[repr(C)]` wrapper around a
+        // `Vector3<f64>`, itself a `#[repr(C)]` matrix containing a
+        // `#[repr(transparent)]` `[[f64; 3]; 1]` array storage. It has the
+        // same size and alignment as `[f64; 3]` (checked at compile time
+        // above), so a slice of `[f64; 3]` can be reinterpreted as a slice of
+        // points with the same length. The returned slice borrows from
+        // `self` through the signature of this function, like `positions`.
+        unsafe { std::slice::from_raw_parts(positions.as_ptr().cast(), positions.len()) }
+    }
+
+    /// Get a mutable view of the positions of the atoms in this frame as
+    /// `nalgebra` points, without copying the data.
+    ///
+    /// This function is only available with the `nalgebra` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// # use nalgebra::{Point3, Rotation3, Vector3};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [1.0, 0.0, 0.0], None);
+    ///
+    /// let rotation = Rotation3::from_axis_angle(&Vector3::z_axis(), std::f64::consts::FRAC_PI_2);
+    /// for position in frame.positions_na_mut() {
+    ///     *position = rotation * *position;
+    /// }
+    ///
+    /// let position = frame.positions_na()[0];
+    /// assert!((position - Point3::new(0.0, 1.0, 0.0)).norm() < 1e-12);
+    /// ```
+    pub fn positions_na_mut(&mut self) -> &mut [nalgebra::Point3<f64>] {
+        let positions = self.positions_mut();
+        // SAFETY: see `Frame::positions_na` for the layout. The mutable
+        // slice is created from the unique borrow of `positions`, and its
+        // lifetime is tied to `&mut self`, so no other access to the
+        // positions can happen while it is alive.
+        unsafe { std::slice::from_raw_parts_mut(positions.as_mut_ptr().cast(), positions.len()) }
+    }
+
+    /// Get a view of the velocities of the atoms in this frame as `nalgebra`
+    /// vectors, without copying the data, or `None` if the frame does not
+    /// contain velocity data.
+    ///
+    /// This function is only available with the `nalgebra` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// assert!(frame.velocities_na().is_none());
+    ///
+    /// frame.add_velocities();
+    /// frame.add_atom(&Atom::new("O"), [0.0; 3], [3.0, 0.0, 4.0]);
+    /// assert_eq!(frame.velocities_na().unwrap()[0].norm(), 5.0);
+    /// ```
+    pub fn velocities_na(&self) -> Option<&[nalgebra::Vector3<f64>]> {
+        let velocities = self.velocities()?;
+        // SAFETY: `Vector3<f64>` is a `#[repr(C)]` matrix containing a
+        // `#[repr(transparent)]` `[[f64; 3]; 1]` array storage, with the same
+        // size and alignment as `[f64; 3]` (checked at compile time above).
+        // The returned slice borrows from `self` like `velocities`.
+        return Some(unsafe { std::slice::from_raw_parts(velocities.as_ptr().cast(), velocities.len()) });
+    }
+
+    /// Get a mutable view of the velocities of the atoms in this frame as
+    /// `nalgebra` vectors, without copying the data, or `None` if the frame
+    /// does not contain velocity data.
+    ///
+    /// This function is only available with the `nalgebra` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_velocities();
+    /// frame.add_atom(&Atom::new("O"), [0.0; 3], [3.0, 0.0, 4.0]);
+    ///
+    /// for velocity in frame.velocities_na_mut().unwrap() {
+    ///     *velocity *= 2.0;
+    /// }
+    /// assert_eq!(frame.velocities().unwrap(), [[6.0, 0.0, 8.0]]);
+    /// ```
+    pub fn velocities_na_mut(&mut self) -> Option<&mut [nalgebra::Vector3<f64>]> {
+        let velocities = self.velocities_mut()?;
+        // SAFETY: see `Frame::velocities_na` for the layout. The mutable
+        // slice is created from the unique borrow of `velocities`, and its
+        // lifetime is tied to `&mut self`.
+        return Some(unsafe { std::slice::from_raw_parts_mut(velocities.as_mut_ptr().cast(), velocities.len()) });
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = ffi::chfl_free(self.as_ptr().cast());
+        }
+    }
+}
+
+// SAFETY: a `Frame` owns its C++ object, which can be used from any thread.
+// Like `Topology`, a `Frame` is not `Sync` because of the lazily computed
+// angles, dihedrals and impropers in its topology. Use `Frame::freeze` to
+// share a frame between threads.
+unsafe impl Send for Frame {}
+
+/// An immutable snapshot of a `Frame`, created by `Frame::freeze`.
+///
+/// Contrary to `Frame`, a `FrozenFrame` can be shared between threads (it
+/// implements `Send` and `Sync`), for example to keep parsed structures in a
+/// cache used by multiple analysis threads. All the read-only methods of
+/// `Frame` are available through `Deref`, and `FrozenFrame::thaw` creates a
+/// new mutable `Frame` with the same data.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, Frame};
+/// let mut frame = Frame::new();
+/// frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+/// let frozen = frame.freeze();
+///
+/// let handle = {
+///     let frozen = std::sync::Arc::clone(&frozen);
+///     std::thread::spawn(move || frozen.positions()[0][1])
+/// };
+/// assert_eq!(handle.join().unwrap(), 2.0);
+/// assert_eq!(frozen.atom(0).name(), "Zn");
+/// ```
+#[derive(Debug)]
+pub struct FrozenFrame {
+    frame: Frame,
+}
+
+// SAFETY: the frame is never modified after being frozen, since only shared
+// references to it can be obtained, and the lazily computed data in the
+// topology is computed by `Frame::freeze`. The C++ library allows concurrent
+// reads of the same object from multiple threads, and the creation and
+// release of handles to sub-objects (atoms, topology, cell) is protected by a
+// lock.
+unsafe impl Send for FrozenFrame {}
+unsafe impl Sync for FrozenFrame {}
+
+impl FrozenFrame {
+    /// Create a new mutable `Frame` containing a copy of the data in this
+    /// frozen frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+    /// let frozen = frame.freeze();
+    ///
+    /// let mut frame = frozen.thaw();
+    /// frame.positions_mut()[0] = [0.0, 0.0, 0.0];
+    /// assert_eq!(frozen.positions()[0], [1.0, 2.0, 3.0]);
+    /// ```
+    pub fn thaw(&self) -> Frame {
+        self.frame.clone()
+    }
+}
+
+impl std::ops::Deref for FrozenFrame {
+    type Target = Frame;
+    fn deref(&self) -> &Frame {
+        &self.frame
+    }
+}
+
+impl<'a> Iterator for AtomIter<'a> {
+    type Item = AtomRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.size <= self.index {
+            return None;
+        }
+        let atom = self.frame.atom(self.index);
+        self.index += 1;
+        Some(atom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bulk_atoms() {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+
+        assert_eq!(frame.names(), ["O", "H", "H"]);
+        assert_eq!(frame.types(), ["O", "H", "H"]);
+        assert_eq!(frame.charges(), [0.0, 0.0, 0.0]);
+        assert_eq!(frame.masses()[1], Atom::new("H").mass());
+
+        frame.set_names(&["OW".to_string(), "HW1".into(), "HW2".into()]);
+        frame.set_types(&["Ow", "Hw", "Hw"]);
+        frame.set_masses(&[15.0, 1.5, 1.5]);
+        frame.set_charges(&[-0.8, 0.4, 0.4]);
+
+        assert_eq!(frame.atom(2).name(), "HW2");
+        assert_eq!(frame.atom(1).atomic_type(), "Hw");
+        assert_eq!(frame.masses(), [15.0, 1.5, 1.5]);
+        assert_eq!(frame.charges(), [-0.8, 0.4, 0.4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 1 charges for this frame, got 2")]
+    fn bulk_atoms_size() {
+        let mut frame = Frame::new();
+        frame.resize(1);
+        frame.set_charges(&[0.0, 1.0]);
+    }
+
+    #[test]
+    fn dump_columns() {
+        let mut frame = Frame::new();
+        assert!(frame.dump_columns().is_empty());
+
+        frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("Ar"), [1.0, 0.0, 0.0], None);
+        frame.set_dump_column("fx", &[0.5, -0.5]);
+        frame.set_dump_column("c_pe", &[-1.2, -1.3]);
+        frame.atom_mut(0).set("flag", 1.0);
+        frame.atom_mut(0).set("label", "a");
+        frame.atom_mut(1).set("label", "b");
+
+        assert_eq!(frame.dump_columns(), ["c_pe", "fx"]);
+        assert_eq!(frame.dump_column("c_pe"), Some(vec![-1.2, -1.3]));
+        assert_eq!(frame.dump_column("flag"), None);
+        assert_eq!(frame.dump_column("label"), None);
+
+        let content = "ITEM: TIMESTEP
+0
+ITEM: NUMBER OF ATOMS
+2
+ITEM: BOX BOUNDS pp pp pp
+0.0 10.0
+0.0 10.0
+0.0 10.0
+ITEM: ATOMS id type x y z c_pe fx
+1 1 0.0 0.0 0.0 -1.5 0.25
+2 1 1.0 0.0 0.0 -1.75 -0.25
+";
+        let mut trajectory = crate::Trajectory::memory_reader(content, "LAMMPS").unwrap();
+        let mut frame = Frame::new();
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.dump_columns(), ["c_pe", "fx"]);
+        assert_eq!(frame.dump_column("fx"), Some(vec![0.25, -0.25]));
+
+        // the columns are written back as additional columns
+        frame.set_dump_column("c_ke", &[0.5, 0.75]);
+        let mut writer = crate::Trajectory::memory_writer("LAMMPS").unwrap();
+        writer.write(&frame).unwrap();
+        let written = writer.memory_buffer().unwrap().to_owned();
+        for column in ["c_ke", "c_pe", "fx"] {
+            assert!(written.contains(column), "missing {column} column in\n{written}");
+        }
+
+        let mut trajectory = crate::Trajectory::memory_reader(written.as_str(), "LAMMPS").unwrap();
+        let mut reread = Frame::new();
+        trajectory.read(&mut reread).unwrap();
+        for column in ["c_ke", "c_pe", "fx"] {
+            assert_eq!(reread.dump_column(column), frame.dump_column(column));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 1 values for this frame, got 2")]
+    fn dump_column_size() {
+        let mut frame = Frame::new();
+        frame.resize(1);
+        frame.set_dump_column("c_pe", &[0.0, 1.0]);
+    }
+
+    #[test]
+    fn subset() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        frame.set_step(42);
+        frame.set("name", "water");
+        frame.add_velocities();
+        frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]);
+        frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], [3.0, 0.0, 0.0]);
+        frame.add_bond_with_order(0, 1, BondOrder::Single);
+        frame.add_bond(0, 2);
+
+        let mut residue = Residue::with_id("HOH", 3);
+        residue.add_atom(0);
+        residue.add_atom(1);
+        residue.add_atom(2);
+        residue.set("chainid", "A");
+        frame.add_residue(&residue).unwrap();
+
+        let subset = frame.subset(&[1, 0]);
+        assert_eq!(subset.size(), 2);
+        assert_eq!(subset.step(), 42);
+        assert_eq!(subset.cell().lengths(), [10.0, 10.0, 10.0]);
+        assert_eq!(subset.get("name"), Some(Property::String("water".into())));
+        assert_eq!(subset.atom(0).name(), "H");
+        assert_eq!(subset.positions(), &[[1.0, 0.0, 0.0], [0.0, 0.0, 0.0]]);
+        assert_eq!(subset.velocities().unwrap(), &[[2.0, 0.0, 0.0], [1.0, 0.0, 0.0]]);
+
+        let topology = subset.topology();
+        assert_eq!(topology.bonds(), [[0, 1]]);
+        assert_eq!(topology.bond_orders(), [BondOrder::Single]);
+        assert_eq!(topology.residues_count(), 1);
+        let residue = topology.residue(0).unwrap();
+        assert_eq!(residue.id(), Some(3));
+        assert_eq!(residue.atoms(), [0, 1]);
+        assert_eq!(residue.get("chainid"), Some(Property::String("A".into())));
+    }
+
+    #[test]
+    fn extract_chain() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        for i in 0..6 {
+            frame.add_atom(&Atom::new("C"), [f64::from(i), 0.0, 0.0], None);
+        }
+        frame.add_bond(0, 1);
+        frame.add_bond(1, 4);
+        frame.add_bond(2, 3);
+
+        // chain A is made of two residues, with atoms interleaved with chain B
+        let chains = [("A", vec![0, 1]), ("B", vec![2, 3]), ("A", vec![4]), ("", vec![5])];
+        for (id, (chain, atoms)) in (0..).zip(&chains) {
+            let mut residue = Residue::with_id("ALA", id);
+            for &atom in atoms {
+                residue.add_atom(atom);
+            }
+            if !chain.is_empty() {
+                residue.set("chainid", *chain);
+            }
+            frame.add_residue(&residue).unwrap();
+        }
+
+        let chain = frame.extract_chain("A");
+        assert_eq!(chain.size(), 3);
+        assert_eq!(chain.positions(), [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [4.0, 0.0, 0.0]]);
+        assert_eq!(chain.cell().lengths(), [10.0, 10.0, 10.0]);
+
+        let topology = chain.topology();
+        assert_eq!(topology.bonds(), [[0, 1], [1, 2]]);
+        assert_eq!(topology.residues_count(), 2);
+        assert_eq!(topology.residue(1).unwrap().atoms(), [2]);
+
+        let chain = frame.extract_chain("B");
+        assert_eq!(chain.size(), 2);
+        assert_eq!(chain.topology().bonds(), [[0, 1]]);
+
+        assert_eq!(frame.extract_chain("Z").size(), 0);
+    }
+
+    #[test]
+    fn supercell() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([4.0, 5.0, 6.0]));
+        frame.set_step(3);
+        frame.add_velocities();
+        // molecule crossing the periodic boundary along x
+        frame.add_atom(&Atom::new("C"), [3.5, 1.0, 1.0], [1.0, 0.0, 0.0]);
+        frame.add_atom(&Atom::new("O"), [0.5, 1.0, 1.0], [2.0, 0.0, 0.0]);
+        frame.add_bond_with_order(0, 1, BondOrder::Double);
+
+        let mut residue = Residue::with_id("CO", 7);
+        residue.add_atom(0);
+        residue.add_atom(1);
+        residue.set("chainid", "A");
+        frame.add_residue(&residue).unwrap();
+
+        let supercell = frame.supercell(2, 1, 3).unwrap();
+        assert_eq!(supercell.size(), 12);
+        assert_eq!(supercell.step(), 3);
+        assert_eq!(supercell.cell().lengths(), [8.0, 5.0, 18.0]);
+        assert_eq!(supercell.positions()[2], [3.5, 1.0, 7.0]);
+        assert_eq!(supercell.positions()[7], [4.5, 1.0, 1.0]);
+        assert_eq!(supercell.velocities().unwrap()[7], [2.0, 0.0, 0.0]);
+        assert_eq!(supercell.atom(7).name(), "O");
+
+        // bonds across the boundary connect to the next replica along x
+        let topology = supercell.topology();
+        let bonds = topology.bonds();
+        assert_eq!(bonds.len(), 6);
+        assert!(bonds.contains(&[0, 7]));
+        assert!(bonds.contains(&[1, 6]));
+        assert_eq!(topology.bond_orders(), [BondOrder::Double; 6]);
+
+        assert_eq!(topology.residues_count(), 6);
+        let residue = topology.residue_for_atom(7).unwrap();
+        assert_eq!(residue.id(), Some(10));
+        assert_eq!(residue.atoms(), [6, 7]);
+        assert_eq!(residue.get("chainid"), Some(Property::String("A".into())));
+
+        assert!(frame.supercell(0, 1, 1).is_err());
+        frame.set_cell(&UnitCell::infinite());
+        assert!(frame.supercell(2, 2, 2).is_err());
+    }
+
+    #[test]
+    fn split_molecules() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        frame.set("name", "water dimer");
+        for i in 0..2 {
+            let x = 5.0 * f64::from(i);
+            frame.add_atom(&Atom::new("O"), [x, 0.0, 0.0], None);
+            frame.add_atom(&Atom::new("H"), [x + 1.0, 0.0, 0.0], None);
+        }
+        frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+        frame.add_atom(&Atom::new("H"), [5.0, 1.0, 0.0], None);
+        frame.add_bond_with_order(0, 1, BondOrder::Single);
+        frame.add_bond(0, 4);
+        frame.add_bond(2, 3);
+        frame.add_bond(2, 5);
+
+        // a single residue spanning both molecules
+        let mut residue = Residue::with_id("WAT", 1);
+        for i in 0..6 {
+            residue.add_atom(i);
+        }
+        frame.add_residue(&residue).unwrap();
+
+        let molecules = frame.split_molecules();
+        assert_eq!(molecules.len(), 2);
+        for molecule in &molecules {
+            assert_eq!(molecule.size(), 3);
+            assert_eq!(molecule.cell().lengths(), [10.0, 10.0, 10.0]);
+            assert_eq!(molecule.get("name"), Some(Property::String("water dimer".into())));
+            assert_eq!(molecule.topology().bonds(), [[0, 1], [0, 2]]);
+
+            let topology = molecule.topology();
+            let residue = topology.residue(0).unwrap();
+            assert_eq!(residue.id(), Some(1));
+            assert_eq!(residue.atoms(), [0, 1, 2]);
+        }
+
+        assert_eq!(molecules[0].topology().bond_order(0, 1), BondOrder::Single);
+        assert_eq!(
+            molecules[1].positions(),
+            [[5.0, 0.0, 0.0], [6.0, 0.0, 0.0], [5.0, 1.0, 0.0]]
+        );
+        assert!(Frame::new().split_molecules().is_empty());
+    }
+
+    #[test]
+    fn freeze() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FrozenFrame>();
+
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        for i in 0..100 {
+            let x = f64::from(i);
+            frame.add_atom(&Atom::new("Ar"), [x, 0.0, 0.0], None);
+        }
+        let frozen = frame.freeze();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let frozen = Arc::clone(&frozen);
+                let _ = scope.spawn(move || {
+                    let sum = frozen.positions().iter().map(|position| position[0]).sum::<f64>();
+                    assert_eq!(sum, 4950.0);
+                    assert_eq!(frozen.cell().lengths(), [10.0, 10.0, 10.0]);
+                    assert!(frozen.iter_atoms().all(|atom| atom.name() == "Ar"));
+                });
+            }
+        });
+
+        let mut thawed = frozen.thaw();
+        thawed.resize(3);
+        thawed.atom_mut(0).set_name("Kr");
+        assert_eq!(frozen.size(), 100);
+        assert_eq!(frozen.atom(0).name(), "Ar");
+    }
+
+    #[test]
+    fn freeze_topology() {
+        // a branched molecule, with angles, dihedrals and impropers
+        let mut frame = Frame::new();
+        for i in 0..6 {
+            frame.add_atom(&Atom::new("C"), [f64::from(i), 0.0, 0.0], None);
+        }
+        for i in 0..4 {
+            frame.add_bond(i, i + 1);
+        }
+        frame.add_bond(1, 5);
+
+        // compute the expected values on a copy, to keep the lazily computed
+        // angles, dihedrals and impropers of `frame` out of date
+        let copy = frame.clone();
+        let expected = copy.topology();
+        let (angles, dihedrals, impropers) = (expected.angles(), expected.dihedrals(), expected.impropers());
+        assert!(!impropers.is_empty());
+
+        // all the threads read the angles, dihedrals and impropers of the
+        // same frozen frame at the same time
+        let frozen = frame.freeze();
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                let frozen = Arc::clone(&frozen);
+                let (angles, dihedrals, impropers) = (&angles, &dihedrals, &impropers);
+                let _ = scope.spawn(move || {
+                    for _ in 0..50 {
+                        let topology = frozen.topology();
+                        assert_eq!(&topology.angles(), angles);
+                        assert_eq!(&topology.dihedrals(), dihedrals);
+                        assert_eq!(&topology.impropers(), impropers);
+                        assert_eq!(topology.bonds_count(), 5);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn centers() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        let mut virtual_site = Atom::new("M");
+        virtual_site.set_virtual(true);
+        frame.add_atom(&Atom::new("O"), [9.5, 5.0, 5.0], None);
+        frame.add_atom(&Atom::new("H"), [0.5, 5.0, 5.0], None);
+        frame.add_atom(&Atom::new("H"), [9.5, 6.0, 5.0], None);
+        frame.add_atom(&virtual_site, [2.0, 2.0, 2.0], None);
+
+        let centroid = frame.centroid("not name M", false).unwrap();
+        crate::assert_vector3d_eq(&centroid, &[6.5, 16.0 / 3.0, 5.0], 1e-12);
+
+        let centroid = frame.centroid("not name M", true).unwrap();
+        crate::assert_vector3d_eq(&centroid, &[29.5 / 3.0, 16.0 / 3.0, 5.0], 1e-12);
+
+        // the center does not depend on the periodic image of the atoms
+        let mut shifted = frame.clone();
+        shifted.positions_mut()[0][0] -= 30.0;
+        shifted.positions_mut()[1][2] += 20.0;
+        let centroid = shifted.centroid("not name M", true).unwrap();
+        crate::assert_vector3d_eq(&centroid, &[29.5 / 3.0, 16.0 / 3.0, 5.0], 1e-12);
+
+        // the virtual site is ignored in the center of mass
+        let total = frame.center_of_mass("all", false).unwrap();
+        let without = frame.center_of_mass("not name M", false).unwrap();
+        crate::assert_vector3d_eq(&total, &without, 1e-12);
+
+        // pairs of atoms at the same distance from the boundary
+        let center = frame.center_of_mass("index 0 1", true).unwrap();
+        let distance = f64::min(center[0], 10.0 - center[0]);
+        assert!(distance < 0.5);
+        approx::assert_ulps_eq!(center[1], 5.0, epsilon = 1e-12);
+
+        assert!(frame.center_of_mass("name M", false).is_err());
+        assert!(frame.centroid("name Zn", false).is_err());
+        assert!(frame.centroid("pairs: all", false).is_err());
+
+        frame.translate([1.0, -1.0, 0.5]);
+        assert_eq!(frame.positions()[3], [3.0, 1.0, 2.5]);
+    }
+
+    #[test]
+    fn debug_validate() {
+        let mut frame = Frame::new();
+        frame.add_velocities();
+        frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+        frame.add_bond(0, 1);
+        assert_eq!(frame.integrity_problem(), None);
+        frame.debug_validate();
+
+        frame.positions_mut()[1][2] = f64::NAN;
+        let problem = frame.integrity_problem().unwrap();
+        assert!(problem.starts_with("position of atom 1 is not finite"));
+
+        frame.positions_mut()[1][2] = 0.0;
+        frame.velocities_mut().unwrap()[0][0] = f64::INFINITY;
+        let problem = frame.integrity_problem().unwrap();
+        assert!(problem.starts_with("velocity of atom 0 is not finite"));
+
+        frame.velocities_mut().unwrap()[0][0] = 0.0;
+        frame.atom_mut(0).set_mass(-1.0);
+        assert_eq!(frame.integrity_problem().unwrap(), "mass of atom 0 is invalid: -1");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid frame at step 0: position of atom 0 is not finite")]
+    fn debug_validate_panic() {
+        let mut frame = Frame::new();
+        frame.resize(1);
+        frame.positions_mut()[0][0] = f64::NAN;
+        frame.debug_validate();
+    }
+
+    #[test]
+    fn gyration() {
+        let mut frame = Frame::new();
+        let mut virtual_site = Atom::new("M");
+        virtual_site.set_virtual(true);
+        frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("O"), [0.0, 2.0, 0.0], None);
+        frame.add_atom(&Atom::new("O"), [0.0, 0.0, 2.0], None);
+        frame.add_atom(&virtual_site, [10.0, 10.0, 10.0], None);
+
+        let mass = frame.atom(0).mass();
+        // distances to the center (0, 2/3, 2/3) are sqrt(8/9), sqrt(20/9), sqrt(20/9)
+        let radius = frame.gyration_radius("all").unwrap();
+        approx::assert_ulps_eq!(radius, f64::sqrt(16.0 / 9.0), epsilon = 1e-12);
+        approx::assert_ulps_eq!(frame.gyration_radius("index 0 1").unwrap(), 1.0, epsilon = 1e-12);
+
+        let tensor = frame.inertia_tensor("all").unwrap();
+        let expected = [
+            [16.0 / 3.0, 0.0, 0.0],
+            [0.0, 8.0 / 3.0, 4.0 / 3.0],
+            [0.0, 4.0 / 3.0, 8.0 / 3.0],
+        ];
+        for (row, expected) in tensor.iter().zip(&expected) {
+            for (&value, &expected) in row.iter().zip(expected) {
+                approx::assert_ulps_eq!(value, mass * expected, epsilon = 1e-10);
+            }
+        }
+
+        // the trace is twice the mass-weighted sum of squared distances
+        let trace = tensor[0][0] + tensor[1][1] + tensor[2][2];
+        approx::assert_ulps_eq!(trace, 2.0 * 3.0 * mass * radius * radius, epsilon = 1e-10);
+
+        assert!(frame.gyration_radius("name M").is_err());
+        assert!(frame.inertia_tensor("name M").is_err());
+        assert!(frame.inertia_tensor("name Zn").is_err());
+    }
+
+    #[test]
+    fn asymmetric_unit() {
+        // rutile TiO2, in the P 42/mnm space group
+        let operations = [
+            "-x,-y,z",
+            "-y+1/2,x+1/2,z+1/2",
+            "y+1/2,-x+1/2,z+1/2",
+            "-x+1/2,y+1/2,-z+1/2",
+            "-x,-y,-z",
+        ];
+        let spacegroup = SpaceGroup::from_operations(&operations).unwrap();
+        assert_eq!(spacegroup.operations_count(), 16);
+
+        let atoms = [(Atom::new("Ti"), [0.0, 0.0, 0.0]), (Atom::new("O"), [0.3, 0.3, 0.0])];
+        let cell = UnitCell::new([4.6, 4.6, 2.96]);
+        let frame = Frame::from_asymmetric_unit(&atoms, &cell, &spacegroup).unwrap();
+
+        assert_eq!(frame.size(), 6);
+        assert_eq!(frame.names(), ["Ti", "Ti", "O", "O", "O", "O"]);
+        assert_eq!(frame.cell().lengths(), [4.6, 4.6, 2.96]);
+        crate::assert_vector3d_eq(&frame.positions()[1], &[2.3, 2.3, 1.48], 1e-12);
+        for position in frame.positions() {
+            let fractional = cell.fractional(position).unwrap();
+            assert!(fractional.iter().all(|&value| (0.0..1.0).contains(&value)));
+        }
+
+        let result = Frame::from_asymmetric_unit(&atoms, &UnitCell::infinite(), &spacegroup);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn mirror() {
+        let mut frame = Frame::new();
+        frame.add_velocities();
+        frame.add_atom(&Atom::new("C"), [1.0, 2.0, 3.0], [0.5, 0.0, 1.0]);
+        frame.add_atom(&Atom::new("F"), [0.0, 0.0, 0.0], None);
+        frame.atom_mut(0).set("chirality", "CW");
+
+        frame.mirror([0.0, 0.0, 1.0], [0.0, 0.0, 2.0]);
+        assert_eq!(frame.positions(), &[[1.0, 2.0, -1.0], [0.0, 0.0, 2.0]]);
+        assert_eq!(frame.velocities().unwrap()[0], [0.5, 0.0, -1.0]);
+        assert_eq!(frame.atom(0).get("chirality"), Some(Property::String("CCW".into())));
+        assert_eq!(frame.atom(1).get("chirality"), None);
+
+        frame.invert([0.0, 0.0, 0.0]);
+        assert_eq!(frame.positions(), &[[-1.0, -2.0, 1.0], [0.0, 0.0, -2.0]]);
+        assert_eq!(frame.velocities().unwrap()[0], [-0.5, 0.0, 1.0]);
+        assert_eq!(frame.atom(0).get("chirality"), Some(Property::String("CW".into())));
+    }
+
+    #[test]
+    fn clone() {
+        let mut frame = Frame::new();
+        assert_eq!(frame.size(), 0);
+        let copy = frame.clone();
+        assert_eq!(copy.size(), 0);
+
+        frame.resize(42);
+        assert_eq!(frame.size(), 42);
+        assert_eq!(copy.size(), 0);
+
+        let copy = frame.try_clone().unwrap();
+        assert_eq!(copy.size(), 42);
+    }
+
+    #[test]
+    fn size() {
+        let mut frame = Frame::new();
+        assert_eq!(frame.size(), 0);
+
+        frame.resize(12);
+        assert_eq!(frame.size(), 12);
+    }
+
+    #[test]
+    fn add_atom() {
+        let mut frame = Frame::new();
+
+        frame.add_atom(&Atom::new("U"), [1.0, 1.0, 2.0], None);
+        assert_eq!(frame.size(), 1);
+        assert_eq!(frame.atom(0).name(), "U");
+
+        let positions = &[[1.0, 1.0, 2.0]];
+        assert_eq!(frame.positions(), positions);
+
+        frame.add_velocities();
+        frame.add_atom(&Atom::new("F"), [1.0, 1.0, 2.0], [4.0, 3.0, 2.0]);
+        assert_eq!(frame.size(), 2);
+        assert_eq!(frame.atom(0).name(), "U");
+        assert_eq!(frame.atom(1).name(), "F");
 
         let positions = &[[1.0, 1.0, 2.0], [1.0, 1.0, 2.0]];
         assert_eq!(frame.positions(), positions);
@@ -1017,6 +3448,10 @@ mod test {
 
         frame.positions_mut().clone_from_slice(expected);
         assert_eq!(frame.positions(), expected);
+
+        let mut empty = Frame::new();
+        assert!(empty.positions().is_empty());
+        assert!(empty.positions_mut().is_empty());
     }
 
     #[test]
@@ -1031,6 +3466,12 @@ mod test {
 
         frame.velocities_mut().unwrap().clone_from_slice(expected);
         assert_eq!(frame.velocities().unwrap(), expected);
+
+        let mut empty = Frame::new();
+        assert!(empty.velocities().is_none());
+        empty.add_velocities();
+        assert!(empty.velocities().unwrap().is_empty());
+        assert!(empty.velocities_mut().unwrap().is_empty());
     }
 
     #[test]
@@ -1039,6 +3480,37 @@ mod test {
         frame.set_cell(&UnitCell::new([3.0, 4.0, 5.0]));
         let cell = frame.cell();
         assert_eq!(cell.lengths(), [3.0, 4.0, 5.0]);
+
+        // modifications through the mutable view are visible in the frame
+        {
+            let mut cell = frame.cell_mut();
+            cell.set_shape(CellShape::Triclinic).unwrap();
+            cell.set_lengths([6.0, 7.0, 8.0]).unwrap();
+            cell.set_angles([80.0, 90.0, 100.0]).unwrap();
+        }
+        assert_eq!(frame.cell().shape(), CellShape::Triclinic);
+        assert_eq!(frame.cell().lengths(), [6.0, 7.0, 8.0]);
+        assert_eq!(frame.cell().angles(), [80.0, 90.0, 100.0]);
+
+        // cloning a view gives an independent copy of the cell
+        let mut copy = (*frame.cell()).clone();
+        copy.set_lengths([1.0, 1.0, 1.0]).unwrap();
+        assert_eq!(frame.cell().lengths(), [6.0, 7.0, 8.0]);
+    }
+
+    #[test]
+    fn wrap() {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Zn"), [12.0, 5.2, -45.3], None);
+        frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+
+        frame.wrap();
+        assert_eq!(frame.positions()[0], [12.0, 5.2, -45.3]);
+
+        frame.set_cell(&UnitCell::new([10.0, 20.0, 30.0]));
+        frame.wrap();
+        crate::assert_vector3d_eq(&frame.positions()[0], &[2.0, 5.2, 14.7], 1e-12);
+        assert_eq!(frame.positions()[1], [1.0, 2.0, 3.0]);
     }
 
     #[test]
@@ -1059,6 +3531,59 @@ mod test {
 
         assert_eq!(frame.atom(0).name(), "Zn");
         assert_eq!(frame.atom(1).name(), "Ar");
+
+        // the view always reflects the current state of the frame
+        frame.atom_mut(0).set_name("Cu");
+        frame.add_bond(0, 1);
+        let topology = frame.topology();
+        assert_eq!(topology.atom(0).name(), "Cu");
+        assert_eq!(topology.bonds(), vec![[0, 1]]);
+
+        // cloning a view gives an independent copy of the topology
+        let mut copy = (*topology).clone();
+        copy.add_atom(&Atom::new("Fe"));
+        assert_eq!(copy.size(), 3);
+        assert_eq!(frame.topology().size(), 2);
+    }
+
+    #[test]
+    fn remove_matching() {
+        let mut frame = Frame::new();
+        frame.add_velocities();
+        frame.set("name", "solvated");
+        for i in 0..3 {
+            let offset = [f64::from(i), 0.0, 0.0];
+            frame.add_atom(&Atom::new("O"), offset, [1.0; 3]);
+            frame.add_atom(&Atom::new("Na"), linalg::add(offset, [0.0, 5.0, 0.0]), [2.0; 3]);
+            frame.add_atom(&Atom::new("H"), linalg::add(offset, [0.0, 1.0, 0.0]), [3.0; 3]);
+        }
+        frame.add_bond(0, 2);
+        frame.add_bond(3, 5);
+        frame.add_bond(6, 8);
+
+        let mut residue = Residue::new("ION");
+        residue.add_atom(4);
+        residue.add_atom(7);
+        frame.add_residue(&residue).unwrap();
+        let mut residue = Residue::new("WAT");
+        residue.add_atom(6);
+        residue.add_atom(8);
+        frame.add_residue(&residue).unwrap();
+
+        let mut selection = Selection::new("name Na").unwrap();
+        assert_eq!(frame.remove_matching(&mut selection), 3);
+        assert_eq!(frame.remove_matching(&mut selection), 0);
+
+        assert_eq!(frame.size(), 6);
+        assert_eq!(frame.atom(1).name(), "H");
+        assert_eq!(frame.positions()[3], [1.0, 1.0, 0.0]);
+        assert_eq!(frame.velocities().unwrap()[3], [3.0; 3]);
+        assert_eq!(frame.topology().bonds(), vec![[0, 1], [2, 3], [4, 5]]);
+        assert_eq!(frame.get("name"), Some(Property::String("solvated".into())));
+
+        let topology = frame.topology();
+        assert_eq!(topology.residues_count(), 1);
+        assert_eq!(topology.residue(0).unwrap().atoms(), vec![4, 5]);
     }
 
     #[test]
@@ -1112,6 +3637,189 @@ mod test {
         frame.add_bond_with_order(300, 7, BondOrder::Unknown);
     }
 
+    #[test]
+    fn guess_bonds() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        frame.add_atom(&Atom::new("O"), [0.5, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("H"), [9.5, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("H"), [0.5, 0.0, 1.0], None);
+        frame.add_atom(&Atom::new("O"), [5.0, 5.0, 5.0], None);
+        frame.add_bond(0, 3);
+
+        frame.guess_bonds_with_tolerance(1.2).unwrap();
+        assert_eq!(frame.topology().bonds(), vec![[0, 1], [0, 2]]);
+        assert_eq!(frame.topology().angles(), vec![[1, 0, 2]]);
+
+        frame.guess_bonds_with_tolerance(0.5).unwrap();
+        assert_eq!(frame.topology().bonds_count(), 0);
+
+        assert!(frame.guess_bonds_with_tolerance(-1.0).is_err());
+        assert!(frame.guess_bonds_with_tolerance(f64::NAN).is_err());
+
+        frame.add_atom(&Atom::new("Xxx"), [2.0, 2.0, 2.0], None);
+        assert!(frame.guess_bonds_with_tolerance(1.2).is_err());
+    }
+
+    #[test]
+    fn altlocs() {
+        use crate::{MemoryTrajectoryReader, Trajectory};
+
+        let pdb = "\
+ATOM      1  N   GLY A  10       1.000   2.000   3.000  1.00 10.00           N
+ATOM      2  CA ASER A  11A      2.000   2.000   3.000  0.60 10.00           C
+ATOM      3  CA BSER A  11A      2.200   2.100   3.000  0.40 10.00           C
+ATOM      4  CB ASER A  11A      3.000   2.000   3.000  0.60 10.00           C
+ATOM      5  CB BSER A  11A      3.100   2.200   3.000  0.40 10.00           C
+ATOM      6  N   ALA A  12       4.000   2.000   3.000  1.00 10.00           N
+END
+";
+        let mut input = MemoryTrajectoryReader::new(pdb.as_bytes(), "PDB").unwrap();
+        let mut frame = Frame::new();
+        input.read(&mut frame).unwrap();
+        assert_eq!(frame.altlocs(), ['A', 'B']);
+
+        assert_eq!(frame.select_altloc('B'), 2);
+        assert_eq!(frame.size(), 4);
+        assert_eq!(frame.altlocs(), ['B']);
+        assert_eq!(frame.atom(0).altloc(), None);
+        assert_eq!(frame.atom(1).altloc(), Some('B'));
+        assert_eq!(frame.positions()[1], [2.2, 2.1, 3.0]);
+        assert_eq!(frame.atom(2).get("occupancy"), Some(Property::Double(1.0)));
+        assert_eq!(frame.atom(3).get("occupancy"), Some(Property::Double(1.0)));
+
+        let topology = frame.topology();
+        let residue = topology.residue_for_atom(1).unwrap();
+        assert_eq!(residue.id(), Some(11));
+        assert_eq!(residue.insertion_code(), Some('A'));
+        assert_eq!(topology.residue_for_atom(3).unwrap().insertion_code(), None);
+
+        // altloc and insertion codes survive a round-trip through PDB
+        let mut output = Trajectory::memory_writer("PDB").unwrap();
+        output.write(&frame).unwrap();
+        let buffer = output.memory_buffer().unwrap();
+        let mut input = MemoryTrajectoryReader::new(buffer.as_bytes(), "PDB").unwrap();
+        let mut roundtrip = Frame::new();
+        input.read(&mut roundtrip).unwrap();
+
+        assert_eq!(roundtrip.size(), 4);
+        assert_eq!(roundtrip.atom(2).altloc(), Some('B'));
+        let topology = roundtrip.topology();
+        assert_eq!(topology.residue_for_atom(2).unwrap().insertion_code(), Some('A'));
+
+        // selecting an unknown altloc only keeps atoms without altloc
+        assert_eq!(frame.select_altloc('Z'), 2);
+        assert_eq!(frame.size(), 2);
+        assert!(frame.altlocs().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "ndarray")]
+    fn ndarray() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 11.0, 12.0]));
+        frame.add_atom(&Atom::new("O"), [1.0, 2.0, 3.0], None);
+        frame.add_atom(&Atom::new("H"), [4.0, 5.0, 6.0], None);
+
+        let positions = frame.positions_array();
+        assert_eq!(positions.shape(), [2, 3]);
+        assert_eq!(positions.row(1).to_vec(), [4.0, 5.0, 6.0]);
+        assert_eq!(positions.column(0).sum(), 5.0);
+        assert!(frame.velocities_array().is_none());
+
+        frame.positions_array_mut().column_mut(2).fill(-1.0);
+        assert_eq!(frame.positions(), [[1.0, 2.0, -1.0], [4.0, 5.0, -1.0]]);
+
+        frame.add_velocities();
+        frame
+            .velocities_array_mut()
+            .unwrap()
+            .row_mut(0)
+            .assign(&ndarray::arr1(&[1.0, 1.0, 1.0]));
+        assert_eq!(frame.velocities().unwrap(), [[1.0, 1.0, 1.0], [0.0, 0.0, 0.0]]);
+
+        let matrix = frame.cell().matrix_array();
+        assert_eq!(matrix, ndarray::arr2(&frame.cell().matrix()));
+        assert_eq!(matrix.diag().to_vec(), [10.0, 11.0, 12.0]);
+    }
+
+    #[test]
+    #[cfg(feature = "nalgebra")]
+    fn nalgebra() {
+        use nalgebra::{Matrix3, Point3, Vector3};
+
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 11.0, 12.0]));
+        frame.add_atom(&Atom::new("O"), [1.0, 2.0, 3.0], None);
+        frame.add_atom(&Atom::new("H"), [4.0, 5.0, 6.0], None);
+
+        assert_eq!(
+            frame.positions_na(),
+            [Point3::new(1.0, 2.0, 3.0), Point3::new(4.0, 5.0, 6.0)]
+        );
+        assert!(frame.velocities_na().is_none());
+
+        // apply a strain to the positions
+        let strain = Matrix3::from_diagonal(&Vector3::new(2.0, 1.0, 1.0));
+        for position in frame.positions_na_mut() {
+            *position = strain * *position;
+        }
+        assert_eq!(frame.positions(), [[2.0, 2.0, 3.0], [8.0, 5.0, 6.0]]);
+
+        frame.add_velocities();
+        frame.velocities_na_mut().unwrap()[1] = Vector3::new(1.0, 1.0, 1.0);
+        assert_eq!(frame.velocities().unwrap(), [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]]);
+
+        let matrix = frame.cell().matrix_na();
+        assert_eq!(matrix, Matrix3::from_diagonal(&Vector3::new(10.0, 11.0, 12.0)));
+
+        let cell = UnitCell::try_from(strain * matrix).unwrap();
+        assert_eq!(cell.lengths(), [20.0, 11.0, 12.0]);
+        assert_eq!(cell.matrix_na(), strain * matrix);
+
+        let error = UnitCell::try_from(-matrix).unwrap_err();
+        assert_eq!(error.status, crate::Status::ChemfilesError);
+    }
+
+    #[test]
+    fn describe() {
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+        frame.add_atom(&Atom::new("O"), [1.0, 2.0, 3.0], None);
+        frame.add_atom(&Atom::new("H"), [1.5, -2.25, 3.0], None);
+        let mut residue = Residue::with_id("WAT", 3);
+        residue.add_atom(0);
+        residue.add_atom(1);
+        frame.add_residue(&residue).unwrap();
+        frame.add_atom(&Atom::new("Na"), [0.0, 0.0, 0.0], None);
+        frame.atom_mut(2).set_charge(1.0);
+
+        let description = frame.describe(10);
+        let lines = description.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "frame at step 0 with 3 atoms and Orthorhombic cell");
+        assert_eq!(
+            lines[1],
+            " index name     resname   resid          x          y          z   charge"
+        );
+        assert_eq!(
+            lines[3],
+            "     1 H        WAT           3      1.500     -2.250      3.000    0.000"
+        );
+        assert_eq!(
+            lines[4],
+            "     2 Na       -             -      0.000      0.000      0.000    1.000"
+        );
+
+        let description = frame.describe(1);
+        assert_eq!(description.lines().count(), 4);
+        assert!(description.ends_with("... and 2 more atoms\n"));
+
+        let summary = frame.topology().describe();
+        assert!(summary.starts_with("topology with 3 atoms and 1 residues\n"));
+        assert!(summary.ends_with("atomic types: H (1), Na (1), O (1)\n"));
+    }
+
     #[test]
     fn residues() {
         let mut frame = Frame::new();
@@ -1149,10 +3857,118 @@ mod test {
                 assert_eq!(property, Property::String("here".into()));
             }
         }
+
+        let mut names = frame.list_properties();
+        names.sort();
+        assert_eq!(names, ["bar", "foo"]);
+        assert_eq!(frame.properties_count(), 2);
+
+        assert_eq!(frame.time(), None);
+        frame.set("time", "not a number");
+        assert_eq!(frame.time(), None);
+        frame.set("time", 2.5);
+        assert_eq!(frame.time(), Some(2.5));
+    }
+
+    #[test]
+    fn groups() {
+        use crate::{MemoryTrajectoryReader, Trajectory};
+
+        let mut frame = Frame::new();
+        for _ in 0..5 {
+            frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+        }
+        frame.save_group("solvent", &[1, 2, 4]);
+        frame.save_group("empty", &[]);
+        frame.set("energy", -3.0);
+
+        let mut output = Trajectory::memory_writer("XYZ").unwrap();
+        output.write(&frame).unwrap();
+        let buffer = output.memory_buffer().unwrap();
+
+        let mut input = MemoryTrajectoryReader::new(buffer.as_bytes(), "XYZ").unwrap();
+        let mut frame = Frame::new();
+        input.read(&mut frame).unwrap();
+        assert_eq!(frame.load_group("solvent").unwrap(), [1, 2, 4]);
+        assert!(frame.load_group("empty").unwrap().is_empty());
+
+        assert!(frame.load_group("energy").is_err());
+        assert!(frame.load_group("missing").is_err());
+
+        frame.set("invalid", "1 two 3");
+        assert!(frame.load_group("invalid").is_err());
+        frame.set("too large", "1 2 12");
+        assert_eq!(frame.load_group("too large").unwrap_err().status, Status::OutOfBounds);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds atomic index in group 'bad'")]
+    fn save_out_of_bounds_group() {
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Ar"), [0.0, 0.0, 0.0], None);
+        frame.save_group("bad", &[0, 1]);
+    }
+
+    #[test]
+    fn sdf_tags() {
+        use crate::{MemoryTrajectoryReader, Trajectory};
+
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("O"), [1.2, 0.0, 0.0], None);
+        frame.set("name", "ligand");
+        frame.set("ID", "CHEMBL25");
+        frame.set("IC50", 12.5);
+
+        let mut output = Trajectory::memory_writer("SDF").unwrap();
+        output.write(&frame).unwrap();
+        let buffer = output.memory_buffer().unwrap();
+        assert!(buffer.contains("> <ID>\nCHEMBL25\n"));
+
+        let mut input = MemoryTrajectoryReader::new(buffer.as_bytes(), "SDF").unwrap();
+        let mut frame = Frame::new();
+        input.read(&mut frame).unwrap();
+
+        let tags = frame.sdf_tags();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags["ID"], "CHEMBL25");
+        assert_eq!(tags["IC50"], "12.5");
+        assert_eq!(frame.get("name"), Some(Property::String("ligand".into())));
+    }
+
+    #[test]
+    fn bond_orders_roundtrip() {
+        use crate::{MemoryTrajectoryReader, Trajectory};
+
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("C"), [0.0, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("O"), [1.2, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("C"), [-1.5, 0.0, 0.0], None);
+        frame.add_atom(&Atom::new("N"), [-2.7, 0.0, 0.0], None);
+        frame.add_bond_with_order(0, 1, BondOrder::Double);
+        frame.add_bond_with_order(0, 2, BondOrder::Single);
+        frame.add_bond_with_order(2, 3, BondOrder::Triple);
+
+        let mut output = Trajectory::memory_writer("SDF").unwrap();
+        output.write(&frame).unwrap();
+        let buffer = output.memory_buffer().unwrap();
+
+        let mut input = MemoryTrajectoryReader::new(buffer.as_bytes(), "SDF").unwrap();
+        let mut frame = Frame::new();
+        input.read(&mut frame).unwrap();
+
+        let topology = frame.topology();
+        assert_eq!(topology.bonds(), vec![[0, 1], [0, 2], [2, 3]]);
+        assert_eq!(
+            topology.bond_orders(),
+            vec![BondOrder::Double, BondOrder::Single, BondOrder::Triple]
+        );
+        assert_eq!(topology.bond_order(3, 2), BondOrder::Triple);
     }
 
     #[test]
     fn pbc_geometry() {
+        use approx::assert_ulps_eq;
         use std::f64::consts::PI;
 
         let mut frame = Frame::new();
@@ -1168,6 +3984,24 @@ mod test {
         assert_eq!(frame.angle(0, 1, 2), PI / 2.0);
         assert_eq!(frame.dihedral(0, 1, 2, 3), PI / 2.0);
         assert_eq!(frame.out_of_plane(1, 4, 0, 2), 2.0);
+
+        // the same functions use the minimal image convention when the
+        // frame has a unit cell
+        let mut frame = Frame::new();
+        frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+
+        frame.add_atom(atom, [9.0, 0.0, 0.0], None);
+        frame.add_atom(atom, [0.0, 0.0, 0.0], None);
+        frame.add_atom(atom, [1.0, 0.0, 0.0], None);
+        frame.add_atom(atom, [0.0, 1.0, 0.0], None);
+        frame.add_atom(atom, [1.0, 9.0, 0.0], None);
+        frame.add_atom(atom, [0.0, 0.0, 9.0], None);
+
+        assert_ulps_eq!(frame.distance(0, 1), 1.0, epsilon = 1e-12);
+        assert_ulps_eq!(frame.distance(0, 2), 2.0, epsilon = 1e-12);
+        assert_ulps_eq!(frame.angle(0, 1, 2), PI, epsilon = 1e-12);
+        assert_ulps_eq!(frame.dihedral(3, 1, 2, 4).abs(), PI, epsilon = 1e-12);
+        assert_ulps_eq!(frame.out_of_plane(1, 5, 2, 3).abs(), 1.0, epsilon = 1e-12);
     }
 
     #[test]
@@ -0,0 +1,213 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::{Element, Frame, Selection};
+
+/// An `AtomGroup` is a sorted set of atomic indexes in a frame, for example
+/// the atoms matching a selection. Groups can be refined with typed queries,
+/// which do not need to go through the selection parser.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Atom, AtomGroup, Element, Frame, Selection};
+/// let mut frame = Frame::new();
+/// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+/// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+/// frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+///
+/// let mut selection = Selection::new("x < 0.5").unwrap();
+/// let group = AtomGroup::from_selection(&mut selection, &frame);
+/// assert_eq!(group.indexes(), [0, 2]);
+///
+/// let hydrogens = group.filter_element(&frame, Element::from_symbol("H").unwrap());
+/// assert_eq!(hydrogens.indexes(), [2]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AtomGroup {
+    indexes: Vec<usize>,
+}
+
+impl AtomGroup {
+    /// Create a new group containing the atoms at the given `indexes`.
+    /// Indexes are sorted and duplicates are removed.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::AtomGroup;
+    /// let group = AtomGroup::new(vec![4, 1, 4, 2]);
+    /// assert_eq!(group.indexes(), [1, 2, 4]);
+    /// ```
+    pub fn new(mut indexes: Vec<usize>) -> AtomGroup {
+        indexes.sort_unstable();
+        indexes.dedup();
+        return AtomGroup { indexes };
+    }
+
+    /// Create a new group containing all the atoms in `frame`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{AtomGroup, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.resize(3);
+    /// assert_eq!(AtomGroup::all(&frame).indexes(), [0, 1, 2]);
+    /// ```
+    pub fn all(frame: &Frame) -> AtomGroup {
+        AtomGroup {
+            indexes: (0..frame.size()).collect(),
+        }
+    }
+
+    /// Create a new group containing the atoms in `frame` matching the
+    /// given `selection`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, AtomGroup, Frame, Selection};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    ///
+    /// let mut selection = Selection::new("name H").unwrap();
+    /// let group = AtomGroup::from_selection(&mut selection, &frame);
+    /// assert_eq!(group.indexes(), [1]);
+    /// ```
+    pub fn from_selection(selection: &mut Selection, frame: &Frame) -> AtomGroup {
+        AtomGroup::new(selection.list(frame))
+    }
+
+    /// Get the sorted indexes of the atoms in this group.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::AtomGroup;
+    /// let group = AtomGroup::new(vec![2, 0]);
+    /// assert_eq!(group.indexes(), [0, 2]);
+    /// ```
+    pub fn indexes(&self) -> &[usize] {
+        &self.indexes
+    }
+
+    /// Get an iterator over the indexes of the atoms in this group.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::AtomGroup;
+    /// let group = AtomGroup::new(vec![2, 0]);
+    /// assert_eq!(group.iter().sum::<usize>(), 2);
+    /// ```
+    pub fn iter(&self) -> std::slice::Iter<'_, usize> {
+        self.indexes.iter()
+    }
+
+    /// Get the number of atoms in this group.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::AtomGroup;
+    /// assert_eq!(AtomGroup::new(vec![2, 0]).len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.indexes.len()
+    }
+
+    /// Check if this group contains no atoms.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::AtomGroup;
+    /// assert!(AtomGroup::new(Vec::new()).is_empty());
+    /// assert!(!AtomGroup::new(vec![3]).is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.indexes.is_empty()
+    }
+
+    /// Check if this group contains the atom at `index`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::AtomGroup;
+    /// let group = AtomGroup::new(vec![2, 5]);
+    /// assert!(group.contains(5));
+    /// assert!(!group.contains(3));
+    /// ```
+    pub fn contains(&self, index: usize) -> bool {
+        self.indexes.binary_search(&index).is_ok()
+    }
+
+    /// Get a new group containing only the atoms in this group which are of
+    /// the given `element` in `frame`. The element of an atom is determined
+    /// from its type, using `Atom::atomic_number`.
+    ///
+    /// # Panics
+    ///
+    /// If any index in this group is out of bounds for `frame`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, AtomGroup, Element, Frame};
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+    /// frame.add_atom(&Atom::new("H"), [0.0, 1.0, 0.0], None);
+    ///
+    /// let group = AtomGroup::new(vec![0, 1]);
+    /// let oxygen = Element::from_symbol("O").unwrap();
+    /// assert_eq!(group.filter_element(&frame, oxygen).indexes(), [0]);
+    /// ```
+    #[must_use]
+    pub fn filter_element(&self, frame: &Frame, element: Element) -> AtomGroup {
+        let number = u64::from(element.atomic_number());
+        let indexes = self
+            .indexes
+            .iter()
+            .copied()
+            .filter(|&i| frame.atom(i).atomic_number() == number)
+            .collect();
+        return AtomGroup { indexes };
+    }
+}
+
+impl From<AtomGroup> for Vec<usize> {
+    fn from(group: AtomGroup) -> Vec<usize> {
+        group.indexes
+    }
+}
+
+impl<'a> IntoIterator for &'a AtomGroup {
+    type Item = &'a usize;
+    type IntoIter = std::slice::Iter<'a, usize>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.indexes.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Atom;
+
+    #[test]
+    fn elements() {
+        let mut frame = Frame::new();
+        for name in ["O", "H", "H", "Zn", "CA", "X"] {
+            frame.add_atom(&Atom::new(name), [0.0; 3], None);
+        }
+
+        let hydrogen = Element::from_symbol("H").unwrap();
+        let group = AtomGroup::all(&frame);
+        assert_eq!(group.len(), 6);
+        assert_eq!(group.filter_element(&frame, hydrogen).indexes(), [1, 2]);
+
+        let zinc = Element::from_symbol("Zn").unwrap();
+        assert_eq!(frame.indices_of_element(zinc), [3]);
+        assert_eq!(frame.atom(3).element(), Some(zinc));
+        assert_eq!(frame.atom(5).element(), None);
+
+        let group = AtomGroup::new(vec![0, 3, 3]);
+        assert_eq!(group.filter_element(&frame, hydrogen), AtomGroup::default());
+        assert!(group.contains(3));
+        assert_eq!(Vec::from(group), [0, 3]);
+    }
+}
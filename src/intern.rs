@@ -0,0 +1,56 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Global interning of the strings returned by chemfiles, to share a single
+//! allocation between all the atoms with the same name or type.
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+struct Interner {
+    strings: HashSet<Arc<str>>,
+    /// Number of strings after the last cleanup, used to decide when to remove
+    /// strings which are no longer used outside of the interner
+    last_cleanup: usize,
+}
+
+static INTERNER: Mutex<Option<Interner>> = Mutex::new(None);
+
+/// Get a shared version of `string`, allocating it only the first time this
+/// function is called with a given string.
+pub(crate) fn intern(string: &str) -> Arc<str> {
+    let mut guard = INTERNER.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+    let interner = guard.get_or_insert_with(|| Interner {
+        strings: HashSet::new(),
+        last_cleanup: 0,
+    });
+
+    if let Some(shared) = interner.strings.get(string) {
+        return Arc::clone(shared);
+    }
+
+    if interner.strings.len() >= 2 * interner.last_cleanup.max(crate::perf::interner_capacity()) {
+        // remove the strings only referenced by the interner
+        interner.strings.retain(|shared| Arc::strong_count(shared) > 1);
+        interner.last_cleanup = interner.strings.len();
+    }
+
+    let shared: Arc<str> = Arc::from(string);
+    let _ = interner.strings.insert(Arc::clone(&shared));
+    return shared;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning() {
+        let first = intern("CA");
+        let second = intern(&String::from("CA"));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(&*first, "CA");
+
+        let other = intern("CB");
+        assert!(!Arc::ptr_eq(&first, &other));
+    }
+}
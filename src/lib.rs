@@ -34,19 +34,33 @@
 #![doc(test(attr(deny(warnings))))]
 #![doc(test(attr(allow(unused_variables))))]
 
+use std::path::Path;
+
 use chemfiles_sys as ffi;
 
+mod intern;
+mod linalg;
+mod spans;
 mod strings;
 
+mod borrowed;
+pub use self::borrowed::{Borrowed, BorrowedMut};
+
 mod errors;
-pub use self::errors::set_warning_callback;
-pub use self::errors::{Error, Status};
+pub use self::errors::{reset_warning_callback, set_warning_callback};
+pub use self::errors::{Error, ErrorContext, Status, TrajectoryOperation};
 
 mod atom;
 pub use self::atom::Atom;
 pub use self::atom::AtomMut;
 pub use self::atom::AtomRef;
 
+mod element;
+pub use self::element::Element;
+
+mod group;
+pub use self::group::AtomGroup;
+
 mod cell;
 pub use self::cell::CellShape;
 pub use self::cell::UnitCell;
@@ -58,16 +72,51 @@ pub use self::residue::Residue;
 pub use self::residue::ResidueRef;
 
 mod topology;
+pub use self::topology::AtomsTable;
 pub use self::topology::BondOrder;
 pub use self::topology::Topology;
 pub use self::topology::TopologyRef;
 
 mod frame;
 pub use self::frame::Frame;
+pub use self::frame::FrozenFrame;
+
+mod spacegroup;
+pub use self::spacegroup::SpaceGroup;
+
+mod edit;
+pub use self::edit::{Edit, EditLog};
 
 mod trajectory;
 pub use self::trajectory::MemoryTrajectoryReader;
-pub use self::trajectory::Trajectory;
+pub use self::trajectory::{
+    BufferedFrames, ErrorPolicy, Frames, IntoFrames, OpenMode, ReadReport, RescaledFrames, SyncTrajectory, Trajectory,
+    TrajectoryBuilder, UnwrappedFrames,
+};
+
+mod convert;
+pub use self::convert::{convert, split, Converter, SplitBy};
+
+mod fidelity;
+pub use self::fidelity::{roundtrip_check, FidelityReport};
+
+mod options;
+pub use self::options::Options;
+
+mod perf;
+pub use self::perf::{perf_options, set_perf_options, PerfOptions};
+
+mod cancel;
+pub use self::cancel::CancelToken;
+
+// the progress types are exported from `analysis`, but also used by `Trajectory`
+mod progress;
+
+mod delta;
+pub use self::delta::{DeltaTrajectoryReader, DeltaTrajectoryWriter};
+
+mod ensemble;
+pub use self::ensemble::Ensemble;
 
 mod selection;
 pub use self::selection::{Match, Selection};
@@ -76,8 +125,18 @@ mod property;
 pub use self::property::PropertiesIter;
 pub use self::property::Property;
 
+pub mod analysis;
+
+pub mod pipeline;
+
+#[cfg(feature = "serde")]
+mod serialization;
+
 mod misc;
-pub use self::misc::{formats_list, guess_format, FormatMetadata};
+pub use self::misc::{build_info, formats_list, guess_format, sniff_format, BuildInfo, FormatMetadata};
+
+#[cfg(test)]
+mod sync;
 
 /// Get the version of the chemfiles library.
 ///
@@ -97,21 +156,28 @@ pub fn version() -> String {
 /// function can be used to add data from another configuration file. Data from
 /// the new configuration file will overwrite any existing data.
 ///
+/// Configuration files can rename atomic types (for example to map a
+/// coarse-grained `CH3` bead to carbon with `[types] CH3 = "C"`), and set
+/// the mass, charge and radii of atoms with a given type in `[atoms.<type>]`
+/// sections. The covalent radii defined there are used when guessing bonds.
+///
 /// # Errors
 ///
-/// This function will fail if there is no file at `path`, or if the file is
-/// incorrectly formatted.
+/// This function will fail if there is no file at `path`, if the file is
+/// incorrectly formatted, or if the path is not valid UTF-8 on platforms
+/// where the C library requires UTF-8 paths (i.e. not on Unix).
 ///
 /// # Example
 /// ```no_run
 /// chemfiles::add_configuration("local-config.toml").unwrap();
 /// // from now on, the data from "local-config.toml" will be used
 /// ```
-pub fn add_configuration<S>(path: S) -> Result<(), Error>
+pub fn add_configuration<P>(path: P) -> Result<(), Error>
 where
-    S: AsRef<str>,
+    P: AsRef<Path>,
 {
-    let buffer = strings::to_c(path.as_ref());
+    errors::install_log_bridge();
+    let buffer = strings::path_to_c(path.as_ref())?;
     unsafe { errors::check(ffi::chfl_add_configuration(buffer.as_ptr())) }
 }
 
@@ -129,4 +195,21 @@ mod tests {
         assert!(!crate::version().is_empty());
         assert!(crate::version().starts_with("0.10"));
     }
+
+    #[test]
+    fn configuration() {
+        let filename = "test-tmp-configuration.toml";
+        std::fs::write(
+            filename,
+            "[types]\nCH3 = \"C\"\n\n[atoms.CH3]\nmass = 15.035\ncovalent_radius = 0.77\n",
+        )
+        .unwrap();
+        crate::add_configuration(std::path::Path::new(filename)).unwrap();
+        std::fs::remove_file(filename).unwrap();
+
+        let atom = crate::Atom::new("CH3");
+        assert_eq!(atom.atomic_type(), "C");
+
+        assert!(crate::add_configuration("not-here.toml").is_err());
+    }
 }
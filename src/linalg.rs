@@ -0,0 +1,394 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Small linear algebra helpers used by the pure-Rust algorithms in this
+//! crate. These work on plain `[f64; 3]` vectors and `[[f64; 3]; 3]` matrices,
+//! using the same conventions as the rest of the API.
+
+pub(crate) type Vector3D = [f64; 3];
+pub(crate) type Matrix3 = [[f64; 3]; 3];
+
+pub(crate) fn add(a: Vector3D, b: Vector3D) -> Vector3D {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub(crate) fn sub(a: Vector3D, b: Vector3D) -> Vector3D {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn scale(a: Vector3D, factor: f64) -> Vector3D {
+    [a[0] * factor, a[1] * factor, a[2] * factor]
+}
+
+pub(crate) fn dot(a: Vector3D, b: Vector3D) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn norm2(a: Vector3D) -> f64 {
+    dot(a, a)
+}
+
+pub(crate) fn cross(a: Vector3D, b: Vector3D) -> Vector3D {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Get the inverse of the matrix `m`, or `None` if the matrix is singular.
+///
+/// The determinant is compared to the product of the norms of the rows of
+/// `m`, which is its largest possible value, so that matrices are considered
+/// singular depending on their shape and not on their scale.
+pub(crate) fn inverse(m: &Matrix3) -> Option<Matrix3> {
+    let determinant = dot(m[0], cross(m[1], m[2]));
+    let scale = norm2(m[0]).sqrt() * norm2(m[1]).sqrt() * norm2(m[2]).sqrt();
+    if determinant.abs() <= f64::EPSILON * scale {
+        return None;
+    }
+
+    let columns = [cross(m[1], m[2]), cross(m[2], m[0]), cross(m[0], m[1])];
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = columns[j][i] / determinant;
+        }
+    }
+    return Some(inverse);
+}
+
+/// Wrap `v` in the cell defined by `matrix` (with cell vectors as columns)
+/// and its `inverse`, by rounding its fractional coordinates to the
+/// [-0.5, 0.5] range. This is the same convention as `chfl_cell_wrap`.
+pub(crate) fn wrap(matrix: &Matrix3, inverse: &Matrix3, v: Vector3D) -> Vector3D {
+    let fractional = mat_vec(inverse, v);
+    let fractional = [
+        fractional[0] - fractional[0].round(),
+        fractional[1] - fractional[1].round(),
+        fractional[2] - fractional[2].round(),
+    ];
+    return mat_vec(matrix, fractional);
+}
+
+/// Get the periodic image of `v` closest to the origin in the cell defined
+/// by `matrix` (with cell vectors as columns) and its `inverse`.
+///
+/// Wrapping the vector in the cell gives the closest image for orthorhombic
+/// cells, but not always for triclinic cells, where the images of the
+/// wrapped vector in the 26 neighboring cells (shifted by -1, 0 or +1 cell
+/// vector in each direction) are also checked. This finds the closest image
+/// for most triclinic cells, but not for strongly skewed cells where the
+/// closest image can be more than one cell away from the wrapped vector.
+pub(crate) fn minimum_image(matrix: &Matrix3, inverse: &Matrix3, v: Vector3D) -> Vector3D {
+    let wrapped = wrap(matrix, inverse, v);
+    let diagonal = matrix[0][1] == 0.0
+        && matrix[0][2] == 0.0
+        && matrix[1][0] == 0.0
+        && matrix[1][2] == 0.0
+        && matrix[2][0] == 0.0
+        && matrix[2][1] == 0.0;
+    if diagonal {
+        return wrapped;
+    }
+
+    let mut image = wrapped;
+    let mut best = norm2(wrapped);
+    for i in [-1.0, 0.0, 1.0] {
+        for j in [-1.0, 0.0, 1.0] {
+            for k in [-1.0, 0.0, 1.0] {
+                let candidate = add(wrapped, mat_vec(matrix, [i, j, k]));
+                let norm = norm2(candidate);
+                if norm < best {
+                    best = norm;
+                    image = candidate;
+                }
+            }
+        }
+    }
+    return image;
+}
+
+/// Multiply the matrix `m` with the (column) vector `v`
+pub(crate) fn mat_vec(m: &Matrix3, v: Vector3D) -> Vector3D {
+    [dot(m[0], v), dot(m[1], v), dot(m[2], v)]
+}
+
+/// Compute the eigenvalues and eigenvectors of the symmetric matrix `matrix`
+/// using the cyclic Jacobi algorithm. The eigenvalues are sorted in increasing
+/// order, and `vectors[i]` is the normalized eigenvector corresponding to
+/// `values[i]`.
+#[allow(clippy::needless_range_loop, clippy::many_single_char_names)]
+pub(crate) fn symmetric_eigen<const N: usize>(matrix: [[f64; N]; N]) -> ([f64; N], [[f64; N]; N]) {
+    let mut a = matrix;
+    // columns of `v` are the eigenvectors
+    let mut v = [[0.0; N]; N];
+    for i in 0..N {
+        v[i][i] = 1.0;
+    }
+
+    let scale = a.iter().flatten().map(|x| x * x).sum::<f64>().sqrt();
+    for _ in 0..100 {
+        let mut off_diagonal = 0.0;
+        for p in 0..N {
+            for q in (p + 1)..N {
+                off_diagonal += a[p][q] * a[p][q];
+            }
+        }
+        if off_diagonal.sqrt() <= 1e-15 * scale || off_diagonal == 0.0 {
+            break;
+        }
+
+        for p in 0..N {
+            for q in (p + 1)..N {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for k in 0..N {
+                    let akp = a[k][p];
+                    let akq = a[k][q];
+                    a[k][p] = c * akp - s * akq;
+                    a[k][q] = s * akp + c * akq;
+                }
+                for k in 0..N {
+                    let apk = a[p][k];
+                    let aqk = a[q][k];
+                    a[p][k] = c * apk - s * aqk;
+                    a[q][k] = s * apk + c * aqk;
+                }
+                for k in 0..N {
+                    let vkp = v[k][p];
+                    let vkq = v[k][q];
+                    v[k][p] = c * vkp - s * vkq;
+                    v[k][q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    let mut order = [0; N];
+    for i in 0..N {
+        order[i] = i;
+    }
+    order.sort_by(|&i, &j| a[i][i].total_cmp(&a[j][j]));
+
+    let mut values = [0.0; N];
+    let mut vectors = [[0.0; N]; N];
+    for (i, &k) in order.iter().enumerate() {
+        values[i] = a[k][k];
+        for j in 0..N {
+            vectors[i][j] = v[j][k];
+        }
+    }
+    return (values, vectors);
+}
+
+/// Optimal superposition of one set of points onto another one
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Superposition {
+    /// Rotation matrix to apply to the centered mobile points
+    pub rotation: Matrix3,
+    /// Weighted center of the mobile points
+    pub mobile_center: Vector3D,
+    /// Weighted center of the reference points
+    pub reference_center: Vector3D,
+    /// Weighted root-mean-square deviation after superposition
+    pub rmsd: f64,
+}
+
+impl Superposition {
+    /// Move the point `x` from the mobile set to its superposed position
+    pub fn apply(&self, x: Vector3D) -> Vector3D {
+        add(
+            mat_vec(&self.rotation, sub(x, self.mobile_center)),
+            self.reference_center,
+        )
+    }
+}
+
+/// Get the weighted center of the `points`.
+pub(crate) fn weighted_center(points: &[Vector3D], weights: Option<&[f64]>) -> Vector3D {
+    let mut center = [0.0; 3];
+    let mut total = 0.0;
+    for (i, &point) in points.iter().enumerate() {
+        let weight = weights.map_or(1.0, |w| w[i]);
+        center = add(center, scale(point, weight));
+        total += weight;
+    }
+    if total == 0.0 {
+        return center;
+    }
+    return scale(center, 1.0 / total);
+}
+
+/// Find the rotation and translation minimizing the (weighted) RMSD between
+/// `mobile` and `reference`, using the quaternion-based method from Horn
+/// (J. Opt. Soc. Am. A, 1987). This gives the same result as the Kabsch
+/// algorithm, but never produces improper rotations.
+///
+/// Both slices must have the same length, as well as `weights` if given.
+pub(crate) fn superpose(reference: &[Vector3D], mobile: &[Vector3D], weights: Option<&[f64]>) -> Superposition {
+    assert_eq!(
+        reference.len(),
+        mobile.len(),
+        "superposition requires the same number of points"
+    );
+    if let Some(weights) = weights {
+        assert_eq!(
+            weights.len(),
+            mobile.len(),
+            "superposition requires one weight per point"
+        );
+    }
+
+    let reference_center = weighted_center(reference, weights);
+    let mobile_center = weighted_center(mobile, weights);
+
+    let mut correlation = [[0.0; 3]; 3];
+    let mut inner = 0.0;
+    let mut total_weight = 0.0;
+    for i in 0..mobile.len() {
+        let weight = weights.map_or(1.0, |w| w[i]);
+        let a = sub(mobile[i], mobile_center);
+        let b = sub(reference[i], reference_center);
+        for j in 0..3 {
+            for k in 0..3 {
+                correlation[j][k] += weight * a[j] * b[k];
+            }
+        }
+        inner += weight * (norm2(a) + norm2(b));
+        total_weight += weight;
+    }
+
+    let [[sxx, sxy, sxz], [syx, syy, syz], [szx, szy, szz]] = correlation;
+    let key = [
+        [sxx + syy + szz, syz - szy, szx - sxz, sxy - syx],
+        [syz - szy, sxx - syy - szz, sxy + syx, szx + sxz],
+        [szx - sxz, sxy + syx, -sxx + syy - szz, syz + szy],
+        [sxy - syx, szx + sxz, syz + szy, -sxx - syy + szz],
+    ];
+
+    let (values, vectors) = symmetric_eigen(key);
+    let [q0, q1, q2, q3] = vectors[3];
+    let rotation = [
+        [
+            q0 * q0 + q1 * q1 - q2 * q2 - q3 * q3,
+            2.0 * (q1 * q2 - q0 * q3),
+            2.0 * (q1 * q3 + q0 * q2),
+        ],
+        [
+            2.0 * (q1 * q2 + q0 * q3),
+            q0 * q0 - q1 * q1 + q2 * q2 - q3 * q3,
+            2.0 * (q2 * q3 - q0 * q1),
+        ],
+        [
+            2.0 * (q1 * q3 - q0 * q2),
+            2.0 * (q2 * q3 + q0 * q1),
+            q0 * q0 - q1 * q1 - q2 * q2 + q3 * q3,
+        ],
+    ];
+
+    let rmsd = if total_weight > 0.0 {
+        (f64::max(inner - 2.0 * values[3], 0.0) / total_weight).sqrt()
+    } else {
+        0.0
+    };
+
+    return Superposition {
+        rotation,
+        mobile_center,
+        reference_center,
+        rmsd,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_ulps_eq;
+
+    #[test]
+    fn eigen() {
+        let (values, vectors) = symmetric_eigen([[2.0, 1.0, 0.0], [1.0, 2.0, 0.0], [0.0, 0.0, 5.0]]);
+        assert_ulps_eq!(values[0], 1.0, epsilon = 1e-12);
+        assert_ulps_eq!(values[1], 3.0, epsilon = 1e-12);
+        assert_ulps_eq!(values[2], 5.0, epsilon = 1e-12);
+
+        let expected = 1.0 / f64::sqrt(2.0);
+        assert_ulps_eq!(vectors[1][0].abs(), expected, epsilon = 1e-12);
+        assert_ulps_eq!(vectors[1][1].abs(), expected, epsilon = 1e-12);
+        assert_ulps_eq!(vectors[2][2].abs(), 1.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn inverse() {
+        let matrix = [[2.0, 0.5, 0.0], [0.0, 3.0, 1.0], [1.0, 0.0, 4.0]];
+        let inverse = super::inverse(&matrix).unwrap();
+        for column in &[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] {
+            let product = mat_vec(&matrix, mat_vec(&inverse, *column));
+            crate::assert_vector3d_eq(&product, column, 1e-12);
+        }
+
+        assert!(super::inverse(&[[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 0.0, 1.0]]).is_none());
+        assert!(super::inverse(&[[0.0; 3]; 3]).is_none());
+
+        // small but valid cells are not singular
+        let matrix = [[1e-6, 0.0, 0.0], [0.0, 2e-6, 0.0], [0.0, 0.0, 1e-6]];
+        let inverse = super::inverse(&matrix).unwrap();
+        crate::assert_vector3d_eq(&inverse[1], &[0.0, 5e5, 0.0], 1e-6);
+        assert_ulps_eq!(norm2(cross([1.0, 0.0, 0.0], [0.0, 1.0, 0.0])), 1.0);
+    }
+
+    #[test]
+    fn minimum_image() {
+        let matrix = [[10.0, 0.0, 0.0], [0.0, 20.0, 0.0], [0.0, 0.0, 30.0]];
+        let inverse = super::inverse(&matrix).unwrap();
+        let image = super::minimum_image(&matrix, &inverse, [12.0, -15.0, 14.0]);
+        crate::assert_vector3d_eq(&image, &[2.0, 5.0, 14.0], 1e-12);
+
+        // strongly skewed cell, where wrapping the vector does not give the
+        // closest image
+        let matrix = [[10.0, 9.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 10.0]];
+        let inverse = super::inverse(&matrix).unwrap();
+        let original = [-9.0, 1.0, 0.0];
+        let wrapped = wrap(&matrix, &inverse, original);
+        let image = super::minimum_image(&matrix, &inverse, original);
+        crate::assert_vector3d_eq(&wrapped, &[-8.0, -1.0, 0.0], 1e-12);
+        crate::assert_vector3d_eq(&image, &[1.0, 1.0, 0.0], 1e-12);
+        assert!(norm2(image) < norm2(wrapped));
+
+        for i in -3..=3 {
+            for j in -3..=3 {
+                let shift = mat_vec(&matrix, [f64::from(i), f64::from(j), 0.0]);
+                assert!(norm2(image) <= norm2(add(original, shift)) + 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn superposition() {
+        let reference = [[0.0, 0.0, 0.0], [1.5, 0.0, 0.0], [1.5, 1.2, 0.0], [0.3, 1.0, 2.0]];
+
+        // rotate by 90° around z and translate
+        let mobile = reference
+            .iter()
+            .map(|&[x, y, z]| [-y + 3.0, x - 1.0, z + 0.5])
+            .collect::<Vec<_>>();
+
+        let superposition = superpose(&reference, &mobile, None);
+        assert_ulps_eq!(superposition.rmsd, 0.0, epsilon = 1e-6);
+        for (&point, &expected) in mobile.iter().zip(&reference) {
+            crate::assert_vector3d_eq(&superposition.apply(point), &expected, 1e-10);
+        }
+
+        let weights = [1.0, 2.0, 3.0, 4.0];
+        let superposition = superpose(&reference, &mobile, Some(&weights));
+        assert_ulps_eq!(superposition.rmsd, 0.0, epsilon = 1e-6);
+    }
+}
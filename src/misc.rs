@@ -6,9 +6,9 @@ use std::path::Path;
 
 use chemfiles_sys as ffi;
 
-use crate::errors::check_success;
+use crate::errors::{check_success, install_log_bridge};
 
-use crate::{errors::check, Error};
+use crate::Error;
 
 /// `FormatMetadata` contains metadata associated with one format.
 #[allow(clippy::struct_excessive_bools)]
@@ -36,7 +36,8 @@ pub struct FormatMetadata {
     pub unit_cell: bool,
     /// Does this format support storing atom names or types?
     pub atoms: bool,
-    /// Does this format support storing bonds between atoms?
+    /// Does this format support storing bonds between atoms? The C API does
+    /// not report whether bond orders are stored as well.
     pub bonds: bool,
     /// Does this format support storing residues?
     pub residues: bool,
@@ -70,6 +71,9 @@ impl FormatMetadata {
 
 /// Get the list of formats known by chemfiles, as well as all associated metadata.
 ///
+/// The metadata can be used to filter formats by capability, for example to
+/// only propose formats able to store velocities when saving a simulation.
+///
 /// # Example
 /// ```
 /// let formats = chemfiles::formats_list();
@@ -81,9 +85,17 @@ impl FormatMetadata {
 ///         format.extension.as_deref().unwrap_or("")
 ///     );
 /// }
+///
+/// let with_velocities = formats
+///     .iter()
+///     .filter(|format| format.write && format.velocities)
+///     .map(|format| format.name)
+///     .collect::<Vec<_>>();
+/// assert!(with_velocities.contains(&"Amber NetCDF"));
 /// ```
 #[must_use]
 pub fn formats_list() -> Vec<FormatMetadata> {
+    install_log_bridge();
     let mut formats = std::ptr::null_mut();
     let mut count: u64 = 0;
     let formats_slice = unsafe {
@@ -97,6 +109,75 @@ pub fn formats_list() -> Vec<FormatMetadata> {
     return formats_vec;
 }
 
+/// `BuildInfo` describes the chemfiles library used by this crate, and the
+/// capabilities it was compiled with.
+///
+/// Support for some formats (for example `"Amber NetCDF"` or `"MMTF"`) depends on
+/// how the underlying C++ library was compiled. Checking the `BuildInfo` at
+/// startup allows applications to report missing capabilities early, instead
+/// of failing when trying to open a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// Version of the chemfiles C++ library, as returned by
+    /// `chemfiles::version`
+    pub version: String,
+    /// Version of these Rust bindings
+    pub bindings_version: &'static str,
+    /// Metadata for all the formats the library was compiled with
+    pub formats: Vec<FormatMetadata>,
+}
+
+impl BuildInfo {
+    /// Get the metadata for the format with the given `name`, if the
+    /// library was compiled with this format.
+    ///
+    /// # Example
+    /// ```
+    /// let info = chemfiles::build_info();
+    /// let pdb = info.format("PDB").unwrap();
+    /// assert!(pdb.residues);
+    /// ```
+    pub fn format(&self, name: &str) -> Option<&FormatMetadata> {
+        self.formats.iter().find(|format| format.name == name)
+    }
+
+    /// Check if the library was compiled with support for the format with
+    /// the given `name`.
+    ///
+    /// # Example
+    /// ```
+    /// let info = chemfiles::build_info();
+    /// assert!(info.has_format("XYZ"));
+    /// assert!(!info.has_format("not a format"));
+    /// ```
+    pub fn has_format(&self, name: &str) -> bool {
+        self.format(name).is_some()
+    }
+}
+
+/// Get information about the chemfiles library used by this crate, including
+/// its version and the list of formats it was compiled with.
+///
+/// # Example
+/// ```
+/// let info = chemfiles::build_info();
+/// assert!(info.version.starts_with("0.10"));
+///
+/// for format in ["Amber NetCDF", "MMTF"] {
+///     if !info.has_format(format) {
+///         println!("warning: chemfiles was compiled without {format} support");
+///     }
+/// }
+/// ```
+#[must_use]
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: crate::version(),
+        bindings_version: env!("CARGO_PKG_VERSION"),
+        formats: formats_list(),
+    }
+}
+
 #[allow(clippy::doc_markdown)]
 /// Get the format that chemfiles would use to read a file at the given
 /// ``path``.
@@ -114,11 +195,9 @@ pub fn formats_list() -> Vec<FormatMetadata> {
 ///
 /// # Errors
 ///
-/// This function returns an error if the file format couldn't be guessed.
-///
-/// # Panics
-///
-/// This function panics if the path can't be converted to a Unicode string.
+/// This function returns an error if the file format couldn't be guessed,
+/// or if the path is not valid UTF-8 on platforms where the C library
+/// requires UTF-8 paths (i.e. not on Unix).
 ///
 /// # Examples
 /// ```
@@ -135,15 +214,199 @@ pub fn guess_format<P>(path: P) -> Result<String, Error>
 where
     P: AsRef<Path>,
 {
-    let path = path.as_ref().to_str().expect("couldn't convert path to Unicode");
-    let path = crate::strings::to_c(path);
-    let mut buffer = vec![0; 128];
-    unsafe {
-        check(ffi::chfl_guess_format(
-            path.as_ptr(),
-            buffer.as_mut_ptr(),
-            buffer.len() as u64,
-        ))?;
+    install_log_bridge();
+    let path = crate::strings::path_to_c(path.as_ref())?;
+    let get_format = |ptr, len| unsafe { ffi::chfl_guess_format(path.as_ptr(), ptr, len) };
+    let buffer = crate::strings::call_autogrow_buffer(32, get_format)?;
+    Ok(crate::strings::from_c_lossy(buffer.as_ptr()))
+}
+
+/// Number of bytes inspected by `sniff_format` for text formats
+const SNIFF_LENGTH: usize = 4096;
+
+/// Guess the format of a file from its content in `bytes`, for example to
+/// read data without a trustworthy extension with `Trajectory::memory_reader`.
+///
+/// This inspects the magic numbers of binary formats (DCD, XTC, TRR, Amber
+/// `NetCDF`) and the start of text files (PDB records, mmCIF/CIF data blocks,
+/// MOL2 and SDF headers, LAMMPS trajectories, XYZ atom count line). Only
+/// the beginning of the data is used, so the whole file does not need to be
+/// in memory. The returned name can be given to the `Trajectory`
+/// constructors, and is `None` if the format could not be recognized.
+/// Compressed data is not recognized.
+///
+/// # Example
+/// ```
+/// let data = b"3\nwater\nO 0 0 0\nH 1 0 0\nH 0 1 0\n";
+/// assert_eq!(chemfiles::sniff_format(data), Some("XYZ"));
+///
+/// let data = b"CRYST1   10.000   10.000   10.000  90.00  90.00  90.00 P 1\n";
+/// assert_eq!(chemfiles::sniff_format(data), Some("PDB"));
+///
+/// assert_eq!(chemfiles::sniff_format(b"not a chemistry file"), None);
+/// ```
+pub fn sniff_format(bytes: &[u8]) -> Option<&'static str> {
+    if let Some(format) = sniff_binary(bytes) {
+        return Some(format);
+    }
+
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(SNIFF_LENGTH)]);
+    return sniff_text(&text);
+}
+
+/// Check for the magic numbers of binary formats at the start of `bytes`
+fn sniff_binary(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"CDF\x01") || bytes.starts_with(b"CDF\x02") {
+        return Some("Amber NetCDF");
+    }
+
+    // Fortran records start with their size (84 bytes for the DCD header),
+    // using either endianness
+    if bytes.len() >= 8 && &bytes[4..8] == b"CORD" {
+        let size = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if u32::from_le_bytes(size) == 84 || u32::from_be_bytes(size) == 84 {
+            return Some("DCD");
+        }
+    }
+
+    // XDR files are big-endian, starting with the magic number of the format
+    if bytes.len() >= 4 {
+        match i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) {
+            1995 => return Some("XTC"),
+            1993 => return Some("TRR"),
+            _ => {}
+        }
+    }
+
+    return None;
+}
+
+/// Check for headers of text formats at the start of `text`
+fn sniff_text(text: &str) -> Option<&'static str> {
+    const PDB_RECORDS: [&str; 9] = [
+        "HEADER", "TITLE ", "COMPND", "REMARK", "CRYST1", "MODEL ", "ATOM  ", "HETATM", "SEQRES",
+    ];
+
+    if text.contains("@<TRIPOS>") {
+        return Some("MOL2");
+    }
+
+    let lines = text.lines().collect::<Vec<_>>();
+    let first = lines.iter().map(|line| line.trim()).find(|line| !line.is_empty())?;
+
+    if first.starts_with("data_") {
+        if text.contains("_atom_site.") {
+            return Some("mmCIF");
+        }
+        return Some("CIF");
+    }
+
+    if first == "ITEM: TIMESTEP" {
+        return Some("LAMMPS");
+    }
+
+    if lines
+        .iter()
+        .take(10)
+        .any(|line| PDB_RECORDS.iter().any(|record| line.starts_with(record)))
+    {
+        return Some("PDB");
+    }
+
+    // the counts line of MDL files is the 4th line
+    if let Some(counts) = lines.get(3) {
+        let counts = counts.trim_end();
+        if counts.ends_with("V2000") || counts.ends_with("V3000") {
+            return Some("SDF");
+        }
+    }
+
+    // XYZ files start with the number of atoms, a comment line, and then
+    // one line per atom with the name and three coordinates
+    if lines[0].trim().parse::<usize>().is_ok() {
+        if let Some(atom) = lines.get(2) {
+            let fields = atom.split_whitespace().collect::<Vec<_>>();
+            if fields.len() >= 4 && fields[1..4].iter().all(|field| field.parse::<f64>().is_ok()) {
+                return Some("XYZ");
+            }
+        }
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let formats = formats_list();
+        assert!(!formats.is_empty());
+
+        let xyz = formats.iter().find(|format| format.name == "XYZ").unwrap();
+        assert_eq!(xyz.extension, Some(".xyz"));
+        assert!(xyz.read);
+        assert!(xyz.write);
+        assert!(xyz.memory);
+        assert!(xyz.positions);
+        assert!(!xyz.bonds);
+
+        let pdb = formats.iter().find(|format| format.name == "PDB").unwrap();
+        assert_eq!(pdb.extension, Some(".pdb"));
+        assert!(pdb.bonds);
+        assert!(pdb.residues);
+        assert!(pdb.unit_cell);
+        assert!(!pdb.velocities);
+    }
+
+    #[test]
+    fn build_info() {
+        let info = super::build_info();
+        assert_eq!(info.version, crate::version());
+        assert_eq!(info.bindings_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.formats, formats_list());
+
+        assert!(info.has_format("PDB"));
+        assert!(info.format("Amber NetCDF").unwrap().velocities);
+        assert!(info.format("unknown").is_none());
+    }
+
+    #[test]
+    fn sniff() {
+        assert_eq!(sniff_format(b"2\n\nZn 1 2 3\nZn 4.5 5 6e-3\n"), Some("XYZ"));
+        assert_eq!(sniff_format(b"2\nnot xyz\nZn 1 2\n"), None);
+
+        let water = std::fs::read("data/water.xyz").unwrap();
+        assert_eq!(sniff_format(&water), Some("XYZ"));
+
+        assert_eq!(sniff_format(b"HEADER    TEST\nREMARK  1\n"), Some("PDB"));
+        assert_eq!(sniff_format(b"ATOM      1  N   MET A   1"), Some("PDB"));
+
+        assert_eq!(sniff_format(b"data_1ABC\nloop_\n_atom_site.id\n"), Some("mmCIF"));
+        assert_eq!(sniff_format(b"data_quartz\n_cell_length_a 4.9\n"), Some("CIF"));
+        assert_eq!(sniff_format(b"@<TRIPOS>MOLECULE\nbenzene\n"), Some("MOL2"));
+        assert_eq!(
+            sniff_format(b"name\n  program\n\n  1  0  0  0  0  0  0  0  0  0999 V2000\n"),
+            Some("SDF")
+        );
+        assert_eq!(sniff_format(b"ITEM: TIMESTEP\n0\n"), Some("LAMMPS"));
+
+        let mut dcd = 84_u32.to_le_bytes().to_vec();
+        dcd.extend_from_slice(b"CORD");
+        assert_eq!(sniff_format(&dcd), Some("DCD"));
+        assert_eq!(sniff_format(&1995_i32.to_be_bytes()), Some("XTC"));
+        assert_eq!(sniff_format(&1993_i32.to_be_bytes()), Some("TRR"));
+        assert_eq!(sniff_format(b"CDF\x02\x00\x00"), Some("Amber NetCDF"));
+
+        assert_eq!(sniff_format(b""), None);
+        assert_eq!(sniff_format(b"\x1f\x8b\x08"), None);
+    }
+
+    #[test]
+    fn guess() {
+        assert_eq!(guess_format("file.pdb").unwrap(), "PDB");
+        assert_eq!(guess_format("file.xyz.gz").unwrap(), "XYZ / GZ");
+        assert!(guess_format("file.not-a-format").is_err());
     }
-    Ok(crate::strings::from_c(buffer.as_ptr()))
 }
@@ -0,0 +1,226 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::errors::{replace_warning_callback, Error, SharedCallback};
+
+/// Lock serializing the scopes created by `Options::apply` across threads
+static OPTIONS_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    /// Number of nested `Options::apply` scopes on the current thread
+    static OPTIONS_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// `Options` manages the global state of the chemfiles library (warnings
+/// handling and configuration files) for the duration of a function call.
+///
+/// The C library keeps this state for the whole process, and not per thread.
+/// To keep multi-threaded programs consistent, only one thread at the time
+/// can run code inside `Options::apply`: other threads calling
+/// `Options::apply` wait until the current scope ends. Scopes can be nested
+/// inside the same thread. Code running outside of `Options::apply` in other
+/// threads still sees the modified state while a scope is active.
+///
+/// # Example
+/// ```
+/// # use chemfiles::{Options, Trajectory};
+/// let result = Options::scoped().warnings(false).apply(|| {
+///     // no warnings are emitted while trying to read this file
+///     Trajectory::memory_reader("not a PDB file\n", "PDB").is_ok()
+/// });
+/// assert!(result.is_ok());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    warnings: Option<bool>,
+    configurations: Vec<PathBuf>,
+}
+
+impl Options {
+    /// Create a new set of options, which does not modify the global state
+    /// until more options are added.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Options;
+    /// let value = Options::scoped().apply(|| 42).unwrap();
+    /// assert_eq!(value, 42);
+    /// ```
+    pub fn scoped() -> Options {
+        Options::default()
+    }
+
+    /// Enable or disable warnings inside the scope. Disabled warnings are
+    /// discarded, and enabled warnings are printed to the standard error,
+    /// ignoring any callback set with `set_warning_callback`. The previous
+    /// warning callback is restored at the end of the scope.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Options;
+    /// Options::scoped().warnings(false).apply(|| {
+    ///     // warnings are silenced here
+    /// }).unwrap();
+    /// ```
+    #[must_use]
+    pub fn warnings(mut self, enabled: bool) -> Options {
+        self.warnings = Some(enabled);
+        self
+    }
+
+    /// Read additional configuration data from the file at `path` when
+    /// entering the scope, as with `chemfiles::add_configuration`.
+    ///
+    /// The C library does not allow to remove configuration data, so the data
+    /// from this file is still used after the end of the scope.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Options;
+    /// Options::scoped().configuration("local-config.toml").apply(|| {
+    ///     // the data from "local-config.toml" is used here
+    /// }).unwrap();
+    /// ```
+    #[must_use]
+    pub fn configuration<P: AsRef<Path>>(mut self, path: P) -> Options {
+        self.configurations.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Call `function` with these options applied to the global state of the
+    /// chemfiles library, and restore the previous state afterward (even if
+    /// `function` panics).
+    ///
+    /// # Errors
+    ///
+    /// This function fails if any of the configuration files can not be read,
+    /// in which case `function` is not called.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Options;
+    /// let sum = Options::scoped().warnings(false).apply(|| 1 + 2).unwrap();
+    /// assert_eq!(sum, 3);
+    /// ```
+    pub fn apply<T>(self, function: impl FnOnce() -> T) -> Result<T, Error> {
+        let mut guard = ScopeGuard::enter();
+
+        for path in &self.configurations {
+            crate::add_configuration(path)?;
+        }
+
+        if let Some(enabled) = self.warnings {
+            let callback: Option<SharedCallback> = if enabled {
+                None
+            } else {
+                Some(Arc::new(Mutex::new(Box::new(|_: &str| {}))))
+            };
+            guard.previous_callback = replace_warning_callback(callback);
+            guard.replaced_callback = true;
+        }
+
+        let result = function();
+        std::mem::drop(guard);
+        return Ok(result);
+    }
+}
+
+/// Guard restoring the global state when an `Options::apply` scope ends
+struct ScopeGuard {
+    lock: Option<MutexGuard<'static, ()>>,
+    /// Was the warning callback modified in this scope?
+    replaced_callback: bool,
+    /// Warning callback to restore at the end of the scope
+    previous_callback: Option<SharedCallback>,
+}
+
+impl ScopeGuard {
+    fn enter() -> ScopeGuard {
+        let depth = OPTIONS_DEPTH.with(Cell::get);
+        let lock = if depth == 0 {
+            Some(OPTIONS_LOCK.lock().unwrap_or_else(PoisonError::into_inner))
+        } else {
+            None
+        };
+        OPTIONS_DEPTH.with(|value| value.set(depth + 1));
+
+        return ScopeGuard {
+            lock,
+            replaced_callback: false,
+            previous_callback: None,
+        };
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        if self.replaced_callback {
+            // drop the callback used in this scope outside of the lock
+            let current = replace_warning_callback(self.previous_callback.take());
+            std::mem::drop(current);
+        }
+        OPTIONS_DEPTH.with(|value| value.set(value.get() - 1));
+        // release the lock last
+        std::mem::drop(self.lock.take());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emit_warning() {
+        let data = "FOOBAR this is not a PDB record\nEND\n";
+        let mut trajectory = crate::Trajectory::memory_reader(data, "PDB").unwrap();
+        let mut frame = crate::Frame::new();
+        let _ = trajectory.read(&mut frame);
+    }
+
+    #[test]
+    fn scoped() {
+        // the outer scope prevents other tests from changing the warning
+        // callback while this one runs
+        Options::scoped()
+            .apply(|| {
+                let warnings = Arc::new(Mutex::new(0));
+                let counter = Arc::clone(&warnings);
+                crate::set_warning_callback(move |_: &str| {
+                    *counter.lock().unwrap() += 1;
+                });
+
+                emit_warning();
+                let count = *warnings.lock().unwrap();
+                assert!(count > 0);
+
+                let nested = Options::scoped()
+                    .warnings(false)
+                    .apply(|| {
+                        emit_warning();
+                        Options::scoped().warnings(false).apply(|| {
+                            emit_warning();
+                            "nested"
+                        })
+                    })
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(nested, "nested");
+                assert_eq!(*warnings.lock().unwrap(), count);
+
+                // the callback is restored after the scope
+                emit_warning();
+                assert!(*warnings.lock().unwrap() > count);
+                crate::reset_warning_callback();
+
+                let result = Options::scoped()
+                    .configuration("not-here.toml")
+                    .apply(|| unreachable!());
+                assert!(result.is_err());
+                assert_eq!(OPTIONS_DEPTH.with(Cell::get), 1);
+            })
+            .unwrap();
+        assert_eq!(OPTIONS_DEPTH.with(Cell::get), 0);
+    }
+}
@@ -0,0 +1,113 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static INTERNER_CAPACITY: AtomicUsize = AtomicUsize::new(512);
+static ATOM_STRING_BUFFER: AtomicUsize = AtomicUsize::new(10);
+
+/// Tuning knobs for the performance of this crate. The default values work
+/// well for most systems, but can be adjusted for specific workloads, for
+/// example when loading systems with a very large number of different atom
+/// names, or with atom names longer than usual.
+///
+/// These options are global to the whole process, and are modified with
+/// `chemfiles::set_perf_options`. They only change performance, never the
+/// results of any computation.
+///
+/// # Example
+/// ```
+/// # use chemfiles::PerfOptions;
+/// let options = PerfOptions::default();
+/// assert_eq!(options.interner_capacity, 512);
+/// assert_eq!(options.atom_string_buffer, 10);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerfOptions {
+    /// Controls how often the global interner used by `Atom::name_interned`
+    /// and `Atom::atomic_type_interned` removes the strings which are no
+    /// longer used: this happens when the interner contains twice this
+    /// number of strings (or twice the number of strings remaining after the
+    /// last cleanup, if larger). Larger values use more memory, but reduce
+    /// the time spent cleaning up the interner when loading systems with many
+    /// different names.
+    pub interner_capacity: usize,
+    /// Initial size (in bytes, including the final NULL byte) of the buffer
+    /// used to get atom names and types from the C library. Names longer
+    /// than this require growing the buffer and calling the C library
+    /// again. Values smaller than 2 are replaced by 2.
+    pub atom_string_buffer: usize,
+}
+
+impl Default for PerfOptions {
+    fn default() -> PerfOptions {
+        PerfOptions {
+            interner_capacity: 512,
+            atom_string_buffer: 10,
+        }
+    }
+}
+
+/// Get the performance options currently used by this crate.
+///
+/// # Example
+/// ```
+/// let options = chemfiles::perf_options();
+/// println!("interner capacity is {}", options.interner_capacity);
+/// ```
+pub fn perf_options() -> PerfOptions {
+    PerfOptions {
+        interner_capacity: INTERNER_CAPACITY.load(Ordering::Relaxed),
+        atom_string_buffer: ATOM_STRING_BUFFER.load(Ordering::Relaxed),
+    }
+}
+
+/// Use the given performance `options` from now on, for all threads.
+///
+/// # Example
+/// ```
+/// # use chemfiles::PerfOptions;
+/// // most atom names in this system are long
+/// chemfiles::set_perf_options(PerfOptions {
+///     atom_string_buffer: 32,
+///     ..chemfiles::perf_options()
+/// });
+/// assert_eq!(chemfiles::perf_options().atom_string_buffer, 32);
+/// # chemfiles::set_perf_options(PerfOptions::default());
+/// ```
+pub fn set_perf_options(options: PerfOptions) {
+    INTERNER_CAPACITY.store(options.interner_capacity, Ordering::Relaxed);
+    ATOM_STRING_BUFFER.store(usize::max(options.atom_string_buffer, 2), Ordering::Relaxed);
+}
+
+/// Get the current capacity of the string interner
+pub(crate) fn interner_capacity() -> usize {
+    INTERNER_CAPACITY.load(Ordering::Relaxed)
+}
+
+/// Get the current initial size of atom name and type buffers
+pub(crate) fn atom_string_buffer() -> usize {
+    ATOM_STRING_BUFFER.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options() {
+        assert_eq!(perf_options(), PerfOptions::default());
+
+        set_perf_options(PerfOptions {
+            interner_capacity: 1024,
+            atom_string_buffer: 0,
+        });
+        assert_eq!(interner_capacity(), 1024);
+        assert_eq!(atom_string_buffer(), 2);
+
+        // long names are still fully read with a small buffer
+        let atom = crate::Atom::new("a-very-long-atom-name");
+        assert_eq!(atom.name(), "a-very-long-atom-name");
+
+        set_perf_options(PerfOptions::default());
+    }
+}
@@ -0,0 +1,439 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Declarative processing of trajectories: read frames from a set of files,
+//! transform them, run analyses and write the results.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::analysis::{CellSeries, Results, Rmsf, Table};
+use crate::errors::{Error, Status};
+use crate::{CellShape, Frame, Selection, Trajectory, UnitCell};
+
+/// Declarative description of a `Pipeline`: which files to read, how to
+/// modify each frame, and which analyses to run.
+///
+/// With the `serde` feature enabled, this can be deserialized from any
+/// format supported by serde (JSON, TOML, YAML, ...). All fields are
+/// optional in the serialized representation, but `Pipeline::from_config`
+/// rejects configurations without any `inputs`.
+///
+/// # Example
+/// ```
+/// # use chemfiles::pipeline::{Analysis, AnalysisConfig, PipelineConfig, Transform};
+/// let config = PipelineConfig {
+///     inputs: vec!["run-1.xyz".into(), "run-2.xyz".into()],
+///     selection: Some("name O".into()),
+///     transforms: vec![Transform::Wrap],
+///     analyses: vec![AnalysisConfig {
+///         analysis: Analysis::Rmsf,
+///         output: Some("rmsf.csv".into()),
+///     }],
+///     ..Default::default()
+/// };
+/// assert_eq!(config.inputs.len(), 2);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct PipelineConfig {
+    /// Paths to the input trajectories, read one after the other
+    pub inputs: Vec<PathBuf>,
+    /// Format to use when reading the inputs. The format is guessed from
+    /// the file extension if this is `None`.
+    pub format: Option<String>,
+    /// Selection of the atoms to keep in each frame. All atoms are kept if
+    /// this is `None`.
+    pub selection: Option<String>,
+    /// Transformations to apply to each frame after the selection, in order
+    pub transforms: Vec<Transform>,
+    /// Analyses to run on the transformed frames
+    pub analyses: Vec<AnalysisConfig>,
+    /// Path where the transformed frames should be written, if any
+    pub output: Option<PathBuf>,
+    /// Format to use when writing the output. The format is guessed from
+    /// the file extension if this is `None`.
+    pub output_format: Option<String>,
+}
+
+/// A transformation applied to each frame in a `Pipeline`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Transform {
+    /// Set the unit cell of the frame, for example when reading formats
+    /// without unit cell information
+    SetCell {
+        /// Lengths of the unit cell, in Angstroms
+        lengths: [f64; 3],
+        /// Angles of the unit cell, in degrees
+        angles: [f64; 3],
+    },
+    /// Wrap all the atoms inside the unit cell
+    Wrap,
+    /// Remove existing bonds, and guess new ones from the positions with
+    /// `Frame::guess_bonds`
+    GuessBonds,
+}
+
+/// An analysis to run in a `Pipeline`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Analysis {
+    /// Unit cell parameters of each frame, see `analysis::cell_series`
+    CellSeries,
+    /// Root mean square fluctuation of each atom, see `analysis::Rmsf`
+    Rmsf,
+}
+
+/// Configuration of a single analysis in a `Pipeline`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct AnalysisConfig {
+    /// Which analysis to run
+    pub analysis: Analysis,
+    /// Path of a CSV file where the results should be written, if any
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub output: Option<PathBuf>,
+}
+
+/// A `Pipeline` reads frames from one or more trajectories, selects and
+/// transforms them, runs analyses on the result, and optionally writes the
+/// transformed frames to a new file. Pipelines are created from a
+/// declarative `PipelineConfig`, making it easy to build configuration-driven
+/// batch tools.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::pipeline::{Pipeline, PipelineConfig, Transform};
+/// let config = PipelineConfig {
+///     inputs: vec!["simulation.xyz".into()],
+///     selection: Some("not name H".into()),
+///     transforms: vec![Transform::GuessBonds],
+///     output: Some("heavy-atoms.pdb".into()),
+///     ..Default::default()
+/// };
+///
+/// let pipeline = Pipeline::from_config(config).unwrap();
+/// pipeline.run().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    config: PipelineConfig,
+}
+
+impl Pipeline {
+    /// Create a new pipeline from the given `config`, checking that the
+    /// configuration is valid.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if there are no input files, or if the selection
+    /// is invalid or matches multiple atoms (for example `"pairs: ..."`).
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::pipeline::{Pipeline, PipelineConfig};
+    /// let config = PipelineConfig {
+    ///     inputs: vec!["water.xyz".into()],
+    ///     selection: Some("name O and".into()),
+    ///     ..Default::default()
+    /// };
+    /// assert!(Pipeline::from_config(config).is_err());
+    ///
+    /// assert!(Pipeline::from_config(PipelineConfig::default()).is_err());
+    /// ```
+    pub fn from_config(config: PipelineConfig) -> Result<Pipeline, Error> {
+        if config.inputs.is_empty() {
+            return Err(Error::new(
+                Status::ConfigurationError,
+                "a pipeline needs at least one input file",
+            ));
+        }
+
+        if let Some(selection) = &config.selection {
+            if Selection::new(selection.as_str())?.size() != 1 {
+                return Err(Error::new(
+                    Status::SelectionError,
+                    "pipelines require a selection matching single atoms",
+                ));
+            }
+        }
+
+        return Ok(Pipeline { config });
+    }
+
+    /// Get the configuration of this pipeline.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::pipeline::{Pipeline, PipelineConfig};
+    /// let config = PipelineConfig {
+    ///     inputs: vec!["water.xyz".into()],
+    ///     ..Default::default()
+    /// };
+    /// let pipeline = Pipeline::from_config(config.clone()).unwrap();
+    /// assert_eq!(pipeline.config(), &config);
+    /// ```
+    pub fn config(&self) -> &PipelineConfig {
+        &self.config
+    }
+
+    /// Run this pipeline, processing all the frames in all the inputs. The
+    /// results of the analyses are written to their output files (if any),
+    /// and returned as tables in the same order as `PipelineConfig::analyses`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if any input can not be read, if the output can
+    /// not be written, or if any of the transformations or analyses fails.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::pipeline::{Analysis, AnalysisConfig, Pipeline, PipelineConfig};
+    /// let config = PipelineConfig {
+    ///     inputs: vec!["npt.dcd".into()],
+    ///     analyses: vec![AnalysisConfig {
+    ///         analysis: Analysis::CellSeries,
+    ///         output: None,
+    ///     }],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let results = Pipeline::from_config(config).unwrap().run().unwrap();
+    /// println!("volumes: {:?}", results[0].rows);
+    /// ```
+    pub fn run(&self) -> Result<Vec<Table>, Error> {
+        let config = &self.config;
+        let mut selection = match &config.selection {
+            Some(selection) => Some(Selection::new(selection.as_str())?),
+            None => None,
+        };
+
+        let mut output = match &config.output {
+            Some(path) => Some(open(path, 'w', config.output_format.as_deref())?),
+            None => None,
+        };
+
+        let mut analyses = config
+            .analyses
+            .iter()
+            .map(|analysis| match analysis.analysis {
+                Analysis::CellSeries => Accumulator::CellSeries(CellSeries::default()),
+                Analysis::Rmsf => Accumulator::Rmsf(Rmsf::new()),
+            })
+            .collect::<Vec<_>>();
+
+        let mut frame = Frame::new();
+        for path in &config.inputs {
+            let mut trajectory = open(path, 'r', config.format.as_deref())?;
+            for step in 0..trajectory.try_nsteps()? {
+                trajectory.read_step(step, &mut frame)?;
+
+                let mut current = match &mut selection {
+                    Some(selection) => frame.subset(&selection.list(&frame)),
+                    None => frame.clone(),
+                };
+
+                for transform in &config.transforms {
+                    transform.apply(&mut current)?;
+                }
+
+                for analysis in &mut analyses {
+                    analysis.add_frame(&current)?;
+                }
+
+                if let Some(output) = &mut output {
+                    output.write(&current)?;
+                }
+            }
+        }
+
+        let mut tables = Vec::with_capacity(analyses.len());
+        for (analysis, config) in analyses.iter().zip(&config.analyses) {
+            let table = analysis.table();
+            if let Some(path) = &config.output {
+                table.to_csv(BufWriter::new(File::create(path)?))?;
+            }
+            tables.push(table);
+        }
+
+        return Ok(tables);
+    }
+}
+
+impl Transform {
+    /// Apply this transformation to the `frame`
+    fn apply(&self, frame: &mut Frame) -> Result<(), Error> {
+        match self {
+            Transform::SetCell { lengths, angles } => {
+                let cell = UnitCell::triclinic(*lengths, *angles);
+                frame.set_cell(&cell);
+            }
+            Transform::Wrap => {
+                let cell = frame.cell().clone();
+                if cell.shape() != CellShape::Infinite {
+                    for position in frame.positions_mut() {
+                        cell.wrap(position);
+                    }
+                }
+            }
+            Transform::GuessBonds => {
+                frame.clear_bonds();
+                frame.guess_bonds()?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// Results of an analysis accumulated while running a pipeline
+enum Accumulator {
+    CellSeries(CellSeries),
+    Rmsf(Rmsf),
+}
+
+impl Accumulator {
+    fn add_frame(&mut self, frame: &Frame) -> Result<(), Error> {
+        match self {
+            Accumulator::CellSeries(series) => series.push(frame),
+            Accumulator::Rmsf(rmsf) => rmsf.add_frame(frame)?,
+        }
+        return Ok(());
+    }
+
+    fn table(&self) -> Table {
+        let results: &dyn Results = match self {
+            Accumulator::CellSeries(series) => series,
+            Accumulator::Rmsf(rmsf) => rmsf,
+        };
+        return Table::new(results.columns(), results.rows());
+    }
+}
+
+/// Open the trajectory at `path`, using `format` if given
+fn open(path: &Path, mode: char, format: Option<&str>) -> Result<Trajectory, Error> {
+    match format {
+        Some(format) => Trajectory::open_with_format(path, mode, format),
+        None => Trajectory::open(path, mode),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run() {
+        let output = "test-tmp-pipeline.xyz";
+        let csv = "test-tmp-pipeline-cell.csv";
+        let config = PipelineConfig {
+            inputs: vec!["data/water.xyz".into(), "data/water.xyz".into()],
+            selection: Some("name O".into()),
+            transforms: vec![
+                Transform::SetCell {
+                    lengths: [15.0, 15.0, 15.0],
+                    angles: [90.0, 90.0, 90.0],
+                },
+                Transform::Wrap,
+            ],
+            analyses: vec![
+                AnalysisConfig {
+                    analysis: Analysis::CellSeries,
+                    output: Some(csv.into()),
+                },
+                AnalysisConfig {
+                    analysis: Analysis::Rmsf,
+                    output: None,
+                },
+            ],
+            output: Some(output.into()),
+            ..Default::default()
+        };
+
+        let results = Pipeline::from_config(config).unwrap().run().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].rows.len(), 200);
+        assert!(results[0].rows.iter().all(|row| row[1] == 15.0));
+        assert_eq!(results[1].columns, ["atom", "rmsf"]);
+        assert_eq!(results[1].rows.len(), 99);
+
+        let mut trajectory = Trajectory::open(output, 'r').unwrap();
+        assert_eq!(trajectory.nsteps(), 200);
+        let mut frame = Frame::new();
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.size(), 99);
+        for position in frame.positions() {
+            assert!(position.iter().all(|&x| (0.0..=15.0).contains(&x)));
+        }
+        std::mem::drop(trajectory);
+
+        let content = std::fs::read_to_string(csv).unwrap();
+        assert!(content.starts_with("step,a,b,c,alpha,beta,gamma,volume\n"));
+        assert_eq!(content.lines().count(), 201);
+
+        std::fs::remove_file(output).unwrap();
+        std::fs::remove_file(csv).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Pipeline::from_config(PipelineConfig::default()).is_err());
+
+        let config = PipelineConfig {
+            inputs: vec!["not-here.xyz".into()],
+            ..Default::default()
+        };
+        let pipeline = Pipeline::from_config(config).unwrap();
+        assert!(pipeline.run().is_err());
+
+        let config = PipelineConfig {
+            inputs: vec!["data/water.xyz".into()],
+            selection: Some("pairs: all".into()),
+            ..Default::default()
+        };
+        let error = Pipeline::from_config(config).unwrap_err();
+        assert_eq!(error.status, Status::SelectionError);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn deserialize() {
+        let config: PipelineConfig = serde_json::from_str(
+            r#"{
+                "inputs": ["data/water.xyz"],
+                "selection": "name O",
+                "transforms": [
+                    {"type": "set_cell", "lengths": [10, 10, 10], "angles": [90, 90, 90]},
+                    {"type": "guess_bonds"}
+                ],
+                "analyses": [{"analysis": "cell_series", "output": "cell.csv"}]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.inputs, [PathBuf::from("data/water.xyz")]);
+        assert_eq!(config.selection.as_deref(), Some("name O"));
+        assert_eq!(config.format, None);
+        assert_eq!(config.transforms[1], Transform::GuessBonds);
+        assert_eq!(
+            config.analyses,
+            [AnalysisConfig {
+                analysis: Analysis::CellSeries,
+                output: Some("cell.csv".into()),
+            }]
+        );
+
+        let json = serde_json::to_string(&config).unwrap();
+        let roundtrip: PipelineConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(roundtrip, config);
+
+        assert!(serde_json::from_str::<PipelineConfig>(r#"{"unknown": 3}"#).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Progress of a long-running analysis, given to a `ProgressSink` after each
+/// frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Number of frames processed so far
+    pub frames: usize,
+    /// Total number of frames to process, if known
+    pub total: Option<usize>,
+    /// Time elapsed since the first frame started processing
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Get the fraction of the frames processed so far, between 0 and 1, if
+    /// the total number of frames is known.
+    ///
+    /// # Example
+    /// ```
+    /// # use std::time::Duration;
+    /// # use chemfiles::analysis::Progress;
+    /// let progress = Progress {
+    ///     frames: 25,
+    ///     total: Some(100),
+    ///     elapsed: Duration::from_secs(10),
+    /// };
+    /// assert_eq!(progress.fraction(), Some(0.25));
+    /// ```
+    pub fn fraction(&self) -> Option<f64> {
+        let total = self.total?;
+        if total == 0 {
+            return Some(1.0);
+        }
+        #[allow(clippy::cast_precision_loss)]
+        return Some(f64::min(self.frames as f64 / total as f64, 1.0));
+    }
+
+    /// Estimate the time remaining until all frames are processed, assuming
+    /// all frames take the same time. This is `None` if the total number of
+    /// frames is not known, or if no frame was processed yet.
+    ///
+    /// # Example
+    /// ```
+    /// # use std::time::Duration;
+    /// # use chemfiles::analysis::Progress;
+    /// let progress = Progress {
+    ///     frames: 25,
+    ///     total: Some(100),
+    ///     elapsed: Duration::from_secs(10),
+    /// };
+    /// assert_eq!(progress.remaining(), Some(Duration::from_secs(30)));
+    /// ```
+    pub fn remaining(&self) -> Option<Duration> {
+        let total = self.total?;
+        if self.frames == 0 {
+            return None;
+        }
+
+        let remaining = total.saturating_sub(self.frames);
+        #[allow(clippy::cast_precision_loss)]
+        let seconds = self.elapsed.as_secs_f64() * remaining as f64 / self.frames as f64;
+        return Some(Duration::from_secs_f64(seconds));
+    }
+}
+
+/// A `ProgressSink` receives progress reports from long-running analyses,
+/// for example to display a progress bar in a CLI or GUI.
+///
+/// This trait is implemented for all closures taking a `&Progress`.
+pub trait ProgressSink: Send {
+    /// Report the current `progress`
+    fn report(&mut self, progress: &Progress);
+}
+
+impl<F> ProgressSink for F
+where
+    F: FnMut(&Progress) + Send,
+{
+    fn report(&mut self, progress: &Progress) {
+        self(progress);
+    }
+}
+
+/// A `ProgressTracker` counts the frames processed by an analysis and sends
+/// the corresponding `Progress` to a `ProgressSink`. Accumulators such as
+/// `Adf` or `Rmsf` accept a tracker with their `set_progress` function, and
+/// the radial distribution function with `analysis::rdf_with_progress`.
+///
+/// Clones of a tracker share the same sink, but count frames separately.
+///
+/// # Example
+/// ```
+/// # use chemfiles::analysis::{Progress, ProgressTracker};
+/// let mut tracker = ProgressTracker::new(
+///     |progress: &Progress| println!("{} frames done", progress.frames),
+///     Some(100),
+/// );
+/// tracker.tick();
+/// assert_eq!(tracker.frames(), 1);
+/// ```
+#[derive(Clone)]
+pub struct ProgressTracker {
+    sink: Arc<Mutex<dyn ProgressSink>>,
+    total: Option<usize>,
+    frames: usize,
+    interval: usize,
+    start: Option<Instant>,
+    /// The total is not known yet, and should be computed before the first
+    /// frame is processed (see `ProgressTracker::with_lazy_total`)
+    lazy_total: bool,
+}
+
+impl std::fmt::Debug for ProgressTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgressTracker")
+            .field("total", &self.total)
+            .field("frames", &self.frames)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProgressTracker {
+    /// Create a new tracker sending reports to `sink`, for an analysis
+    /// running over `total` frames (if this number is known).
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::{Progress, ProgressTracker};
+    /// let tracker = ProgressTracker::new(|_: &Progress| {}, None);
+    /// assert_eq!(tracker.frames(), 0);
+    /// ```
+    pub fn new<S: ProgressSink + 'static>(sink: S, total: Option<usize>) -> ProgressTracker {
+        ProgressTracker {
+            sink: Arc::new(Mutex::new(sink)),
+            total,
+            frames: 0,
+            interval: 1,
+            start: None,
+            lazy_total: false,
+        }
+    }
+
+    /// Only send the progress to the sink every `interval` frames, and
+    /// after the last frame if the total number of frames is known. By
+    /// default, the progress is sent after each frame.
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use chemfiles::analysis::{Progress, ProgressTracker};
+    /// let reports = Arc::new(Mutex::new(Vec::new()));
+    /// let collected = Arc::clone(&reports);
+    /// let mut tracker = ProgressTracker::new(
+    ///     move |progress: &Progress| collected.lock().unwrap().push(progress.frames),
+    ///     Some(5),
+    /// )
+    /// .with_interval(2);
+    ///
+    /// for _ in 0..5 {
+    ///     tracker.tick();
+    /// }
+    /// assert_eq!(*reports.lock().unwrap(), [2, 4, 5]);
+    /// ```
+    #[must_use]
+    pub fn with_interval(mut self, interval: usize) -> ProgressTracker {
+        assert!(interval > 0, "the progress reporting interval can not be zero");
+        self.interval = interval;
+        self
+    }
+
+    /// Mark the total number of frames as not yet known, to be given with
+    /// `ProgressTracker::set_total` once the first frame has been processed.
+    /// This is used by `Trajectory`, where computing the total can be
+    /// expensive.
+    #[must_use]
+    pub(crate) fn with_lazy_total(mut self) -> ProgressTracker {
+        self.lazy_total = true;
+        self
+    }
+
+    /// Check if the total number of frames still needs to be given to this
+    /// tracker with `ProgressTracker::set_total`.
+    pub(crate) fn needs_total(&self) -> bool {
+        self.lazy_total
+    }
+
+    /// Set the total number of frames for a tracker created with
+    /// `ProgressTracker::with_lazy_total`.
+    pub(crate) fn set_total(&mut self, total: Option<usize>) {
+        self.total = total;
+        self.lazy_total = false;
+    }
+
+    /// Get the number of frames processed so far.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::{Progress, ProgressTracker};
+    /// let mut tracker = ProgressTracker::new(|_: &Progress| {}, None);
+    /// tracker.tick();
+    /// tracker.tick();
+    /// assert_eq!(tracker.frames(), 2);
+    /// ```
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Start measuring the time, if this was not already done. This is
+    /// called automatically by `ProgressTracker::tick`, but calling it
+    /// before processing the first frame gives more accurate estimates.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::analysis::{Progress, ProgressTracker};
+    /// let mut tracker = ProgressTracker::new(|_: &Progress| {}, Some(10));
+    /// tracker.start();
+    /// ```
+    pub fn start(&mut self) {
+        if self.start.is_none() {
+            self.start = Some(Instant::now());
+        }
+    }
+
+    /// Record that one more frame was processed, and send the updated
+    /// progress to the sink (see also `ProgressTracker::with_interval`).
+    ///
+    /// # Example
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use chemfiles::analysis::{Progress, ProgressTracker};
+    /// let reports = Arc::new(Mutex::new(Vec::new()));
+    /// let collected = Arc::clone(&reports);
+    /// let mut tracker = ProgressTracker::new(
+    ///     move |progress: &Progress| collected.lock().unwrap().push(progress.frames),
+    ///     Some(2),
+    /// );
+    ///
+    /// tracker.tick();
+    /// tracker.tick();
+    /// assert_eq!(*reports.lock().unwrap(), [1, 2]);
+    /// ```
+    pub fn tick(&mut self) {
+        self.start();
+        self.frames += 1;
+        if self.frames.checked_rem(self.interval) != Some(0) && Some(self.frames) != self.total {
+            return;
+        }
+
+        let progress = Progress {
+            frames: self.frames,
+            total: self.total,
+            elapsed: self.start.map_or(Duration::ZERO, |start| start.elapsed()),
+        };
+        let mut sink = self.sink.lock().unwrap_or_else(PoisonError::into_inner);
+        sink.report(&progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress() {
+        let progress = Progress {
+            frames: 0,
+            total: None,
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(progress.fraction(), None);
+        assert_eq!(progress.remaining(), None);
+
+        let progress = Progress {
+            frames: 4,
+            total: Some(3),
+            elapsed: Duration::from_secs(2),
+        };
+        assert_eq!(progress.fraction(), Some(1.0));
+        assert_eq!(progress.remaining(), Some(Duration::ZERO));
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&reports);
+        let mut tracker = ProgressTracker::new(
+            move |progress: &Progress| collected.lock().unwrap().push(*progress),
+            Some(3),
+        );
+        let mut clone = tracker.clone();
+        tracker.tick();
+        tracker.tick();
+        clone.tick();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[1].frames, 2);
+        assert_eq!(reports[1].total, Some(3));
+        assert_eq!(reports[2].frames, 1);
+        assert!(reports[0].elapsed <= reports[1].elapsed);
+    }
+
+    #[test]
+    fn interval() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&reports);
+        let mut tracker = ProgressTracker::new(
+            move |progress: &Progress| collected.lock().unwrap().push(progress.frames),
+            None,
+        )
+        .with_interval(3);
+        for _ in 0..7 {
+            tracker.tick();
+        }
+        assert_eq!(tracker.frames(), 7);
+        assert_eq!(*reports.lock().unwrap(), [3, 6]);
+    }
+
+    #[test]
+    fn lazy_total() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&reports);
+        let mut tracker = ProgressTracker::new(
+            move |progress: &Progress| collected.lock().unwrap().push(*progress),
+            None,
+        )
+        .with_lazy_total()
+        .with_interval(2);
+        assert!(tracker.needs_total());
+
+        tracker.set_total(Some(3));
+        assert!(!tracker.needs_total());
+        for _ in 0..3 {
+            tracker.tick();
+        }
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].total, Some(3));
+        assert_eq!(reports[1].frames, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "the progress reporting interval can not be zero")]
+    fn zero_interval() {
+        let _ = ProgressTracker::new(|_: &Progress| {}, None).with_interval(0);
+    }
+}
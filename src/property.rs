@@ -1,8 +1,10 @@
 // Chemfiles, a modern library for chemistry file reading and writing
 // Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::convert::TryFrom;
+
 use chemfiles_sys as ffi;
 
-use crate::errors::{check, check_not_null, check_success, Error};
+use crate::errors::{check, check_not_null, check_success, Error, Status};
 use crate::strings;
 
 /// A thin wrapper around `ffi::CHFL_PROPERTY`
@@ -101,9 +103,24 @@ impl Drop for RawProperty {
     }
 }
 
-/// A `Property` is a piece of data that can be associated with an `Atom` or a
-/// `Frame`.
+/// A `Property` is a piece of data that can be associated with an `Atom`, a
+/// `Residue` or a `Frame`.
+///
+/// Properties can be converted back to the corresponding Rust type with the
+/// `as_*` functions, or with `TryFrom`:
+///
+/// ```
+/// # use chemfiles::{Frame, Property};
+/// let mut frame = Frame::new();
+/// frame.set("energy", -42.0);
+///
+/// let energy = f64::try_from(frame.get("energy").unwrap()).unwrap();
+/// assert_eq!(energy, -42.0);
+///
+/// assert!(String::try_from(Property::Double(3.0)).is_err());
+/// ```
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Property {
     /// Boolean property
     Bool(bool),
@@ -145,7 +162,117 @@ impl From<[f64; 3]> for Property {
     }
 }
 
+macro_rules! impl_try_from_property {
+    ($type: ty, $variant: ident, $kind: expr) => {
+        impl TryFrom<Property> for $type {
+            type Error = Error;
+
+            fn try_from(property: Property) -> Result<Self, Error> {
+                match property {
+                    Property::$variant(value) => Ok(value),
+                    other => Err(Error::new(
+                        Status::PropertyError,
+                        format!(
+                            "expected a {} property, got a {} property",
+                            $kind,
+                            other.kind_name()
+                        ),
+                    )),
+                }
+            }
+        }
+    };
+}
+
+impl_try_from_property!(bool, Bool, "bool");
+impl_try_from_property!(f64, Double, "double");
+impl_try_from_property!(String, String, "string");
+impl_try_from_property!([f64; 3], Vector3D, "vector3d");
+
 impl Property {
+    /// Get the value of this property if it is a `Property::Bool`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Property;
+    /// assert_eq!(Property::Bool(true).as_bool(), Some(true));
+    /// assert_eq!(Property::Double(1.0).as_bool(), None);
+    /// ```
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Property::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value of this property if it is a `Property::Double`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Atom;
+    /// let mut atom = Atom::new("C");
+    /// atom.set("bfactor", 12.5);
+    ///
+    /// let bfactor = atom.get("bfactor").and_then(|p| p.as_double());
+    /// assert_eq!(bfactor, Some(12.5));
+    /// ```
+    pub fn as_double(&self) -> Option<f64> {
+        match *self {
+            Property::Double(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value of this property if it is a `Property::String`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Property;
+    /// assert_eq!(Property::String("A".into()).as_str(), Some("A"));
+    /// assert_eq!(Property::Bool(false).as_str(), None);
+    /// ```
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Property::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value of this property if it is a `Property::Vector3D`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Property;
+    /// assert_eq!(Property::Vector3D([1.0, 2.0, 3.0]).as_vector3d(), Some([1.0, 2.0, 3.0]));
+    /// assert_eq!(Property::Double(1.0).as_vector3d(), None);
+    /// ```
+    pub fn as_vector3d(&self) -> Option<[f64; 3]> {
+        match *self {
+            Property::Vector3D(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value of this property as a single character, if it is a
+    /// `Property::String` starting with a non-whitespace character. This is
+    /// used for one-letter codes such as alternate locations or insertion
+    /// codes.
+    pub(crate) fn as_code(&self) -> Option<char> {
+        self.as_str()
+            .and_then(|value| value.chars().next())
+            .filter(|code| !code.is_whitespace())
+    }
+
+    /// Get the name of the kind of this property, for error messages
+    fn kind_name(&self) -> &'static str {
+        match *self {
+            Property::Bool(_) => "bool",
+            Property::Double(_) => "double",
+            Property::String(_) => "string",
+            Property::Vector3D(_) => "vector3d",
+        }
+    }
+
     pub(crate) fn as_raw(&self) -> RawProperty {
         match *self {
             Property::Bool(value) => RawProperty::bool(value),
@@ -233,6 +360,27 @@ mod tests {
     mod rust {
         use super::super::*;
 
+        #[test]
+        fn conversions() {
+            assert_eq!(bool::try_from(Property::Bool(true)), Ok(true));
+            assert_eq!(f64::try_from(Property::Double(2.5)), Ok(2.5));
+            assert_eq!(String::try_from(Property::String("A".into())), Ok("A".into()));
+            assert_eq!(
+                <[f64; 3]>::try_from(Property::Vector3D([1.0, 2.0, 3.0])),
+                Ok([1.0, 2.0, 3.0])
+            );
+
+            let error = f64::try_from(Property::Bool(true)).unwrap_err();
+            assert_eq!(error.status, Status::PropertyError);
+            assert_eq!(error.message, "expected a double property, got a bool property");
+
+            let property = Property::String("A".into());
+            assert_eq!(property.as_str(), Some("A"));
+            assert_eq!(property.as_bool(), None);
+            assert_eq!(property.as_double(), None);
+            assert_eq!(property.as_vector3d(), None);
+        }
+
         #[test]
         fn bool() {
             let property = Property::Bool(false);
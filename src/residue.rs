@@ -1,10 +1,10 @@
 // Chemfiles, a modern library for chemistry file reading and writing
 // Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
-use std::marker::PhantomData;
 
 use chemfiles_sys as ffi;
 
-use crate::errors::{check_not_null, check_success};
+use crate::borrowed::Borrowed;
+use crate::errors::{check_allocation, check_not_null, check_success, install_log_bridge, Error};
 use crate::property::{PropertiesIter, Property, RawProperty};
 use crate::strings;
 
@@ -16,26 +16,13 @@ pub struct Residue {
     handle: *mut ffi::CHFL_RESIDUE,
 }
 
-/// An analog to a reference to a residue (`&Residue`)
-#[derive(Debug)]
-pub struct ResidueRef<'a> {
-    inner: Residue,
-    marker: PhantomData<&'a Residue>,
-}
-
-impl<'a> std::ops::Deref for ResidueRef<'a> {
-    type Target = Residue;
-    fn deref(&self) -> &Residue {
-        &self.inner
-    }
-}
+/// An analog to a reference to a residue (`&Residue`), for residues owned by
+/// a `Topology`. See `Borrowed` for more information.
+pub type ResidueRef<'a> = Borrowed<'a, Residue>;
 
 impl Clone for Residue {
     fn clone(&self) -> Residue {
-        unsafe {
-            let new_handle = ffi::chfl_residue_copy(self.as_ptr());
-            Residue::from_ptr(new_handle)
-        }
+        self.try_clone().expect("failed to copy residue")
     }
 }
 
@@ -57,10 +44,7 @@ impl Residue {
     #[inline]
     #[allow(clippy::ptr_cast_constness)]
     pub(crate) unsafe fn ref_from_ptr<'a>(ptr: *const ffi::CHFL_RESIDUE) -> ResidueRef<'a> {
-        ResidueRef {
-            inner: Residue::from_ptr(ptr as *mut ffi::CHFL_RESIDUE),
-            marker: PhantomData,
-        }
+        Borrowed::new(Residue::from_ptr(ptr as *mut ffi::CHFL_RESIDUE))
     }
 
     /// Get the underlying C pointer as a const pointer.
@@ -75,6 +59,28 @@ impl Residue {
         self.handle
     }
 
+    /// Create a copy of this residue, returning an error instead of panicking
+    /// if the memory allocation fails. `Clone::clone` panics in this case.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the C++ library can not allocate memory for
+    /// the copy.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Residue;
+    /// let residue = Residue::new("ALA");
+    /// let copy = residue.try_clone().unwrap();
+    /// assert_eq!(copy.name(), "ALA");
+    /// ```
+    pub fn try_clone(&self) -> Result<Residue, Error> {
+        unsafe {
+            let new_handle = check_allocation(ffi::chfl_residue_copy(self.as_ptr()))?;
+            return Ok(Residue::from_ptr(new_handle));
+        }
+    }
+
     /// Create a new residue with the given `name`
     ///
     /// # Example
@@ -85,6 +91,7 @@ impl Residue {
     /// assert_eq!(residue.id(), None);
     /// ```
     pub fn new<'a>(name: impl Into<&'a str>) -> Residue {
+        install_log_bridge();
         let buffer = strings::to_c(name.into());
         unsafe {
             let handle = ffi::chfl_residue(buffer.as_ptr());
@@ -102,6 +109,7 @@ impl Residue {
     /// assert_eq!(residue.id(), Some(67));
     /// ```
     pub fn with_id<'a>(name: impl Into<&'a str>, id: i64) -> Residue {
+        install_log_bridge();
         let buffer = strings::to_c(name.into());
         unsafe {
             let handle = ffi::chfl_residue_with_id(buffer.as_ptr(), id);
@@ -168,6 +176,37 @@ impl Residue {
         return strings::from_c(name.as_ptr());
     }
 
+    /// Get the insertion code of this residue, as read from the
+    /// `"insertion_code"` property set by the PDB and mmCIF readers.
+    /// Residues without insertion code return `None`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Residue;
+    /// let mut residue = Residue::with_id("GLY", 52);
+    /// assert_eq!(residue.insertion_code(), None);
+    ///
+    /// residue.set_insertion_code('A');
+    /// assert_eq!(residue.insertion_code(), Some('A'));
+    /// ```
+    pub fn insertion_code(&self) -> Option<char> {
+        self.get("insertion_code").and_then(|code| code.as_code())
+    }
+
+    /// Set the insertion code of this residue to `code`, in the
+    /// `"insertion_code"` property used by the PDB and mmCIF writers.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Property, Residue};
+    /// let mut residue = Residue::with_id("GLY", 52);
+    /// residue.set_insertion_code('C');
+    /// assert_eq!(residue.get("insertion_code"), Some(Property::String("C".into())));
+    /// ```
+    pub fn set_insertion_code(&mut self, code: char) {
+        self.set("insertion_code", code.to_string());
+    }
+
     /// Add the atom at index `atom` in this residue.
     ///
     /// This will fail if the atom is already in the residue.
@@ -303,31 +342,59 @@ impl Residue {
     /// }
     /// ```
     pub fn properties(&self) -> PropertiesIter {
+        PropertiesIter {
+            names: self.list_properties().into_iter(),
+            getter: Box::new(move |name| self.get(name).expect("failed to get property")),
+        }
+    }
+
+    /// Get the number of properties associated with this residue.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Residue;
+    /// let mut residue = Residue::new("ALA");
+    /// assert_eq!(residue.properties_count(), 0);
+    ///
+    /// residue.set("foo", 22.2);
+    /// residue.set("bar", false);
+    /// assert_eq!(residue.properties_count(), 2);
+    /// ```
+    pub fn properties_count(&self) -> usize {
         let mut count = 0;
         unsafe {
             check_success(ffi::chfl_residue_properties_count(self.as_ptr(), &mut count));
         }
-
         #[allow(clippy::cast_possible_truncation)]
-        let size = count as usize;
-        let mut c_names = vec![std::ptr::null_mut(); size];
+        return count as usize;
+    }
+
+    /// Get the names of all the properties associated with this residue,
+    /// without getting the corresponding values.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Residue;
+    /// let mut residue = Residue::new("ALA");
+    /// residue.set("foo", 22.2);
+    /// residue.set("bar", false);
+    ///
+    /// let mut names = residue.list_properties();
+    /// names.sort();
+    /// assert_eq!(names, ["bar", "foo"]);
+    /// ```
+    pub fn list_properties(&self) -> Vec<String> {
+        let count = self.properties_count();
+        let mut c_names = vec![std::ptr::null_mut(); count];
         unsafe {
             check_success(ffi::chfl_residue_list_properties(
                 self.as_ptr(),
                 c_names.as_mut_ptr(),
-                count,
+                count as u64,
             ));
         }
 
-        let mut names = Vec::new();
-        for ptr in c_names {
-            names.push(strings::from_c(ptr));
-        }
-
-        PropertiesIter {
-            names: names.into_iter(),
-            getter: Box::new(move |name| self.get(name).expect("failed to get property")),
-        }
+        return c_names.into_iter().map(|ptr| strings::from_c(ptr)).collect();
     }
 }
 
@@ -339,6 +406,11 @@ impl Drop for Residue {
     }
 }
 
+// SAFETY: a `Residue` owns its C++ object, which is only modified through
+// `&mut self`.
+unsafe impl Send for Residue {}
+unsafe impl Sync for Residue {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -355,6 +427,9 @@ mod tests {
         residue.add_atom(7);
         assert_eq!(residue.size(), 2);
         assert_eq!(copy.size(), 0);
+
+        let copy = residue.try_clone().unwrap();
+        assert_eq!(copy.atoms(), [3, 7]);
     }
 
     #[test]
@@ -407,5 +482,10 @@ mod tests {
                 assert_eq!(property, Property::String("here".into()));
             }
         }
+
+        assert_eq!(residue.properties_count(), 2);
+        let mut names = residue.list_properties();
+        names.sort();
+        assert_eq!(names, ["bar", "foo"]);
     }
 }
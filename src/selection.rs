@@ -1,14 +1,36 @@
 // Chemfiles, a modern library for chemistry file reading and writing
 // Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use std::convert::TryFrom;
+
 use chemfiles_sys as ffi;
 
-use crate::errors::{check, check_not_null, check_success, Error, Status};
+use crate::errors::{check, check_allocation, check_not_null, check_success, install_log_bridge, Error, Status};
 use crate::frame::Frame;
+use crate::spans::Span;
 use crate::strings;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// A `Match` is a set of atomic indexes matching a given selection. It can
 /// mostly be used like a `&[usize]`.
+///
+/// When the size of the selection is known, a match can be converted to an
+/// array of the corresponding size with `TryFrom`:
+///
+/// ```
+/// # use chemfiles::{Atom, Frame, Selection};
+/// let mut frame = Frame::new();
+/// frame.add_atom(&Atom::new("H"), [1.0, 0.0, 0.0], None);
+/// frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+/// frame.add_bond(0, 1);
+///
+/// let mut selection = Selection::new("bonds: all").unwrap();
+/// for atomic_match in selection.evaluate(&frame) {
+///     let [i, j] = <[usize; 2]>::try_from(&atomic_match).unwrap();
+///     assert_eq!((i, j), (0, 1));
+///
+///     assert!(<[usize; 3]>::try_from(&atomic_match).is_err());
+/// }
+/// ```
 pub struct Match {
     size: usize,
     atoms: [usize; 4],
@@ -73,8 +95,52 @@ impl Match {
     pub fn iter(&self) -> std::slice::Iter<usize> {
         self.atoms[..self.len()].iter()
     }
+
+    /// Get the atomic indexes in the match as a slice.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use chemfiles::Match;
+    /// let atomic_match = Match::new(&[3, 4, 5]);
+    /// assert_eq!(atomic_match.as_slice(), &[3, 4, 5]);
+    /// ```
+    pub fn as_slice(&self) -> &[usize] {
+        &self.atoms[..self.len()]
+    }
+}
+
+macro_rules! impl_try_from_match {
+    ($size: expr, $context: expr) => {
+        impl TryFrom<&Match> for [usize; $size] {
+            type Error = Error;
+
+            fn try_from(atomic_match: &Match) -> Result<Self, Error> {
+                if atomic_match.len() == $size {
+                    let mut atoms = [0; $size];
+                    atoms.copy_from_slice(atomic_match.as_slice());
+                    Ok(atoms)
+                } else {
+                    Err(Error::new(
+                        Status::SelectionError,
+                        format!(
+                            "expected a match with {} atoms ({}), got a match with {} atoms",
+                            $size,
+                            $context,
+                            atomic_match.len()
+                        ),
+                    ))
+                }
+            }
+        }
+    };
 }
 
+impl_try_from_match!(1, "single atom");
+impl_try_from_match!(2, "pair or bond");
+impl_try_from_match!(3, "three atoms or angle");
+impl_try_from_match!(4, "four atoms or dihedral");
+
 impl std::ops::Index<usize> for Match {
     type Output = usize;
     fn index(&self, i: usize) -> &Self::Output {
@@ -104,10 +170,7 @@ pub struct Selection {
 
 impl Clone for Selection {
     fn clone(&self) -> Selection {
-        unsafe {
-            let new_handle = ffi::chfl_selection_copy(self.as_ptr());
-            Selection::from_ptr(new_handle)
-        }
+        self.try_clone().expect("failed to copy selection")
     }
 }
 
@@ -119,6 +182,12 @@ impl Drop for Selection {
     }
 }
 
+// SAFETY: the methods taking `&self` only read the selection string and
+// size. Evaluating the selection modifies the C++ object to store the
+// matches, and requires `&mut self`.
+unsafe impl Send for Selection {}
+unsafe impl Sync for Selection {}
+
 impl Selection {
     /// Create a `Selection` from a C pointer.
     ///
@@ -141,6 +210,28 @@ impl Selection {
         self.handle
     }
 
+    /// Create a copy of this selection, returning an error instead of panicking
+    /// if the memory allocation fails. `Clone::clone` panics in this case.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the C++ library can not allocate memory for
+    /// the copy.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Selection;
+    /// let selection = Selection::new("name H").unwrap();
+    /// let copy = selection.try_clone().unwrap();
+    /// assert_eq!(copy.string(), "name H");
+    /// ```
+    pub fn try_clone(&self) -> Result<Selection, Error> {
+        unsafe {
+            let new_handle = check_allocation(ffi::chfl_selection_copy(self.as_ptr()))?;
+            return Ok(Selection::from_ptr(new_handle));
+        }
+    }
+
     /// Create a new selection from the given selection string.
     ///
     /// # Errors
@@ -153,14 +244,12 @@ impl Selection {
     /// let selection = Selection::new("pairs: name(#1) H and name(#2) O").unwrap();
     /// ```
     pub fn new<'a, S: Into<&'a str>>(selection: S) -> Result<Selection, Error> {
+        install_log_bridge();
         let buffer = strings::to_c(selection.into());
         unsafe {
             let handle = ffi::chfl_selection(buffer.as_ptr());
             if handle.is_null() {
-                Err(Error {
-                    status: Status::SelectionError,
-                    message: Error::last_error(),
-                })
+                Err(Error::new(Status::SelectionError, Error::last_error()))
             } else {
                 Ok(Selection::from_ptr(handle))
             }
@@ -229,6 +318,7 @@ impl Selection {
     /// ```
     pub fn evaluate(&mut self, frame: &Frame) -> Vec<Match> {
         #![allow(clippy::cast_possible_truncation)]
+        let _span = Span::selection(self, frame.size());
         let mut count = 0;
         unsafe {
             check(ffi::chfl_selection_evaluate(
@@ -307,6 +397,9 @@ mod tests {
         let copy = selection.clone();
         assert_eq!(selection.size(), 1);
         assert_eq!(copy.size(), 1);
+
+        let copy = selection.try_clone().unwrap();
+        assert_eq!(copy.string(), "name H");
     }
 
     fn testing_frame() -> Frame {
@@ -330,6 +423,18 @@ mod tests {
     mod matches {
         use super::*;
 
+        #[test]
+        fn typed() {
+            let m = Match::new(&[1, 2, 3]);
+            assert_eq!(m.as_slice(), &[1, 2, 3]);
+            assert_eq!(<[usize; 3]>::try_from(&m), Ok([1, 2, 3]));
+
+            let error = <[usize; 2]>::try_from(&m).unwrap_err();
+            assert_eq!(error.status, Status::SelectionError);
+            assert!(<[usize; 1]>::try_from(&m).is_err());
+            assert!(<[usize; 4]>::try_from(&m).is_err());
+        }
+
         #[test]
         fn index() {
             let m = Match::new(&[1, 2, 3, 4]);
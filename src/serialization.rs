@@ -0,0 +1,429 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Implementation of `serde::Serialize` and `serde::Deserialize` for the main
+//! data types of this crate.
+//!
+//! All types are converted to and from plain data structures defined in this
+//! module, and deserialization goes through the usual constructors and
+//! setters, so the deserialized objects are as valid as the ones created
+//! manually. Invalid data (out of bounds indexes, inconsistent sizes, invalid
+//! cell parameters, ...) is reported as a deserialization error.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Atom, BondOrder, CellShape, Error, Frame, Property, Residue, Status, Topology, UnitCell};
+
+type Properties = BTreeMap<String, Property>;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AtomData {
+    name: String,
+    #[serde(rename = "type")]
+    atomic_type: String,
+    mass: f64,
+    charge: f64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    properties: Properties,
+}
+
+impl From<&Atom> for AtomData {
+    fn from(atom: &Atom) -> AtomData {
+        AtomData {
+            name: atom.name(),
+            atomic_type: atom.atomic_type(),
+            mass: atom.mass(),
+            charge: atom.charge(),
+            properties: atom.properties().collect(),
+        }
+    }
+}
+
+impl From<AtomData> for Atom {
+    fn from(data: AtomData) -> Atom {
+        let mut atom = Atom::new(data.name.as_str());
+        atom.set_atomic_type(data.atomic_type.as_str());
+        atom.set_mass(data.mass);
+        atom.set_charge(data.charge);
+        for (name, property) in data.properties {
+            atom.set(&name, property);
+        }
+        return atom;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ResidueData {
+    name: String,
+    #[serde(default)]
+    id: Option<i64>,
+    #[serde(default)]
+    atoms: Vec<usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    properties: Properties,
+}
+
+impl From<&Residue> for ResidueData {
+    fn from(residue: &Residue) -> ResidueData {
+        ResidueData {
+            name: residue.name(),
+            id: residue.id(),
+            atoms: residue.atoms(),
+            properties: residue.properties().collect(),
+        }
+    }
+}
+
+impl From<ResidueData> for Residue {
+    fn from(data: ResidueData) -> Residue {
+        let mut residue = match data.id {
+            Some(id) => Residue::with_id(data.name.as_str(), id),
+            None => Residue::new(data.name.as_str()),
+        };
+        for atom in data.atoms {
+            residue.add_atom(atom);
+        }
+        for (name, property) in data.properties {
+            residue.set(&name, property);
+        }
+        return residue;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TopologyData {
+    atoms: Vec<AtomData>,
+    #[serde(default)]
+    residues: Vec<ResidueData>,
+    #[serde(default)]
+    bonds: Vec<[usize; 2]>,
+    /// Order of the bonds, either empty (all bonds have an unknown order) or
+    /// with the same size as `bonds`
+    #[serde(default)]
+    bond_orders: Vec<BondOrder>,
+}
+
+impl From<&Topology> for TopologyData {
+    fn from(topology: &Topology) -> TopologyData {
+        let bond_orders = topology.bond_orders();
+        let bond_orders = if bond_orders.iter().all(|&order| order == BondOrder::Unknown) {
+            Vec::new()
+        } else {
+            bond_orders
+        };
+
+        TopologyData {
+            atoms: (0..topology.size())
+                .map(|i| AtomData::from(&*topology.atom(i)))
+                .collect(),
+            residues: topology
+                .iter_residues()
+                .map(|residue| ResidueData::from(&*residue))
+                .collect(),
+            bonds: topology.bonds(),
+            bond_orders,
+        }
+    }
+}
+
+impl TryFrom<TopologyData> for Topology {
+    type Error = Error;
+
+    fn try_from(data: TopologyData) -> Result<Topology, Error> {
+        let natoms = data.atoms.len();
+        if !data.bond_orders.is_empty() && data.bond_orders.len() != data.bonds.len() {
+            return Err(Error::new(
+                Status::FormatError,
+                format!(
+                    "got {} bond orders for {} bonds in topology",
+                    data.bond_orders.len(),
+                    data.bonds.len()
+                ),
+            ));
+        }
+
+        let mut topology = Topology::new();
+        for atom in data.atoms {
+            topology.add_atom(&Atom::from(atom));
+        }
+
+        for residue in data.residues {
+            if let Some(&atom) = residue.atoms.iter().find(|&&atom| atom >= natoms) {
+                return Err(Error::new(
+                    Status::OutOfBounds,
+                    format!("residue atom index {atom} is out of bounds for a topology with {natoms} atoms"),
+                ));
+            }
+            topology.add_residue(&Residue::from(residue))?;
+        }
+
+        for (index, &[i, j]) in data.bonds.iter().enumerate() {
+            if i >= natoms || j >= natoms || i == j {
+                return Err(Error::new(
+                    Status::OutOfBounds,
+                    format!("invalid bond between atoms {i} and {j} in a topology with {natoms} atoms"),
+                ));
+            }
+            let order = data.bond_orders.get(index).copied().unwrap_or(BondOrder::Unknown);
+            topology.add_bond_with_order(i, j, order);
+        }
+
+        return Ok(topology);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UnitCellData {
+    shape: CellShape,
+    lengths: [f64; 3],
+    angles: [f64; 3],
+}
+
+impl From<&UnitCell> for UnitCellData {
+    fn from(cell: &UnitCell) -> UnitCellData {
+        UnitCellData {
+            shape: cell.shape(),
+            lengths: cell.lengths(),
+            angles: cell.angles(),
+        }
+    }
+}
+
+impl TryFrom<UnitCellData> for UnitCell {
+    type Error = Error;
+
+    fn try_from(data: UnitCellData) -> Result<UnitCell, Error> {
+        if data.shape == CellShape::Infinite {
+            return Ok(UnitCell::infinite());
+        }
+        let mut cell = UnitCell::try_triclinic(data.lengths, data.angles)?;
+        cell.set_shape(data.shape)?;
+        return Ok(cell);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrameData<'a> {
+    #[serde(default)]
+    step: usize,
+    cell: UnitCellData,
+    topology: TopologyData,
+    positions: Cow<'a, [[f64; 3]]>,
+    #[serde(default)]
+    velocities: Option<Cow<'a, [[f64; 3]]>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    properties: Properties,
+}
+
+impl<'a> From<&'a Frame> for FrameData<'a> {
+    fn from(frame: &'a Frame) -> FrameData<'a> {
+        FrameData {
+            step: frame.step(),
+            cell: UnitCellData::from(&*frame.cell()),
+            topology: TopologyData::from(&*frame.topology()),
+            positions: Cow::Borrowed(frame.positions()),
+            velocities: frame.velocities().map(Cow::Borrowed),
+            properties: frame.properties().collect(),
+        }
+    }
+}
+
+impl TryFrom<FrameData<'_>> for Frame {
+    type Error = Error;
+
+    fn try_from(data: FrameData<'_>) -> Result<Frame, Error> {
+        let natoms = data.positions.len();
+        if let Some(ref velocities) = data.velocities {
+            if velocities.len() != natoms {
+                return Err(Error::new(
+                    Status::FormatError,
+                    format!("got {} velocities for {natoms} atoms in frame", velocities.len()),
+                ));
+            }
+        }
+
+        let mut frame = Frame::new();
+        frame.resize(natoms);
+        frame.set_step(data.step);
+        frame.set_cell(&UnitCell::try_from(data.cell)?);
+        frame.set_topology(&Topology::try_from(data.topology)?)?;
+
+        frame.positions_mut().copy_from_slice(&data.positions);
+        if let Some(velocities) = data.velocities {
+            frame.add_velocities();
+            if let Some(frame_velocities) = frame.velocities_mut() {
+                frame_velocities.copy_from_slice(&velocities);
+            }
+        }
+
+        for (name, property) in data.properties {
+            frame.set(&name, property);
+        }
+        return Ok(frame);
+    }
+}
+
+impl Serialize for Atom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        AtomData::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Atom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Atom, D::Error> {
+        AtomData::deserialize(deserializer).map(Atom::from)
+    }
+}
+
+impl Serialize for Residue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ResidueData::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Residue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Residue, D::Error> {
+        ResidueData::deserialize(deserializer).map(Residue::from)
+    }
+}
+
+impl Serialize for Topology {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TopologyData::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Topology {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Topology, D::Error> {
+        let data = TopologyData::deserialize(deserializer)?;
+        Topology::try_from(data).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for UnitCell {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        UnitCellData::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for UnitCell {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<UnitCell, D::Error> {
+        let data = UnitCellData::deserialize(deserializer)?;
+        UnitCell::try_from(data).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Frame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FrameData::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Frame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Frame, D::Error> {
+        let data = FrameData::deserialize(deserializer)?;
+        Frame::try_from(data).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> T {
+        let json = serde_json::to_string(value).unwrap();
+        return serde_json::from_str(&json).unwrap();
+    }
+
+    #[test]
+    fn atom() {
+        let mut atom = Atom::new("CA");
+        atom.set_atomic_type("C");
+        atom.set_charge(-0.5);
+        atom.set("altloc", "A");
+
+        let copy = roundtrip(&atom);
+        assert_eq!(copy.name(), "CA");
+        assert_eq!(copy.atomic_type(), "C");
+        assert_eq!(copy.mass(), atom.mass());
+        assert_eq!(copy.charge(), -0.5);
+        assert_eq!(copy.get("altloc"), Some(Property::String("A".into())));
+    }
+
+    #[test]
+    fn cell() {
+        let cell = roundtrip(&UnitCell::triclinic([10.0, 11.0, 12.0], [90.0, 90.0, 90.0]));
+        assert_eq!(cell.shape(), CellShape::Triclinic);
+        assert_eq!(cell.lengths(), [10.0, 11.0, 12.0]);
+
+        assert_eq!(roundtrip(&UnitCell::infinite()).shape(), CellShape::Infinite);
+
+        let json = r#"{"shape": "Orthorhombic", "lengths": [-1, 2, 3], "angles": [90, 90, 90]}"#;
+        assert!(serde_json::from_str::<UnitCell>(json).is_err());
+    }
+
+    #[test]
+    fn topology() {
+        let mut topology = Topology::new();
+        for name in ["C", "O", "H"] {
+            topology.add_atom(&Atom::new(name));
+        }
+        topology.add_bond_with_order(0, 1, BondOrder::Double);
+        topology.add_bond(0, 2);
+
+        let mut residue = Residue::with_id("FOO", 3);
+        residue.add_atom(0);
+        residue.add_atom(1);
+        residue.set_insertion_code('B');
+        topology.add_residue(&residue).unwrap();
+
+        let copy = roundtrip(&topology);
+        assert_eq!(copy.size(), 3);
+        assert_eq!(copy.atom(1).name(), "O");
+        assert_eq!(copy.bonds(), topology.bonds());
+        assert_eq!(copy.bond_orders(), topology.bond_orders());
+
+        let residue = copy.residue(0).unwrap();
+        assert_eq!(residue.name(), "FOO");
+        assert_eq!(residue.id(), Some(3));
+        assert_eq!(residue.atoms(), [0, 1]);
+        assert_eq!(residue.insertion_code(), Some('B'));
+
+        let json = r#"{"atoms": [], "bonds": [[0, 1]]}"#;
+        let error = serde_json::from_str::<Topology>(json).unwrap_err();
+        assert!(error.to_string().contains("invalid bond between atoms 0 and 1"));
+    }
+
+    #[test]
+    fn frame() {
+        let mut frame = Frame::new();
+        frame.set_step(42);
+        frame.set_cell(&UnitCell::new([20.0, 20.0, 20.0]));
+        frame.add_velocities();
+        frame.add_atom(&Atom::new("O"), [1.0, 2.0, 3.0], [0.1, 0.2, 0.3]);
+        frame.add_atom(&Atom::new("H"), [1.5, 2.0, 3.0], [0.0, 0.0, 0.0]);
+        frame.add_bond(0, 1);
+        frame.set("energy", -12.5);
+
+        let copy = roundtrip(&frame);
+        assert_eq!(copy.step(), 42);
+        assert_eq!(copy.cell().lengths(), [20.0, 20.0, 20.0]);
+        assert_eq!(copy.positions(), frame.positions());
+        assert_eq!(copy.velocities(), frame.velocities());
+        assert_eq!(copy.topology().bonds(), [[0, 1]]);
+        assert_eq!(copy.atom(1).name(), "H");
+        assert_eq!(copy.get("energy"), Some(Property::Double(-12.5)));
+
+        let json = serde_json::to_string(&frame).unwrap().replace(",[0.0,0.0,0.0]]", "]");
+        assert!(serde_json::from_str::<Frame>(&json).is_err());
+    }
+}
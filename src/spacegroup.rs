@@ -0,0 +1,423 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+use crate::errors::{Error, Status};
+
+/// Maximal number of operations in a space group, reached by the
+/// face-centered cubic groups in their conventional cell
+const MAX_OPERATIONS: usize = 192;
+
+/// Tolerance used to compare the translations of symmetry operations
+const TRANSLATION_TOLERANCE: f64 = 1e-6;
+
+/// Generators of the space groups known by `SpaceGroup::from_symbol`, in
+/// their standard setting (unique axis b for monoclinic groups and origin at
+/// the inversion center). Symbols are normalized with `normalize_symbol`.
+const KNOWN_GROUPS: &[(&str, &[&str])] = &[
+    ("p1", &[]),
+    ("p-1", &["-x,-y,-z"]),
+    ("p21", &["-x,y+1/2,-z"]),
+    ("c2", &["-x,y,-z", "x+1/2,y+1/2,z"]),
+    ("p21/c", &["-x,y+1/2,-z+1/2", "-x,-y,-z"]),
+    ("c2/c", &["-x,y,-z+1/2", "-x,-y,-z", "x+1/2,y+1/2,z"]),
+    ("p212121", &["-x+1/2,-y,z+1/2", "-x,y+1/2,-z+1/2"]),
+    ("pbca", &["-x+1/2,-y,z+1/2", "-x,y+1/2,-z+1/2", "-x,-y,-z"]),
+    ("p63/mmc", &["-y,x-y,z", "-x,-y,z+1/2", "y,x,-z", "-x,-y,-z"]),
+    ("pm-3m", &["-x,-y,z", "-x,y,-z", "z,x,y", "y,x,-z", "-x,-y,-z"]),
+    (
+        "im-3m",
+        &["-x,-y,z", "-x,y,-z", "z,x,y", "y,x,-z", "-x,-y,-z", "x+1/2,y+1/2,z+1/2"],
+    ),
+    (
+        "fm-3m",
+        &[
+            "-x,-y,z",
+            "-x,y,-z",
+            "z,x,y",
+            "y,x,-z",
+            "-x,-y,-z",
+            "x,y+1/2,z+1/2",
+            "x+1/2,y,z+1/2",
+        ],
+    ),
+];
+
+/// A symmetry operation acting on fractional coordinates, as a rotation
+/// matrix and a translation in `[0, 1)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Operation {
+    rotation: [[i32; 3]; 3],
+    translation: [f64; 3],
+}
+
+impl Operation {
+    const IDENTITY: Operation = Operation {
+        rotation: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        translation: [0.0; 3],
+    };
+
+    /// Parse an operation in the `"-x+1/2, y, z"` notation used by CIF files
+    fn parse(operation: &str) -> Result<Operation, Error> {
+        let error = || {
+            Error::new(
+                Status::ChemfilesError,
+                format!("invalid symmetry operation '{operation}'"),
+            )
+        };
+
+        let components = operation.split(',').collect::<Vec<_>>();
+        if components.len() != 3 {
+            return Err(error());
+        }
+
+        let mut rotation = [[0; 3]; 3];
+        let mut translation = [0.0; 3];
+        for (i, component) in components.iter().enumerate() {
+            let component = component.replace(' ', "").to_lowercase();
+            if component.is_empty() {
+                return Err(error());
+            }
+
+            // split the component into signed terms, e.g. "-x+1/2" => ["-x", "+1/2"]
+            let mut terms = Vec::new();
+            let mut start = 0;
+            for (position, c) in component.char_indices() {
+                if (c == '+' || c == '-') && position != start {
+                    terms.push(&component[start..position]);
+                    start = position;
+                }
+            }
+            terms.push(&component[start..]);
+
+            for term in terms {
+                let (sign, value) = match term.as_bytes()[0] {
+                    b'-' => (-1, &term[1..]),
+                    b'+' => (1, &term[1..]),
+                    _ => (1, term),
+                };
+                match value {
+                    "x" => rotation[i][0] += sign,
+                    "y" => rotation[i][1] += sign,
+                    "z" => rotation[i][2] += sign,
+                    _ => {
+                        let number = parse_number(value).ok_or_else(error)?;
+                        translation[i] += f64::from(sign) * number;
+                    }
+                }
+            }
+        }
+
+        // the rotation must map the lattice onto itself, which also ensures
+        // that composing operations can not overflow
+        if rotation.iter().flatten().any(|&value| !(-1..=1).contains(&value)) || determinant(&rotation).abs() != 1 {
+            return Err(Error::new(
+                Status::ChemfilesError,
+                format!("invalid symmetry operation '{operation}': the rotation part is not unimodular"),
+            ));
+        }
+
+        let mut operation = Operation { rotation, translation };
+        operation.normalize();
+        return Ok(operation);
+    }
+
+    /// Bring the translation back in `[0, 1)`
+    fn normalize(&mut self) {
+        for value in &mut self.translation {
+            *value = value.rem_euclid(1.0);
+            if (1.0 - *value).abs() < TRANSLATION_TOLERANCE {
+                *value = 0.0;
+            }
+        }
+    }
+
+    /// Apply this operation to the `fractional` coordinates
+    fn apply(&self, fractional: [f64; 3]) -> [f64; 3] {
+        let mut result = self.translation;
+        for (i, row) in self.rotation.iter().enumerate() {
+            for (j, &coefficient) in row.iter().enumerate() {
+                result[i] += f64::from(coefficient) * fractional[j];
+            }
+        }
+        return result;
+    }
+
+    /// Get the operation corresponding to applying `other`, then `self`
+    fn compose(&self, other: &Operation) -> Operation {
+        let mut rotation = [[0; 3]; 3];
+        for (i, row) in rotation.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.rotation[i][k] * other.rotation[k][j]).sum();
+            }
+        }
+
+        let mut operation = Operation {
+            rotation,
+            translation: self.apply(other.translation),
+        };
+        operation.normalize();
+        return operation;
+    }
+
+    /// Check if this operation is the same as `other`, modulo lattice
+    /// translations
+    fn same_as(&self, other: &Operation) -> bool {
+        if self.rotation != other.rotation {
+            return false;
+        }
+        return self.translation.iter().zip(&other.translation).all(|(a, b)| {
+            let delta = a - b;
+            (delta - delta.round()).abs() < TRANSLATION_TOLERANCE
+        });
+    }
+}
+
+/// Get the determinant of an integer `matrix`
+fn determinant(matrix: &[[i32; 3]; 3]) -> i32 {
+    matrix[0][0] * (matrix[1][1] * matrix[2][2] - matrix[1][2] * matrix[2][1])
+        - matrix[0][1] * (matrix[1][0] * matrix[2][2] - matrix[1][2] * matrix[2][0])
+        + matrix[0][2] * (matrix[1][0] * matrix[2][1] - matrix[1][1] * matrix[2][0])
+}
+
+/// Parse a number in the `1/2`, `0.5` or `1` notations
+fn parse_number(value: &str) -> Option<f64> {
+    if let Some((numerator, denominator)) = value.split_once('/') {
+        let numerator = numerator.parse::<f64>().ok()?;
+        let denominator = denominator.parse::<f64>().ok()?;
+        if denominator == 0.0 {
+            return None;
+        }
+        return Some(numerator / denominator);
+    }
+    return value.parse::<f64>().ok().filter(|value| value.is_finite());
+}
+
+/// Normalize a Hermann-Mauguin symbol by removing spaces and underscores
+fn normalize_symbol(symbol: &str) -> String {
+    symbol
+        .chars()
+        .filter(|&c| c != ' ' && c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// A `SpaceGroup` contains the symmetry operations of a crystal, and is used
+/// with `Frame::from_asymmetric_unit` to build the full content of a unit
+/// cell from the atoms in the asymmetric unit.
+///
+/// The operations are always completed to form a group, so giving only the
+/// generators of the group is enough.
+///
+/// # Example
+/// ```
+/// # use chemfiles::SpaceGroup;
+/// let group = SpaceGroup::from_symbol("P 21/c").unwrap();
+/// assert_eq!(group.operations_count(), 4);
+///
+/// // same group, using the operations from a CIF file
+/// let group = SpaceGroup::from_operations(&["x,y,z", "-x,y+1/2,-z+1/2", "-x,-y,-z", "x,-y+1/2,z+1/2"]).unwrap();
+/// assert_eq!(group.operations_count(), 4);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpaceGroup {
+    operations: Vec<Operation>,
+}
+
+impl SpaceGroup {
+    /// Create a space group from its Hermann-Mauguin `symbol`, such as
+    /// `"P 21/c"` or `"Fm-3m"`. Spaces and underscores in the symbol are
+    /// ignored, as well as the case of the letters.
+    ///
+    /// Only a few common groups are known, in their standard setting: P1,
+    /// P-1, P21, C2, P21/c, C2/c, P212121, Pbca, P63/mmc, Pm-3m, Im-3m and
+    /// Fm-3m. Other groups can be created with `SpaceGroup::from_operations`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the space group is not known.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::SpaceGroup;
+    /// let group = SpaceGroup::from_symbol("Fm-3m").unwrap();
+    /// assert_eq!(group.operations_count(), 192);
+    ///
+    /// assert!(SpaceGroup::from_symbol("P 42/n").is_err());
+    /// ```
+    pub fn from_symbol(symbol: &str) -> Result<SpaceGroup, Error> {
+        let normalized = normalize_symbol(symbol);
+        for &(name, generators) in KNOWN_GROUPS {
+            if name == normalized {
+                return SpaceGroup::from_operations(generators);
+            }
+        }
+
+        return Err(Error::new(
+            Status::ChemfilesError,
+            format!("unknown space group '{symbol}', use SpaceGroup::from_operations instead"),
+        ));
+    }
+
+    /// Create a space group from a list of symmetry `operations`, in the
+    /// `"-x+1/2, y, z"` notation used by the `_symmetry_equiv_pos_as_xyz` and
+    /// `_space_group_symop_operation_xyz` fields of CIF files.
+    ///
+    /// The identity operation is always included, and all the products of
+    /// the given operations are added to complete the group.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if one of the operations can not be parsed, or if
+    /// the operations do not generate a valid space group.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::SpaceGroup;
+    /// let group = SpaceGroup::from_operations(&["-x, -y, -z"]).unwrap();
+    /// assert_eq!(group.operations_count(), 2);
+    ///
+    /// assert!(SpaceGroup::from_operations(&["x, y"]).is_err());
+    /// ```
+    pub fn from_operations<S: AsRef<str>>(operations: &[S]) -> Result<SpaceGroup, Error> {
+        let mut group = vec![Operation::IDENTITY];
+        for operation in operations {
+            let operation = Operation::parse(operation.as_ref())?;
+            if !group.iter().any(|existing| existing.same_as(&operation)) {
+                group.push(operation);
+            }
+        }
+
+        // add products of operations until the group is closed
+        let mut checked = 0;
+        while checked < group.len() {
+            let current = group.len();
+            for i in 0..current {
+                for j in 0..current {
+                    if i < checked && j < checked {
+                        continue;
+                    }
+
+                    let product = group[i].compose(&group[j]);
+                    if !group.iter().any(|existing| existing.same_as(&product)) {
+                        group.push(product);
+                    }
+                    if group.len() > MAX_OPERATIONS {
+                        return Err(Error::new(
+                            Status::ChemfilesError,
+                            "the symmetry operations do not form a valid space group",
+                        ));
+                    }
+                }
+            }
+            checked = current;
+        }
+
+        return Ok(SpaceGroup { operations: group });
+    }
+
+    /// Get the number of symmetry operations in this space group, including
+    /// the centering translations.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::SpaceGroup;
+    /// assert_eq!(SpaceGroup::from_symbol("P1").unwrap().operations_count(), 1);
+    /// assert_eq!(SpaceGroup::from_symbol("C 2/c").unwrap().operations_count(), 8);
+    /// ```
+    pub fn operations_count(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Get the images of the `fractional` coordinates by all the operations
+    /// in this group, wrapped inside the unit cell. The first image is always
+    /// the initial position (wrapped in the cell).
+    pub(crate) fn images(&self, fractional: [f64; 3]) -> Vec<[f64; 3]> {
+        return self
+            .operations
+            .iter()
+            .map(|operation| {
+                let mut image = operation.apply(fractional);
+                for value in &mut image {
+                    *value = value.rem_euclid(1.0);
+                    if *value >= 1.0 {
+                        // rem_euclid can round tiny negative values up to 1
+                        *value = 0.0;
+                    }
+                }
+                image
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let operation = Operation::parse("-x+1/2, y-z, 0.25+z").unwrap();
+        assert_eq!(operation.rotation, [[-1, 0, 0], [0, 1, -1], [0, 0, 1]]);
+        assert_eq!(operation.translation, [0.5, 0.0, 0.25]);
+
+        let operation = Operation::parse("X-1/2,-Y,+Z").unwrap();
+        assert_eq!(operation.rotation, [[1, 0, 0], [0, -1, 0], [0, 0, 1]]);
+        assert_eq!(operation.translation, [0.5, 0.0, 0.0]);
+
+        assert!(Operation::parse("x,y").is_err());
+        assert!(Operation::parse("x,y,").is_err());
+        assert!(Operation::parse("x,y,w").is_err());
+        assert!(Operation::parse("x,y,z+1/0").is_err());
+
+        // rotations which are not unimodular
+        assert!(Operation::parse("x+x,y,z").is_err());
+        assert!(Operation::parse("x,x,z").is_err());
+        assert!(Operation::parse("x+y,x-y,z").is_err());
+        assert!(Operation::parse("x-y,x,z").is_ok());
+    }
+
+    #[test]
+    fn groups() {
+        let expected = [
+            ("P 1", 1),
+            ("P-1", 2),
+            ("P 2_1", 2),
+            ("C 2", 4),
+            ("P 21/c", 4),
+            ("C 2/c", 8),
+            ("P 21 21 21", 4),
+            ("P b c a", 8),
+            ("P 63/m m c", 24),
+            ("P m -3 m", 48),
+            ("I m -3 m", 96),
+            ("F m -3 m", 192),
+        ];
+        for &(symbol, count) in &expected {
+            let group = SpaceGroup::from_symbol(symbol).unwrap();
+            assert_eq!(
+                group.operations_count(),
+                count,
+                "wrong number of operations for {symbol}"
+            );
+            assert_eq!(group.operations[0], Operation::IDENTITY);
+        }
+
+        assert!(SpaceGroup::from_symbol("P 42/n").is_err());
+
+        // irrational translations never close the group
+        assert!(SpaceGroup::from_operations(&["x+0.41421356,y,z"]).is_err());
+
+        let group = SpaceGroup::from_operations(&["x,-y+1/2,z+1/2", "-x,-y,-z"]).unwrap();
+        let reference = SpaceGroup::from_symbol("P21/c").unwrap();
+        assert_eq!(group.operations_count(), 4);
+        for operation in &reference.operations {
+            assert!(group.operations.iter().any(|other| other.same_as(operation)));
+        }
+
+        let images = group.images([0.1, 0.2, 0.3]);
+        assert_eq!(images.len(), 4);
+        approx::assert_ulps_eq!(images[0][0], 0.1);
+        for image in &images {
+            assert!(image.iter().all(|&value| (0.0..1.0).contains(&value)));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! `tracing` spans around the expensive calls to the C library, emitted with
+//! the `tracing` feature. Without this feature, the spans are empty and
+//! optimized away.
+#![cfg_attr(not(feature = "tracing"), allow(unused_variables, clippy::unused_self))]
+
+use std::path::Path;
+
+use crate::Selection;
+
+/// A span entered for the duration of a call to the C library, and exited
+/// when dropped
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+impl Span {
+    /// Enter a span for reading the given `step` of the trajectory at `path`,
+    /// or in memory if `path` is `None`. The number of atoms can be recorded
+    /// with `Span::record_natoms` once the frame has been read.
+    pub(crate) fn read(path: Option<&Path>, step: usize) -> Span {
+        Span {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                target: "chemfiles",
+                "read",
+                path = %display_path(path),
+                step,
+                natoms = tracing::field::Empty,
+            )
+            .entered(),
+        }
+    }
+
+    /// Enter a span for writing the given `step` containing `natoms` atoms to
+    /// the trajectory at `path`, or in memory if `path` is `None`.
+    pub(crate) fn write(path: Option<&Path>, step: usize, natoms: usize) -> Span {
+        Span {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(target: "chemfiles", "write", path = %display_path(path), step, natoms)
+                .entered(),
+        }
+    }
+
+    /// Enter a span for the evaluation of `selection` on a frame containing
+    /// `natoms` atoms.
+    pub(crate) fn selection(selection: &Selection, natoms: usize) -> Span {
+        Span {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(target: "chemfiles", "selection", selection = %selection.string(), natoms)
+                .entered(),
+        }
+    }
+
+    /// Record the number of atoms in the frame read in this span
+    pub(crate) fn record_natoms(&self, natoms: usize) {
+        #[cfg(feature = "tracing")]
+        let _ = self.span.record("natoms", natoms);
+    }
+}
+
+/// Get a displayable version of the `path` of a trajectory
+#[cfg(feature = "tracing")]
+fn display_path(path: Option<&Path>) -> String {
+    match path {
+        Some(path) => path.display().to_string(),
+        None => "<memory>".into(),
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::{Frame, Selection, Trajectory};
+
+    type Fields = BTreeMap<String, String>;
+
+    /// Minimal subscriber recording the name and fields of all spans
+    #[derive(Default)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<(String, Fields)>>>,
+    }
+
+    struct FieldsVisitor<'a>(&'a mut Fields);
+
+    impl Visit for FieldsVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            let _ = self.0.insert(field.name().into(), format!("{value:?}"));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+            let mut fields = Fields::new();
+            attributes.record(&mut FieldsVisitor(&mut fields));
+
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((attributes.metadata().name().into(), fields));
+            return Id::from_u64(spans.len() as u64);
+        }
+
+        fn record(&self, id: &Id, values: &Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            #[allow(clippy::cast_possible_truncation)]
+            let (_, fields) = &mut spans[id.into_u64() as usize - 1];
+            values.record(&mut FieldsVisitor(fields));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    fn fields(values: &[(&str, &str)]) -> Fields {
+        values
+            .iter()
+            .map(|&(name, value)| (name.into(), value.into()))
+            .collect()
+    }
+
+    #[test]
+    fn spans() {
+        let recorder = Recorder::default();
+        let spans = Arc::clone(&recorder.spans);
+
+        tracing::subscriber::with_default(recorder, || {
+            let data = "2\n\nO 0 0 0\nH 1 0 0\n";
+            let mut trajectory = Trajectory::memory_reader(data, "XYZ").unwrap();
+            let mut frame = Frame::new();
+            trajectory.read(&mut frame).unwrap();
+
+            let mut selection = Selection::new("name O").unwrap();
+            assert_eq!(selection.evaluate(&frame).len(), 1);
+
+            frame.set_step(3);
+            let mut writer = Trajectory::memory_writer("XYZ").unwrap();
+            writer.write(&frame).unwrap();
+        });
+
+        let spans = spans.lock().unwrap();
+        assert_eq!(
+            *spans,
+            [
+                (
+                    "read".into(),
+                    fields(&[("natoms", "2"), ("path", "<memory>"), ("step", "0")])
+                ),
+                ("selection".into(), fields(&[("natoms", "2"), ("selection", "name O")])),
+                (
+                    "write".into(),
+                    fields(&[("natoms", "2"), ("path", "<memory>"), ("step", "3")])
+                ),
+            ]
+        );
+    }
+}
@@ -4,6 +4,7 @@
 //! String conversions between C and Rust
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
+use std::path::Path;
 
 use crate::errors::{check, Error};
 
@@ -15,6 +16,26 @@ pub fn from_c(buffer: *const c_char) -> String {
     }
 }
 
+/// Create a Rust string from a C string which might not be valid UTF-8,
+/// replacing invalid sequences with U+FFFD. This is used for error messages
+/// and paths, which can contain arbitrary bytes on Unix.
+pub fn from_c_lossy(buffer: *const c_char) -> String {
+    unsafe {
+        return CStr::from_ptr(buffer).to_string_lossy().into_owned();
+    }
+}
+
+/// Get a Rust string slice from a buffer filled by a C function, without
+/// copying the data.
+pub fn as_str(buffer: &[c_char]) -> &str {
+    assert!(buffer.contains(&0), "C string buffer should be null-terminated");
+    unsafe {
+        return CStr::from_ptr(buffer.as_ptr())
+            .to_str()
+            .expect("Invalid Rust string from C");
+    }
+}
+
 /// Create a C string from a Rust string.
 pub fn to_c<S>(string: S) -> CString
 where
@@ -23,6 +44,27 @@ where
     CString::new(string).expect("Invalid C string from Rust")
 }
 
+/// Create a C string from a `path`.
+///
+/// On Unix, paths are arbitrary bytes which are given as-is to the C library,
+/// so they do not need to be valid UTF-8. On other platforms, the C library
+/// expects UTF-8 paths, and this function fails if `path` can not be
+/// represented as UTF-8.
+#[cfg_attr(unix, allow(clippy::unnecessary_wraps))]
+pub fn path_to_c(path: &Path) -> Result<CString, Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        return Ok(to_c(path.as_os_str().as_bytes()));
+    }
+
+    #[cfg(not(unix))]
+    {
+        let utf8 = path.to_str().ok_or_else(|| Error::utf8_path_error(path))?;
+        return Ok(to_c(utf8));
+    }
+}
+
 /// Check if a string buffer was big enough when passed to a C function
 fn buffer_was_big_enough(buffer: &[c_char]) -> bool {
     let len = buffer.len();
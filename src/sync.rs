@@ -0,0 +1,177 @@
+// Chemfiles, a modern library for chemistry file reading and writing
+// Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
+
+//! Tests using chemfiles from multiple threads at the same time.
+//!
+//! The C++ library allows to use different objects from different threads
+//! concurrently, and the global state (warning callback, configuration) is
+//! protected by locks. These tests create, use and destroy handles on many
+//! threads at once, to check that the handle lifetimes managed by the Rust
+//! side stay consistent. They are most useful when running the test suite
+//! with a sanitizer (`RUSTFLAGS="-Z sanitizer=thread"` on nightly).
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+use crate::{Atom, Frame, Options, Residue, Selection, Trajectory, UnitCell};
+
+const THREADS: usize = 8;
+
+#[test]
+fn concurrent_trajectories() {
+    let barrier = Barrier::new(THREADS);
+    thread::scope(|scope| {
+        for _ in 0..THREADS {
+            let _ = scope.spawn(|| {
+                let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+                let mut selection = Selection::new("name O").unwrap();
+                let mut frame = Frame::new();
+
+                // start reading at the same time in all threads
+                let _ = barrier.wait();
+                for step in 0..10 {
+                    trajectory.read_step(step, &mut frame).unwrap();
+                    assert_eq!(frame.size(), 297);
+                    assert_eq!(selection.list(&frame).len(), 99);
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn concurrent_handles() {
+    thread::scope(|scope| {
+        for i in 0..THREADS {
+            let _ = scope.spawn(move || {
+                for _ in 0..50 {
+                    let mut frame = Frame::new();
+                    frame.set_cell(&UnitCell::new([10.0, 10.0, 10.0]));
+                    frame.add_atom(&Atom::new("O"), [0.0, 0.0, 0.0], None);
+                    frame.add_atom(&Atom::new("H"), [0.96, 0.0, 0.0], None);
+
+                    // borrowed views, clones and owned handles all live and
+                    // die on this thread
+                    let copy = frame.clone();
+                    let topology = copy.topology();
+                    assert_eq!(topology.size(), 2);
+                    assert_eq!(frame.cell().lengths(), [10.0, 10.0, 10.0]);
+                    frame.atom_mut(0).set_name(format!("O{i}").as_str());
+                    assert_eq!(copy.atom(0).name(), "O");
+
+                    frame.guess_bonds().unwrap();
+                    assert_eq!(frame.topology().bonds_count(), 1);
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn moved_handles() {
+    // handles created on this thread are used and released on other threads
+    let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+    let mut selection = Selection::new("name O").unwrap();
+    let mut frame = Frame::new();
+    trajectory.read(&mut frame).unwrap();
+    let topology = frame.topology().clone();
+    let atom = Atom::new("Zn");
+    let cell = UnitCell::new([10.0, 10.0, 10.0]);
+    let residue = Residue::new("ALA");
+
+    let frame = thread::scope(|scope| {
+        let reader = scope.spawn(move || {
+            let mut frame = Frame::new();
+            trajectory.read(&mut frame).unwrap();
+            assert_eq!(frame.step(), 1);
+            frame
+        });
+
+        let _ = scope.spawn(move || {
+            assert_eq!(selection.list(&frame).len(), 99);
+            assert_eq!(topology.size(), 297);
+            assert_eq!(atom.name(), "Zn");
+            assert_eq!(cell.lengths(), [10.0, 10.0, 10.0]);
+            assert_eq!(residue.name(), "ALA");
+        });
+
+        // objects created on another thread are returned to this one
+        reader.join().unwrap()
+    });
+    assert_eq!(frame.size(), 297);
+}
+
+#[test]
+fn shared_handles() {
+    // read-only access to the same objects from multiple threads at once
+    let atom = Atom::new("Zn");
+    let cell = UnitCell::new([10.0, 10.0, 10.0]);
+    let residue = Residue::with_id("ALA", 3);
+    let selection = Selection::new("pairs: name(#1) O and name(#2) H").unwrap();
+
+    let barrier = Barrier::new(THREADS);
+    thread::scope(|scope| {
+        for _ in 0..THREADS {
+            let _ = scope.spawn(|| {
+                let _ = barrier.wait();
+                for _ in 0..50 {
+                    assert_eq!(atom.name(), "Zn");
+                    assert_eq!(atom.clone().mass(), atom.mass());
+                    assert_eq!(cell.volume(), 1000.0);
+                    assert_eq!(residue.id(), Some(3));
+                    assert_eq!(selection.size(), 2);
+                    assert_eq!(selection.string(), "pairs: name(#1) O and name(#2) H");
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn concurrent_warnings() {
+    Options::scoped()
+        .apply(|| {
+            let count = Arc::new(AtomicUsize::new(0));
+            let counter = Arc::clone(&count);
+            crate::set_warning_callback(move |_: &str| {
+                let _ = counter.fetch_add(1, Ordering::SeqCst);
+            });
+
+            thread::scope(|scope| {
+                for _ in 0..THREADS {
+                    let _ = scope.spawn(|| {
+                        let data = "FOOBAR this is not a PDB record\nEND\n";
+                        let mut trajectory = Trajectory::memory_reader(data, "PDB").unwrap();
+                        let mut frame = Frame::new();
+                        let _ = trajectory.read(&mut frame);
+                    });
+                }
+            });
+            crate::reset_warning_callback();
+
+            // all the warnings from all the threads reached the callback
+            assert!(count.load(Ordering::SeqCst) >= THREADS);
+        })
+        .unwrap();
+}
+
+#[test]
+fn concurrent_options() {
+    let inside = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..THREADS {
+            let _ = scope.spawn(|| {
+                Options::scoped()
+                    .warnings(false)
+                    .apply(|| {
+                        // only one thread at the time runs inside a scope
+                        assert_eq!(inside.fetch_add(1, Ordering::SeqCst), 0);
+                        thread::yield_now();
+                        assert_eq!(inside.fetch_sub(1, Ordering::SeqCst), 1);
+                    })
+                    .unwrap();
+            });
+        }
+    });
+}
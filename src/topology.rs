@@ -1,18 +1,24 @@
 // Chemfiles, a modern library for chemistry file reading and writing
 // Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
-use std::marker::PhantomData;
-use std::ops::{Deref, Drop};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Drop;
 
 use chemfiles_sys as ffi;
 
-use crate::errors::{check, check_not_null, check_success, Error};
+use crate::borrowed::Borrowed;
+use crate::errors::{check, check_allocation, check_not_null, check_success, install_log_bridge, Error};
 use crate::{Atom, AtomMut, AtomRef};
 use crate::{Residue, ResidueRef};
 
-/// Possible bond order associated with bonds
+/// Possible bond order associated with bonds.
+///
+/// Bond orders are read from and written to the formats storing them, such
+/// as SDF, MOL2 or SMILES, and default to `BondOrder::Unknown` otherwise.
 #[repr(C)]
 #[non_exhaustive]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BondOrder {
     /// Unknown or unspecified bond order
     Unknown = ffi::chfl_bond_order::CHFL_BOND_UNKNOWN as isize,
@@ -70,26 +76,39 @@ pub struct Topology {
     handle: *mut ffi::CHFL_TOPOLOGY,
 }
 
-/// An analog to a reference to a topology (`&Topology`)
-#[derive(Debug)]
-pub struct TopologyRef<'a> {
-    inner: Topology,
-    marker: PhantomData<&'a Topology>,
+/// Metadata of all the atoms in a topology, stored as columns. This is
+/// created by `Topology::atoms_table`, and can be used to build dataframes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AtomsTable {
+    /// Names of the atoms
+    pub names: Vec<String>,
+    /// Atomic types of the atoms
+    pub types: Vec<String>,
+    /// Masses of the atoms, in atomic mass units
+    pub masses: Vec<f64>,
+    /// Charges of the atoms, in number of the electron charge *e*
+    pub charges: Vec<f64>,
+    /// Identifier of the residue containing each atom, or `None` if the atom
+    /// is not in a residue or if the residue does not have an identifier
+    pub residue_ids: Vec<Option<i64>>,
 }
 
-impl<'a> Deref for TopologyRef<'a> {
-    type Target = Topology;
-    fn deref(&self) -> &Topology {
-        &self.inner
-    }
+/// An analog to a reference to a topology (`&Topology`), for the topology
+/// of a `Frame`. See `Borrowed` for more information.
+pub type TopologyRef<'a> = Borrowed<'a, Topology>;
+
+/// Iterator over the residues in a topology, created by
+/// `Topology::iter_residues`
+#[derive(Debug)]
+pub struct ResidueIter<'a> {
+    topology: &'a Topology,
+    index: usize,
+    size: usize,
 }
 
 impl Clone for Topology {
     fn clone(&self) -> Topology {
-        unsafe {
-            let new_handle = ffi::chfl_topology_copy(self.as_ptr());
-            Topology::from_ptr(new_handle)
-        }
+        self.try_clone().expect("failed to copy topology")
     }
 }
 
@@ -112,10 +131,7 @@ impl Topology {
     #[inline]
     #[allow(clippy::ptr_cast_constness)]
     pub(crate) unsafe fn ref_from_ptr<'a>(ptr: *const ffi::CHFL_TOPOLOGY) -> TopologyRef<'a> {
-        TopologyRef {
-            inner: Topology::from_ptr(ptr as *mut ffi::CHFL_TOPOLOGY),
-            marker: PhantomData,
-        }
+        Borrowed::new(Topology::from_ptr(ptr as *mut ffi::CHFL_TOPOLOGY))
     }
 
     /// Get the underlying C pointer as a const pointer.
@@ -141,6 +157,29 @@ impl Topology {
         self.handle
     }
 
+    /// Create a copy of this topology, returning an error instead of panicking
+    /// if the memory allocation fails. `Clone::clone` panics in this case.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the C++ library can not allocate memory for
+    /// the copy.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Topology;
+    /// let mut topology = Topology::new();
+    /// topology.resize(10);
+    /// let copy = topology.try_clone().unwrap();
+    /// assert_eq!(copy.size(), 10);
+    /// ```
+    pub fn try_clone(&self) -> Result<Topology, Error> {
+        unsafe {
+            let new_handle = check_allocation(ffi::chfl_topology_copy(self.as_ptr()))?;
+            return Ok(Topology::from_ptr(new_handle));
+        }
+    }
+
     /// Create a new empty topology.
     ///
     /// # Example
@@ -150,6 +189,7 @@ impl Topology {
     /// assert_eq!(topology.size(), 0);
     /// ```
     pub fn new() -> Topology {
+        install_log_bridge();
         unsafe { Topology::from_ptr(ffi::chfl_topology()) }
     }
 
@@ -352,7 +392,7 @@ impl Topology {
     /// ```
     /// # use chemfiles::Topology;
     /// let mut topology = Topology::new();
-    /// assert_eq!(topology.dihedrals_count(), 0);
+    /// assert_eq!(topology.impropers_count(), 0);
     /// topology.resize(4);
     ///
     /// topology.add_bond(0, 1);
@@ -751,6 +791,29 @@ impl Topology {
         unsafe { check(ffi::chfl_topology_add_residue(self.as_mut_ptr(), residue.as_ptr())) }
     }
 
+    /// Gets an iterator over the residues in this topology, in the same order
+    /// as `Topology::residue`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Topology, Residue};
+    /// let mut topology = Topology::new();
+    /// topology.add_residue(&Residue::with_id("ALA", 1)).unwrap();
+    /// topology.add_residue(&Residue::with_id("GLY", 2)).unwrap();
+    ///
+    /// let names = topology.iter_residues().map(|residue| residue.name()).collect::<Vec<_>>();
+    /// assert_eq!(names, ["ALA", "GLY"]);
+    /// ```
+    pub fn iter_residues(&self) -> ResidueIter<'_> {
+        #[allow(clippy::cast_possible_truncation)]
+        let size = self.residues_count() as usize;
+        ResidueIter {
+            topology: self,
+            index: 0,
+            size,
+        }
+    }
+
     /// Check if the two residues `first` and `second` from the `topology` are
     /// linked together, *i.e.* if there is a bond between one atom in the
     /// first residue and one atom in the second one.
@@ -779,6 +842,239 @@ impl Topology {
         }
         return linked != 0;
     }
+
+    /// Get the molecules in this topology, *i.e.* the groups of atoms
+    /// connected together by bonds. Each molecule is given as a sorted list
+    /// of atomic indexes, and molecules are sorted by their first atom. Atoms
+    /// without bonds are molecules on their own.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Topology;
+    /// let mut topology = Topology::new();
+    /// topology.resize(5);
+    ///
+    /// topology.add_bond(0, 3);
+    /// topology.add_bond(3, 4);
+    /// topology.add_bond(1, 2);
+    /// assert_eq!(topology.molecules(), vec![vec![0, 3, 4], vec![1, 2]]);
+    /// ```
+    pub fn molecules(&self) -> Vec<Vec<usize>> {
+        fn root(parents: &mut [usize], mut i: usize) -> usize {
+            while parents[i] != i {
+                // path halving
+                parents[i] = parents[parents[i]];
+                i = parents[i];
+            }
+            return i;
+        }
+
+        let size = self.size();
+        let mut parents = (0..size).collect::<Vec<_>>();
+        for [i, j] in self.bonds() {
+            let root_i = root(&mut parents, i);
+            let root_j = root(&mut parents, j);
+            // always use the smallest index as root, so molecules are
+            // created in the order of their first atom
+            if root_i < root_j {
+                parents[root_j] = root_i;
+            } else {
+                parents[root_i] = root_j;
+            }
+        }
+
+        let mut molecules: Vec<Vec<usize>> = Vec::new();
+        let mut molecule_ids = vec![usize::MAX; size];
+        for atom in 0..size {
+            let root = root(&mut parents, atom);
+            if molecule_ids[root] == usize::MAX {
+                molecule_ids[root] = molecules.len();
+                molecules.push(Vec::new());
+            }
+            molecules[molecule_ids[root]].push(atom);
+        }
+
+        return molecules;
+    }
+
+    /// Set the molecule id (see `Atom::molecule_id`) of all the atoms in this
+    /// topology from the connected components of the bonds graph, and return
+    /// the number of molecules. The id of each atom is the index of its
+    /// molecule in `Topology::molecules`, so ids are stable for a given
+    /// topology, and start at 0 with the molecule containing the first atom.
+    ///
+    /// The molecule ids stored in some formats (such as LAMMPS data files)
+    /// are not used by this function, which only looks at the bonds. These
+    /// files are read with one residue per molecule, and the original
+    /// molecule id of an atom is the id of the residue returned by
+    /// `Topology::residue_for_atom`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::Topology;
+    /// let mut topology = Topology::new();
+    /// topology.resize(4);
+    /// topology.add_bond(0, 2);
+    /// topology.add_bond(1, 3);
+    ///
+    /// assert_eq!(topology.assign_molecule_ids(), 2);
+    /// assert_eq!(topology.atom(2).molecule_id(), Some(0));
+    /// assert_eq!(topology.atom(3).molecule_id(), Some(1));
+    /// ```
+    pub fn assign_molecule_ids(&mut self) -> usize {
+        let molecules = self.molecules();
+        for (id, molecule) in molecules.iter().enumerate() {
+            for &atom in molecule {
+                self.atom_mut(atom).set_molecule_id(id);
+            }
+        }
+        return molecules.len();
+    }
+
+    /// Get a human-readable summary of this topology, with the number of
+    /// atoms, residues, bonds, angles, dihedrals and impropers, and the
+    /// number of atoms of each type. This is intended for logs and debugging,
+    /// and the exact format might change in the future.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Topology};
+    /// let mut topology = Topology::new();
+    /// topology.add_atom(&Atom::new("O"));
+    /// topology.add_atom(&Atom::new("H"));
+    /// topology.add_atom(&Atom::new("H"));
+    /// topology.add_bond(0, 1);
+    /// topology.add_bond(0, 2);
+    ///
+    /// assert_eq!(
+    ///     topology.describe(),
+    ///     "topology with 3 atoms and 0 residues\n\
+    ///      bonds: 2, angles: 1, dihedrals: 0, impropers: 0\n\
+    ///      atomic types: H (2), O (1)\n"
+    /// );
+    /// ```
+    pub fn describe(&self) -> String {
+        let mut types = BTreeMap::new();
+        for i in 0..self.size() {
+            *types.entry(self.atom(i).atomic_type()).or_insert(0) += 1;
+        }
+
+        let mut description = String::new();
+        let _ = writeln!(
+            description,
+            "topology with {} atoms and {} residues",
+            self.size(),
+            self.residues_count()
+        );
+        let _ = writeln!(
+            description,
+            "bonds: {}, angles: {}, dihedrals: {}, impropers: {}",
+            self.bonds_count(),
+            self.angles_count(),
+            self.dihedrals_count(),
+            self.impropers_count()
+        );
+
+        let types = types
+            .iter()
+            .map(|(name, count)| format!("{name} ({count})"))
+            .collect::<Vec<_>>();
+        let _ = writeln!(description, "atomic types: {}", types.join(", "));
+        return description;
+    }
+
+    /// Get the names, types, masses, charges and residue identifiers of all
+    /// the atoms in this topology, as columns.
+    ///
+    /// The C library does not provide a way to get this data for all atoms at
+    /// once, so this goes through each atom individually, with a few calls to
+    /// the C library for each atom.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Residue, Topology};
+    /// let mut topology = Topology::new();
+    /// topology.add_atom(&Atom::new("O"));
+    /// topology.add_atom(&Atom::new("H"));
+    ///
+    /// let mut residue = Residue::with_id("HOH", 3);
+    /// residue.add_atom(0);
+    /// topology.add_residue(&residue).unwrap();
+    ///
+    /// let table = topology.atoms_table();
+    /// assert_eq!(table.names, ["O", "H"]);
+    /// assert_eq!(table.residue_ids, [Some(3), None]);
+    /// ```
+    pub fn atoms_table(&self) -> AtomsTable {
+        let size = self.size();
+        let mut table = AtomsTable {
+            names: Vec::with_capacity(size),
+            types: Vec::with_capacity(size),
+            masses: Vec::with_capacity(size),
+            charges: Vec::with_capacity(size),
+            residue_ids: vec![None; size],
+        };
+
+        for i in 0..size {
+            let atom = self.atom(i);
+            table.names.push(atom.name());
+            table.types.push(atom.atomic_type());
+            table.masses.push(atom.mass());
+            table.charges.push(atom.charge());
+        }
+
+        for residue in self.iter_residues() {
+            let id = residue.id();
+            for atom in residue.atoms() {
+                table.residue_ids[atom] = id;
+            }
+        }
+
+        return table;
+    }
+
+    /// Call `get` on all the atoms in this topology, and collect the results
+    pub(crate) fn map_atoms<T>(&self, mut get: impl FnMut(&Atom) -> T) -> Vec<T> {
+        return (0..self.size()).map(|i| get(&self.atom(i))).collect();
+    }
+
+    /// Create a copy of this topology, calling `update` on each residue before
+    /// adding it to the copy. This is the only way to modify residues already
+    /// in a topology, since the C API does not give mutable access to them.
+    pub(crate) fn with_updated_residues(&self, mut update: impl FnMut(usize, &mut Residue)) -> Topology {
+        let mut topology = Topology::new();
+        for i in 0..self.size() {
+            topology.add_atom(&self.atom(i));
+        }
+
+        for (bond, order) in self.bonds().into_iter().zip(self.bond_orders()) {
+            topology.add_bond_with_order(bond[0], bond[1], order);
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        for i in 0..self.residues_count() as usize {
+            let mut residue = Residue::clone(&self.residue(i).expect("residue should exist"));
+            update(i, &mut residue);
+            topology
+                .add_residue(&residue)
+                .expect("residues from a valid topology should be valid");
+        }
+
+        return topology;
+    }
+}
+
+impl<'a> Iterator for ResidueIter<'a> {
+    type Item = ResidueRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.size <= self.index {
+            return None;
+        }
+        let residue = self.topology.residue(self.index);
+        self.index += 1;
+        residue
+    }
 }
 
 impl Drop for Topology {
@@ -789,6 +1085,12 @@ impl Drop for Topology {
     }
 }
 
+// SAFETY: a `Topology` owns its C++ object, which can be used from any
+// thread. It is not `Sync` because the C++ topology computes and caches the
+// angles, dihedrals and impropers the first time they are requested, even
+// through `&self`, which would be a data race between threads.
+unsafe impl Send for Topology {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -804,6 +1106,9 @@ mod test {
         topology.resize(10);
         assert_eq!(topology.size(), 10);
         assert_eq!(copy.size(), 0);
+
+        let copy = topology.try_clone().unwrap();
+        assert_eq!(copy.size(), 10);
     }
 
     #[test]
@@ -995,9 +1300,74 @@ mod test {
         let second = topology.residue(0).unwrap();
         assert!(topology.are_linked(&first, &second));
 
+        let atoms = topology
+            .iter_residues()
+            .map(|residue| residue.atoms())
+            .collect::<Vec<_>>();
+        assert_eq!(atoms, vec![vec![0, 2], vec![3]]);
+        assert_eq!(Topology::new().iter_residues().count(), 0);
+
         // missing residue
         assert!(topology.residue_for_atom(1).is_none());
         // out of bounds
         assert!(topology.residue_for_atom(67).is_none());
     }
+
+    #[test]
+    fn atoms_table() {
+        let mut topology = Topology::new();
+        topology.add_atom(&Atom::new("O"));
+        topology.add_atom(&Atom::new("H1"));
+        topology.atom_mut(1).set_atomic_type("H");
+        topology.add_atom(&Atom::new("Zn"));
+        topology.atom_mut(2).set_charge(2.0);
+
+        let mut residue = Residue::with_id("HOH", 7);
+        residue.add_atom(0);
+        residue.add_atom(1);
+        topology.add_residue(&residue).unwrap();
+
+        let table = topology.atoms_table();
+        assert_eq!(table.names, ["O", "H1", "Zn"]);
+        assert_eq!(table.types, ["O", "H", "Zn"]);
+        assert_eq!(table.charges, [0.0, 0.0, 2.0]);
+        assert_eq!(table.masses[0], Atom::new("O").mass());
+        assert_eq!(table.residue_ids, [Some(7), Some(7), None]);
+
+        assert_eq!(Topology::new().atoms_table(), AtomsTable::default());
+    }
+
+    #[test]
+    fn molecules() {
+        let mut topology = Topology::new();
+        assert!(topology.molecules().is_empty());
+
+        topology.resize(6);
+        topology.add_bond(5, 0);
+        topology.add_bond(2, 4);
+        topology.add_bond(4, 1);
+        assert_eq!(topology.molecules(), vec![vec![0, 5], vec![1, 2, 4], vec![3]]);
+
+        topology.add_bond(5, 4);
+        assert_eq!(topology.molecules(), vec![vec![0, 1, 2, 4, 5], vec![3]]);
+    }
+
+    #[test]
+    fn assign_molecule_ids() {
+        let mut topology = Topology::new();
+        assert_eq!(topology.assign_molecule_ids(), 0);
+
+        topology.resize(5);
+        topology.add_bond(4, 1);
+        topology.add_bond(2, 0);
+        assert_eq!(topology.assign_molecule_ids(), 3);
+
+        let ids = (0..5).map(|i| topology.atom(i).molecule_id()).collect::<Vec<_>>();
+        assert_eq!(ids, [Some(0), Some(1), Some(0), Some(2), Some(1)]);
+
+        // ids are updated when the bonds change
+        topology.add_bond(3, 4);
+        assert_eq!(topology.assign_molecule_ids(), 2);
+        assert_eq!(topology.atom(3).molecule_id(), Some(1));
+    }
 }
@@ -2,13 +2,21 @@
 // Copyright (C) 2015-2018 Guillaume Fraux -- BSD licensed
 use std::convert::TryInto;
 use std::os::raw::c_char;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 
 use chemfiles_sys as ffi;
 
-use crate::errors::{check, check_success, Error, Status};
+use crate::analysis::Unwrapper;
+use crate::errors::{
+    capture_warnings, check, check_success, install_log_bridge, send_warning, Error, ErrorContext, Status,
+    TrajectoryOperation,
+};
+use crate::linalg;
+use crate::progress::{ProgressSink, ProgressTracker};
+use crate::spans::Span;
 use crate::strings;
-use crate::{Frame, Topology, UnitCell};
+use crate::{CancelToken, CellShape, Frame, Property, Topology, UnitCell};
 
 /// The `Trajectory` type is the main entry point when using chemfiles. A
 /// `Trajectory` behave a bit like a file, allowing to read and/or write
@@ -16,29 +24,144 @@ use crate::{Frame, Topology, UnitCell};
 #[derive(Debug)]
 pub struct Trajectory {
     handle: *mut ffi::CHFL_TRAJECTORY,
+    /// Format given when creating this trajectory, or `None` if the format
+    /// should be guessed from the path. The guessed format is only computed
+    /// when it is needed for a `ReadReport`.
+    format: Option<String>,
+    /// Path of the file, or `None` for in-memory trajectories
+    path: Option<PathBuf>,
+    /// Diagnostics about the last successful read
+    last_read: Option<ReadReport>,
+    /// Token used to cancel reading and writing
+    cancel: Option<CancelToken>,
+    /// Progress reporting for the frames read from this trajectory
+    progress: Option<ProgressTracker>,
+    /// What to do with frames which can not be read
+    error_policy: ErrorPolicy,
+    /// Index of the step read by the next call to `read`
+    next_step: usize,
+    /// Steps skipped because they could not be read
+    skipped: Vec<usize>,
+}
+
+/// Mode used to open a `Trajectory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpenMode {
+    /// Open an existing file for reading
+    Read,
+    /// Create a new file for writing, removing any existing file at the same
+    /// path
+    Write,
+    /// Open an existing file to add new frames at the end, or create a new
+    /// file if it does not exist
+    Append,
+}
+
+impl OpenMode {
+    /// Get the character corresponding to this mode in the C API
+    fn as_char(self) -> char {
+        match self {
+            OpenMode::Read => 'r',
+            OpenMode::Write => 'w',
+            OpenMode::Append => 'a',
+        }
+    }
+}
+
+/// What a `Trajectory` should do when one of its frames can not be read,
+/// set with `TrajectoryBuilder::on_error` or `Trajectory::set_error_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorPolicy {
+    /// Return the error to the caller. This is the default.
+    Abort,
+    /// Emit a warning (see `chemfiles::set_warning_callback`), skip the
+    /// frame and continue reading at the next step. This is useful to read
+    /// trajectories from crashed simulations, which often end with a
+    /// truncated frame.
+    Skip,
+}
+
+impl From<OpenMode> for char {
+    fn from(mode: OpenMode) -> char {
+        mode.as_char()
+    }
+}
+
+/// Diagnostics about the last frame read by a `Trajectory`, to help finding
+/// out how chemfiles interpreted a file.
+///
+/// Chemfiles reports skipped records, guessed data and other recoverable
+/// issues as warnings, which are collected here for the corresponding frame.
+/// The C++ library does not expose the number of bytes consumed by the
+/// reader, so this information is not available.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadReport {
+    /// Format used to read the frame, as `"<format name> [/ <compression>]"`.
+    /// This is empty if the format was not given and could not be guessed.
+    pub format: String,
+    /// Step of the frame that was read
+    pub step: usize,
+    /// Number of atoms in the frame
+    pub natoms: usize,
+    /// Did the file contain velocities for this frame?
+    pub has_velocities: bool,
+    /// Shape of the unit cell of the frame
+    pub cell_shape: CellShape,
+    /// Number of bonds in the frame, read from the file or guessed by chemfiles
+    pub bonds_count: usize,
+    /// Warnings emitted while reading this frame, for example about skipped
+    /// records or guessed elements
+    pub warnings: Vec<String>,
 }
 
 impl Drop for Trajectory {
     fn drop(&mut self) {
+        if self.handle.is_null() {
+            // the trajectory was already closed by `Trajectory::close`
+            return;
+        }
         unsafe {
             let _ = ffi::chfl_trajectory_close(self.as_ptr());
         }
     }
 }
 
+// SAFETY: a `Trajectory` owns its C++ object and the associated file, which
+// can be used from any thread. It is not `Sync` since reading changes the
+// state of the file, use `SyncTrajectory` to share a trajectory between
+// threads.
+unsafe impl Send for Trajectory {}
+
 impl Trajectory {
     /// Create a `Trajectory` from a C pointer.
     ///
     /// This function is unsafe because no validity check is made on the pointer.
     #[inline]
-    pub(crate) unsafe fn from_ptr(ptr: *mut ffi::CHFL_TRAJECTORY) -> Result<Trajectory, Error> {
+    pub(crate) unsafe fn from_ptr(
+        ptr: *mut ffi::CHFL_TRAJECTORY,
+        format: Option<String>,
+        path: Option<PathBuf>,
+    ) -> Result<Trajectory, Error> {
         if ptr.is_null() {
-            Err(Error {
-                status: Status::FileError,
-                message: Error::last_error(),
-            })
+            Err(
+                Error::new(Status::FileError, Error::last_error()).with_context(ErrorContext {
+                    operation: TrajectoryOperation::Open,
+                    path,
+                    step: None,
+                }),
+            )
         } else {
-            Ok(Trajectory { handle: ptr })
+            Ok(Trajectory {
+                handle: ptr,
+                format,
+                path,
+                last_read: None,
+                cancel: None,
+                progress: None,
+                error_policy: ErrorPolicy::Abort,
+                next_step: 0,
+                skipped: Vec::new(),
+            })
         }
     }
 
@@ -57,6 +180,12 @@ impl Trajectory {
     /// Open the file at the given `path` in the given `mode`.
     ///
     /// Valid modes are `'r'` for read, `'w'` for write and `'a'` for append.
+    /// See also `Trajectory::open_with` to use an `OpenMode` instead.
+    ///
+    /// On Unix, `path` is given as-is to the C library and does not need to be
+    /// valid UTF-8. On other platforms, the C library only supports UTF-8
+    /// paths, and opening a path which is not valid UTF-8 fails with
+    /// `Status::UTF8PathError`.
     ///
     /// # Errors
     ///
@@ -73,19 +202,39 @@ impl Trajectory {
     where
         P: AsRef<Path>,
     {
-        let path = path
-            .as_ref()
-            .to_str()
-            .ok_or_else(|| Error::utf8_path_error(path.as_ref()))?;
-
-        let path = strings::to_c(path);
+        install_log_bridge();
+        let path = path.as_ref();
+        let c_path = strings::path_to_c(path)?;
         unsafe {
             #[allow(clippy::cast_possible_wrap)]
-            let handle = ffi::chfl_trajectory_open(path.as_ptr(), mode as c_char);
-            Trajectory::from_ptr(handle)
+            let handle = ffi::chfl_trajectory_open(c_path.as_ptr(), mode as c_char);
+            Trajectory::from_ptr(handle, None, Some(path.to_path_buf()))
         }
     }
 
+    /// Open the file at the given `path` in the given `mode`. This is the same
+    /// as `Trajectory::open`, using a type-checked `OpenMode` instead of a
+    /// character.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the file is not accessible for the given mode, if
+    /// it is incorrectly formatted for the corresponding format, or in case of
+    /// I/O errors from the OS.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Frame, OpenMode, Trajectory};
+    /// let mut trajectory = Trajectory::open_with("water.xyz", OpenMode::Append).unwrap();
+    /// trajectory.write(&Frame::new()).unwrap();
+    /// ```
+    pub fn open_with<P>(path: P, mode: OpenMode) -> Result<Trajectory, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Trajectory::open(path, mode.as_char())
+    }
+
     /// Open the file at the given `path` using a specific file `format` and the
     /// given `mode`.
     ///
@@ -112,25 +261,115 @@ impl Trajectory {
         P: AsRef<Path>,
         S: Into<&'a str>,
     {
-        let filename = filename
-            .as_ref()
-            .to_str()
-            .ok_or_else(|| Error::utf8_path_error(filename.as_ref()))?;
+        install_log_bridge();
+        let filename = filename.as_ref();
+        let format = format.into();
+        let format_name = if format.is_empty() {
+            None
+        } else {
+            Some(format.to_owned())
+        };
 
-        let filename = strings::to_c(filename);
-        let format = strings::to_c(format.into());
+        let c_filename = strings::path_to_c(filename)?;
+        let format = strings::to_c(format);
         unsafe {
             #[allow(clippy::cast_possible_wrap)]
-            let handle = ffi::chfl_trajectory_with_format(filename.as_ptr(), mode as c_char, format.as_ptr());
-            Trajectory::from_ptr(handle)
+            let handle = ffi::chfl_trajectory_with_format(c_filename.as_ptr(), mode as c_char, format.as_ptr());
+            Trajectory::from_ptr(handle, format_name, Some(filename.to_path_buf()))
+        }
+    }
+
+    /// Get a `TrajectoryBuilder` to set the format, topology and unit cell of
+    /// a trajectory before opening it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let trajectory = Trajectory::builder()
+    ///     .format("LAMMPS Data")
+    ///     .topology_file("topol.pdb")
+    ///     .open("system.data")
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> TrajectoryBuilder {
+        TrajectoryBuilder::default()
+    }
+
+    /// Read a memory buffer as though it was a formatted file.
+    ///
+    /// This is a shortcut for `MemoryTrajectoryReader::new`: the `data` is
+    /// borrowed for the lifetime of the returned reader, which can be used as
+    /// a `Trajectory` everywhere. The `format` parameter is required and
+    /// should follow the same rules as in the main `Trajectory` constructor.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the data is incorrectly formatted for the
+    /// corresponding format, or if the format do not support in-memory readers.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Frame, Trajectory};
+    /// let bytes = b"2\n\nO 0 0 0\nH 1 0 0\n".to_vec();
+    /// let mut trajectory = Trajectory::memory_reader(&bytes, "XYZ").unwrap();
+    ///
+    /// let mut frame = Frame::new();
+    /// trajectory.read(&mut frame).unwrap();
+    /// assert_eq!(frame.size(), 2);
+    /// ```
+    pub fn memory_reader<Data, Format>(data: &Data, format: Format) -> Result<MemoryTrajectoryReader<'_>, Error>
+    where
+        Data: AsRef<[u8]> + ?Sized,
+        Format: AsRef<str>,
+    {
+        MemoryTrajectoryReader::new(data.as_ref(), format)
+    }
+
+    /// Read a gzip-compressed memory buffer as though it was a formatted
+    /// file, without decompressing it to a temporary file first.
+    ///
+    /// The `format` parameter should follow the same rules as in the main
+    /// `Trajectory` constructor, without a compression specification: this
+    /// function adds it. Writing compressed data to memory is not supported
+    /// by chemfiles, see `Trajectory::memory_writer`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if `data` does not start with the gzip magic
+    /// number, if it is incorrectly formatted for the corresponding format,
+    /// or if the format do not support in-memory readers.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Frame, Trajectory};
+    /// let bytes = std::fs::read("water.xyz.gz").unwrap();
+    /// let mut trajectory = Trajectory::memory_reader_compressed(&bytes, "XYZ").unwrap();
+    ///
+    /// let mut frame = Frame::new();
+    /// trajectory.read(&mut frame).unwrap();
+    /// ```
+    pub fn memory_reader_compressed<Data, Format>(
+        data: &Data,
+        format: Format,
+    ) -> Result<MemoryTrajectoryReader<'_>, Error>
+    where
+        Data: AsRef<[u8]> + ?Sized,
+        Format: AsRef<str>,
+    {
+        let data = data.as_ref();
+        if !data.starts_with(&[0x1f, 0x8b]) {
+            return Err(Error::new(Status::FormatError, "memory buffer is not gzip-compressed"));
         }
+
+        let format = format!("{} / GZ", format.as_ref().trim());
+        MemoryTrajectoryReader::new(data, format)
     }
 
     /// Write to a memory buffer as though it was a formatted file.
     ///
     /// The `format` parameter should follow the same rules as in the main
     /// `Trajectory` constructor, except that compression specification
-    /// is not supported.
+    /// is not supported: the data is always written uncompressed.
     ///
     /// The `memory_buffer` function can be used to retrieve the data written
     /// to memory of the `Trajectory`.
@@ -151,10 +390,13 @@ impl Trajectory {
     where
         S: Into<&'a str>,
     {
-        let format = strings::to_c(format.into());
+        install_log_bridge();
+        let format = format.into();
+        let format_name = Some(format.to_owned());
+        let format = strings::to_c(format);
         unsafe {
             let handle = ffi::chfl_trajectory_memory_writer(format.as_ptr());
-            Trajectory::from_ptr(handle)
+            Trajectory::from_ptr(handle, format_name, None)
         }
     }
 
@@ -166,7 +408,9 @@ impl Trajectory {
     /// # Errors
     ///
     /// This function fails if the data is incorrectly formatted for the
-    /// corresponding format, or in case of I/O errors from the OS.
+    /// corresponding format, or in case of I/O errors from the OS. The
+    /// `Error::context` of the error contains the path of the file and the
+    /// step which could not be read.
     ///
     /// # Example
     /// ```no_run
@@ -177,7 +421,27 @@ impl Trajectory {
     /// trajectory.read(&mut frame).unwrap();
     /// ```
     pub fn read(&mut self, frame: &mut Frame) -> Result<(), Error> {
-        unsafe { check(ffi::chfl_trajectory_read(self.as_mut_ptr(), frame.as_mut_ptr())) }
+        if self.error_policy == ErrorPolicy::Skip {
+            return self.read_skipping_errors(frame);
+        }
+        return self.read_next(frame);
+    }
+
+    /// Read the next step of this trajectory into a `frame`, keeping
+    /// `next_step` in sync with the step read by the C library
+    fn read_next(&mut self, frame: &mut Frame) -> Result<(), Error> {
+        self.check_cancelled()?;
+        let step = self.next_step;
+        let span = Span::read(self.path.as_deref(), step);
+        let (status, warnings) =
+            capture_warnings(|| unsafe { check(ffi::chfl_trajectory_read(self.as_mut_ptr(), frame.as_mut_ptr())) });
+        let status = status.map_err(|error| self.add_context(error, TrajectoryOperation::Read, Some(step)));
+        self.record_read(&status, frame, warnings);
+        if status.is_ok() {
+            span.record_natoms(frame.size());
+            self.next_step = step + 1;
+        }
+        return status;
     }
 
     /// Read a specific `step` of this trajectory into a `frame`.
@@ -199,13 +463,267 @@ impl Trajectory {
     /// trajectory.read_step(10, &mut frame).unwrap();
     /// ```
     pub fn read_step(&mut self, step: usize, frame: &mut Frame) -> Result<(), Error> {
-        unsafe {
+        self.check_cancelled()?;
+        let span = Span::read(self.path.as_deref(), step);
+        let (status, warnings) = capture_warnings(|| unsafe {
             check(ffi::chfl_trajectory_read_step(
                 self.as_mut_ptr(),
                 step as u64,
                 frame.as_mut_ptr(),
             ))
+        });
+        let status = status.map_err(|error| self.add_context(error, TrajectoryOperation::Read, Some(step)));
+        self.record_read(&status, frame, warnings);
+        if status.is_ok() {
+            span.record_natoms(frame.size());
+            self.next_step = step + 1;
+        }
+        return status;
+    }
+
+    /// Implementation of `Trajectory::read` with `ErrorPolicy::Skip`, reading
+    /// the next step which does not contain any error.
+    ///
+    /// The steps are read sequentially, and the number of steps is only used
+    /// after an error, to check whether the error comes from reading past the
+    /// end of the trajectory. Counting the steps can fail for the same reason
+    /// as reading (for example with a truncated final frame), in which case
+    /// the failed step is only skipped if the next one can be read.
+    fn read_skipping_errors(&mut self, frame: &mut Frame) -> Result<(), Error> {
+        let mut result = self.read_next(frame);
+        loop {
+            let error = match result {
+                Err(error) if error.status != Status::Cancelled => error,
+                result => return result,
+            };
+
+            let step = self.next_step;
+            match self.try_nsteps() {
+                // there are no more steps to read
+                Ok(nsteps) if step >= nsteps => return Err(error),
+                Ok(_) => {
+                    self.skip_step(step, &error);
+                    self.next_step = step + 1;
+                    result = self.read_step(step + 1, frame);
+                }
+                Err(_) => {
+                    let next = self.read_step(step + 1, frame);
+                    if next.is_err() {
+                        return Err(error);
+                    }
+                    self.skip_step(step, &error);
+                    return next;
+                }
+            }
+        }
+    }
+
+    /// Attach context about the `operation` on this trajectory at the given
+    /// `step` to an `error`
+    fn add_context(&self, error: Error, operation: TrajectoryOperation, step: Option<usize>) -> Error {
+        return error.with_context(ErrorContext {
+            operation,
+            path: self.path.clone(),
+            step,
+        });
+    }
+
+    /// Record that `step` was skipped because of `error`
+    fn skip_step(&mut self, step: usize, error: &Error) {
+        send_warning(&format!(
+            "skipping step {step} which can not be read: {}",
+            error.message
+        ));
+        self.skipped.push(step);
+    }
+
+    /// Set the `policy` to use when a frame of this trajectory can not be
+    /// read.
+    ///
+    /// With `ErrorPolicy::Skip`, `Trajectory::read` and the iterators over
+    /// frames (`Trajectory::frames`, ...) skip steps which can not be read,
+    /// after emitting a warning, and continue with the next step. Reading a
+    /// specific step with `Trajectory::read_step` still returns an error.
+    /// Resuming at the next step relies on the format being able to locate
+    /// it: this works for truncated frames at the end of a file or corrupted
+    /// values inside a frame, but some errors (for example a wrong number of
+    /// atoms in XYZ files) can prevent reading any further.
+    ///
+    /// Since the iterators over frames can yield less frames than there are
+    /// steps in the trajectory with this policy, the lower bound of their
+    /// `size_hint` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{ErrorPolicy, MemoryTrajectoryReader};
+    /// let data = "1\n\nO 0 0 0\n1\n\nO 0 0 xxx\n1\n\nO 0 0 2\n";
+    /// let mut trajectory = MemoryTrajectoryReader::new(data.as_bytes(), "XYZ").unwrap();
+    /// trajectory.set_error_policy(ErrorPolicy::Skip);
+    ///
+    /// let frames = trajectory.frames().collect::<Result<Vec<_>, _>>().unwrap();
+    /// assert_eq!(frames.len(), 2);
+    /// assert_eq!(trajectory.skipped_steps(), [1]);
+    /// ```
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    /// Get the policy used when a frame of this trajectory can not be read.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{ErrorPolicy, Trajectory};
+    /// let trajectory = Trajectory::open("water.xyz", 'r').unwrap();
+    /// assert_eq!(trajectory.error_policy(), ErrorPolicy::Abort);
+    /// ```
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
+    /// Get the steps which were skipped because they could not be read, with
+    /// `ErrorPolicy::Skip`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{ErrorPolicy, Trajectory};
+    /// let mut trajectory = Trajectory::builder().on_error(ErrorPolicy::Skip).open("crashed.xyz").unwrap();
+    /// for frame in trajectory.frames() {
+    ///     let frame = frame.unwrap();
+    ///     // ...
+    /// }
+    ///
+    /// if !trajectory.skipped_steps().is_empty() {
+    ///     println!("skipped steps {:?}", trajectory.skipped_steps());
+    /// }
+    /// ```
+    pub fn skipped_steps(&self) -> &[usize] {
+        &self.skipped
+    }
+
+    /// Read the frame at index `step` in this trajectory into `frame`, with
+    /// negative indexes counting from the end of the trajectory: `-1` is the
+    /// last frame, `-2` the one before, *etc.*
+    ///
+    /// Formats supporting random access only read the requested frame, and
+    /// do not need to go through all the previous frames.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if `step` is out of bounds for this trajectory, if
+    /// the data is incorrectly formatted for the corresponding format, or in
+    /// case of I/O errors from the OS.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Trajectory, Frame};
+    /// let mut trajectory = Trajectory::open("water.xyz", 'r').unwrap();
+    /// let mut frame = Frame::new();
+    ///
+    /// // read the second to last frame
+    /// trajectory.read_at(-2, &mut frame).unwrap();
+    /// ```
+    pub fn read_at(&mut self, step: isize, frame: &mut Frame) -> Result<(), Error> {
+        let nsteps = self.try_nsteps()?;
+        let index = if step < 0 {
+            nsteps.checked_sub(step.unsigned_abs())
+        } else {
+            Some(step.unsigned_abs()).filter(|&index| index < nsteps)
+        };
+
+        match index {
+            Some(index) => self.read_step(index, frame),
+            None => Err(Error::new(
+                Status::OutOfBounds,
+                format!("can not read step {step} in a trajectory with {nsteps} steps"),
+            )),
+        }
+    }
+
+    /// Read the last frame of this trajectory into `frame`. This is the same
+    /// as `trajectory.read_at(-1, frame)`.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the trajectory is empty, if the data is
+    /// incorrectly formatted for the corresponding format, or in case of I/O
+    /// errors from the OS.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Trajectory, Frame};
+    /// let mut trajectory = Trajectory::open("simulation.dcd", 'r').unwrap();
+    /// let mut frame = Frame::new();
+    ///
+    /// trajectory.read_last(&mut frame).unwrap();
+    /// println!("final step: {}", frame.step());
+    /// ```
+    pub fn read_last(&mut self, frame: &mut Frame) -> Result<(), Error> {
+        self.read_at(-1, frame)
+    }
+
+    /// Update the diagnostics for the last read after reading `frame`
+    fn record_read(&mut self, status: &Result<(), Error>, frame: &Frame, warnings: Vec<String>) {
+        if status.is_err() {
+            self.last_read = None;
+            return;
+        }
+
+        #[cfg(all(feature = "strict-debug", debug_assertions))]
+        frame.debug_validate();
+
+        if let Some(mut progress) = self.progress.take() {
+            if progress.needs_total() {
+                progress.set_total(self.try_nsteps().ok());
+            }
+            progress.tick();
+            self.progress = Some(progress);
+        }
+
+        self.last_read = Some(ReadReport {
+            format: self.format_name(),
+            step: frame.step(),
+            natoms: frame.size(),
+            has_velocities: frame.has_velocities(),
+            cell_shape: frame.cell().shape(),
+            bonds_count: frame.topology().bonds_count(),
+            warnings,
+        });
+    }
+
+    /// Get the name of the format used by this trajectory, guessing it from
+    /// the path the first time if it was not given when opening the file.
+    fn format_name(&mut self) -> String {
+        if self.format.is_none() {
+            let guessed = match &self.path {
+                Some(path) => crate::guess_format(path).unwrap_or_default(),
+                None => String::new(),
+            };
+            self.format = Some(guessed);
         }
+        return self.format.clone().unwrap_or_default();
+    }
+
+    /// Get diagnostics about the last frame read from this trajectory, or
+    /// `None` if no frame was read yet or if the last read failed.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Frame, MemoryTrajectoryReader};
+    /// let data = "2\n\nO 0 0 0\nH 1 0 0\n";
+    /// let mut trajectory = MemoryTrajectoryReader::new(data.as_bytes(), "XYZ").unwrap();
+    /// assert!(trajectory.last_read_report().is_none());
+    ///
+    /// let mut frame = Frame::new();
+    /// trajectory.read(&mut frame).unwrap();
+    ///
+    /// let report = trajectory.last_read_report().unwrap();
+    /// assert_eq!(report.format, "XYZ");
+    /// assert_eq!(report.natoms, 2);
+    /// assert!(!report.has_velocities);
+    /// assert!(report.warnings.is_empty());
+    /// ```
+    pub fn last_read_report(&self) -> Option<&ReadReport> {
+        self.last_read.as_ref()
     }
 
     /// Write a `frame` to this trajectory.
@@ -224,7 +742,145 @@ impl Trajectory {
     /// trajectory.write(&mut frame).unwrap();
     /// ```
     pub fn write(&mut self, frame: &Frame) -> Result<(), Error> {
-        unsafe { check(ffi::chfl_trajectory_write(self.as_mut_ptr(), frame.as_ptr())) }
+        self.check_cancelled()?;
+        #[cfg(all(feature = "strict-debug", debug_assertions))]
+        frame.debug_validate();
+        let _span = Span::write(self.path.as_deref(), frame.step(), frame.size());
+        let status = unsafe { check(ffi::chfl_trajectory_write(self.as_mut_ptr(), frame.as_ptr())) };
+        return status.map_err(|error| self.add_context(error, TrajectoryOperation::Write, Some(frame.step())));
+    }
+
+    /// Write all the frames from `iter` to this trajectory, stopping at the
+    /// first error, and return the number of frames written. This is the
+    /// fallible version of `Extend<Frame>`, and can be used directly with
+    /// another trajectory to convert between formats.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if any item of `iter` is an error, or if a frame
+    /// can not be written.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let input = Trajectory::open("water.nc", 'r').unwrap();
+    /// let mut output = Trajectory::open("water.xyz", 'w').unwrap();
+    ///
+    /// let count = output.extend_results(input).unwrap();
+    /// println!("converted {} frames", count);
+    /// ```
+    pub fn extend_results<I>(&mut self, iter: I) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Result<Frame, Error>>,
+    {
+        let mut count = 0;
+        for frame in iter {
+            self.write(&frame?)?;
+            count += 1;
+        }
+        return Ok(count);
+    }
+
+    /// Use the given cancellation `token` for this trajectory. Once the token
+    /// is cancelled, all calls to `read`, `read_step` and `write` will fail
+    /// with `Status::Cancelled`, and iterators over the frames of this
+    /// trajectory will stop after yielding this error.
+    ///
+    /// The token is checked before reading or writing each frame, so an
+    /// operation on a single frame which already started will not be
+    /// interrupted.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{CancelToken, Frame, MemoryTrajectoryReader, Status};
+    /// let data = "1\n\nO 0 0 0\n1\n\nO 0 0 1\n";
+    /// let mut trajectory = MemoryTrajectoryReader::new(data.as_bytes(), "XYZ").unwrap();
+    ///
+    /// let token = CancelToken::new();
+    /// trajectory.set_cancel_token(token.clone());
+    ///
+    /// let mut frame = Frame::new();
+    /// trajectory.read(&mut frame).unwrap();
+    ///
+    /// // this would typically be called from another thread
+    /// token.cancel();
+    /// let error = trajectory.read(&mut frame).unwrap_err();
+    /// assert_eq!(error.status, Status::Cancelled);
+    /// ```
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        self.cancel = Some(token);
+    }
+
+    /// Report the progress of reading this trajectory to `sink` every
+    /// `every` frames, and after reading the last frame. This is intended to
+    /// display progress bars when reading large trajectories.
+    ///
+    /// Each successful call to `read`, `read_step` (and the functions using
+    /// them, such as `Trajectory::frames`) counts as one frame. The total
+    /// number of frames in the `Progress` is the number of steps in the
+    /// trajectory. Since counting the steps can require going through the
+    /// whole file once for some text formats, this is only done after the
+    /// first frame is read, and not when setting the callback. The C++ library does not
+    /// expose the number of bytes read, so the progress is only given in
+    /// frames.
+    ///
+    /// Calling this function again replaces the previous sink and restarts
+    /// counting frames from zero.
+    ///
+    /// # Panics
+    ///
+    /// If `every` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use chemfiles::MemoryTrajectoryReader;
+    /// # use chemfiles::analysis::Progress;
+    /// let data = "1\n\nO 0 0 0\n1\n\nO 0 0 1\n1\n\nO 0 0 2\n";
+    /// let mut trajectory = MemoryTrajectoryReader::new(data.as_bytes(), "XYZ").unwrap();
+    ///
+    /// let reports = Arc::new(Mutex::new(Vec::new()));
+    /// let collected = Arc::clone(&reports);
+    /// trajectory.set_progress_callback(2, move |progress: &Progress| {
+    ///     collected.lock().unwrap().push((progress.frames, progress.total));
+    /// });
+    ///
+    /// for frame in trajectory.frames() {
+    ///     frame.unwrap();
+    /// }
+    /// assert_eq!(*reports.lock().unwrap(), [(2, Some(3)), (3, Some(3))]);
+    /// ```
+    pub fn set_progress_callback<S: ProgressSink + 'static>(&mut self, every: usize, sink: S) {
+        let mut tracker = ProgressTracker::new(sink, None).with_interval(every).with_lazy_total();
+        tracker.start();
+        self.progress = Some(tracker);
+    }
+
+    /// Stop reporting the progress of reading this trajectory, removing the
+    /// sink set with `Trajectory::set_progress_callback`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Frame, MemoryTrajectoryReader};
+    /// # use chemfiles::analysis::Progress;
+    /// let mut trajectory = MemoryTrajectoryReader::new(b"1\n\nO 0 0 0\n".as_ref(), "XYZ").unwrap();
+    /// trajectory.set_progress_callback(1, |_: &Progress| panic!("progress should not be reported"));
+    /// trajectory.remove_progress_callback();
+    ///
+    /// let mut frame = Frame::new();
+    /// trajectory.read(&mut frame).unwrap();
+    /// ```
+    pub fn remove_progress_callback(&mut self) {
+        self.progress = None;
+    }
+
+    /// Return an error if the cancellation token of this trajectory was
+    /// cancelled
+    fn check_cancelled(&self) -> Result<(), Error> {
+        match self.cancel {
+            Some(ref token) => token.check(),
+            None => Ok(()),
+        }
     }
 
     /// Set the `topology` associated with this trajectory. This topology will
@@ -269,12 +925,7 @@ impl Trajectory {
     where
         P: AsRef<Path>,
     {
-        let path = path
-            .as_ref()
-            .to_str()
-            .ok_or_else(|| Error::utf8_path_error(path.as_ref()))?;
-
-        let path = strings::to_c(path);
+        let path = strings::path_to_c(path.as_ref())?;
         unsafe {
             check(ffi::chfl_trajectory_topology_file(
                 self.as_mut_ptr(),
@@ -307,13 +958,8 @@ impl Trajectory {
         P: AsRef<Path>,
         S: Into<&'a str>,
     {
-        let path = path
-            .as_ref()
-            .to_str()
-            .ok_or_else(|| Error::utf8_path_error(path.as_ref()))?;
-
         let format = strings::to_c(format.into());
-        let path = strings::to_c(path);
+        let path = strings::path_to_c(path.as_ref())?;
         unsafe {
             check(ffi::chfl_trajectory_topology_file(
                 self.as_mut_ptr(),
@@ -341,23 +987,93 @@ impl Trajectory {
 
     /// Get the number of steps (the number of frames) in a trajectory.
     ///
+    /// This is the number of frames available for reading, and can be used
+    /// to display progress while reading the trajectory. See
+    /// `Trajectory::try_nsteps` for a version of this function returning an
+    /// error instead of panicking.
+    ///
+    /// # Panics
+    ///
+    /// If the number of steps can not be determined, for example if the file
+    /// is incorrectly formatted.
+    ///
     /// # Example
     /// ```no_run
-    /// # use chemfiles::Trajectory;
+    /// # use chemfiles::{Frame, Trajectory};
     /// let mut trajectory = Trajectory::open("water.xyz", 'r').unwrap();
     ///
-    /// println!("This trajectory contains {} steps", trajectory.nsteps());
+    /// let nsteps = trajectory.nsteps();
+    /// let mut frame = Frame::new();
+    /// for step in 0..nsteps {
+    ///     trajectory.read(&mut frame).unwrap();
+    ///     println!("frame {}/{} from {}", step + 1, nsteps, trajectory.path());
+    /// }
     /// ```
     // FIXME should this take &self instead? The file can be modified by this
     // function, but the format should reset the state.
     pub fn nsteps(&mut self) -> usize {
+        self.try_nsteps()
+            .expect("failed to get the number of steps in this trajectory")
+    }
+
+    /// Get the number of steps (the number of frames) in a trajectory,
+    /// returning an error if it can not be determined.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the trajectory can not be scanned to count the
+    /// frames, for example if the file is incorrectly formatted.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let mut trajectory = Trajectory::open("water.xyz", 'r').unwrap();
+    ///
+    /// match trajectory.try_nsteps() {
+    ///     Ok(nsteps) => println!("This trajectory contains {} steps", nsteps),
+    ///     Err(error) => println!("failed to count steps: {}", error),
+    /// }
+    /// ```
+    pub fn try_nsteps(&mut self) -> Result<usize, Error> {
         let mut res = 0;
         unsafe {
-            check(ffi::chfl_trajectory_nsteps(self.as_mut_ptr(), &mut res))
-                .expect("failed to get the number of steps in this trajectory");
+            check(ffi::chfl_trajectory_nsteps(self.as_mut_ptr(), &mut res))?;
         }
         #[allow(clippy::cast_possible_truncation)]
-        return res as usize;
+        return Ok(res as usize);
+    }
+
+    /// Read all the frames in this trajectory, starting from the first step,
+    /// and collect the frame property with the given `name` for each step.
+    /// Steps where the frame does not have this property give `None`.
+    ///
+    /// This is useful to extract time series such as the energy or the
+    /// temperature stored in extended XYZ files. Only one frame is kept in
+    /// memory at any time.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if any of the frames can not be read.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let mut trajectory = Trajectory::open("dataset.xyz", 'r').unwrap();
+    /// for (step, energy) in trajectory.property_series("energy").unwrap().iter().enumerate() {
+    ///     if let Some(energy) = energy {
+    ///         println!("energy at step {}: {:?}", step, energy);
+    ///     }
+    /// }
+    /// ```
+    pub fn property_series(&mut self, name: &str) -> Result<Vec<Option<Property>>, Error> {
+        let nsteps = self.try_nsteps()?;
+        let mut series = Vec::with_capacity(nsteps);
+        let mut frame = Frame::new();
+        for step in 0..nsteps {
+            self.read_step(step, &mut frame)?;
+            series.push(frame.get(name));
+        }
+        return Ok(series);
     }
 
     /// Obtain the memory buffer written to by the trajectory.
@@ -382,36 +1098,969 @@ impl Trajectory {
     /// let result = trajectory_memory.memory_buffer();
     /// assert_eq!(result.unwrap(), "CC\n");
     /// ```
-    #[allow(clippy::cast_possible_truncation)]
     pub fn memory_buffer(&self) -> Result<&str, Error> {
-        let mut ptr: *const c_char = std::ptr::null();
-        let mut count: u64 = 0;
-        let buffer = unsafe {
+        let string = std::str::from_utf8(self.memory_bytes()?)?;
+        Ok(string)
+    }
+
+    /// Obtain the memory buffer written to by the trajectory, as raw bytes.
+    ///
+    /// Contrary to `Trajectory::memory_buffer`, this function does not
+    /// require the data to be valid UTF-8.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the trajectory was not opened with
+    /// `Trajectory::memory_writer`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, Trajectory};
+    /// let mut trajectory = Trajectory::memory_writer("XYZ").unwrap();
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Zn"), [0.0, 0.0, 0.0], None);
+    /// trajectory.write(&frame).unwrap();
+    ///
+    /// let bytes = trajectory.memory_bytes().unwrap();
+    /// assert!(bytes.starts_with(b"1\n"));
+    /// ```
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn memory_bytes(&self) -> Result<&[u8], Error> {
+        let mut ptr: *const c_char = std::ptr::null();
+        let mut count: u64 = 0;
+        unsafe {
             check(ffi::chfl_trajectory_memory_buffer(self.as_ptr(), &mut ptr, &mut count))?;
+        }
+
+        if count == 0 || ptr.is_null() {
+            return Ok(&[]);
+        }
+
+        let buffer = unsafe {
             std::slice::from_raw_parts(ptr.cast(), count.try_into().expect("failed to convert u64 to usize"))
         };
+        Ok(buffer)
+    }
+
+    /// Close this trajectory and get the data written to the memory buffer,
+    /// for example to send it over the network.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the trajectory was not opened with
+    /// `Trajectory::memory_writer`.
+    ///
+    /// # Example
+    /// ```
+    /// # use chemfiles::{Atom, Frame, Trajectory};
+    /// let mut trajectory = Trajectory::memory_writer("XYZ").unwrap();
+    ///
+    /// let mut frame = Frame::new();
+    /// frame.add_atom(&Atom::new("Zn"), [0.0, 0.0, 0.0], None);
+    /// trajectory.write(&frame).unwrap();
+    ///
+    /// let bytes: Vec<u8> = trajectory.into_bytes().unwrap();
+    /// let content = String::from_utf8(bytes).unwrap();
+    /// assert!(content.contains("Zn 0 0 0"));
+    /// ```
+    pub fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        self.memory_bytes().map(<[u8]>::to_vec)
+    }
+
+    /// Close this trajectory, flushing any buffered data to the file and
+    /// reporting errors happening while doing so.
+    ///
+    /// Dropping a trajectory also closes it, but any error is then silently
+    /// ignored, which can lead to truncated files (for example if the disk is
+    /// full). Use this function after writing to make sure all the data was
+    /// written.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if chemfiles reported an error while flushing or
+    /// closing the file. The error message contains all the corresponding
+    /// messages.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Frame, Trajectory};
+    /// let mut trajectory = Trajectory::open("output.xyz", 'w').unwrap();
+    /// trajectory.write(&Frame::new()).unwrap();
+    /// trajectory.close().unwrap();
+    /// ```
+    pub fn close(mut self) -> Result<(), Error> {
+        let handle = std::mem::replace(&mut self.handle, std::ptr::null_mut());
+        // `chfl_trajectory_close` does not return a status: the C++ library
+        // can not throw from destructors, and reports errors happening while
+        // flushing and closing files as warnings instead
+        let ((), warnings) = capture_warnings(|| unsafe {
+            let _ = ffi::chfl_trajectory_close(handle);
+        });
+
+        if warnings.is_empty() {
+            return Ok(());
+        }
+        return Err(Error::new(Status::FileError, warnings.join("\n")));
+    }
+
+    /// Get file path for this trajectory. This is the path used to open the
+    /// trajectory, and it can be converted to a `Path` with `Path::new`.
+    /// Paths which are not valid UTF-8 are converted lossily, replacing
+    /// invalid sequences with U+FFFD.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let trajectory = Trajectory::open("water.xyz", 'r').unwrap();
+    ///
+    /// assert_eq!(trajectory.path(), "water.xyz");
+    /// ```
+    pub fn path(&self) -> String {
+        let get_string = |ptr, len| unsafe { ffi::chfl_trajectory_path(self.as_ptr(), ptr, len) };
+        let path = strings::call_autogrow_buffer(1024, get_string).expect("failed to get path string");
+        return strings::from_c_lossy(path.as_ptr());
+    }
+
+    /// Get an iterator over all the frames in this trajectory, starting from
+    /// the first step.
+    ///
+    /// Each frame is read when the iterator advances, and the iterator yields
+    /// an error if a step can not be read. If the number of steps in the
+    /// trajectory can not be determined, the iterator yields this error and
+    /// then stops. Adapters skipping over steps such as
+    /// `skip`, `nth` or `step_by` do not read the skipped steps. Each item is a
+    /// newly allocated frame: use `Trajectory::buffered_frames` to reuse the
+    /// same memory for all steps.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let mut trajectory = Trajectory::open("water.xyz", 'r').unwrap();
+    ///
+    /// for frame in trajectory.frames().step_by(10) {
+    ///     let frame = frame.unwrap();
+    ///     println!("step {} contains {} atoms", frame.step(), frame.size());
+    /// }
+    /// ```
+    pub fn frames(&mut self) -> Frames<'_> {
+        let (nsteps, error) = count_steps(self);
+        Frames {
+            trajectory: self,
+            step: 0,
+            nsteps,
+            error,
+        }
+    }
+
+    /// Get a lending iterator over all the frames in this trajectory,
+    /// starting from the first step. All steps are read into the same
+    /// `Frame`, which avoids allocating a new frame for each step.
+    ///
+    /// The frames are accessed with `BufferedFrames::next_frame`, which
+    /// returns a reference to the frame, valid until the next call. Errors
+    /// are handled in the same way as `Trajectory::frames`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let mut trajectory = Trajectory::open("water.xyz", 'r').unwrap();
+    ///
+    /// let mut frames = trajectory.buffered_frames();
+    /// while let Some(frame) = frames.next_frame() {
+    ///     let frame = frame.unwrap();
+    ///     println!("step {} contains {} atoms", frame.step(), frame.size());
+    /// }
+    /// ```
+    pub fn buffered_frames(&mut self) -> BufferedFrames<'_> {
+        let (nsteps, error) = count_steps(self);
+        BufferedFrames {
+            trajectory: self,
+            frame: Frame::new(),
+            step: 0,
+            nsteps,
+            error,
+        }
+    }
+
+    /// Get an iterator over all the frames in this trajectory, with the
+    /// positions of each frame affinely mapped into the `reference` unit
+    /// cell.
+    ///
+    /// The fractional coordinates of every atom are preserved, and the unit
+    /// cell of the frames is replaced by `reference`. This is useful to
+    /// average structures over a NPT simulation, where the simulation box
+    /// fluctuates. Velocities are not modified.
+    ///
+    /// The iterator yields an error for steps which can not be read, and for
+    /// frames with a degenerated (for example infinite) unit cell, which do
+    /// not define fractional coordinates.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Trajectory, UnitCell};
+    /// let mut trajectory = Trajectory::open("npt.xyz", 'r').unwrap();
+    ///
+    /// let reference = UnitCell::new([20.0, 20.0, 20.0]);
+    /// for frame in trajectory.rescale_to_reference_cell(&reference) {
+    ///     let frame = frame.unwrap();
+    ///     assert_eq!(frame.cell().lengths(), [20.0, 20.0, 20.0]);
+    /// }
+    /// ```
+    pub fn rescale_to_reference_cell(&mut self, reference: &UnitCell) -> RescaledFrames<'_> {
+        RescaledFrames {
+            frames: self.frames(),
+            reference: reference.clone(),
+        }
+    }
+
+    /// Get an iterator over all the frames in this trajectory, removing the
+    /// jumps of atoms crossing the periodic boundaries between consecutive
+    /// frames with an `analysis::Unwrapper`. The atoms then have continuous
+    /// coordinates over the whole trajectory.
+    ///
+    /// The iterator yields an error for steps which can not be read, and for
+    /// frames with an infinite unit cell or a different number of atoms than
+    /// the first frame.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let mut trajectory = Trajectory::open("water.nc", 'r').unwrap();
+    ///
+    /// for frame in trajectory.unwrapped_frames() {
+    ///     let frame = frame.unwrap();
+    ///     println!("{:?}", frame.positions()[0]);
+    /// }
+    /// ```
+    pub fn unwrapped_frames(&mut self) -> UnwrappedFrames<'_> {
+        UnwrappedFrames {
+            frames: self.frames(),
+            unwrapper: Unwrapper::new(),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Trajectory {
+    /// Call `function` on all the frames in this trajectory, starting from
+    /// the first step, and collect the results in a vector, in the same
+    /// order as the frames.
+    ///
+    /// The frames are read sequentially on the current thread, and the calls
+    /// to `function` are distributed over the threads of the current rayon
+    /// thread pool. Frames are read in batches, so only a few frames per
+    /// thread are kept in memory at the same time.
+    ///
+    /// This function is only available with the `rayon` feature.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if any of the frames can not be read. In this
+    /// case, `function` is not called on the frames following the failing
+    /// one.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let mut trajectory = Trajectory::open("water.xyz", 'r').unwrap();
+    /// let sizes = trajectory.par_map(|frame| frame.size()).unwrap();
+    /// println!("number of atoms in each frame: {:?}", sizes);
+    /// ```
+    pub fn par_map<F, T>(&mut self, function: F) -> Result<Vec<T>, Error>
+    where
+        F: Fn(&Frame) -> T + Sync + Send,
+        T: Send,
+    {
+        use rayon::prelude::*;
+
+        let nsteps = self.try_nsteps()?;
+        let batch_size = 4 * rayon::current_num_threads();
+        let mut results = Vec::with_capacity(nsteps);
+        let mut batch = Vec::with_capacity(batch_size);
+
+        let mut step = 0;
+        while step < nsteps {
+            batch.clear();
+            while batch.len() < batch_size && step < nsteps {
+                let mut frame = Frame::new();
+                self.read_step(step, &mut frame)?;
+                batch.push(frame);
+                step += 1;
+            }
+
+            let mut values = Vec::with_capacity(batch.len());
+            batch
+                .par_drain(..)
+                .map(|frame| function(&frame))
+                .collect_into_vec(&mut values);
+            results.extend(values);
+        }
+
+        return Ok(results);
+    }
+}
+
+/// Source of the topology set by a `TrajectoryBuilder`
+#[derive(Debug, Clone)]
+enum TopologySource {
+    /// Use this topology directly
+    Topology(Topology),
+    /// Read the topology from the first frame of a file, with an optional
+    /// format (empty to guess it from the extension)
+    File(PathBuf, String),
+}
+
+/// A `TrajectoryBuilder` collects the settings of a `Trajectory` (format,
+/// open mode, topology, unit cell) before opening it, guaranteeing that all
+/// of them are applied before the first frame is read or written. It is
+/// created with `Trajectory::builder`.
+///
+/// Format-specific write options (such as the precision of XYZ or XTC
+/// files, or writing `CONECT` records in PDB) are not configurable: the
+/// chemfiles C API does not expose them, and always uses the defaults of
+/// each format. This includes the formatting of numbers in text formats
+/// (fixed or scientific notation, number of decimals and column widths),
+/// which is fixed by the format specification for formats such as GRO or
+/// PDB.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{Frame, Trajectory, UnitCell};
+/// let mut trajectory = Trajectory::builder()
+///     .format("LAMMPS Data")
+///     .topology_file("topol.pdb")
+///     .cell(&UnitCell::new([20.0, 20.0, 20.0]))
+///     .open("system.data")
+///     .unwrap();
+///
+/// let mut frame = Frame::new();
+/// trajectory.read(&mut frame).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TrajectoryBuilder {
+    mode: OpenMode,
+    format: String,
+    topology: Option<TopologySource>,
+    cell: Option<UnitCell>,
+    cancel: Option<CancelToken>,
+    error_policy: ErrorPolicy,
+}
+
+impl Default for TrajectoryBuilder {
+    fn default() -> TrajectoryBuilder {
+        TrajectoryBuilder {
+            mode: OpenMode::Read,
+            format: String::new(),
+            topology: None,
+            cell: None,
+            cancel: None,
+            error_policy: ErrorPolicy::Abort,
+        }
+    }
+}
+
+impl TrajectoryBuilder {
+    /// Set the `mode` used to open the trajectory. The default is to open
+    /// the file for reading.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Frame, OpenMode, Trajectory};
+    /// let mut trajectory = Trajectory::builder()
+    ///     .mode(OpenMode::Write)
+    ///     .open("output.xyz")
+    ///     .unwrap();
+    /// trajectory.write(&Frame::new()).unwrap();
+    /// ```
+    #[must_use]
+    pub fn mode(mut self, mode: OpenMode) -> TrajectoryBuilder {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the file `format` of the trajectory. If this is not called or if
+    /// `format` is an empty string, the format will be guessed from the
+    /// extension.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let trajectory = Trajectory::builder().format("XYZ").open("water.zeo").unwrap();
+    /// ```
+    #[must_use]
+    pub fn format(mut self, format: &str) -> TrajectoryBuilder {
+        self.format = format.into();
+        self
+    }
+
+    /// Use the given `topology` when reading and writing the trajectory,
+    /// replacing any topology in the frames or files. This replaces any
+    /// topology previously set on this builder.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Atom, Topology, Trajectory};
+    /// let mut topology = Topology::new();
+    /// topology.add_atom(&Atom::new("Cs"));
+    ///
+    /// let trajectory = Trajectory::builder().topology(&topology).open("cesium.xyz").unwrap();
+    /// ```
+    #[must_use]
+    pub fn topology(mut self, topology: &Topology) -> TrajectoryBuilder {
+        self.topology = Some(TopologySource::Topology(topology.clone()));
+        self
+    }
+
+    /// Use the topology of the first frame of the file at `path` when reading
+    /// and writing the trajectory. The format of the topology file is guessed
+    /// from its extension. This replaces any topology previously set on this
+    /// builder.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let trajectory = Trajectory::builder().topology_file("topology.pdb").open("water.nc").unwrap();
+    /// ```
+    #[must_use]
+    pub fn topology_file<P: AsRef<Path>>(self, path: P) -> TrajectoryBuilder {
+        self.topology_with_format(path, "")
+    }
+
+    /// Use the topology of the first frame of the file at `path`, read with
+    /// the given `format`, when reading and writing the trajectory. If
+    /// `format` is an empty string, the format will be guessed from the
+    /// extension. This replaces any topology previously set on this builder.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let trajectory = Trajectory::builder()
+    ///     .topology_with_format("topology.mol", "PDB")
+    ///     .open("water.nc")
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn topology_with_format<P: AsRef<Path>>(mut self, path: P, format: &str) -> TrajectoryBuilder {
+        self.topology = Some(TopologySource::File(path.as_ref().to_path_buf(), format.into()));
+        self
+    }
+
+    /// Use the given unit `cell` when reading and writing the trajectory,
+    /// replacing any unit cell in the frames or files.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Trajectory, UnitCell};
+    /// let trajectory = Trajectory::builder()
+    ///     .cell(&UnitCell::new([10.0, 11.0, 12.5]))
+    ///     .open("water.xyz")
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn cell(mut self, cell: &UnitCell) -> TrajectoryBuilder {
+        self.cell = Some(cell.clone());
+        self
+    }
+
+    /// Use the given cancellation `token` for the trajectory, see
+    /// `Trajectory::set_cancel_token` for more information.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{CancelToken, Trajectory};
+    /// let token = CancelToken::new();
+    /// let trajectory = Trajectory::builder().cancel_token(token.clone()).open("water.xyz").unwrap();
+    /// ```
+    #[must_use]
+    pub fn cancel_token(mut self, token: CancelToken) -> TrajectoryBuilder {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Use the given `policy` when frames can not be read, see
+    /// `Trajectory::set_error_policy` for more information.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{ErrorPolicy, Trajectory};
+    /// let trajectory = Trajectory::builder().on_error(ErrorPolicy::Skip).open("crashed.xyz").unwrap();
+    /// ```
+    #[must_use]
+    pub fn on_error(mut self, policy: ErrorPolicy) -> TrajectoryBuilder {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Open the file at the given `path` with the settings of this builder.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the file is not accessible for the given mode,
+    /// if it is incorrectly formatted for the corresponding format, if the
+    /// topology file can not be read, or in case of I/O errors from the OS.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{Trajectory, UnitCell};
+    /// let trajectory = Trajectory::builder()
+    ///     .format("XYZ")
+    ///     .cell(&UnitCell::new([10.0, 10.0, 10.0]))
+    ///     .open("water.txt")
+    ///     .unwrap();
+    /// ```
+    pub fn open<P: AsRef<Path>>(self, path: P) -> Result<Trajectory, Error> {
+        let mut trajectory = Trajectory::open_with_format(path, self.mode.as_char(), self.format.as_str())?;
+
+        match self.topology {
+            Some(TopologySource::Topology(ref topology)) => trajectory.set_topology(topology),
+            Some(TopologySource::File(ref path, ref format)) => {
+                trajectory.set_topology_with_format(path, format.as_str())?;
+            }
+            None => {}
+        }
+
+        if let Some(ref cell) = self.cell {
+            trajectory.set_cell(cell);
+        }
+
+        if let Some(token) = self.cancel {
+            trajectory.set_cancel_token(token);
+        }
+        trajectory.set_error_policy(self.error_policy);
+
+        return Ok(trajectory);
+    }
+}
+
+/// An iterator over the frames of a `Trajectory`, created by
+/// `Trajectory::frames`.
+#[derive(Debug)]
+pub struct Frames<'a> {
+    trajectory: &'a mut Trajectory,
+    step: usize,
+    nsteps: usize,
+    /// Error from `Trajectory::try_nsteps`, yielded by the first call to `next`
+    error: Option<Error>,
+}
+
+/// Get the number of steps in `trajectory` for the frames iterators. If it
+/// can not be determined, the iterator should yield the returned error and
+/// then stop.
+fn count_steps(trajectory: &mut Trajectory) -> (usize, Option<Error>) {
+    match trajectory.try_nsteps() {
+        Ok(nsteps) => (nsteps, None),
+        Err(error) => (0, Some(error)),
+    }
+}
+
+/// Get the size hint for the frames iterators. With `ErrorPolicy::Skip`,
+/// steps which can not be read are not yielded, so the number of steps is
+/// only an upper bound.
+fn frames_size_hint(trajectory: &Trajectory, step: usize, nsteps: usize, has_error: bool) -> (usize, Option<usize>) {
+    let remaining = nsteps.saturating_sub(step) + usize::from(has_error);
+    if trajectory.error_policy == ErrorPolicy::Skip {
+        return (0, Some(remaining));
+    }
+    return (remaining, Some(remaining));
+}
+
+/// Read the frame at `step` in `trajectory` for the frames iterators, and
+/// advance `step`. This returns `None` once `step` reaches `nsteps`.
+fn next_frame(trajectory: &mut Trajectory, step: &mut usize, nsteps: usize) -> Option<Result<Frame, Error>> {
+    let mut frame = Frame::new();
+    let result = read_next_step(trajectory, step, nsteps, &mut frame)?;
+    return Some(result.map(|()| frame));
+}
+
+/// Same as `next_frame`, reading the data in an existing `frame`.
+fn read_next_step(
+    trajectory: &mut Trajectory,
+    step: &mut usize,
+    nsteps: usize,
+    frame: &mut Frame,
+) -> Option<Result<(), Error>> {
+    loop {
+        if *step >= nsteps {
+            return None;
+        }
+
+        let current = *step;
+        let result = trajectory.read_step(current, frame);
+        *step += 1;
+
+        if let Err(ref error) = result {
+            if error.status == Status::Cancelled {
+                // stop iterating after a cancellation
+                *step = nsteps;
+            } else if trajectory.error_policy == ErrorPolicy::Skip {
+                trajectory.skip_step(current, error);
+                continue;
+            }
+        }
+        return Some(result);
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        next_frame(self.trajectory, &mut self.step, self.nsteps)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        frames_size_hint(self.trajectory, self.step, self.nsteps, self.error.is_some())
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.step = self.step.saturating_add(n);
+        self.next()
+    }
+}
+
+/// A lending iterator over the frames of a `Trajectory`, reading all steps
+/// in the same `Frame`. This is created by `Trajectory::buffered_frames`.
+///
+/// This can not implement `Iterator`, since the frames borrow from the
+/// iterator itself. Use `BufferedFrames::next_frame` in a `while let` loop
+/// instead.
+#[derive(Debug)]
+pub struct BufferedFrames<'a> {
+    trajectory: &'a mut Trajectory,
+    frame: Frame,
+    step: usize,
+    nsteps: usize,
+    error: Option<Error>,
+}
+
+impl BufferedFrames<'_> {
+    /// Read the next step of the trajectory, and get a reference to the
+    /// corresponding frame. This returns `None` once all steps have been
+    /// read.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::Trajectory;
+    /// let mut trajectory = Trajectory::open("water.xyz", 'r').unwrap();
+    /// let mut frames = trajectory.buffered_frames();
+    ///
+    /// let first = frames.next_frame().unwrap().unwrap();
+    /// assert_eq!(first.step(), 0);
+    /// ```
+    pub fn next_frame(&mut self) -> Option<Result<&Frame, Error>> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        let result = read_next_step(self.trajectory, &mut self.step, self.nsteps, &mut self.frame)?;
+        return Some(result.map(|()| &self.frame));
+    }
+
+    /// Get the bounds on the number of frames remaining in this iterator,
+    /// with the same meaning as `Iterator::size_hint`.
+    pub fn size_hint(&self) -> (usize, Option<usize>) {
+        frames_size_hint(self.trajectory, self.step, self.nsteps, self.error.is_some())
+    }
+}
+
+/// An iterator over the frames of a `Trajectory`, taking ownership of the
+/// trajectory. This is created by the `IntoIterator` implementation of
+/// `Trajectory`, and behaves like `Frames`.
+#[derive(Debug)]
+pub struct IntoFrames {
+    trajectory: Trajectory,
+    step: usize,
+    nsteps: usize,
+    error: Option<Error>,
+}
+
+impl Iterator for IntoFrames {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        next_frame(&mut self.trajectory, &mut self.step, self.nsteps)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        frames_size_hint(&self.trajectory, self.step, self.nsteps, self.error.is_some())
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.step = self.step.saturating_add(n);
+        self.next()
+    }
+}
+
+impl IntoIterator for Trajectory {
+    type Item = Result<Frame, Error>;
+    type IntoIter = IntoFrames;
+
+    /// Iterate over all the frames in this trajectory, see
+    /// `Trajectory::frames` for more information.
+    fn into_iter(mut self) -> IntoFrames {
+        let (nsteps, error) = count_steps(&mut self);
+        IntoFrames {
+            trajectory: self,
+            step: 0,
+            nsteps,
+            error,
+        }
+    }
+}
+
+impl Extend<Frame> for Trajectory {
+    /// Write all the frames from `iter` to this trajectory. See
+    /// `Trajectory::extend_results` for a version of this function returning
+    /// errors instead of panicking.
+    ///
+    /// # Panics
+    ///
+    /// If any of the frames can not be written.
+    fn extend<I: IntoIterator<Item = Frame>>(&mut self, iter: I) {
+        for frame in iter {
+            if let Err(error) = self.write(&frame) {
+                panic!("failed to write frame: {error}");
+            }
+        }
+    }
+}
+
+impl<'a> Extend<&'a Frame> for Trajectory {
+    /// Write all the frames from `iter` to this trajectory.
+    ///
+    /// # Panics
+    ///
+    /// If any of the frames can not be written.
+    fn extend<I: IntoIterator<Item = &'a Frame>>(&mut self, iter: I) {
+        for frame in iter {
+            if let Err(error) = self.write(frame) {
+                panic!("failed to write frame: {error}");
+            }
+        }
+    }
+}
+
+/// An iterator over the frames of a `Trajectory` mapped into a reference unit
+/// cell, created by `Trajectory::rescale_to_reference_cell`.
+#[derive(Debug)]
+pub struct RescaledFrames<'a> {
+    frames: Frames<'a>,
+    reference: UnitCell,
+}
 
-        let string = std::str::from_utf8(buffer)?;
-        Ok(string)
+impl RescaledFrames<'_> {
+    /// Map the positions of the frame in `result` into the reference cell,
+    /// keeping fractional coordinates constant
+    fn rescale(&self, result: Result<Frame, Error>) -> Result<Frame, Error> {
+        let mut frame = result?;
+        let inverse = linalg::inverse(&frame.cell().matrix()).ok_or_else(|| {
+            Error::new(
+                Status::ChemfilesError,
+                format!(
+                    "can not rescale frame at step {}: the unit cell is degenerated",
+                    frame.step()
+                ),
+            )
+        })?;
+
+        let reference = self.reference.matrix();
+        for position in frame.positions_mut() {
+            *position = linalg::mat_vec(&reference, linalg::mat_vec(&inverse, *position));
+        }
+        frame.set_cell(&self.reference);
+        return Ok(frame);
+    }
+}
+
+impl Iterator for RescaledFrames<'_> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.frames.next()?;
+        return Some(self.rescale(result));
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.frames.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let result = self.frames.nth(n)?;
+        return Some(self.rescale(result));
+    }
+}
+
+/// An iterator over the unwrapped frames of a `Trajectory`, created by
+/// `Trajectory::unwrapped_frames`.
+#[derive(Debug)]
+pub struct UnwrappedFrames<'a> {
+    frames: Frames<'a>,
+    unwrapper: Unwrapper,
+}
+
+impl Iterator for UnwrappedFrames<'_> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.frames.next()?;
+        return Some(result.and_then(|mut frame| {
+            self.unwrapper.unwrap(&mut frame)?;
+            Ok(frame)
+        }));
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.frames.size_hint()
+    }
+}
+
+/// A `Trajectory` protected by a mutex, which can be shared between threads.
+///
+/// `Trajectory` can be sent to another thread, but not shared between
+/// threads since reading frames changes the state of the underlying file.
+/// `SyncTrajectory` locks the trajectory for each operation, allowing
+/// multiple worker threads to read frames from the same file on demand.
+///
+/// # Example
+/// ```no_run
+/// # use chemfiles::{SyncTrajectory, Trajectory};
+/// let trajectory = SyncTrajectory::new(Trajectory::open("water.xyz", 'r').unwrap());
+/// let nsteps = trajectory.nsteps().unwrap();
+///
+/// std::thread::scope(|scope| {
+///     for worker in 0..4 {
+///         let trajectory = &trajectory;
+///         scope.spawn(move || {
+///             for step in (worker..nsteps).step_by(4) {
+///                 let frame = trajectory.read_step(step).unwrap();
+///                 // ...
+///             }
+///         });
+///     }
+/// });
+/// ```
+#[derive(Debug)]
+pub struct SyncTrajectory {
+    inner: Mutex<Trajectory>,
+}
+
+impl SyncTrajectory {
+    /// Create a new `SyncTrajectory` wrapping the given `trajectory`.
+    pub fn new(trajectory: Trajectory) -> SyncTrajectory {
+        SyncTrajectory {
+            inner: Mutex::new(trajectory),
+        }
+    }
+
+    /// Read the next step of the trajectory into a new frame. When multiple
+    /// threads are reading with this function, each step is read by exactly
+    /// one of the threads.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the data is incorrectly formatted for the
+    /// corresponding format, or in case of I/O errors from the OS.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{SyncTrajectory, Trajectory};
+    /// let trajectory = SyncTrajectory::new(Trajectory::open("water.xyz", 'r').unwrap());
+    /// std::thread::scope(|scope| {
+    ///     for _ in 0..4 {
+    ///         scope.spawn(|| {
+    ///             while let Ok(frame) = trajectory.read() {
+    ///                 // ...
+    ///             }
+    ///         });
+    ///     }
+    /// });
+    /// ```
+    pub fn read(&self) -> Result<Frame, Error> {
+        let mut frame = Frame::new();
+        self.lock().read(&mut frame)?;
+        return Ok(frame);
     }
 
-    /// Get file path for this trajectory.
+    /// Read a specific `step` of the trajectory into a new frame.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the data is incorrectly formatted for the
+    /// corresponding format, or in case of I/O errors from the OS.
     ///
     /// # Example
     /// ```no_run
-    /// # use chemfiles::Trajectory;
-    /// let trajectory = Trajectory::open("water.xyz", 'r').unwrap();
+    /// # use chemfiles::{SyncTrajectory, Trajectory};
+    /// let trajectory = SyncTrajectory::new(Trajectory::open("water.xyz", 'r').unwrap());
+    /// let frame = trajectory.read_step(10).unwrap();
+    /// ```
+    pub fn read_step(&self, step: usize) -> Result<Frame, Error> {
+        let mut frame = Frame::new();
+        self.lock().read_step(step, &mut frame)?;
+        return Ok(frame);
+    }
+
+    /// Get the number of steps (the number of frames) in the trajectory.
     ///
-    /// assert_eq!(trajectory.path(), "water.xyz");
+    /// # Errors
+    ///
+    /// This function fails if the number of steps can not be determined,
+    /// see `Trajectory::try_nsteps`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{SyncTrajectory, Trajectory};
+    /// let trajectory = SyncTrajectory::new(Trajectory::open("water.xyz", 'r').unwrap());
+    /// let nsteps = trajectory.nsteps().unwrap();
     /// ```
-    pub fn path(&self) -> String {
-        let get_string = |ptr, len| unsafe { ffi::chfl_trajectory_path(self.as_ptr(), ptr, len) };
-        let path = strings::call_autogrow_buffer(1024, get_string).expect("failed to get path string");
-        return strings::from_c(path.as_ptr());
+    pub fn nsteps(&self) -> Result<usize, Error> {
+        self.lock().try_nsteps()
+    }
+
+    /// Lock the trajectory for exclusive access from the current thread,
+    /// until the returned guard is dropped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{SyncTrajectory, Trajectory};
+    /// let trajectory = SyncTrajectory::new(Trajectory::open("water.xyz", 'r').unwrap());
+    /// for frame in trajectory.lock().frames() {
+    ///     let frame = frame.unwrap();
+    /// }
+    /// ```
+    pub fn lock(&self) -> MutexGuard<'_, Trajectory> {
+        // a panic while the lock is held does not leave the trajectory in
+        // an invalid state
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Get back the wrapped `Trajectory`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use chemfiles::{SyncTrajectory, Trajectory};
+    /// let trajectory = SyncTrajectory::new(Trajectory::open("water.xyz", 'r').unwrap());
+    /// let trajectory: Trajectory = trajectory.into_inner();
+    /// ```
+    pub fn into_inner(self) -> Trajectory {
+        self.inner.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl From<Trajectory> for SyncTrajectory {
+    fn from(trajectory: Trajectory) -> SyncTrajectory {
+        SyncTrajectory::new(trajectory)
     }
 }
 
 /// `MemoryTrajectoryReader` is a handle for a `Trajectory` in memory.
+#[derive(Debug)]
 pub struct MemoryTrajectoryReader<'data> {
     inner: Trajectory,
     phantom: std::marker::PhantomData<&'data [u8]>,
@@ -443,11 +2092,13 @@ impl<'data> MemoryTrajectoryReader<'data> {
         Data: Into<&'data [u8]>,
         Format: AsRef<str>,
     {
+        install_log_bridge();
         let data = data.into();
+        let format_name = Some(format.as_ref().to_owned());
         let format = strings::to_c(format.as_ref());
         let trajectory = unsafe {
             let handle = ffi::chfl_trajectory_memory_reader(data.as_ptr().cast(), data.len() as u64, format.as_ptr());
-            Trajectory::from_ptr(handle)
+            Trajectory::from_ptr(handle, format_name, None)
         };
         Ok(MemoryTrajectoryReader {
             inner: trajectory?,
@@ -498,6 +2149,7 @@ mod test {
         }
 
         assert_eq!(file.nsteps(), 100);
+        assert_eq!(file.try_nsteps().unwrap(), 100);
 
         let mut frame = Frame::new();
         assert!(file.read(&mut frame).is_ok());
@@ -560,6 +2212,31 @@ mod test {
         assert_eq!(frame.size(), 125);
     }
 
+    #[test]
+    fn builder() {
+        let root = Path::new(file!()).parent().unwrap().join("..");
+        let mut file = Trajectory::builder()
+            .topology_file(root.join("data").join("topology.xyz"))
+            .cell(&UnitCell::new([30.0, 30.0, 30.0]))
+            .open(root.join("data").join("water.xyz"))
+            .unwrap();
+
+        let mut frame = Frame::new();
+        assert!(file.read(&mut frame).is_ok());
+        assert_eq!(frame.atom(100).name(), "Rd");
+        assert_eq!(frame.cell().lengths(), [30.0, 30.0, 30.0]);
+
+        let filename = root.join("data").join("helium.xyz.but.not.really");
+        let mut file = Trajectory::builder().format("XYZ").open(filename).unwrap();
+        assert!(file.read(&mut frame).is_ok());
+        assert_eq!(frame.size(), 125);
+
+        let result = Trajectory::builder()
+            .topology_file(root.join("data").join("not-here.xyz"))
+            .open(root.join("data").join("water.xyz"));
+        assert!(result.is_err());
+    }
+
     fn write_file<P>(path: P)
     where
         P: AsRef<Path>,
@@ -581,6 +2258,73 @@ mod test {
         assert!(file.write(&frame).is_ok());
     }
 
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn par_map() {
+        let mut file = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let oxygens = file
+            .par_map(|frame| frame.iter_atoms().filter(|atom| atom.name() == "O").count())
+            .unwrap();
+        assert_eq!(oxygens, vec![99; 100]);
+
+        let steps = file.par_map(|frame| frame.positions()[0]).unwrap();
+        let mut frame = Frame::new();
+        for (step, position) in steps.iter().enumerate() {
+            file.read_step(step, &mut frame).unwrap();
+            assert_eq!(frame.positions()[0], *position);
+        }
+    }
+
+    #[test]
+    fn property_series() {
+        let mut file = Trajectory::memory_writer("XYZ").unwrap();
+        for step in 0..3 {
+            let mut frame = Frame::new();
+            frame.add_atom(&Atom::new("Ar"), [0.0; 3], None);
+            if step != 1 {
+                frame.set("energy", 10.0 * f64::from(step));
+            }
+            frame.set("name", format!("step {step}").as_str());
+            file.write(&frame).unwrap();
+        }
+
+        let data = file.memory_buffer().unwrap().to_owned();
+        let mut file = Trajectory::memory_reader(&data, "XYZ").unwrap();
+        let energies = file.property_series("energy").unwrap();
+        assert_eq!(
+            energies,
+            [Some(Property::Double(0.0)), None, Some(Property::Double(20.0))]
+        );
+
+        let names = file.property_series("name").unwrap();
+        assert_eq!(names[2], Some(Property::String("step 2".into())));
+        assert_eq!(file.property_series("missing").unwrap(), [None, None, None]);
+    }
+
+    #[test]
+    fn read_at() {
+        let mut file = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let mut frame = Frame::new();
+
+        file.read_at(41, &mut frame).unwrap();
+        assert_ulps_eq!(frame.positions()[0][0], 0.761277);
+
+        let mut other = Frame::new();
+        file.read_at(-59, &mut other).unwrap();
+        assert_eq!(other.positions(), frame.positions());
+
+        file.read_step(99, &mut frame).unwrap();
+        file.read_last(&mut other).unwrap();
+        assert_eq!(other.positions(), frame.positions());
+        file.read_at(0, &mut other).unwrap();
+        assert_ulps_eq!(other.positions()[0][0], 0.417219);
+
+        let error = file.read_at(100, &mut frame).unwrap_err();
+        assert_eq!(error.status, Status::OutOfBounds);
+        assert_eq!(error.message, "can not read step 100 in a trajectory with 100 steps");
+        assert!(file.read_at(-101, &mut frame).is_err());
+    }
+
     #[test]
     fn write() {
         let filename = "test-tmp.xyz";
@@ -603,6 +2347,56 @@ X 1 2 3"
         std::fs::remove_file(filename).unwrap();
     }
 
+    #[test]
+    fn close() {
+        let filename = "test-close-tmp.xyz";
+        let mut file = Trajectory::open(filename, 'w').unwrap();
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+        file.write(&frame).unwrap();
+        file.close().unwrap();
+
+        let mut file = Trajectory::open(filename, 'r').unwrap();
+        assert_eq!(file.nsteps(), 1);
+        file.close().unwrap();
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn close_error() {
+        // writing to /dev/full always fails with "no space left on device",
+        // but the small frame stays in the buffer until the file is closed
+        let mut file = Trajectory::open_with_format("/dev/full", 'w', "XYZ").unwrap();
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+        file.write(&frame).unwrap();
+
+        let error = file.close().unwrap_err();
+        assert!(!error.message.is_empty());
+    }
+
+    #[test]
+    fn append() {
+        let filename = "test-tmp-append.xyz";
+        write_file(filename);
+
+        let mut file = Trajectory::open_with(filename, OpenMode::Append).unwrap();
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Zn"), [0.0; 3], None);
+        file.write(&frame).unwrap();
+        std::mem::drop(file);
+
+        let mut file = Trajectory::open_with(filename, OpenMode::Read).unwrap();
+        assert_eq!(file.nsteps(), 2);
+        file.read_step(1, &mut frame).unwrap();
+        assert_eq!(frame.atom(0).name(), "Zn");
+        std::mem::drop(file);
+
+        assert_eq!(char::from(OpenMode::Write), 'w');
+        std::fs::remove_file(filename).unwrap();
+    }
+
     #[test]
     fn memory() {
         // formats in decreasing order of their memory buffer length to check null termination
@@ -629,4 +2423,434 @@ X 1 2 3"
             crate::assert_vector3d_eq(&frame_read.positions()[2], &[-1.5, 10.0, 0.0], 1e-4);
         }
     }
+
+    #[test]
+    fn frames() {
+        let mut trajectory = Trajectory::open("src/../data/water.xyz", 'r').unwrap();
+
+        let frames = trajectory.frames();
+        assert_eq!(frames.size_hint(), (100, Some(100)));
+
+        let steps = trajectory
+            .frames()
+            .skip(2)
+            .step_by(40)
+            .map(|frame| frame.unwrap().step())
+            .collect::<Vec<_>>();
+        assert_eq!(steps, vec![2, 42, 82]);
+
+        let frame = trajectory.frames().last().unwrap().unwrap();
+        assert_eq!(frame.size(), 297);
+        assert_eq!(frame.step(), 99);
+        assert!(trajectory.frames().nth(100).is_none());
+    }
+
+    #[test]
+    fn buffered_frames() {
+        let mut trajectory = Trajectory::open("src/../data/water.xyz", 'r').unwrap();
+
+        let mut frames = trajectory.buffered_frames();
+        assert_eq!(frames.size_hint(), (100, Some(100)));
+
+        let mut steps = Vec::new();
+        while let Some(frame) = frames.next_frame() {
+            let frame = frame.unwrap();
+            assert_eq!(frame.size(), 297);
+            steps.push(frame.step());
+        }
+        assert_eq!(frames.size_hint(), (0, Some(0)));
+        assert_eq!(steps, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn extend() {
+        let input = Trajectory::open("src/../data/water.xyz", 'r').unwrap();
+        let mut output = Trajectory::memory_writer("XYZ").unwrap();
+        assert_eq!(output.extend_results(input.into_iter().take(3)).unwrap(), 3);
+
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+        output.extend(vec![frame.clone()]);
+        output.extend(&[frame]);
+
+        let data = output.memory_buffer().unwrap().to_owned();
+        let mut input = Trajectory::memory_reader(&data, "XYZ").unwrap();
+        let mut count = 0;
+        for frame in input.frames() {
+            let frame = frame.unwrap();
+            assert!(frame.size() == 297 || frame.size() == 1);
+            count += 1;
+        }
+        assert_eq!(count, 5);
+
+        let error = Error::new(Status::FileError, "bad frame");
+        assert!(output.extend_results(vec![Err(error)]).is_err());
+    }
+
+    #[test]
+    fn memory_writer() {
+        let mut trajectory = Trajectory::memory_writer("XYZ").unwrap();
+        assert_eq!(trajectory.memory_bytes().unwrap(), b"");
+
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+        trajectory.write(&frame).unwrap();
+
+        let expected = "1\nProperties=species:S:1:pos:R:3\nZn 1 2 3\n";
+        assert_eq!(trajectory.memory_buffer().unwrap(), expected);
+        assert_eq!(trajectory.memory_bytes().unwrap(), expected.as_bytes());
+        assert_eq!(trajectory.into_bytes().unwrap(), expected.as_bytes());
+
+        let trajectory = Trajectory::open("src/../data/water.xyz", 'r').unwrap();
+        assert!(trajectory.into_bytes().is_err());
+    }
+
+    #[test]
+    fn rescale_to_reference_cell() {
+        let data = String::from(concat!(
+            "1\nLattice=\"10 0 0 0 10 0 0 0 10\"\nZn 5 5 5\n",
+            "1\nLattice=\"20 0 0 0 20 0 0 0 20\"\nZn 5 10 15\n",
+            "1\n\nZn 1 2 3\n",
+        ));
+        let mut trajectory = Trajectory::memory_reader(&data, "XYZ").unwrap();
+
+        let reference = UnitCell::new([10.0, 10.0, 10.0]);
+        let frames = trajectory.rescale_to_reference_cell(&reference).collect::<Vec<_>>();
+        assert_eq!(frames.len(), 3);
+
+        let frame = frames[0].as_ref().unwrap();
+        assert_eq!(frame.positions(), [[5.0, 5.0, 5.0]]);
+
+        let frame = frames[1].as_ref().unwrap();
+        assert_ulps_eq!(frame.positions()[0][0], 2.5);
+        assert_ulps_eq!(frame.positions()[0][1], 5.0);
+        assert_ulps_eq!(frame.positions()[0][2], 7.5);
+        assert_eq!(frame.cell().lengths(), [10.0, 10.0, 10.0]);
+
+        // infinite cells do not define fractional coordinates
+        assert!(frames[2].is_err());
+    }
+
+    #[test]
+    fn unwrapped_frames() {
+        let data = String::from(concat!(
+            "1\nLattice=\"10 0 0 0 10 0 0 0 10\"\nZn 9 5 5\n",
+            "1\nLattice=\"10 0 0 0 10 0 0 0 10\"\nZn 1 5 5\n",
+            "1\nLattice=\"10 0 0 0 10 0 0 0 10\"\nZn 3 5 5\n",
+            "1\n\nZn 1 2 3\n",
+        ));
+        let mut trajectory = Trajectory::memory_reader(&data, "XYZ").unwrap();
+
+        let frames = trajectory.unwrapped_frames().collect::<Vec<_>>();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].as_ref().unwrap().positions(), [[9.0, 5.0, 5.0]]);
+        assert_eq!(frames[1].as_ref().unwrap().positions(), [[11.0, 5.0, 5.0]]);
+        assert_eq!(frames[2].as_ref().unwrap().positions(), [[13.0, 5.0, 5.0]]);
+        assert!(frames[3].is_err());
+    }
+
+    #[test]
+    fn memory_reader_compressed() {
+        let error = Trajectory::memory_reader_compressed("1\n\nZn 1 2 3\n", "XYZ").unwrap_err();
+        assert_eq!(error.status, Status::FormatError);
+
+        // "1\n\nZn 1 2 3\n", compressed with gzip
+        let data = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x33, 0xe4, 0xe2, 0x8a, 0xca, 0x53, 0x30, 0x54,
+            0x30, 0x52, 0x30, 0xe6, 0x02, 0x00, 0x5c, 0x35, 0x42, 0x94, 0x0c, 0x00, 0x00, 0x00,
+        ];
+        let mut trajectory = Trajectory::memory_reader_compressed(&data, "XYZ").unwrap();
+        let mut frame = Frame::new();
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.positions(), [[1.0, 2.0, 3.0]]);
+    }
+
+    #[test]
+    fn memory_reader() {
+        let data = String::from("1\n\nZn 1 2 3\n1\n\nZn 4 5 6\n");
+        let mut trajectory = Trajectory::memory_reader(&data, "XYZ").unwrap();
+        assert_eq!(trajectory.nsteps(), 2);
+
+        let mut frame = Frame::new();
+        trajectory.read_step(1, &mut frame).unwrap();
+        assert_eq!(frame.atom(0).name(), "Zn");
+        assert_eq!(frame.positions()[0], [4.0, 5.0, 6.0]);
+
+        let bytes: &[u8] = data.as_bytes();
+        let trajectory = Trajectory::memory_reader(bytes, "XYZ").unwrap();
+        assert_eq!(trajectory.path(), "");
+
+        let error = Trajectory::memory_reader(&data, "not a format").unwrap_err();
+        assert_eq!(error.status, Status::FileError);
+    }
+
+    #[test]
+    fn cancel() {
+        let mut trajectory = Trajectory::open("src/../data/water.xyz", 'r').unwrap();
+        let token = CancelToken::new();
+        trajectory.set_cancel_token(token.clone());
+
+        let mut count = 0;
+        let mut cancelled = false;
+        for frame in trajectory.frames() {
+            match frame {
+                Ok(_) => count += 1,
+                Err(error) => {
+                    assert_eq!(error.status, Status::Cancelled);
+                    cancelled = true;
+                }
+            }
+            if count == 10 {
+                token.cancel();
+            }
+        }
+        assert_eq!(count, 10);
+        assert!(cancelled);
+
+        let mut output = Trajectory::memory_writer("XYZ").unwrap();
+        output.set_cancel_token(token);
+        assert!(output.write(&Frame::new()).is_err());
+    }
+
+    #[test]
+    fn progress() {
+        use crate::analysis::Progress;
+        use std::sync::{Arc, Mutex};
+
+        let mut trajectory = Trajectory::open("src/../data/water.xyz", 'r').unwrap();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&reports);
+        trajectory.set_progress_callback(30, move |progress: &Progress| {
+            collected.lock().unwrap().push((progress.frames, progress.total));
+        });
+
+        // failed reads are not counted
+        let mut frame = Frame::new();
+        assert!(trajectory.read_step(200, &mut frame).is_err());
+        for frame in trajectory.frames() {
+            let _ = frame.unwrap();
+        }
+
+        let reports = reports.lock().unwrap().clone();
+        assert_eq!(
+            reports,
+            [(30, Some(100)), (60, Some(100)), (90, Some(100)), (100, Some(100))]
+        );
+
+        trajectory.remove_progress_callback();
+        trajectory.read_step(0, &mut frame).unwrap();
+    }
+
+    #[test]
+    fn error_policy() {
+        let data = "1\n\nO 0 0 0\n1\n\nO 0 0 xxx\n1\n\nO 0 0 2\n";
+
+        let mut trajectory = Trajectory::memory_reader(data, "XYZ").unwrap();
+        assert_eq!(trajectory.error_policy(), ErrorPolicy::Abort);
+        let frames = trajectory.frames().collect::<Vec<_>>();
+        assert_eq!(frames.len(), 3);
+        assert!(frames[1].is_err());
+        assert!(trajectory.skipped_steps().is_empty());
+
+        let mut trajectory = Trajectory::memory_reader(data, "XYZ").unwrap();
+        trajectory.set_error_policy(ErrorPolicy::Skip);
+        assert_eq!(trajectory.frames().size_hint(), (0, Some(3)));
+        let frames = trajectory.frames().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].positions(), [[0.0, 0.0, 2.0]]);
+        assert_eq!(trajectory.skipped_steps(), [1]);
+
+        let mut trajectory = Trajectory::memory_reader(data, "XYZ").unwrap();
+        trajectory.set_error_policy(ErrorPolicy::Skip);
+        let mut frame = Frame::new();
+        trajectory.read(&mut frame).unwrap();
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.positions(), [[0.0, 0.0, 2.0]]);
+        assert!(trajectory.read(&mut frame).is_err());
+        // reading a specific step never skips
+        assert!(trajectory.read_step(1, &mut frame).is_err());
+        assert_eq!(trajectory.skipped_steps(), [1]);
+
+        // truncated final frame, which can prevent counting the steps
+        let data = "1\n\nO 0 0 0\n1\n\nO 0 0 1\n2\n\nO 0 0 2\n";
+        let mut trajectory = Trajectory::memory_reader(data, "XYZ").unwrap();
+        trajectory.set_error_policy(ErrorPolicy::Skip);
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.positions(), [[0.0, 0.0, 0.0]]);
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.positions(), [[0.0, 0.0, 1.0]]);
+        assert!(trajectory.read(&mut frame).is_err());
+        assert!(trajectory.skipped_steps().is_empty());
+    }
+
+    #[test]
+    fn frames_nsteps_error() {
+        // the number of atoms in the second frame can not be parsed
+        let data = "1\n\nO 0 0 0\nnot a number\n\nO 0 0 1\n";
+
+        let mut trajectory = Trajectory::memory_reader(data, "XYZ").unwrap();
+        assert!(trajectory.try_nsteps().is_err());
+
+        let mut frames = trajectory.frames();
+        assert_eq!(frames.size_hint(), (1, Some(1)));
+        assert!(frames.next().unwrap().is_err());
+        assert!(frames.next().is_none());
+
+        let mut trajectory = Trajectory::memory_reader(data, "XYZ").unwrap();
+        assert!(trajectory.frames().collect::<Result<Vec<_>, _>>().is_err());
+        assert!(crate::analysis::cell_series(&mut trajectory).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"test-tmp-\xff.xyz"));
+        assert!(path.to_str().is_none());
+
+        let mut frame = Frame::new();
+        frame.add_atom(&Atom::new("Zn"), [1.0, 2.0, 3.0], None);
+        {
+            let mut trajectory = Trajectory::open(path, 'w').unwrap();
+            trajectory.write(&frame).unwrap();
+        }
+
+        let mut trajectory = Trajectory::open(path, 'r').unwrap();
+        assert_eq!(trajectory.format, None);
+        trajectory.read(&mut frame).unwrap();
+        assert_eq!(frame.positions(), [[1.0, 2.0, 3.0]]);
+        assert_eq!(trajectory.last_read_report().unwrap().format, "XYZ");
+        drop(trajectory);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn non_utf8_path_errors() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let missing = Path::new(OsStr::from_bytes(b"not-here-\xff.xyz"));
+        let error = Trajectory::open(missing, 'r').unwrap_err();
+        assert_eq!(error.status, Status::FileError);
+        assert!(!error.message.is_empty());
+
+        let unknown = Path::new(OsStr::from_bytes(b"file-\xff.unknown"));
+        assert!(crate::guess_format(unknown).is_err());
+
+        let path = Path::new(OsStr::from_bytes(b"test-tmp-path-\xff.xyz"));
+        let trajectory = Trajectory::open(path, 'w').unwrap();
+        assert_eq!(trajectory.path(), "test-tmp-path-\u{fffd}.xyz");
+        drop(trajectory);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+
+        assert_send::<Trajectory>();
+        assert_send::<MemoryTrajectoryReader<'_>>();
+        assert_send::<Frame>();
+        assert_send::<Topology>();
+        assert_send::<crate::Selection>();
+
+        assert_sync::<SyncTrajectory>();
+        assert_sync::<Atom>();
+        assert_sync::<UnitCell>();
+        assert_sync::<crate::Residue>();
+        assert_sync::<crate::Selection>();
+    }
+
+    #[test]
+    fn sync_trajectory() {
+        let trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let trajectory = SyncTrajectory::new(trajectory);
+        assert_eq!(trajectory.nsteps().unwrap(), 100);
+
+        let counts = std::thread::scope(|scope| {
+            let workers = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut count = 0;
+                        while let Ok(frame) = trajectory.read() {
+                            assert_eq!(frame.size(), 297);
+                            count += 1;
+                        }
+                        count
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(counts.iter().sum::<usize>(), 100);
+
+        let frame = trajectory.read_step(3).unwrap();
+        assert_eq!(frame.size(), 297);
+
+        let mut frame = Frame::new();
+        std::thread::spawn(move || trajectory.into_inner().read_step(0, &mut frame).unwrap())
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn error_context() {
+        let error = Trajectory::open("data/not-here.xyz", 'r').unwrap_err();
+        let context = error.context().unwrap();
+        assert_eq!(context.operation, TrajectoryOperation::Open);
+        assert_eq!(context.path, Some(PathBuf::from("data/not-here.xyz")));
+        assert_eq!(context.step, None);
+
+        let data = "1\n\nO 0 0 0\n1\n\nO 0 0 xxx\n";
+        let mut trajectory = Trajectory::memory_reader(data, "XYZ").unwrap();
+        let mut frame = Frame::new();
+        trajectory.read(&mut frame).unwrap();
+        let error = trajectory.read(&mut frame).unwrap_err();
+        let context = error.context().unwrap();
+        assert_eq!(context.operation, TrajectoryOperation::Read);
+        assert_eq!(context.path, None);
+        assert_eq!(context.step, Some(1));
+        assert!(error.to_string().ends_with("(while reading step 1 in memory)"));
+
+        let mut trajectory = Trajectory::open("data/water.xyz", 'r').unwrap();
+        let error = trajectory.read_step(200, &mut frame).unwrap_err();
+        let context = error.context().unwrap();
+        assert_eq!(context.path, Some(PathBuf::from("data/water.xyz")));
+        assert_eq!(context.step, Some(200));
+    }
+
+    #[test]
+    fn read_report() {
+        let data = "FOOBAR this is not a PDB record
+ATOM      1  O   HOH A   1       0.000   0.000   0.000  1.00  0.00           O
+ATOM      2  H1  HOH A   1       0.960   0.000   0.000  1.00  0.00           H
+END
+";
+        let mut trajectory = MemoryTrajectoryReader::new(data.as_bytes(), "PDB").unwrap();
+        assert!(trajectory.last_read_report().is_none());
+
+        let mut frame = Frame::new();
+        trajectory.read(&mut frame).unwrap();
+
+        let report = trajectory.last_read_report().unwrap();
+        assert_eq!(report.format, "PDB");
+        assert_eq!(report.step, 0);
+        assert_eq!(report.natoms, 2);
+        assert!(!report.has_velocities);
+        assert_eq!(report.cell_shape, CellShape::Infinite);
+        assert!(!report.warnings.is_empty());
+
+        // reading past the end clears the report
+        assert!(trajectory.read(&mut frame).is_err());
+        assert!(trajectory.last_read_report().is_none());
+    }
 }